@@ -191,11 +191,64 @@ fn bench_write_fixed_skiplist(c: &mut Criterion) {
   j.join().unwrap();
 }
 
+fn bench_get_small_keys(c: &mut Criterion) {
+  const N: u64 = 100_000;
+
+  let mut group = c.benchmark_group("small_key_get");
+  for inline in [0, 8] {
+    let list = SkipMap::with_options(
+      Options::new()
+        .with_capacity(64 << 20)
+        .with_inline_keys(inline),
+    )
+    .unwrap();
+    for i in 0..N {
+      list.insert(0, &i.to_be_bytes(), b"00123").unwrap();
+    }
+
+    let mut rng = rand::thread_rng();
+    group.bench_with_input(BenchmarkId::new("inline_keys", inline), &list, |b, l| {
+      b.iter_batched(
+        || rng.gen_range(0..N).to_be_bytes(),
+        |key| {
+          assert!(l.get(0, &key).is_some());
+        },
+        BatchSize::SmallInput,
+      )
+    });
+  }
+  group.finish();
+}
+
+fn bench_compare_shared_prefix(c: &mut Criterion) {
+  let mut group = c.benchmark_group("compare_shared_prefix");
+  for len in [16, 64, 256] {
+    // The keys only differ in the last byte, so the whole prefix is compared.
+    let a = vec![b'k'; len];
+    let mut b = a.clone();
+    b[len - 1] = b'l';
+
+    group.bench_with_input(
+      BenchmarkId::new("ascend", len),
+      &(&a, &b),
+      |bencher, (a, b)| bencher.iter(|| Ascend.compare(black_box(a), black_box(b))),
+    );
+    group.bench_with_input(
+      BenchmarkId::new("slice_cmp", len),
+      &(&a, &b),
+      |bencher, (a, b)| bencher.iter(|| black_box(a).cmp(black_box(b))),
+    );
+  }
+  group.finish();
+}
+
 criterion_group!(
   benches,
   bench_read_write_fixed_skiplist,
   bench_write_fixed_map,
   bench_write_fixed_skiplist,
   bench_read_write_fixed_map,
+  bench_get_small_keys,
+  bench_compare_shared_prefix,
 );
 criterion_main!(benches);
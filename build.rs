@@ -0,0 +1,33 @@
+use std::{env, process::Command};
+
+fn main() {
+  println!("cargo:rerun-if-changed=build.rs");
+
+  let minor = match rustc_minor_version() {
+    Some(minor) => minor,
+    None => return,
+  };
+
+  // Rust 1.80 checks the names of the cfgs, older versions do not know the instruction.
+  if minor >= 80 {
+    println!("cargo:rustc-check-cfg=cfg(core_error)");
+  }
+
+  // `core::error::Error` is stable since Rust 1.81, the errors only implement it without `std`
+  // when the compiler supports it, so `rust-version` is not raised for the `no_std` builds.
+  if minor >= 81 {
+    println!("cargo:rustc-cfg=core_error");
+  }
+}
+
+/// Returns the minor version of the compiler, e.g. `81` for `rustc 1.81.0`.
+fn rustc_minor_version() -> Option<u32> {
+  let rustc = env::var_os("RUSTC")?;
+  let output = Command::new(rustc).arg("--version").output().ok()?;
+  let version = core::str::from_utf8(&output.stdout).ok()?;
+  let mut pieces = version.split('.');
+  if pieces.next() != Some("rustc 1") {
+    return None;
+  }
+  pieces.next()?.parse().ok()
+}
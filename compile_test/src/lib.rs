@@ -0,0 +1,36 @@
+//! Checks that the errors of `skl` can be constructed, formatted and used as
+//! [`core::error::Error`] without `std`.
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+use alloc::{format, string::String};
+use skl::{map::Error, ArenaError, CompareError};
+
+/// Formats the error through [`core::error::Error`].
+pub fn describe(err: &dyn core::error::Error) -> String {
+  format!("{err}")
+}
+
+/// Constructs and formats some of the errors of the map.
+pub fn errors() -> [String; 4] {
+  [
+    describe(&Error::KeyTooLarge { size: 10, max: 5 }),
+    describe(&Error::read_only()),
+    describe(&Error::from(ArenaError::ReadOnly)),
+    describe(&Error::from(CompareError::new("malformed key"))),
+  ]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_errors() {
+    let [too_large, read_only, arena, compare] = errors();
+    assert_eq!(too_large, "key size 10 is too large, the maximum is 5");
+    assert_eq!(read_only, arena);
+    assert_eq!(compare, "failed to compare keys: malformed key");
+  }
+}
@@ -16,7 +16,10 @@ extern crate alloc as std;
 #[cfg(feature = "std")]
 extern crate std;
 
-use core::{cmp, ops::RangeBounds};
+use core::{
+  cmp,
+  ops::{Bound, RangeBounds},
+};
 
 /// A map implementation based on skiplist
 pub mod map;
@@ -31,36 +34,55 @@ mod types;
 pub use types::*;
 
 pub use either;
-pub use map::{AllVersionsIter, SkipMap};
+pub use map::{get_layered_timed, AllVersionsIter, SkipMap};
 pub use rarena_allocator::{Arena, Error as ArenaError};
 pub use ux2::{u27, u5};
 
 const MAX_HEIGHT: usize = 32;
 
 #[cfg(feature = "std")]
-fn random_height(max_height: u8) -> u32 {
+fn random_u32() -> u32 {
   use rand::{thread_rng, Rng};
-  let mut rng = thread_rng();
-  let rnd: u32 = rng.gen();
-  let mut h = 1;
-  let max_height = max_height as usize;
-
-  while h < max_height && rnd <= PROBABILITIES[h] {
-    h += 1;
-  }
-  h as u32
+  thread_rng().gen()
 }
 
 #[cfg(not(feature = "std"))]
-fn random_height(max_height: u8) -> u32 {
+fn random_u32() -> u32 {
   use rand::{rngs::OsRng, Rng};
+  OsRng.gen()
+}
 
-  let max_height = max_height as usize;
-  let rnd: u32 = OsRng.gen();
+/// Returns the next number of the splitmix64 sequence whose state is `state`.
+fn splitmix64(state: &sync::AtomicU64) -> u64 {
+  const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+  let mut z = state
+    .fetch_add(GAMMA, sync::Ordering::Relaxed)
+    .wrapping_add(GAMMA);
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+  z ^ (z >> 31)
+}
+
+/// Returns a random height in `1..=max_height` for the random number `rnd`, each level is
+/// promoted with the probability `p`, `None` means the default probability (inverse of Euler's number).
+fn random_height(rnd: u32, max_height: u8, p: Option<f64>) -> u32 {
   let mut h = 1;
+  let max_height = max_height as usize;
 
-  while h < max_height && rnd <= PROBABILITIES[h] {
-    h += 1;
+  match p {
+    None => {
+      while h < max_height && rnd <= PROBABILITIES[h] {
+        h += 1;
+      }
+    }
+    Some(p) => {
+      let mut threshold = p;
+      while h < max_height && rnd as f64 <= u32::MAX as f64 * threshold {
+        h += 1;
+        threshold *= p;
+      }
+    }
   }
   h as u32
 }
@@ -95,9 +117,23 @@ pub trait Comparator: core::fmt::Debug {
   where
     &'a [u8]: PartialOrd<Q>,
     Q: ?Sized + PartialOrd<&'a [u8]>;
+
+  /// Returns a number identifying the ordering of this comparator.
+  ///
+  /// The discriminant is persisted by file backed maps, and reopening a map with a comparator
+  /// whose discriminant differs from the stored one is rejected. `0` means unknown,
+  /// and disables the check.
+  #[inline]
+  fn discriminant(&self) -> u8 {
+    0
+  }
 }
 
 /// Ascend is a comparator that compares byte slices in ascending order.
+///
+/// The slices are compared by [`Ord`] for `[u8]`, which is specialized to `memcmp`, so the shared
+/// prefix of long keys is already compared many bytes at a time, a hand-rolled comparison of
+/// 8 or 16 byte chunks is slower, see the `compare_shared_prefix` benchmark.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Ascend;
 
@@ -115,6 +151,11 @@ impl Comparator for Ascend {
   {
     range.contains(&key)
   }
+
+  #[inline]
+  fn discriminant(&self) -> u8 {
+    1
+  }
 }
 
 /// Descend is a comparator that compares byte slices in descending order.
@@ -135,6 +176,189 @@ impl Comparator for Descend {
   {
     range.contains(&key)
   }
+
+  #[inline]
+  fn discriminant(&self) -> u8 {
+    2
+  }
+}
+
+/// PrefixComparator is a comparator that only compares the first `len` bytes of the keys
+/// with the inner comparator, a key shorter than `len` is compared as a whole.
+///
+/// The keys sharing the same prefix are equal for the map, so they are stored as the versions
+/// of the same key: the key of the first inserted entry is kept, and inserting another key
+/// with the same prefix and version finds the existing entry. The ranges are also checked
+/// against the prefixes of the keys, so their bounds are usually prefixes as well.
+///
+/// # Example
+///
+/// ```rust
+/// use skl::{Ascend, PrefixComparator, SkipMap};
+///
+/// let map = SkipMap::with_comparator(PrefixComparator::new(Ascend, 4)).unwrap();
+/// map.insert(0, b"abcd-1", b"1").unwrap();
+/// map.insert(1, b"abcd-2", b"2").unwrap();
+/// map.insert(0, b"abce-1", b"3").unwrap();
+///
+/// assert_eq!(map.len(), 3);
+/// assert_eq!(map.get(1, b"abcd").unwrap().value(), b"2");
+/// assert_eq!(map.get(0, b"abcd-9").unwrap().value(), b"1");
+/// assert_eq!(map.iter(1).count(), 2);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrefixComparator<C = Ascend> {
+  inner: C,
+  len: usize,
+}
+
+impl<C> PrefixComparator<C> {
+  /// Creates a new comparator comparing the first `len` bytes of the keys with `inner`.
+  #[inline]
+  pub const fn new(inner: C, len: usize) -> Self {
+    Self { inner, len }
+  }
+
+  /// Returns the length of the compared prefix.
+  #[inline]
+  pub const fn prefix_len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns the inner comparator.
+  #[inline]
+  pub const fn inner(&self) -> &C {
+    &self.inner
+  }
+
+  #[inline]
+  fn prefix<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+    &key[..self.len.min(key.len())]
+  }
+}
+
+impl<C: Comparator> Comparator for PrefixComparator<C> {
+  #[inline]
+  fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+    self.inner.compare(self.prefix(a), self.prefix(b))
+  }
+
+  #[inline]
+  fn contains<'a, Q>(&self, range: &impl RangeBounds<Q>, key: &'a [u8]) -> bool
+  where
+    &'a [u8]: PartialOrd<Q>,
+    Q: ?Sized + PartialOrd<&'a [u8]>,
+  {
+    self.inner.contains(range, self.prefix(key))
+  }
+}
+
+/// TryComparator is a comparator which may fail to compare two keys, e.g. because the keys are
+/// structured records and one of them is malformed.
+///
+/// Every [`Comparator`] is an infallible `TryComparator`. A fallible one is used by a map through
+/// the [`Fallible`] adapter, whose [`SkipMap::try_get`] and [`SkipMap::try_insert`] report the
+/// [`CompareError`]s.
+///
+/// A key is valid if it can be compared with itself, the comparator must be able to compare
+/// any two valid keys.
+pub trait TryComparator: core::fmt::Debug {
+  /// Compares two byte slices, or returns an error if they cannot be compared.
+  fn try_compare(&self, a: &[u8], b: &[u8]) -> Result<cmp::Ordering, CompareError>;
+}
+
+impl<C: Comparator> TryComparator for C {
+  #[inline]
+  fn try_compare(&self, a: &[u8], b: &[u8]) -> Result<cmp::Ordering, CompareError> {
+    Ok(self.compare(a, b))
+  }
+}
+
+/// Fallible adapts a [`TryComparator`] to a [`Comparator`], so that it can order a map.
+///
+/// The methods of the map which cannot report an error order the keys which cannot be compared
+/// with themselves before all the other keys, and by their bytes among themselves, use
+/// [`SkipMap::try_get`] and [`SkipMap::try_insert`] to get the errors instead.
+///
+/// The bounds of a range cannot be compared by the inner comparator, so a key is checked against
+/// them by its bytes if it is valid, and an invalid key is only contained in the ranges with
+/// an unbounded start.
+///
+/// # Example
+///
+/// ```rust
+/// use skl::{CompareError, Fallible, SkipMap, TryComparator};
+/// use core::cmp::Ordering;
+///
+/// #[derive(Debug)]
+/// struct U64Keys;
+///
+/// impl TryComparator for U64Keys {
+///   fn try_compare(&self, a: &[u8], b: &[u8]) -> Result<Ordering, CompareError> {
+///     let decode = |k: &[u8]| {
+///       k.try_into()
+///         .map(u64::from_be_bytes)
+///         .map_err(|_| CompareError::new("the key is not 8 bytes"))
+///     };
+///     Ok(decode(a)?.cmp(&decode(b)?))
+///   }
+/// }
+///
+/// let map = SkipMap::with_comparator(Fallible::new(U64Keys)).unwrap();
+/// map.try_insert(0, &1u64.to_be_bytes(), b"one").unwrap();
+///
+/// assert_eq!(map.try_get(0, &1u64.to_be_bytes()).unwrap().unwrap().value(), b"one");
+/// assert!(map.try_get(0, b"bad").is_err());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fallible<C>(C);
+
+impl<C> Fallible<C> {
+  /// Creates a new comparator from a [`TryComparator`].
+  #[inline]
+  pub const fn new(cmp: C) -> Self {
+    Self(cmp)
+  }
+
+  /// Returns the inner comparator.
+  #[inline]
+  pub const fn inner(&self) -> &C {
+    &self.0
+  }
+}
+
+impl<C: TryComparator> Fallible<C> {
+  #[inline]
+  fn is_valid(&self, key: &[u8]) -> bool {
+    self.0.try_compare(key, key).is_ok()
+  }
+}
+
+impl<C: TryComparator> Comparator for Fallible<C> {
+  #[inline]
+  fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+    match self.0.try_compare(a, b) {
+      Ok(ord) => ord,
+      Err(_) => match (self.is_valid(a), self.is_valid(b)) {
+        (false, true) => cmp::Ordering::Less,
+        (true, false) => cmp::Ordering::Greater,
+        _ => a.cmp(b),
+      },
+    }
+  }
+
+  #[inline]
+  fn contains<'a, Q>(&self, range: &impl RangeBounds<Q>, key: &'a [u8]) -> bool
+  where
+    &'a [u8]: PartialOrd<Q>,
+    Q: ?Sized + PartialOrd<&'a [u8]>,
+  {
+    if self.is_valid(key) {
+      range.contains(&key)
+    } else {
+      matches!(range.start_bound(), Bound::Unbounded)
+    }
+  }
 }
 
 /// A trait for extra information that can be stored with entry in the skiplist.
@@ -145,6 +369,16 @@ impl Comparator for Descend {
 pub unsafe trait Trailer: Copy + core::fmt::Debug {
   /// Returns the version of the trailer.
   fn version(&self) -> u64;
+
+  /// Returns `true` if the entry is expired at `now`, the expired entries are skipped by
+  /// [`SkipMap::get_unexpired`] and the iterators created with [`AllVersionsIter::skip_expired`].
+  ///
+  /// The default implementation never expires, see [`ExpirableTrailer`].
+  #[inline]
+  fn is_expired(&self, now: u64) -> bool {
+    let _ = now;
+    false
+  }
 }
 
 unsafe impl Trailer for u64 {
@@ -163,6 +397,65 @@ unsafe impl Trailer for () {
   }
 }
 
+/// A [`Trailer`] which carries an expiry timestamp alongside the version.
+///
+/// The entry is expired once `now` reaches `expire_at`, the unit of the timestamps is up to the user,
+/// e.g. seconds or milliseconds since the UNIX epoch, as long as `now` is in the same unit.
+///
+/// # Example
+///
+/// ```rust
+/// use skl::{ExpirableTrailer, SkipMap};
+///
+/// let map = SkipMap::<ExpirableTrailer>::new().unwrap();
+/// map.insert(ExpirableTrailer::new(0, 100), b"a", b"a0").unwrap();
+/// map.insert(ExpirableTrailer::new(0, 200), b"b", b"b0").unwrap();
+///
+/// assert!(map.get_unexpired(0, 150, b"a").is_none());
+/// assert_eq!(map.get_unexpired(0, 150, b"b").unwrap().value(), b"b0");
+/// assert_eq!(map.iter(0).skip_expired(150).count(), 1);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct ExpirableTrailer {
+  version: u64,
+  expire_at: u64,
+}
+
+impl ExpirableTrailer {
+  /// Creates a new trailer with the given version and expiry timestamp.
+  #[inline]
+  pub const fn new(version: u64, expire_at: u64) -> Self {
+    Self { version, expire_at }
+  }
+
+  /// Returns the version of the trailer.
+  #[inline]
+  pub const fn version(&self) -> u64 {
+    self.version
+  }
+
+  /// Returns the timestamp from which the entry is expired.
+  #[inline]
+  pub const fn expire_at(&self) -> u64 {
+    self.expire_at
+  }
+}
+
+unsafe impl Trailer for ExpirableTrailer {
+  /// Returns the version of the trailer.
+  #[inline]
+  fn version(&self) -> u64 {
+    self.version
+  }
+
+  /// Returns `true` if `now` reached the expiry timestamp.
+  #[inline]
+  fn is_expired(&self, now: u64) -> bool {
+    self.expire_at <= now
+  }
+}
+
 mod sync {
   #[cfg(not(feature = "loom"))]
   pub(crate) use core::sync::atomic::*;
@@ -3,11 +3,11 @@ use core::{
   convert::Infallible,
   marker::PhantomData,
   mem,
-  ops::{Bound, RangeBounds},
+  ops::{Bound, ControlFlow, RangeBounds},
   ptr::{self, NonNull},
 };
 
-use std::boxed::Box;
+use std::{boxed::Box, vec::Vec};
 
 use crate::{Key, Trailer, VacantBuffer};
 
@@ -20,23 +20,42 @@ mod api;
 
 use either::Either;
 
+mod bloom;
+
 mod error;
 pub use error::Error;
 mod entry;
 pub use entry::*;
 mod iterator;
 pub use iterator::*;
+mod value_index;
+pub use value_index::*;
+mod reservation;
+pub use reservation::*;
+mod snapshot;
+pub use snapshot::*;
+
+mod session;
+pub use session::*;
+mod layered;
+pub use layered::get_layered_timed;
+
+#[cfg(feature = "serde")]
+mod serde_impl;
 
 use rarena_allocator::Error as ArenaError;
 
 #[cfg(test)]
 mod tests;
 
-const CURRENT_VERSION: u16 = 0;
+const CURRENT_VERSION: u16 = 1;
 
 /// The tombstone value size, if a node's value size is equal to this value, then it is a tombstone.
 const REMOVE: u32 = u32::MAX;
 
+/// The maximum number of entries printed by the `Debug` implementation of [`SkipMap`].
+const DEBUG_ENTRIES: usize = 16;
+
 type UpdateOk<'a, 'b, T, C> = Either<
   Option<VersionedEntryRef<'a, T, C>>,
   Result<VersionedEntryRef<'a, T, C>, VersionedEntryRef<'a, T, C>>,
@@ -53,19 +72,51 @@ struct Meta {
   magic_version: u16,
   /// Current height. 1 <= height <= 31. CAS.
   height: AtomicU8,
-  reserved_byte: u8,
+  /// The state bits of the skiplist, see [`Meta::SEALED`].
+  flags: AtomicU8,
+  /// The [`Comparator::discriminant`] of the comparator the skiplist was created with.
+  comparator: u8,
+  /// The CRC32C checksum of the ARENA stored by the last flush, see [`Meta::CHECKSUM`].
+  checksum: AtomicU32,
+  /// The number of linked nodes whose value is removed.
+  tombstones: AtomicU32,
+  /// The sum of the key sizes of the linked nodes, a key shared by several versions is counted once per version.
+  key_bytes: AtomicU64,
+  /// The sum of the value sizes of the linked nodes whose value is not removed.
+  value_bytes: AtomicU64,
+  /// The offset of the reserved region in the ARENA, see [`Options::with_reserved`].
+  reserved_offset: u32,
+  /// The size of the reserved region.
+  reserved: u32,
 }
 
 impl Meta {
+  /// The skiplist is sealed, no more writes are accepted.
+  const SEALED: u8 = 1;
+  /// The checksum field is set.
+  #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+  const CHECKSUM: u8 = 2;
+  /// The head and the tail nodes are not allocated yet, see [`Options::with_lazy_init`].
+  const UNINIT: u8 = 4;
+  /// A writer is allocating the head and the tail nodes.
+  const INITIALIZING: u8 = 8;
+
   #[inline]
-  fn new(version: u16) -> Self {
+  fn new(version: u16, comparator: u8, reserved_offset: u32, reserved: u32) -> Self {
     Self {
       max_version: AtomicU64::new(0),
-      min_version: AtomicU64::new(0),
+      min_version: AtomicU64::new(u64::MAX),
       magic_version: version,
       height: AtomicU8::new(1),
       len: AtomicU32::new(0),
-      reserved_byte: 0,
+      flags: AtomicU8::new(0),
+      comparator,
+      checksum: AtomicU32::new(0),
+      tombstones: AtomicU32::new(0),
+      key_bytes: AtomicU64::new(0),
+      value_bytes: AtomicU64::new(0),
+      reserved_offset,
+      reserved,
     }
   }
 
@@ -74,14 +125,29 @@ impl Meta {
     self.magic_version
   }
 
+  #[inline]
+  const fn comparator(&self) -> u8 {
+    self.comparator
+  }
+
+  #[inline]
+  const fn reserved(&self) -> u32 {
+    self.reserved
+  }
+
   #[inline]
   fn max_version(&self) -> u64 {
     self.max_version.load(Ordering::Acquire)
   }
 
+  /// The minimum version starts at `u64::MAX` so that the first write lowers it to its own version,
+  /// until then the maximum version, `0`, is returned.
   #[inline]
   fn min_version(&self) -> u64 {
-    self.min_version.load(Ordering::Acquire)
+    self
+      .min_version
+      .load(Ordering::Acquire)
+      .min(self.max_version())
   }
 
   #[inline]
@@ -94,11 +160,139 @@ impl Meta {
     self.len.load(Ordering::Acquire)
   }
 
+  #[inline]
+  fn is_sealed(&self) -> bool {
+    self.flags.load(Ordering::Acquire) & Self::SEALED != 0
+  }
+
+  /// Returns the checksum stored by the last flush, if any.
+  #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+  #[inline]
+  fn checksum(&self) -> Option<u32> {
+    if self.flags.load(Ordering::Acquire) & Self::CHECKSUM != 0 {
+      Some(self.checksum.load(Ordering::Acquire))
+    } else {
+      None
+    }
+  }
+
+  #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+  #[inline]
+  fn set_checksum(&self, checksum: u32) {
+    self.checksum.store(checksum, Ordering::Release);
+    self.flags.fetch_or(Self::CHECKSUM, Ordering::AcqRel);
+  }
+
+  #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+  #[inline]
+  fn clear_checksum(&self) {
+    if self.flags.load(Ordering::Acquire) & Self::CHECKSUM != 0 {
+      self.flags.fetch_and(!Self::CHECKSUM, Ordering::AcqRel);
+    }
+  }
+
+  /// Marks the skiplist as sealed, returns `false` if it was already sealed.
+  #[inline]
+  fn seal(&self) -> bool {
+    self.flags.fetch_or(Self::SEALED, Ordering::AcqRel) & Self::SEALED == 0
+  }
+
+  #[inline]
+  fn is_uninit(&self) -> bool {
+    self.flags.load(Ordering::Acquire) & Self::UNINIT != 0
+  }
+
+  #[inline]
+  fn mark_uninit(&self) {
+    self.flags.fetch_or(Self::UNINIT, Ordering::AcqRel);
+  }
+
+  /// Claims the allocation of the head and the tail nodes, returns `false` if they are allocated
+  /// or another writer claimed it.
+  #[inline]
+  fn begin_init(&self) -> bool {
+    let flags = self.flags.load(Ordering::Acquire);
+    flags & (Self::UNINIT | Self::INITIALIZING) == Self::UNINIT
+      && self
+        .flags
+        .compare_exchange(
+          flags,
+          flags | Self::INITIALIZING,
+          Ordering::AcqRel,
+          Ordering::Acquire,
+        )
+        .is_ok()
+  }
+
+  /// Releases the claim taken by [`begin_init`](Self::begin_init), the head and the tail nodes
+  /// are allocated if `done` is `true`.
+  #[inline]
+  fn end_init(&self, done: bool) {
+    let clear = if done {
+      Self::UNINIT | Self::INITIALIZING
+    } else {
+      Self::INITIALIZING
+    };
+    self.flags.fetch_and(!clear, Ordering::Release);
+  }
+
   #[inline]
   fn increase_len(&self) {
     self.len.fetch_add(1, Ordering::Release);
   }
 
+  #[inline]
+  fn decrease_len(&self) {
+    self.len.fetch_sub(1, Ordering::Release);
+  }
+
+  #[inline]
+  fn tombstones(&self) -> u32 {
+    self.tombstones.load(Ordering::Acquire)
+  }
+
+  #[inline]
+  fn increase_tombstones(&self) {
+    self.tombstones.fetch_add(1, Ordering::Release);
+  }
+
+  #[inline]
+  fn decrease_tombstones(&self) {
+    self.tombstones.fetch_sub(1, Ordering::Release);
+  }
+
+  #[inline]
+  fn key_bytes(&self) -> u64 {
+    self.key_bytes.load(Ordering::Acquire)
+  }
+
+  #[inline]
+  fn value_bytes(&self) -> u64 {
+    self.value_bytes.load(Ordering::Acquire)
+  }
+
+  /// Accounts the key and the value of a node, a removed value has no bytes.
+  #[inline]
+  fn increase_bytes(&self, key_size: u32, value_size: u32) {
+    self.key_bytes.fetch_add(key_size as u64, Ordering::Release);
+    if value_size != REMOVE {
+      self
+        .value_bytes
+        .fetch_add(value_size as u64, Ordering::Release);
+    }
+  }
+
+  /// Reverts [`increase_bytes`](Self::increase_bytes).
+  #[inline]
+  fn decrease_bytes(&self, key_size: u32, value_size: u32) {
+    self.key_bytes.fetch_sub(key_size as u64, Ordering::Release);
+    if value_size != REMOVE {
+      self
+        .value_bytes
+        .fetch_sub(value_size as u64, Ordering::Release);
+    }
+  }
+
   fn update_max_version(&self, version: u64) {
     let mut current = self.max_version.load(Ordering::Acquire);
 
@@ -110,7 +304,7 @@ impl Meta {
       match self.max_version.compare_exchange_weak(
         current,
         version,
-        Ordering::SeqCst,
+        Ordering::AcqRel,
         Ordering::Acquire,
       ) {
         Ok(_) => break,
@@ -130,7 +324,7 @@ impl Meta {
       match self.min_version.compare_exchange_weak(
         current,
         version,
-        Ordering::SeqCst,
+        Ordering::AcqRel,
         Ordering::Acquire,
       ) {
         Ok(_) => break,
@@ -173,10 +367,26 @@ impl AtomicValuePointer {
     )
   }
 
+  /// Replaces the pointer with the new one, only if the pointer is still `current`.
+  #[inline]
+  fn compare_replace(&self, (offset, len): (u32, u32), (new_offset, new_len): (u32, u32)) -> bool {
+    let old = encode_value_pointer(offset, len);
+    let new = encode_value_pointer(new_offset, new_len);
+    self
+      .0
+      .compare_exchange(old, new, Ordering::AcqRel, Ordering::Relaxed)
+      .is_ok()
+  }
+
+  /// Marks the value as removed, only if the pointer is still `current`.
   #[inline]
-  fn compare_remove(&self, success: Ordering, failure: Ordering) -> Result<(u32, u32), (u32, u32)> {
-    let old = self.0.load(Ordering::Acquire);
-    let (offset, _) = decode_value_pointer(old);
+  fn compare_remove(
+    &self,
+    (offset, len): (u32, u32),
+    success: Ordering,
+    failure: Ordering,
+  ) -> Result<(u32, u32), (u32, u32)> {
+    let old = encode_value_pointer(offset, len);
     let new = encode_value_pointer(offset, REMOVE);
     self
       .0
@@ -200,6 +410,11 @@ impl<T> Clone for NodePtr<T> {
 
 impl<T> Copy for NodePtr<T> {}
 
+// Safety: a NodePtr only gives shared access to a node in the ARENA, and the node is
+// only mutated through atomics, so it can be shared if the trailer can.
+unsafe impl<T: Sync> Send for NodePtr<T> {}
+unsafe impl<T: Sync> Sync for NodePtr<T> {}
+
 impl<T> NodePtr<T> {
   const NULL: Self = Self {
     ptr: ptr::null_mut(),
@@ -247,6 +462,13 @@ impl<T> NodePtr<T> {
     *tower_ptr = Link::new(next_offset, prev_offset);
   }
 
+  /// Loads the next offset of the tower at the given level.
+  ///
+  /// A node is published by a CAS with `AcqRel` on the `next_offset` of its predecessor,
+  /// and all the writes to the node (key, trailer, value and its own tower) happen before that CAS.
+  /// So an `Acquire` load here is enough to observe a fully initialized node at the loaded offset,
+  /// no sequentially consistent ordering is required on the search path.
+  ///
   /// ## Safety
   ///
   /// - The caller must ensure that the node is allocated by the arena.
@@ -322,6 +544,41 @@ impl Link {
   }
 }
 
+/// Estimates the ARENA capacity for the entries of a `SkipMap<u64>`,
+/// see [`Options::with_capacity_for`].
+pub(crate) fn estimate_capacity(
+  opts: &Options,
+  num_entries: usize,
+  avg_key_len: usize,
+  avg_value_len: usize,
+) -> usize {
+  const fn align_up(size: usize, align: usize) -> usize {
+    (size + align - 1) & !(align - 1)
+  }
+
+  let trailer_size = mem::size_of::<u64>();
+  let align = mem::align_of::<Node<u64>>().max(mem::align_of::<u64>());
+  let max_node_size = Node::<u64>::size(opts.max_height().into()) + trailer_size;
+
+  // A node of height `h` is built with probability `p^(h - 1)`, so the expected tower
+  // has `1 / (1 - p)` links, rounded up without `f64::ceil`, which is not in `core`.
+  let expected_links = 1.0 / (1.0 - opts.branching_probability());
+  let tower = (expected_links * Link::SIZE as f64) as usize + 1;
+  let entry = Node::<u64>::SIZE
+    + tower
+    + align_up(avg_key_len, align)
+    + align_up(trailer_size + avg_value_len, align);
+
+  // The meta, the reserved region, the head and the tail nodes, and one extra node of the
+  // maximum height, which covers the first nodes being taller than expected.
+  let fixed =
+    align_up(mem::size_of::<Meta>() + opts.reserved() as usize, align) + 3 * max_node_size;
+  let total = fixed.saturating_add(num_entries.saturating_mul(entry));
+
+  // A 1/8 margin for the variance of the heights and the header of the ARENA.
+  total.saturating_add(total / 8)
+}
+
 #[repr(C)]
 struct Node<T> {
   // A byte slice is 24 bytes. We are trying to save space here.
@@ -401,7 +658,7 @@ impl<T> Node<T> {
     trailer: T,
     value_size: u32,
     f: &impl Fn(&mut VacantBuffer<'a>) -> Result<(), E>,
-  ) -> Result<(), Either<E, Error>> {
+  ) -> Result<u32, Either<E, Error>> {
     let mut bytes = arena
       .alloc_aligned_bytes::<T>(value_size)
       .map_err(|e| Either::Right(e.into()))?;
@@ -414,12 +671,12 @@ impl<T> Node<T> {
     });
     f(&mut oval).map_err(Either::Left)?;
 
+    // The ARENA counts the bytes it cannot reclaim as discarded.
     let remaining = oval.remaining();
-    let mut discard = 0;
-    if remaining != 0
-      && unsafe { !arena.dealloc((value_offset + oval.len()) as u32, remaining as u32) }
-    {
-      discard += remaining;
+    if remaining != 0 {
+      unsafe {
+        arena.dealloc((value_offset + oval.len()) as u32, remaining as u32);
+      }
     }
 
     bytes.detach();
@@ -427,18 +684,56 @@ impl<T> Node<T> {
       trailer_ptr.write(trailer);
     }
 
-    if discard != 0 {
-      arena.increase_discarded(discard as u32);
+    let (old_offset, old_size) = self.value.swap(trailer_offset as u32, value_size);
+
+    // on success, which means that old value is removed, we need to dealloc the old value.
+    // A removed value owns no value bytes, and its trailer may already be reclaimed by `clear_value`.
+    if old_size != REMOVE {
+      unsafe {
+        arena.dealloc(old_offset, (mem::size_of::<T>() as u32) + old_size);
+      }
     }
 
-    let (old_offset, old_size) = self.value.swap(trailer_offset as u32, value_size);
+    Ok(old_size)
+  }
+
+  /// Like [`set_value`](Self::set_value), but the value is copied from `value`, and only replaced if
+  /// the value pointer is still `current`, returns `Ok(false)` otherwise.
+  ///
+  /// The old value is not deallocated, because the readers may still hold it, its bytes are counted
+  /// as discarded instead.
+  #[inline]
+  fn replace_value_if(
+    &self,
+    arena: &Arena,
+    current: (u32, u32),
+    trailer: T,
+    value: &[u8],
+  ) -> Result<bool, Error> {
+    let value_size = value.len() as u32;
+    let mut bytes = arena.alloc_aligned_bytes::<T>(value_size)?;
+    let trailer_ptr = bytes.as_mut_ptr().cast::<T>();
+    let trailer_offset = bytes.offset();
+    let value_offset = trailer_offset + mem::size_of::<T>();
 
-    // on success, which means that old value is removed, we need to dealloc the old value
     unsafe {
-      arena.dealloc(old_offset, (mem::size_of::<T>() as u32) + old_size);
+      arena
+        .get_bytes_mut(value_offset, value.len())
+        .copy_from_slice(value);
+      trailer_ptr.write(trailer);
     }
 
-    Ok(())
+    // On failure, `bytes` is deallocated when dropped.
+    if !self
+      .value
+      .compare_replace(current, (trailer_offset as u32, value_size))
+    {
+      return Ok(false);
+    }
+
+    bytes.detach();
+    arena.increase_discarded(mem::size_of::<T>() as u32 + current.1);
+    Ok(true)
   }
 
   #[inline]
@@ -447,10 +742,23 @@ impl<T> Node<T> {
     arena: &Arena,
     success: Ordering,
     failure: Ordering,
-  ) -> Result<(), (u32, u32)> {
+  ) -> Result<u32, (u32, u32)> {
+    self.clear_value_if(arena, self.value.load(Ordering::Acquire), success, failure)
+  }
+
+  /// Like [`clear_value`](Self::clear_value), but the value is only removed if the value pointer
+  /// is still `current`.
+  #[inline]
+  fn clear_value_if(
+    &self,
+    arena: &Arena,
+    current: (u32, u32),
+    success: Ordering,
+    failure: Ordering,
+  ) -> Result<u32, (u32, u32)> {
     self
       .value
-      .compare_remove(success, failure)
+      .compare_remove(current, success, failure)
       .map(|(offset, size)| {
         if size != u32::MAX {
           unsafe {
@@ -461,6 +769,8 @@ impl<T> Node<T> {
             arena.dealloc(offset, mem::size_of::<T>() as u32);
           }
         }
+
+        size
       })
   }
 }
@@ -561,7 +871,19 @@ impl<T: Copy> Node<T> {
 /// entries that shadow existing entries and perform deletion via tombstones. It
 /// is up to the user to process these shadow entries and tombstones
 /// appropriately during retrieval.
-#[derive(Debug)]
+///
+/// The map is `Send` and `Sync` if the trailer and the comparator are, all the writes go through
+/// `&self`, so it can be shared across threads by reference or with an `Arc`. The entries and
+/// the iterators borrowing a shared map can be sent to other threads as well.
+///
+/// The reads load the links and the value pointers with `Acquire` ordering, which pairs with the
+/// `Release` half of the compare-and-swap publishing a node or a new value, so a reader which
+/// observes a node, or a new value of a node, also observes the bytes written before it was published.
+/// The same holds for readers of a mapping shared with the writer, e.g. another process mapping the
+/// same file. The orderings are not configurable: a weaker load could follow a link to a node whose
+/// contents are not visible yet, and a stronger one does not make a published write visible sooner.
+///
+/// The `Debug` output lists the first 16 entries visible at the latest version.
 pub struct SkipMap<T = u64, C = Ascend> {
   arena: Arena,
   meta: NonNull<Meta>,
@@ -569,6 +891,16 @@ pub struct SkipMap<T = u64, C = Ascend> {
   tail: NodePtr<T>,
   data_offset: u32,
   opts: Options,
+  /// The state of the deterministic height generator, only set when
+  /// [`Options::with_height_seed`] is used.
+  height_rng: Option<AtomicU64>,
+  /// The number of inserts which increased the height of the skiplist, shared by the clones.
+  #[cfg(feature = "stats")]
+  height_promotions: std::sync::Arc<AtomicU64>,
+  /// The single-flight locks of [`SkipMap::get_or_compute_single_flight`], allocated on the
+  /// first miss or the first clone, and shared by the clones.
+  #[cfg(feature = "std")]
+  single_flight: LazySingleFlight,
   /// If set to true by tests, then extra delays are added to make it easier to
   /// detect unusual race conditions.
   #[cfg(all(test, feature = "std"))]
@@ -590,6 +922,14 @@ impl<T, C: Clone> Clone for SkipMap<T, C> {
       tail: self.tail,
       data_offset: self.data_offset,
       opts: self.opts,
+      height_rng: self
+        .height_rng
+        .as_ref()
+        .map(|state| AtomicU64::new(state.load(Ordering::Relaxed))),
+      #[cfg(feature = "stats")]
+      height_promotions: self.height_promotions.clone(),
+      #[cfg(feature = "std")]
+      single_flight: self.single_flight.share(),
       #[cfg(all(test, feature = "std"))]
       yield_now: self.yield_now,
       cmp: self.cmp.clone(),
@@ -597,63 +937,238 @@ impl<T, C: Clone> Clone for SkipMap<T, C> {
   }
 }
 
+impl<T: Trailer, C: Comparator> core::fmt::Debug for SkipMap<T, C> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    struct Entries<'a, T, C>(&'a SkipMap<T, C>);
+
+    impl<'a, T: Trailer, C: Comparator> core::fmt::Debug for Entries<'a, T, C> {
+      fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut list = f.debug_list();
+        let mut iter = self.0.iter(u64::MAX);
+        for ent in iter.by_ref().take(DEBUG_ENTRIES) {
+          list.entry(&(ent.key(), ent.value(), ent.version()));
+        }
+
+        if iter.next().is_some() {
+          list.entry(&format_args!("..."));
+        }
+        list.finish()
+      }
+    }
+
+    f.debug_struct("SkipMap")
+      .field("capacity", &self.capacity())
+      .field("allocated", &self.allocated())
+      .field("len", &self.len())
+      .field("entries", &Entries(self))
+      .finish()
+  }
+}
+
 impl<T, C> Drop for SkipMap<T, C> {
   fn drop(&mut self) {
-    if self.arena.refs() == 1 && !self.opts.unify() {
-      unsafe {
-        let _ = Box::from_raw(self.meta.as_ptr());
-      }
+    // Safety: the meta belongs to the map of the ARENA.
+    unsafe { self.release().run(&self.arena) }
+  }
+}
+
+/// Computes the CRC32C checksum of the allocated region of the ARENA after the meta.
+#[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+fn checksum(arena: &Arena) -> u32 {
+  let alignment = mem::align_of::<Meta>();
+  let start = ((arena.data_offset() + alignment - 1) & !(alignment - 1)) + mem::size_of::<Meta>();
+  let end = arena.allocated();
+  // Safety: the region is allocated by the ARENA.
+  crc32c::crc32c(unsafe { arena.get_bytes(start, end - start) })
+}
+
+/// The state needed to release a map, which is shared by the clones of the map and the
+/// [`ArenaBytes`](entry::ArenaBytes) handles, since all of them own the ARENA.
+///
+/// The last owner to be dropped flushes a file backed map if [`Options::with_flush_on_drop`]
+/// is enabled, and frees the meta if it is not allocated in the ARENA.
+#[derive(Clone, Copy)]
+struct Release {
+  meta: NonNull<Meta>,
+  opts: Options,
+}
+
+// Safety: the meta is only accessed through atomics, and freed by the last owner of the ARENA.
+unsafe impl Send for Release {}
+unsafe impl Sync for Release {}
+
+impl Release {
+  /// Stores the checksum of the ARENA in the meta, if [`Options::with_checksum`] is enabled.
+  #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+  #[inline]
+  fn store_checksum(&self, arena: &Arena) {
+    if self.opts.checksum() && self.opts.unify() && !arena.read_only() {
+      // Safety: the meta is alive as long as the ARENA is owned.
+      unsafe { self.meta.as_ref() }.set_checksum(checksum(arena));
+    }
+  }
+
+  /// Releases the map if `arena` is its last owner.
+  ///
+  /// ## Safety
+  /// - The meta must belong to the map of `arena`, and `arena` must be dropped right after.
+  unsafe fn run(self, arena: &Arena) {
+    if arena.refs() != 1 {
+      return;
+    }
+
+    #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+    if self.opts.flush_on_drop() && !arena.read_only() {
+      self.store_checksum(arena);
+      // Drop cannot report the error, users who need it should flush explicitly.
+      let _ = arena.flush();
+    }
+
+    if !self.opts.unify() {
+      let _ = Box::from_raw(self.meta.as_ptr());
+    }
+  }
+}
+
+impl<T, C, K, V> Extend<(T, K, V)> for SkipMap<T, C>
+where
+  T: Trailer,
+  C: Comparator,
+  K: AsRef<[u8]>,
+  V: AsRef<[u8]>,
+{
+  /// Upserts all the `(trailer, key, value)` tuples of the iterator.
+  ///
+  /// # Panics
+  ///
+  /// Panics if any insertion fails, e.g. when the ARENA runs out of space,
+  /// use [`SkipMap::try_extend`] to handle the error instead.
+  fn extend<I: IntoIterator<Item = (T, K, V)>>(&mut self, iter: I) {
+    if let Err(e) = self.try_extend(iter) {
+      panic!("failed to extend the skipmap: {e}, consider allocating a larger ARENA or using `SkipMap::try_extend`");
     }
   }
 }
 
+impl<'a, T: Trailer, C: Comparator> IntoIterator for &'a SkipMap<T, C> {
+  type Item = EntryRef<'a, T, C>;
+  type IntoIter = iterator::Iter<'a, T, C>;
+
+  /// Returns an iterator over the latest version of all the entries in the map, as [`SkipMap::iter`]
+  /// with the maximum version `u64::MAX`, use [`SkipMap::iter`] to read at another version.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(1, b"a", b"2").unwrap();
+  /// map.insert(0, b"b", b"3").unwrap();
+  ///
+  /// let mut values = Vec::new();
+  /// for ent in &map {
+  ///   values.push(ent.value().to_vec());
+  /// }
+  /// assert_eq!(values, [b"2", b"3"]);
+  /// ```
+  #[inline]
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter(u64::MAX)
+  }
+}
+
 impl<T, C> SkipMap<T, C> {
-  fn new_in(arena: Arena, cmp: C, opts: Options) -> Result<Self, Error> {
-    let data_offset = Self::check_capacity(&arena, opts.max_height().into())?;
+  fn new_in(arena: Arena, cmp: C, mut opts: Options) -> Result<Self, Error>
+  where
+    C: Comparator,
+  {
+    // A skiplist needs at least one level, which is a plain sorted linked list.
+    if u8::from(opts.max_height()) == 0 {
+      opts = opts.with_max_height(u5::new(1));
+    }
+
+    if let Some(p) = opts.custom_branching_probability() {
+      // also rejects NaN
+      if !(p > 0.0 && p < 1.0) {
+        return Err(Error::InvalidBranchingProbability);
+      }
+    }
+
+    // The filter hashes the raw key bytes, so the keys equal for the comparator must have
+    // equal bytes, which is only known for the byte-wise comparators.
+    if opts.bloom_filter() != 0 && !matches!(cmp.discriminant(), 1 | 2) {
+      return Err(Error::UnsupportedBloomFilter);
+    }
 
     if arena.read_only() {
+      // Make sure the meta can be read before using the size of the reserved region stored in it.
+      Self::check_capacity(&arena, opts.max_height().into(), 0)?;
+      // Safety: the meta fits in the arena, and it is valid as long as the arena is alive.
+      let reserved = unsafe { Self::get_meta(&arena).as_ref().reserved() };
+      let data_offset = Self::check_capacity(&arena, opts.max_height().into(), reserved)?;
       let (meta, head, tail) = Self::get_pointers(&arena);
-      return Ok(Self::construct(
-        arena,
-        meta,
-        head,
-        tail,
-        data_offset,
-        opts,
-        cmp,
-      ));
+      // Safety: the meta is in the arena, and it is valid as long as the arena is alive.
+      if unsafe { meta.as_ref().is_uninit() } {
+        opts = opts.with_lazy_init(true);
+      }
+      // Safety: the meta is in the arena, and it is valid as long as the arena is alive.
+      let stored = unsafe { meta.as_ref().comparator() };
+      let expected = cmp.discriminant();
+      if stored != 0 && expected != 0 && stored != expected {
+        return Err(Error::IncompatibleFormat);
+      }
+
+      let map = Self::construct(arena, meta, head, tail, data_offset, opts, cmp);
+      #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+      if let Some(checksum) = map.meta().checksum() {
+        if checksum != self::checksum(&map.arena) {
+          return Err(Error::ChecksumMismatch);
+        }
+      }
+
+      return Ok(map);
     }
 
+    let data_offset = Self::check_capacity(&arena, opts.max_height().into(), opts.reserved())?;
+
     let meta = if opts.unify() {
-      Self::allocate_meta(&arena, opts.magic_version())?
+      Self::allocate_meta(
+        &arena,
+        opts.magic_version(),
+        cmp.discriminant(),
+        opts.reserved(),
+      )?
     } else {
+      let reserved_offset = Self::allocate_reserved(&arena, opts.reserved())?;
       unsafe {
         NonNull::new_unchecked(Box::into_raw(Box::new(Meta {
           max_version: AtomicU64::new(0),
-          min_version: AtomicU64::new(0),
+          min_version: AtomicU64::new(u64::MAX),
           height: AtomicU8::new(1),
           len: AtomicU32::new(0),
           magic_version: opts.magic_version(),
-          reserved_byte: 0,
+          flags: AtomicU8::new(0),
+          comparator: cmp.discriminant(),
+          checksum: AtomicU32::new(0),
+          tombstones: AtomicU32::new(0),
+          key_bytes: AtomicU64::new(0),
+          value_bytes: AtomicU64::new(0),
+          reserved_offset,
+          reserved: opts.reserved(),
         })))
       }
     };
 
     let max_height: u8 = opts.max_height().into();
-    let head = Self::allocate_full_node(&arena, max_height)?;
-    let tail = Self::allocate_full_node(&arena, max_height)?;
-
-    // Safety:
-    // We will always allocate enough space for the head node and the tail node.
-    unsafe {
-      // Link all head/tail levels together.
-      for i in 0..(max_height as usize) {
-        let head_link = head.tower(&arena, i);
-        let tail_link = tail.tower(&arena, i);
-        head_link.next_offset.store(tail.offset, Ordering::Relaxed);
-        tail_link.prev_offset.store(head.offset, Ordering::Relaxed);
-      }
-    }
+    let (head, tail) = if opts.lazy_init() {
+      // Safety: the meta is valid, and the map is not shared yet.
+      unsafe { meta.as_ref().mark_uninit() };
+      Self::reserve_sentinels(&arena, max_height)
+    } else {
+      Self::allocate_sentinels(&arena, max_height)?
+    };
 
     Ok(Self::construct(
       arena,
@@ -666,15 +1181,34 @@ impl<T, C> SkipMap<T, C> {
     ))
   }
 
+  /// Clears the checksum stored by the last flush before a write, which it would not cover,
+  /// see [`Options::with_checksum`].
+  #[inline]
+  fn invalidate_checksum(&self) {
+    #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+    if !self.arena.read_only() {
+      self.meta().clear_checksum();
+    }
+  }
+
+  /// Returns the state needed to release the map, see [`Release`].
+  #[inline]
+  fn release(&self) -> Release {
+    Release {
+      meta: self.meta,
+      opts: self.opts,
+    }
+  }
+
   /// Checks if the arena has enough capacity to store the skiplist,
   /// and returns the data offset.
   #[inline]
-  const fn check_capacity(arena: &Arena, max_height: u8) -> Result<u32, Error> {
+  const fn check_capacity(arena: &Arena, max_height: u8, reserved: u32) -> Result<u32, Error> {
     let offset = arena.data_offset();
 
     let alignment = mem::align_of::<Meta>();
     let meta_offset = (offset + alignment - 1) & !(alignment - 1);
-    let meta_end = meta_offset + mem::size_of::<Meta>();
+    let meta_end = meta_offset + mem::size_of::<Meta>() + reserved as usize;
 
     let alignment = mem::align_of::<Node<T>>();
     let head_offset = (meta_end + alignment - 1) & !(alignment - 1);
@@ -721,6 +1255,10 @@ impl<T, C> SkipMap<T, C> {
       .check_node_size(height, key_size, value_size)
       .map_err(Either::Right)?;
 
+    if self.is_inline_key(key_size) {
+      return self.allocate_inline_key_node(height, trailer, key_size, kf, value_size, Some(vf));
+    }
+
     unsafe {
       let mut node = self
         .arena
@@ -839,6 +1377,17 @@ impl<T, C> SkipMap<T, C> {
       .check_node_size(height, key_size, value_size)
       .map_err(Either::Right)?;
 
+    if self.is_inline_key(key_size) {
+      return self.allocate_inline_key_node(
+        height,
+        trailer,
+        key_size,
+        kf,
+        value_size,
+        None::<fn(&mut VacantBuffer<'a>) -> Result<(), E>>,
+      );
+    }
+
     unsafe {
       let mut node = self
         .arena
@@ -889,54 +1438,84 @@ impl<T, C> SkipMap<T, C> {
     }
   }
 
-  /// Allocates a `Node`, trailer and value
-  fn allocate_value_node<'a, 'b: 'a, E>(
+  /// Returns `true` if a new key of the given size should be stored inline with its node.
+  #[inline]
+  fn is_inline_key(&self, key_size: u32) -> bool {
+    key_size != 0 && key_size <= self.opts.inline_keys()
+  }
+
+  /// Allocates a `Node` with the key stored right after its tower, and a trailer and value.
+  ///
+  /// If `vf` is `None`, only the trailer is allocated, which is used by the remove nodes.
+  fn allocate_inline_key_node<'a, E>(
     &'a self,
     height: u32,
     trailer: T,
     key_size: u32,
-    key_offset: u32,
+    kf: impl FnOnce(&mut VacantBuffer<'a>) -> Result<(), E>,
     value_size: u32,
-    vf: impl FnOnce(&mut VacantBuffer<'a>) -> Result<(), E>,
+    vf: Option<impl FnOnce(&mut VacantBuffer<'a>) -> Result<(), E>>,
   ) -> Result<(NodePtr<T>, Deallocator), Either<E, Error>> {
-    self
-      .check_node_size(height, key_size, value_size)
-      .map_err(Either::Right)?;
-
     unsafe {
       let mut node = self
         .arena
-        .alloc_aligned_bytes::<Node<T>>(height * Link::SIZE as u32)
+        .alloc_aligned_bytes::<Node<T>>(height * Link::SIZE as u32 + key_size)
         .map_err(|e| Either::Right(e.into()))?;
       let node_ptr = node.as_mut_ptr().cast::<Node<T>>();
       let node_offset = node.offset();
+      let key_offset = (node_offset + Node::<T>::size(height as u8)) as u32;
 
-      let mut trailer_and_value = self
+      // The key lives in the node's allocation, if the key cannot be filled,
+      // dropping the node releases the key as well.
+      let buf = self
         .arena
-        .alloc_aligned_bytes::<T>(value_size)
-        .map_err(|e| Either::Right(e.into()))?;
-      let trailer_offset = trailer_and_value.offset();
-      let trailer_ptr = trailer_and_value.as_mut_ptr().cast::<T>();
-      trailer_ptr.write(trailer);
-      let value_offset = (trailer_offset + mem::size_of::<T>()) as u32;
+        .get_bytes_mut(key_offset as usize, key_size as usize);
+      let mut key = VacantBuffer::new(key_size as usize, key_offset, buf);
+      kf(&mut key).map_err(Either::Left)?;
+
+      let (trailer_offset, value_deallocate_info) = match vf {
+        Some(vf) => {
+          let mut trailer_and_value = self
+            .arena
+            .alloc_aligned_bytes::<T>(value_size)
+            .map_err(|e| Either::Right(e.into()))?;
+          let trailer_offset = trailer_and_value.offset();
+          let trailer_ptr = trailer_and_value.as_mut_ptr().cast::<T>();
+          trailer_ptr.write(trailer);
+          let value_offset = (trailer_offset + mem::size_of::<T>()) as u32;
+
+          trailer_and_value.detach();
+          let (_, value_deallocate_info) = self
+            .fill_vacant_value(
+              trailer_offset as u32,
+              trailer_and_value.capacity() as u32,
+              value_size,
+              value_offset,
+              vf,
+            )
+            .map_err(Either::Left)?;
+          (trailer_offset, value_deallocate_info)
+        }
+        None => {
+          let mut trailer_ref = self
+            .arena
+            .alloc::<T>()
+            .map_err(|e| Either::Right(e.into()))?;
+          let trailer_offset = trailer_ref.offset();
+          trailer_ref.write(trailer);
+          trailer_ref.detach();
+          (
+            trailer_offset,
+            Pointer::new(trailer_offset as u32, mem::size_of::<T>() as u32),
+          )
+        }
+      };
 
       // Safety: the node is well aligned
       let node_ref = &mut *node_ptr;
       node_ref.value = AtomicValuePointer::new(trailer_offset as u32, value_size);
       node_ref.key_offset = key_offset;
       node_ref.key_size_and_height = encode_key_size_and_height(key_size, height as u8);
-
-      trailer_and_value.detach();
-      let (_, value_deallocate_info) = self
-        .fill_vacant_value(
-          trailer_offset as u32,
-          trailer_and_value.capacity() as u32,
-          value_size,
-          value_offset,
-          vf,
-        )
-        .map_err(Either::Left)?;
-
       node.detach();
 
       Ok((
@@ -950,10 +1529,71 @@ impl<T, C> SkipMap<T, C> {
     }
   }
 
-  fn allocate_full_node(arena: &Arena, max_height: u8) -> Result<NodePtr<T>, ArenaError> {
-    // Safety: node, links and trailer do not need to be dropped, and they are recoverable.
-    unsafe {
-      let mut node =
+  /// Allocates a `Node`, trailer and value
+  fn allocate_value_node<'a, 'b: 'a, E>(
+    &'a self,
+    height: u32,
+    trailer: T,
+    key_size: u32,
+    key_offset: u32,
+    value_size: u32,
+    vf: impl FnOnce(&mut VacantBuffer<'a>) -> Result<(), E>,
+  ) -> Result<(NodePtr<T>, Deallocator), Either<E, Error>> {
+    self
+      .check_node_size(height, key_size, value_size)
+      .map_err(Either::Right)?;
+
+    unsafe {
+      let mut node = self
+        .arena
+        .alloc_aligned_bytes::<Node<T>>(height * Link::SIZE as u32)
+        .map_err(|e| Either::Right(e.into()))?;
+      let node_ptr = node.as_mut_ptr().cast::<Node<T>>();
+      let node_offset = node.offset();
+
+      let mut trailer_and_value = self
+        .arena
+        .alloc_aligned_bytes::<T>(value_size)
+        .map_err(|e| Either::Right(e.into()))?;
+      let trailer_offset = trailer_and_value.offset();
+      let trailer_ptr = trailer_and_value.as_mut_ptr().cast::<T>();
+      trailer_ptr.write(trailer);
+      let value_offset = (trailer_offset + mem::size_of::<T>()) as u32;
+
+      // Safety: the node is well aligned
+      let node_ref = &mut *node_ptr;
+      node_ref.value = AtomicValuePointer::new(trailer_offset as u32, value_size);
+      node_ref.key_offset = key_offset;
+      node_ref.key_size_and_height = encode_key_size_and_height(key_size, height as u8);
+
+      trailer_and_value.detach();
+      let (_, value_deallocate_info) = self
+        .fill_vacant_value(
+          trailer_offset as u32,
+          trailer_and_value.capacity() as u32,
+          value_size,
+          value_offset,
+          vf,
+        )
+        .map_err(Either::Left)?;
+
+      node.detach();
+
+      Ok((
+        NodePtr::new(node_ptr as _, node_offset as u32),
+        Deallocator {
+          node: Some(Pointer::new(node_offset as u32, node.capacity() as u32)),
+          key: None,
+          value: Some(value_deallocate_info),
+        },
+      ))
+    }
+  }
+
+  fn allocate_full_node(arena: &Arena, max_height: u8) -> Result<NodePtr<T>, ArenaError> {
+    // Safety: node, links and trailer do not need to be dropped, and they are recoverable.
+    unsafe {
+      let mut node =
         arena.alloc_aligned_bytes::<Node<T>>(((max_height as usize) * Link::SIZE) as u32)?;
 
       // Safety: node and trailer do not need to be dropped.
@@ -977,25 +1617,152 @@ impl<T, C> SkipMap<T, C> {
     }
   }
 
+  /// Allocates the head and the tail nodes, and links all their levels together.
+  fn allocate_sentinels(
+    arena: &Arena,
+    max_height: u8,
+  ) -> Result<(NodePtr<T>, NodePtr<T>), ArenaError> {
+    let head = Self::allocate_full_node(arena, max_height)?;
+    let tail = Self::allocate_full_node(arena, max_height)?;
+
+    // Safety:
+    // We will always allocate enough space for the head node and the tail node.
+    unsafe {
+      // Link all head/tail levels together.
+      for i in 0..(max_height as usize) {
+        let head_link = head.tower(arena, i);
+        let tail_link = tail.tower(arena, i);
+        head_link.next_offset.store(tail.offset, Ordering::Relaxed);
+        tail_link.prev_offset.store(head.offset, Ordering::Relaxed);
+      }
+    }
+
+    Ok((head, tail))
+  }
+
+  /// Returns the head and the tail nodes [`allocate_sentinels`](Self::allocate_sentinels) will
+  /// allocate next, without allocating them. Their memory is zeroed, so the map reads as empty
+  /// until they are allocated.
+  fn reserve_sentinels(arena: &Arena, max_height: u8) -> (NodePtr<T>, NodePtr<T>) {
+    let (head, tail, end) = Self::sentinel_offsets(arena.allocated(), max_height);
+    // Safety: the range is in the capacity of the ARENA, checked by `check_capacity`,
+    // and nothing but the sentinels will be allocated there.
+    unsafe {
+      arena.get_bytes_mut(head, end - head).fill(0);
+      (
+        NodePtr::new(arena.get_pointer(head) as _, head as u32),
+        NodePtr::new(arena.get_pointer(tail) as _, tail as u32),
+      )
+    }
+  }
+
+  /// Returns the offsets of the head and the tail nodes, and the end of the tail node's trailer,
+  /// if they are allocated from `allocated`.
+  const fn sentinel_offsets(allocated: usize, max_height: u8) -> (usize, usize, usize) {
+    const fn align_up(offset: usize, alignment: usize) -> usize {
+      (offset + alignment - 1) & !(alignment - 1)
+    }
+
+    const fn node_end<T>(node_offset: usize, max_height: u8) -> usize {
+      let end = node_offset + mem::size_of::<Node<T>>() + Link::SIZE * max_height as usize;
+      if mem::size_of::<T>() != 0 {
+        align_up(end, mem::align_of::<T>()) + mem::size_of::<T>()
+      } else {
+        end
+      }
+    }
+
+    let alignment = mem::align_of::<Node<T>>();
+    let head = align_up(allocated, alignment);
+    let tail = align_up(node_end::<T>(head, max_height), alignment);
+    (head, tail, node_end::<T>(tail, max_height))
+  }
+
+  /// Allocates the head and the tail nodes of a map created with [`Options::with_lazy_init`],
+  /// if no write did it yet.
+  fn ensure_init(&self) -> Result<(), Error> {
+    if !self.opts.lazy_init() {
+      return Ok(());
+    }
+
+    let meta = self.meta();
+    while meta.is_uninit() {
+      if !meta.begin_init() {
+        // Another writer is allocating them.
+        core::hint::spin_loop();
+        continue;
+      }
+
+      // The sentinels can only land where the map expects them if the ARENA was not allocated
+      // from before, e.g. through `SkipMap::allocator`.
+      if self.arena.allocated() > self.head.offset as usize {
+        meta.end_init(false);
+        return Err(Error::Corrupted(LAZY_INIT_ALLOCATED));
+      }
+
+      let (head, tail) = match Self::allocate_sentinels(&self.arena, self.opts.max_height().into())
+      {
+        Ok(sentinels) => sentinels,
+        Err(e) => {
+          meta.end_init(false);
+          return Err(e.into());
+        }
+      };
+      if head.offset != self.head.offset || tail.offset != self.tail.offset {
+        meta.end_init(false);
+        return Err(Error::Corrupted(LAZY_INIT_ALLOCATED));
+      }
+      meta.end_init(true);
+    }
+    Ok(())
+  }
+
   #[inline]
-  fn allocate_meta(arena: &Arena, magic_version: u16) -> Result<NonNull<Meta>, ArenaError> {
+  fn allocate_meta(
+    arena: &Arena,
+    magic_version: u16,
+    comparator: u8,
+    reserved: u32,
+  ) -> Result<NonNull<Meta>, ArenaError> {
     // Safety: meta does not need to be dropped, and it is recoverable.
     unsafe {
       let mut meta = arena.alloc::<Meta>()?;
       meta.detach();
+      // The reserved region directly follows the meta, so it can be found again on reopen.
+      let reserved_offset = Self::allocate_reserved(arena, reserved)?;
 
       meta.write(Meta {
         max_version: AtomicU64::new(0),
-        min_version: AtomicU64::new(0),
+        min_version: AtomicU64::new(u64::MAX),
         height: AtomicU8::new(1),
         len: AtomicU32::new(0),
         magic_version,
-        reserved_byte: 0,
+        flags: AtomicU8::new(0),
+        comparator,
+        checksum: AtomicU32::new(0),
+        tombstones: AtomicU32::new(0),
+        key_bytes: AtomicU64::new(0),
+        value_bytes: AtomicU64::new(0),
+        reserved_offset,
+        reserved,
       });
       Ok(meta.as_mut_ptr())
     }
   }
 
+  /// Allocates the reserved region, and returns its offset, `0` if the region is empty.
+  #[inline]
+  fn allocate_reserved(arena: &Arena, size: u32) -> Result<u32, ArenaError> {
+    if size == 0 {
+      return Ok(0);
+    }
+
+    let mut bytes = arena.alloc_bytes(size)?;
+    // the region is never deallocated, it lives as long as the ARENA.
+    bytes.detach();
+    Ok(bytes.offset() as u32)
+  }
+
   #[inline]
   unsafe fn fill_vacant_key<'a, E>(
     &'a self,
@@ -1064,13 +1831,36 @@ impl<T, C> SkipMap<T, C> {
     Ok((oval.len() as u32, Pointer::new(offset, size)))
   }
 
+  #[inline]
+  fn get_meta(arena: &Arena) -> NonNull<Meta> {
+    unsafe {
+      let meta = arena.get_aligned_pointer::<Meta>(arena.data_offset());
+      NonNull::new_unchecked(meta as _)
+    }
+  }
+
   #[inline]
   fn get_pointers(arena: &Arena) -> (NonNull<Meta>, NodePtr<T>, NodePtr<T>) {
     unsafe {
-      let offset = arena.data_offset();
-      let meta = arena.get_aligned_pointer::<Meta>(offset);
+      let meta = Self::get_meta(arena);
+      if meta.as_ref().is_uninit() {
+        // The head and the tail nodes were never allocated, their links are zeroed,
+        // so any height finds the map empty.
+        let start = arena.offset(meta.as_ptr() as _)
+          + mem::size_of::<Meta>()
+          + meta.as_ref().reserved() as usize;
+        let (head, tail, _) = Self::sentinel_offsets(start, 1);
+        return (
+          meta,
+          NodePtr::new(arena.get_pointer(head) as _, head as u32),
+          NodePtr::new(arena.get_pointer(tail) as _, tail as u32),
+        );
+      }
 
-      let offset = arena.offset(meta as _) + mem::size_of::<Meta>();
+      // The head follows the meta and the reserved region.
+      let offset = arena.offset(meta.as_ptr() as _)
+        + mem::size_of::<Meta>()
+        + meta.as_ref().reserved() as usize;
       let head_ptr = arena.get_aligned_pointer::<Node<T>>(offset);
       let head_offset = arena.offset(head_ptr as _);
       let head = NodePtr::new(head_ptr as _, head_offset as u32);
@@ -1080,7 +1870,7 @@ impl<T, C> SkipMap<T, C> {
       let tail_ptr = arena.get_aligned_pointer::<Node<T>>(offset);
       let tail_offset = arena.offset(tail_ptr as _);
       let tail = NodePtr::new(tail_ptr as _, tail_offset as u32);
-      (NonNull::new_unchecked(meta as _), head, tail)
+      (meta, head, tail)
     }
   }
 
@@ -1093,7 +1883,10 @@ impl<T, C> SkipMap<T, C> {
 
     let max_key_size: u32 = self.opts.max_key_size().into();
     if key_size > max_key_size {
-      return Err(Error::KeyTooLarge(key_size as u64));
+      return Err(Error::KeyTooLarge {
+        size: key_size as u64,
+        max: max_key_size as u64,
+      });
     }
 
     // if value_size is u32::MAX, it means that the value is removed.
@@ -1104,7 +1897,10 @@ impl<T, C> SkipMap<T, C> {
     };
 
     if value_size > self.opts.max_value_size() {
-      return Err(Error::ValueTooLarge(value_size as u64));
+      return Err(Error::ValueTooLarge {
+        size: value_size as u64,
+        max: self.opts.max_value_size() as u64,
+      });
     }
 
     let entry_size = (value_size as u64 + key_size as u64) + Node::<T>::size(height as u8) as u64;
@@ -1131,7 +1927,12 @@ impl<T, C> SkipMap<T, C> {
       head,
       tail,
       data_offset,
+      height_rng: opts.height_seed().map(AtomicU64::new),
       opts,
+      #[cfg(feature = "stats")]
+      height_promotions: std::sync::Arc::new(AtomicU64::new(0)),
+      #[cfg(feature = "std")]
+      single_flight: LazySingleFlight::new(),
       #[cfg(all(test, feature = "std"))]
       yield_now: false,
       cmp,
@@ -1145,7 +1946,35 @@ impl<T, C> SkipMap<T, C> {
   }
 }
 
+impl<T, C> SkipMap<T, C> {
+  /// Returns `false` if the Bloom filter built by [`seal`](SkipMap::seal) rejects the key.
+  #[inline]
+  fn bloom_may_contain(&self, key: &[u8]) -> bool {
+    // Safety: the tail node is allocated by the ARENA.
+    let (offset, len) = unsafe { self.tail.as_ref().value.load(Ordering::Acquire) };
+    if len == 0 {
+      return true;
+    }
+
+    // Safety: the filter is allocated by the ARENA and never changes after sealing.
+    let filter = unsafe { self.arena.get_bytes(offset as usize, len as usize) };
+    bloom::may_contain(filter, key)
+  }
+}
+
 impl<T: Trailer, C> SkipMap<T, C> {
+  fn random_height(&self) -> u32 {
+    let rnd = match &self.height_rng {
+      Some(state) => (super::splitmix64(state) >> 32) as u32,
+      None => super::random_u32(),
+    };
+    super::random_height(
+      rnd,
+      self.opts.max_height().into(),
+      self.opts.custom_branching_probability(),
+    )
+  }
+
   fn new_node<'a, 'b: 'a, E>(
     &'a self,
     key: &Key<'a, 'b>,
@@ -1153,7 +1982,7 @@ impl<T: Trailer, C> SkipMap<T, C> {
     value_size: u32,
     f: &impl Fn(&mut VacantBuffer<'a>) -> Result<(), E>,
   ) -> Result<(NodePtr<T>, u32, Deallocator), Either<E, Error>> {
-    let height = super::random_height(self.opts.max_height().into());
+    let height = self.random_height();
     let (nd, deallocator) = match key {
       Key::Occupied(key) => self.allocate_entry_node(
         height,
@@ -1196,12 +2025,19 @@ impl<T: Trailer, C> SkipMap<T, C> {
       match self.meta().height.compare_exchange_weak(
         list_height,
         height as u8,
-        Ordering::SeqCst,
+        Ordering::AcqRel,
         Ordering::Acquire,
       ) {
         // Successfully increased skiplist.height.
-        Ok(_) => break,
-        Err(h) => list_height = h,
+        Ok(_) => {
+          #[cfg(feature = "stats")]
+          self.height_promotions.fetch_add(1, Ordering::Relaxed);
+          break;
+        }
+        Err(h) => {
+          list_height = h;
+          self.opts.backoff().snooze(0);
+        }
       }
     }
     Ok((nd, height, deallocator))
@@ -1219,6 +2055,10 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     }
 
     let offset = nd.prev_offset(&self.arena, height);
+    // The links of the head and the tail nodes are zeroed until they are allocated.
+    if offset == 0 {
+      return NodePtr::NULL;
+    }
     let ptr = self.arena.get_pointer(offset as usize);
     NodePtr::new(ptr as _, offset)
   }
@@ -1232,12 +2072,101 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       return NodePtr::NULL;
     }
     let offset = nptr.next_offset(&self.arena, height);
+    if offset == 0 {
+      return NodePtr::NULL;
+    }
     let ptr = self.arena.get_pointer(offset as usize);
     NodePtr::new(ptr as _, offset)
   }
 
+  /// Unlinks the node from all the levels of its tower.
+  ///
+  /// ## Safety
+  ///
+  /// - `nd` must be a node (not the head or the tail) linked into this map.
+  /// - No other write may run concurrently.
+  unsafe fn unlink(&self, nd: NodePtr<T>) {
+    let node = nd.as_ref();
+    for level in 0..node.height() as usize {
+      let prev = self.get_prev(nd, level);
+      let next = self.get_next(nd, level);
+      let _ = prev.cas_next_offset(
+        &self.arena,
+        level,
+        nd.offset,
+        next.offset,
+        Ordering::SeqCst,
+        Ordering::Acquire,
+      );
+      let _ = next.cas_prev_offset(
+        &self.arena,
+        level,
+        nd.offset,
+        prev.offset,
+        Ordering::SeqCst,
+        Ordering::Acquire,
+      );
+    }
+  }
+
+  /// Returns the node at the given offset, if the offset points at the start of a node
+  /// which is linked into this map.
+  fn node_at(&self, offset: u32) -> Option<NodePtr<T>> {
+    let allocated = self.arena.allocated();
+    let in_bounds = |offset: u32, size: usize| offset as usize + size <= allocated;
+
+    // Entry nodes are always allocated after the tail node.
+    if offset <= self.tail.offset
+      || offset % Node::<T>::ALIGN != 0
+      || !in_bounds(offset, Node::<T>::SIZE)
+    {
+      return None;
+    }
+
+    unsafe {
+      let ptr = NodePtr::<T>::new(self.arena.get_pointer(offset as usize) as _, offset);
+      let node = ptr.as_ref();
+      let height = node.height() as usize;
+      if height == 0
+        || height > super::MAX_HEIGHT
+        || !in_bounds(offset, Node::<T>::size(height as u8))
+        || !in_bounds(node.key_offset, node.key_size() as usize)
+      {
+        return None;
+      }
+
+      // The trailer and the value must be in bounds before they are read.
+      let (value_offset, value_len) = node.value.load(Ordering::Acquire);
+      if !in_bounds(value_offset, 0) {
+        return None;
+      }
+      let trailer_offset = Node::<T>::align_offset(value_offset);
+      let value_size = if value_len == REMOVE {
+        0
+      } else {
+        value_len as usize
+      };
+      if !in_bounds(trailer_offset, mem::size_of::<T>() + value_size) {
+        return None;
+      }
+
+      // A node is only valid if searching its key and version finds the node itself,
+      // arbitrary bytes which look like a node are never linked into the map.
+      let key = node.get_key(&self.arena);
+      let version = node
+        .get_trailer_by_offset(&self.arena, trailer_offset)
+        .version();
+      match self.find_near(version, key, false, true) {
+        (Some(found), true) if found.offset == offset => {}
+        _ => return None,
+      }
+
+      Some(ptr)
+    }
+  }
+
   /// Returns the first entry in the map.
-  fn first_in(&self, version: u64) -> Option<NodePtr<T>> {
+  fn first_in(&self) -> Option<NodePtr<T>> {
     // Safety: head node was definitely allocated by self.arena
     let nd = unsafe { self.get_next(self.head, 0) };
 
@@ -1245,26 +2174,49 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       return None;
     }
 
-    unsafe {
-      let node = nd.as_ref();
-      let curr_key = node.get_key(&self.arena);
-      self.ge(version, curr_key)
+    // The versions of a key are sorted from the newest to the oldest, so the callers
+    // only have to skip the nodes which are not visible at their version.
+    Some(nd)
+  }
+
+  /// Returns the node holding the oldest version of the key of `nd`.
+  ///
+  /// ## Safety
+  /// - `nd` must be a node (not the head or the tail) allocated by this map.
+  unsafe fn oldest_version(&self, mut nd: NodePtr<T>) -> NodePtr<T> {
+    let key = nd.as_ref().get_key(&self.arena);
+    loop {
+      let next = self.get_next(nd, 0);
+      if next.is_null()
+        || next.ptr == self.tail.ptr
+        || self.cmp.compare(next.as_ref().get_key(&self.arena), key) != cmp::Ordering::Equal
+      {
+        return nd;
+      }
+      nd = next;
     }
   }
 
   /// Returns the last entry in the map.
   fn last_in(&self, version: u64) -> Option<NodePtr<T>> {
     // Safety: tail node was definitely allocated by self.arena
-    let nd = unsafe { self.get_prev(self.tail, 0) };
+    let mut nd = unsafe { self.get_prev(self.tail, 0) };
 
-    if nd.is_null() || nd.ptr == self.head.ptr {
-      return None;
-    }
+    loop {
+      if nd.is_null() || nd.ptr == self.head.ptr {
+        return None;
+      }
 
-    unsafe {
-      let node = nd.as_ref();
-      let curr_key = node.get_key(&self.arena);
-      self.le(version, curr_key)
+      unsafe {
+        let node = nd.as_ref();
+        // Skip the keys which have no version visible at the version.
+        if node.get_trailer(&self.arena).version() <= version {
+          let curr_key = node.get_key(&self.arena);
+          return self.le(version, curr_key);
+        }
+
+        nd = self.get_prev(nd, 0);
+      }
     }
   }
 
@@ -1328,6 +2280,46 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     }
   }
 
+  /// Returns the entry greater than or equal to the given key, if it exists.
+  ///
+  /// Unlike [`SkipMap::ge`], the search starts from `from` instead of the head node,
+  /// so the cost depends on the distance between `from` and `key` rather than on the
+  /// size of the map.
+  ///
+  /// ## Safety
+  /// - `from` must be a node (not the head or the tail) allocated by this map.
+  /// - The key of `from` must be less than `key`.
+  unsafe fn ge_from(&self, version: u64, from: NodePtr<T>, key: &[u8]) -> Option<NodePtr<T>> {
+    let mut x = from;
+    let mut level = x.as_ref().height() as usize - 1;
+
+    loop {
+      // Assume x.key < key.
+      let next = self.get_next(x, level);
+      let at_end = next.is_null() || next.ptr == self.tail.ptr;
+      if !at_end {
+        let next_key = next.as_ref().get_key(&self.arena);
+        if self.cmp.compare(key, next_key) == cmp::Ordering::Greater {
+          // x.key < next.key < key. We can continue to move right.
+          x = next;
+          continue;
+        }
+      }
+
+      // x.key < key <= next.key, descend to get closer to key.
+      if level > 0 {
+        level -= 1;
+        continue;
+      }
+
+      if at_end {
+        return None;
+      }
+
+      return self.find_next_max_version(next, version);
+    }
+  }
+
   /// Returns the entry less than or equal to the given key, if it exists.
   ///
   /// e.g.
@@ -1347,88 +2339,59 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     }
   }
 
+  /// Returns the newest version less or equal to `version` of the key of `curr`, or of the nearest
+  /// key before it which has such a version.
+  ///
+  /// ## Safety
+  /// - `curr` must be the oldest version of its key, allocated by this map.
   unsafe fn find_prev_max_version(&self, mut curr: NodePtr<T>, version: u64) -> Option<NodePtr<T>> {
-    let mut prev = self.get_prev(curr, 0);
-
+    // Walking backward, the versions of a key go from the oldest to the newest.
+    let mut found = None;
     loop {
-      let curr_node = curr.as_ref();
-      let curr_key = curr_node.get_key(&self.arena);
-      // if the current version is greater than the given version, we should return.
-      let version_cmp = curr_node.get_trailer(&self.arena).version().cmp(&version);
-      if version_cmp == cmp::Ordering::Greater {
-        return None;
-      }
-
-      if prev.is_null() || prev.ptr == self.head.ptr {
-        if let cmp::Ordering::Less | cmp::Ordering::Equal = version_cmp {
-          return Some(curr);
-        }
-
-        return None;
+      if curr.is_null() || curr.ptr == self.head.ptr {
+        return found;
       }
 
-      let prev_node = prev.as_ref();
-      let prev_key = prev_node.get_key(&self.arena);
-      if self.cmp.compare(prev_key, curr_key) == cmp::Ordering::Less {
-        return Some(curr);
-      }
-
-      let version_cmp = prev_node.get_trailer(&self.arena).version().cmp(&version);
-
-      if version_cmp == cmp::Ordering::Equal {
-        return Some(prev);
+      let curr_node = curr.as_ref();
+      if curr_node.get_trailer(&self.arena).version() <= version {
+        found = Some(curr);
       }
 
-      if version_cmp == cmp::Ordering::Greater {
-        return Some(curr);
+      let prev = self.get_prev(curr, 0);
+      if found.is_some()
+        && (prev.is_null()
+          || prev.ptr == self.head.ptr
+          || self.cmp.compare(
+            prev.as_ref().get_key(&self.arena),
+            curr_node.get_key(&self.arena),
+          ) != cmp::Ordering::Equal)
+      {
+        return found;
       }
 
       curr = prev;
-      prev = self.get_prev(curr, 0);
     }
   }
 
+  /// Returns the first node from `curr` on whose version is less or equal to `version`. If `curr` is
+  /// the newest version of its key, this is the newest visible version of the key, or of the nearest
+  /// key after it which has one.
+  ///
+  /// ## Safety
+  /// - `curr` must be a node allocated by this map.
   unsafe fn find_next_max_version(&self, mut curr: NodePtr<T>, version: u64) -> Option<NodePtr<T>> {
-    let mut next = self.get_next(curr, 0);
-
+    // Walking forward, the versions of a key go from the newest to the oldest,
+    // so the first version less or equal to `version` is the newest visible one.
     loop {
-      let curr_node = curr.as_ref();
-      let curr_key = curr_node.get_key(&self.arena);
-      // if the current version is less or equal to the given version, we should return.
-      let version_cmp = curr_node.get_trailer(&self.arena).version().cmp(&version);
-      if let cmp::Ordering::Less | cmp::Ordering::Equal = version_cmp {
-        return Some(curr);
-      }
-
-      if next.is_null() || next.ptr == self.head.ptr {
-        if let cmp::Ordering::Less | cmp::Ordering::Equal = version_cmp {
-          return Some(curr);
-        }
-
-        return None;
-      }
-
-      let next_node = next.as_ref();
-      let next_key = next_node.get_key(&self.arena);
-      let version_cmp = next_node.get_trailer(&self.arena).version().cmp(&version);
-      if self.cmp.compare(next_key, curr_key) == cmp::Ordering::Greater {
-        if let cmp::Ordering::Less | cmp::Ordering::Equal = version_cmp {
-          return Some(curr);
-        }
-
+      if curr.is_null() || curr.ptr == self.tail.ptr {
         return None;
       }
 
-      if let cmp::Ordering::Less | cmp::Ordering::Equal = version_cmp {
-        if next.ptr == self.tail.ptr {
-          return None;
-        }
-
-        return Some(next);
+      if curr.as_ref().get_trailer(&self.arena).version() <= version {
+        return Some(curr);
       }
 
-      curr = next;
-      next = self.get_next(curr, 0);
+      curr = self.get_next(curr, 0);
     }
   }
 
@@ -1445,6 +2408,23 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     less: bool,
     allow_equal: bool,
   ) -> (Option<NodePtr<T>>, bool) {
+    match self.try_find_near(version, key, less, allow_equal, |a, b| {
+      Ok::<_, Infallible>(self.cmp.compare(a, b))
+    }) {
+      Ok(res) => res,
+      Err(e) => match e {},
+    }
+  }
+
+  /// Like `find_near`, but compares the keys with a fallible `compare`, and returns its first error.
+  unsafe fn try_find_near<E>(
+    &self,
+    version: u64,
+    key: &[u8],
+    less: bool,
+    allow_equal: bool,
+    compare: impl Fn(&[u8], &[u8]) -> Result<cmp::Ordering, E>,
+  ) -> Result<(Option<NodePtr<T>>, bool), E> {
     let mut x = self.head;
     let mut level = self.height() as usize - 1;
 
@@ -1461,22 +2441,20 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
 
         // level == 0. Can't descend further. Let's return something that makes sense.
         if !less {
-          return (None, false);
+          return Ok((None, false));
         }
 
         // Try to return x. Make sure it is not a head node.
         if x.ptr == self.head.ptr {
-          return (None, false);
+          return Ok((None, false));
         }
 
-        return (Some(x), false);
+        return Ok((Some(x), false));
       }
 
       let next_node = next.as_ref();
       let next_key = next_node.get_key(&self.arena);
-      let cmp = self
-        .cmp
-        .compare(key, next_key)
+      let cmp = compare(key, next_key)?
         .then_with(|| next_node.get_trailer(&self.arena).version().cmp(&version));
 
       match cmp {
@@ -1488,12 +2466,12 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
         cmp::Ordering::Equal => {
           // x.key < key == next.key.
           if allow_equal {
-            return (Some(next), true);
+            return Ok((Some(next), true));
           }
 
           if !less {
             // We want >, so go to base level to grab the next bigger node.
-            return (Some(self.get_next(next, 0)), false);
+            return Ok((Some(self.get_next(next, 0)), false));
           }
 
           // We want <. If not base level, we should go closer in the next level.
@@ -1503,7 +2481,7 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
           }
 
           // On base level, Return x.
-          return (Some(x), false);
+          return Ok((Some(x), false));
         }
         // In other words, x.key < key < next.
         cmp::Ordering::Less => {
@@ -1514,29 +2492,32 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
 
           // On base level. Need to return something.
           if !less {
-            return (Some(next), false);
+            return Ok((Some(next), false));
           }
 
           // Try to return x. Make sure it is not a head node.
           if x.ptr == self.head.ptr {
-            return (None, false);
+            return Ok((None, false));
           }
 
-          return (Some(x), false);
+          return Ok((Some(x), false));
         }
       }
     }
   }
 
+  /// Compares the keys with `compare`, and returns its first error.
+  ///
   /// ## Safety:
   /// - All of splices in the inserter must be contains node ptrs are allocated by the current skip map.
-  unsafe fn find_splice<'a, 'b: 'a>(
+  unsafe fn find_splice<'a, 'b: 'a, E>(
     &'a self,
     version: u64,
     key: &'b [u8],
     ins: &mut Inserter<T>,
     returned_when_found: bool,
-  ) -> (bool, Option<Pointer>, Option<NodePtr<T>>) {
+    compare: &impl Fn(&[u8], &[u8]) -> Result<cmp::Ordering, E>,
+  ) -> Result<(bool, Option<Pointer>, Option<NodePtr<T>>), E> {
     let list_height = self.height() as u32;
     let mut level = 0;
 
@@ -1558,20 +2539,28 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
           continue;
         }
 
-        if spl.prev.ptr != self.head.ptr && !self.key_is_after_node(spl.prev, version, key) {
-          // Key lies before splice.
-          level = list_height as usize;
-          break;
+        if spl.prev.ptr != self.head.ptr {
+          ins.profile.comparisons += 1;
+          if !self.key_is_after_node(spl.prev, version, key, compare)? {
+            // Key lies before splice.
+            level = list_height as usize;
+            break;
+          }
         }
 
-        if spl.next.ptr != self.tail.ptr && !self.key_is_after_node(spl.next, version, key) {
-          // Key lies after splice.
-          level = list_height as usize;
-          break;
+        if spl.next.ptr != self.tail.ptr {
+          ins.profile.comparisons += 1;
+          if self.key_is_after_node(spl.next, version, key, compare)? {
+            // Key lies after splice.
+            level = list_height as usize;
+            break;
+          }
         }
 
-        // The splice brackets the key!
+        // The splice brackets the key! Search this level again from its start, so the
+        // node with the same key and version is found if it is the next one.
         prev = spl.prev;
+        level += 1;
         break;
       }
     }
@@ -1579,7 +2568,8 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     let mut found = false;
     let mut found_key = None;
     for lvl in (0..level).rev() {
-      let mut fr = self.find_splice_for_level(version, key, lvl, prev);
+      let mut fr =
+        self.find_splice_for_level(version, key, lvl, prev, &mut ins.profile, compare)?;
       if fr.splice.next.is_null() {
         fr.splice.next = self.tail;
       }
@@ -1588,44 +2578,54 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
         found_key.get_or_insert(key);
       }
       if found && returned_when_found {
-        return (found, found_key, fr.curr);
+        // The splice of the lower levels is not computed, so it must not be reused.
+        ins.height = 0;
+        return Ok((found, found_key, fr.curr));
       }
+      // Continue the search of the lower level from where this level stopped.
+      prev = fr.splice.prev;
       ins.spl[lvl] = fr.splice;
     }
 
-    (found, found_key, None)
+    Ok((found, found_key, None))
   }
 
+  /// Compares the keys with `compare`, and returns its first error.
+  ///
   /// ## Safety
   /// - `level` is less than `MAX_HEIGHT`.
   /// - `start` must be allocated by self's arena.
-  unsafe fn find_splice_for_level(
+  unsafe fn find_splice_for_level<E>(
     &self,
     version: u64,
     key: &[u8],
     level: usize,
     start: NodePtr<T>,
-  ) -> FindResult<T> {
+    profile: &mut InsertProfile,
+    compare: &impl Fn(&[u8], &[u8]) -> Result<cmp::Ordering, E>,
+  ) -> Result<FindResult<T>, E> {
     let mut prev = start;
+    profile.levels_traversed += 1;
 
     loop {
       // Assume prev.key < key.
       let next = self.get_next(prev, level);
       if next.ptr == self.tail.ptr {
         // Tail node, so done.
-        return FindResult {
+        return Ok(FindResult {
           splice: Splice { prev, next },
           found: false,
           found_key: None,
           curr: None,
-        };
+        });
       }
 
       // offset is not zero, so we can safely dereference the next node ptr.
       let next_node = next.as_ref();
       let next_key = next_node.get_key(&self.arena);
 
-      let cmp = self.cmp.compare(key, next_key);
+      let cmp = compare(key, next_key)?;
+      profile.comparisons += 1;
 
       let mut found_key = None;
 
@@ -1634,7 +2634,6 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
           found_key = Some(Pointer {
             offset: next_node.key_offset,
             size: next_node.key_size(),
-            height: Some(next_node.height()),
           });
         }
         cmp::Ordering::Greater => {
@@ -1642,7 +2641,6 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
             found_key = Some(Pointer {
               offset: next_node.key_offset,
               size: key.len() as u32,
-              height: Some(next_node.height()),
             });
           }
         }
@@ -1652,22 +2650,22 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       match cmp.then_with(|| next_node.get_trailer(&self.arena).version().cmp(&version)) {
         // We are done for this level, since prev.key < key < next.key.
         cmp::Ordering::Less => {
-          return FindResult {
+          return Ok(FindResult {
             splice: Splice { prev, next },
             found: false,
             found_key,
             curr: None,
-          };
+          });
         }
         // Keep moving right on this level.
         cmp::Ordering::Greater => prev = next,
         cmp::Ordering::Equal => {
-          return FindResult {
+          return Ok(FindResult {
             splice: Splice { prev, next },
             found: true,
             found_key,
             curr: Some(next),
-          };
+          });
         }
       }
     }
@@ -1676,23 +2674,28 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
   /// ## Safety
   /// - The caller must ensure that the node is allocated by the arena.
   /// - The caller must ensure that the node is not null.
-  unsafe fn key_is_after_node(&self, nd: NodePtr<T>, version: u64, key: &[u8]) -> bool {
+  unsafe fn key_is_after_node<E>(
+    &self,
+    nd: NodePtr<T>,
+    version: u64,
+    key: &[u8],
+    compare: &impl Fn(&[u8], &[u8]) -> Result<cmp::Ordering, E>,
+  ) -> Result<bool, E> {
     let nd = &*nd.ptr;
     let nd_key = self
       .arena
       .get_bytes(nd.key_offset as usize, nd.key_size() as usize);
 
-    match self
-      .cmp
-      .compare(nd_key, key)
-      // .then_with(|| version.cmp(&nd.version))
-    {
+    Ok(match compare(nd_key, key)? {
       cmp::Ordering::Less => true,
       cmp::Ordering::Greater => false,
       cmp::Ordering::Equal => {
-        matches!(version.cmp(&nd.get_trailer(&self.arena).version()), cmp::Ordering::Less)
+        matches!(
+          version.cmp(&nd.get_trailer(&self.arena).version()),
+          cmp::Ordering::Less
+        )
       }
-    }
+    })
   }
 
   fn fetch_vacant_key<'a, 'b: 'a, E>(
@@ -1700,6 +2703,10 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     key_size: u32,
     key: impl FnOnce(&mut VacantBuffer<'a>) -> Result<(), E>,
   ) -> Result<VacantBuffer<'a>, Either<E, Error>> {
+    // The sentinel nodes are allocated before anything else.
+    self.ensure_init().map_err(Either::Right)?;
+    self.invalidate_checksum();
+
     let (key_offset, key_size) = self
       .arena
       .alloc_bytes(key_size)
@@ -1726,7 +2733,114 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       .map(|_| vk)
   }
 
+  /// Appends the entries, which must be sorted by key and by descending version for the same key,
+  /// to an empty map, which must not be shared with other threads while appending.
+  ///
+  /// Every level keeps its rightmost node, so a new node is linked between the rightmost nodes and
+  /// the tail without searching. An entry with the same key and version as the previous one is
+  /// skipped, the same as [`get_or_insert`](SkipMap::get_or_insert) does.
+  fn append_sorted<K, V>(&self, iter: impl IntoIterator<Item = (T, K, V)>) -> Result<(), Error>
+  where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    if self.meta().is_sealed() {
+      return Err(Error::Sealed);
+    }
+
+    self.ensure_init()?;
+    self.invalidate_checksum();
+
+    let mut rightmost = [self.head; super::MAX_HEIGHT];
+    let mut last: Option<(NodePtr<T>, u64)> = None;
+    for (trailer, key, value) in iter {
+      let (key, value) = (key.as_ref(), value.as_ref());
+      let version = trailer.version();
+
+      // Safety: the last node is allocated by the ARENA.
+      let k = match last {
+        None => Key::Occupied(key),
+        Some((prev, prev_version)) => unsafe {
+          let prev = prev.as_ref();
+          match self.cmp.compare(prev.get_key(&self.arena), key) {
+            cmp::Ordering::Less => Key::Occupied(key),
+            cmp::Ordering::Equal => match version.cmp(&prev_version) {
+              // An older version of the same key shares the key of the previous node.
+              cmp::Ordering::Less => Key::Pointer {
+                arena: &self.arena,
+                offset: prev.key_offset,
+                len: prev.key_size(),
+              },
+              cmp::Ordering::Equal => continue,
+              cmp::Ordering::Greater => return Err(Error::Unsorted),
+            },
+            cmp::Ordering::Greater => return Err(Error::Unsorted),
+          }
+        },
+      };
+
+      let copy = |buf: &mut VacantBuffer| {
+        let _ = buf.write(value);
+        Ok(())
+      };
+      let (nd, height, _) = self
+        .new_node::<Infallible>(&k, trailer, value.len() as u32, &copy)
+        .map_err(|e| e.expect_right("must be map::Error"))?;
+
+      // Safety: the node is allocated by the ARENA, and the map is not shared, so the links of
+      // the rightmost nodes and the tail cannot be changed by others.
+      unsafe {
+        for (i, prev) in rightmost.iter_mut().enumerate().take(height as usize) {
+          nd.write_tower(&self.arena, i, prev.offset, self.tail.offset);
+          let linked = prev
+            .cas_next_offset(
+              &self.arena,
+              i,
+              self.tail.offset,
+              nd.offset,
+              Ordering::AcqRel,
+              Ordering::Acquire,
+            )
+            .and_then(|_| {
+              self.tail.cas_prev_offset(
+                &self.arena,
+                i,
+                prev.offset,
+                nd.offset,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+              )
+            });
+          assert!(
+            linked.is_ok(),
+            "the map must be empty and not shared while appending"
+          );
+          *prev = nd;
+        }
+
+        let node = nd.as_ref();
+        self.meta().increase_len();
+        self
+          .meta()
+          .increase_bytes(node.key_size(), node.value.load(Ordering::Relaxed).1);
+      }
+      self.meta().update_max_version(version);
+      self.meta().update_min_version(version);
+      last = Some((nd, version));
+    }
+
+    #[cfg(feature = "debug-validate")]
+    self.validate_write();
+
+    Ok(())
+  }
+
   #[allow(clippy::too_many_arguments)]
+  #[inline]
   fn update<'a, 'b: 'a, E>(
     &'a self,
     trailer: T,
@@ -1738,11 +2852,83 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     ins: &mut Inserter<T>,
     upsert: bool,
   ) -> Result<UpdateOk<'a, 'b, T, C>, Either<E, Error>> {
+    self.try_update(
+      trailer,
+      key,
+      value_size,
+      f,
+      success,
+      failure,
+      ins,
+      upsert,
+      |a, b| Ok(self.cmp.compare(a, b)),
+    )
+  }
+
+  /// Like `update`, but compares the keys with a fallible `compare`, and fails with
+  /// [`Error::Compare`] if it fails before the node is linked.
+  #[allow(clippy::too_many_arguments)]
+  #[inline]
+  fn try_update<'a, 'b: 'a, E>(
+    &'a self,
+    trailer: T,
+    key: Key<'a, 'b>,
+    value_size: u32,
+    f: impl Fn(&mut VacantBuffer<'a>) -> Result<(), E>,
+    success: Ordering,
+    failure: Ordering,
+    ins: &mut Inserter<T>,
+    upsert: bool,
+    compare: impl Fn(&[u8], &[u8]) -> Result<cmp::Ordering, CompareError>,
+  ) -> Result<UpdateOk<'a, 'b, T, C>, Either<E, Error>> {
+    let res = self.update_in(
+      trailer, key, value_size, f, success, failure, ins, upsert, compare,
+    );
+
+    #[cfg(feature = "debug-validate")]
+    self.validate_write();
+
+    res
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn update_in<'a, 'b: 'a, E>(
+    &'a self,
+    trailer: T,
+    key: Key<'a, 'b>,
+    value_size: u32,
+    f: impl Fn(&mut VacantBuffer<'a>) -> Result<(), E>,
+    success: Ordering,
+    failure: Ordering,
+    ins: &mut Inserter<T>,
+    upsert: bool,
+    compare: impl Fn(&[u8], &[u8]) -> Result<cmp::Ordering, CompareError>,
+  ) -> Result<UpdateOk<'a, 'b, T, C>, Either<E, Error>> {
+    if self.meta().is_sealed() {
+      key.on_fail(&self.arena);
+      return Err(Either::Right(Error::Sealed));
+    }
+
+    if let Err(e) = self.ensure_init() {
+      key.on_fail(&self.arena);
+      return Err(Either::Right(e));
+    }
+    self.invalidate_checksum();
+
     let version = trailer.version();
 
     // Safety: a fresh new Inserter, so safe here
     let found_key = unsafe {
-      let (found, found_key, ptr) = self.find_splice(version, key.as_ref(), ins, true);
+      let (found, found_key, ptr) =
+        match self.find_splice(version, key.as_ref(), ins, true, &compare) {
+          Ok(res) => res,
+          Err(e) => {
+            // The splice may be partially computed, so it must not be reused.
+            ins.height = 0;
+            key.on_fail(&self.arena);
+            return Err(Either::Right(Error::Compare(e)));
+          }
+        };
       if found {
         let node_ptr = ptr.expect("the NodePtr cannot be `None` when we found");
         let old = VersionedEntryRef::from_node(node_ptr, self);
@@ -1751,7 +2937,7 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
 
         if upsert {
           return self.upsert(
-            old, node_ptr, &key, trailer, value_size, &f, success, failure,
+            old, node_ptr, &key, trailer, value_size, &f, success, failure, ins,
           );
         }
 
@@ -1797,6 +2983,11 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
         k.on_fail(&self.arena);
         e
       })?;
+    // Safety: the node is allocated by the ARENA, and not linked yet.
+    let (key_size, node_value_size) = unsafe {
+      let node = nd.as_ref();
+      (node.key_size(), node.value.load(Ordering::Relaxed).1)
+    };
 
     // We always insert from the base level and up. After you add a node in base
     // level, we cannot create a node in the level above because it would have
@@ -1830,7 +3021,18 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       // 2. CAS prevNextOffset to repoint from next to nd.
       // 3. CAS nextPrevOffset to repoint from prev to nd.
       unsafe {
+        let mut attempt = 0;
         loop {
+          if i == 0
+            && ins
+              .expected_next
+              .map_or(false, |expected| expected != next.offset)
+          {
+            k.on_fail(&self.arena);
+            deallocator.dealloc(&self.arena);
+            return Ok(Either::Left(None));
+          }
+
           let prev_offset = prev.offset;
           let next_offset = next.offset;
           nd.write_tower(&self.arena, i, prev_offset, next_offset);
@@ -1858,7 +3060,7 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
                 i,
                 next_prev_offset,
                 prev_offset,
-                Ordering::SeqCst,
+                Ordering::AcqRel,
                 Ordering::Acquire,
               );
             }
@@ -1869,7 +3071,7 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
             i,
             next.offset,
             nd.offset,
-            Ordering::SeqCst,
+            Ordering::AcqRel,
             Ordering::Acquire,
           ) {
             Ok(_) => {
@@ -1888,7 +3090,7 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
                 i,
                 prev_offset,
                 nd.offset,
-                Ordering::SeqCst,
+                Ordering::AcqRel,
                 Ordering::Acquire,
               );
 
@@ -1899,7 +3101,48 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
               // be helpful to try to use a different level as we redo the search,
               // because it is unlikely that lots of nodes are inserted between prev
               // and next.
-              let fr = self.find_splice_for_level(trailer.version(), k.as_ref(), i, prev);
+              ins.profile.cas_retries += 1;
+
+              // Gives up before the node is published, once it is linked at the base level
+              // the insert must finish linking the upper levels.
+              if i == 0 && ins.max_retries.map_or(false, |max| attempt as usize >= max) {
+                ins.contended = true;
+                k.on_fail(&self.arena);
+                deallocator.dealloc(&self.arena);
+                return Ok(Either::Left(None));
+              }
+
+              self.opts.backoff().snooze(attempt);
+              attempt += 1;
+              let fr = match self.find_splice_for_level(
+                trailer.version(),
+                k.as_ref(),
+                i,
+                prev,
+                &mut ins.profile,
+                &compare,
+              ) {
+                Ok(fr) => fr,
+                // Gives up before the node is published, the same as above.
+                Err(e) if i == 0 => {
+                  k.on_fail(&self.arena);
+                  deallocator.dealloc(&self.arena);
+                  return Err(Either::Right(Error::Compare(e)));
+                }
+                // Once the node is linked at the base level, the upper levels are ordered by
+                // the comparator of the map, as the other writes order them.
+                Err(_) => match self.find_splice_for_level(
+                  trailer.version(),
+                  k.as_ref(),
+                  i,
+                  prev,
+                  &mut ins.profile,
+                  &|a, b| Ok::<_, Infallible>(self.cmp.compare(a, b)),
+                ) {
+                  Ok(fr) => fr,
+                  Err(e) => match e {},
+                },
+              };
               if fr.found {
                 if i != 0 {
                   panic!("how can another thread have inserted a node at a non-base level?");
@@ -1914,7 +3157,9 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
 
                 if upsert {
                   deallocator.dealloc(&self.arena);
-                  return self.upsert(old, node_ptr, &k, trailer, value_size, &f, success, failure);
+                  return self.upsert(
+                    old, node_ptr, &k, trailer, value_size, &f, success, failure, ins,
+                  );
                 }
 
                 deallocator.dealloc(&self.arena);
@@ -1929,7 +3174,8 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
                 k.on_fail(&self.arena);
                 let node = nd.as_mut();
                 node.key_offset = p.offset;
-                node.key_size_and_height = encode_key_size_and_height(p.size, p.height.unwrap());
+                // Only the key is shared, the node keeps its own height.
+                node.key_size_and_height = encode_key_size_and_height(p.size, height as u8);
                 deallocator.key = None;
                 k = Key::Pointer {
                   arena: &self.arena,
@@ -1959,7 +3205,13 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
         ins.spl[i].prev = nd;
       }
     }
+    ins.profile.bytes_allocated += deallocator.size() as u64;
+    ins.inserted = true;
     self.meta().increase_len();
+    if k.is_remove() {
+      self.meta().increase_tombstones();
+    }
+    self.meta().increase_bytes(key_size, node_value_size);
     self.meta().update_max_version(version);
     self.meta().update_min_version(version);
 
@@ -1977,17 +3229,33 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     f: &impl Fn(&mut VacantBuffer<'a>) -> Result<(), E>,
     success: Ordering,
     failure: Ordering,
+    ins: &mut Inserter<T>,
   ) -> Result<UpdateOk<'a, 'b, T, C>, Either<E, Error>> {
     match key {
       Key::Occupied(_) | Key::Vacant(_) | Key::Pointer { .. } => node_ptr
         .as_ref()
         .set_value(&self.arena, trailer, value_size, f)
-        .map(|_| Either::Left(if old.is_removed() { None } else { Some(old) })),
+        .map(|old_size| {
+          if old_size == REMOVE {
+            self.meta().decrease_tombstones();
+          }
+          self.meta().decrease_bytes(0, old_size);
+          self.meta().increase_bytes(0, value_size);
+          Either::Left(if old.is_removed() { None } else { Some(old) })
+        }),
       Key::Remove(_) | Key::RemoveVacant(_) | Key::RemovePointer { .. } => {
         let node = node_ptr.as_ref();
         let key = node.get_key(&self.arena);
         match node.clear_value(&self.arena, success, failure) {
-          Ok(_) => Ok(Either::Left(None)),
+          Ok(old_size) => {
+            let had_value = old_size != REMOVE;
+            if had_value {
+              self.meta().increase_tombstones();
+            }
+            self.meta().decrease_bytes(0, old_size);
+            ins.removed = had_value;
+            Ok(Either::Left(None))
+          }
           Err((offset, len)) => {
             let trailer = node.get_trailer_by_offset(&self.arena, offset);
             let value = node.get_value_by_offset(&self.arena, offset, len);
@@ -2009,6 +3277,18 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
 pub struct Inserter<'a, T> {
   spl: [Splice<T>; super::MAX_HEIGHT],
   height: u32,
+  profile: InsertProfile,
+  /// The number of times the publish CAS may fail before the insert gives up, `None` means unbounded.
+  max_retries: Option<usize>,
+  /// Set when the insert gave up because of `max_retries`.
+  contended: bool,
+  /// Set when the insert linked a new node.
+  inserted: bool,
+  /// Set when the remove marked an existing value as removed.
+  removed: bool,
+  /// The offset of the node which must follow the new node at the base level, the insert gives
+  /// up before linking the new node anywhere else.
+  expected_next: Option<u32>,
   _m: core::marker::PhantomData<&'a ()>,
 }
 
@@ -2018,11 +3298,149 @@ impl<'a, T: Copy> Default for Inserter<'a, T> {
     Self {
       spl: [Splice::default(); super::MAX_HEIGHT],
       height: 0,
+      profile: InsertProfile::default(),
+      max_retries: None,
+      contended: false,
+      inserted: false,
+      removed: false,
+      expected_next: None,
       _m: core::marker::PhantomData,
     }
   }
 }
 
+/// The statistics of a single insert, see [`SkipMap::get_or_insert_profiled`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InsertProfile {
+  comparisons: u64,
+  cas_retries: u64,
+  levels_traversed: u64,
+  bytes_allocated: u64,
+}
+
+impl InsertProfile {
+  /// Returns the number of key comparisons made to find the position of the key.
+  #[inline]
+  pub const fn comparisons(&self) -> u64 {
+    self.comparisons
+  }
+
+  /// Returns the number of failed CAS operations when linking the new node, each of them
+  /// means another thread inserted a node at the same position concurrently.
+  #[inline]
+  pub const fn cas_retries(&self) -> u64 {
+    self.cas_retries
+  }
+
+  /// Returns the number of levels searched, including the levels searched again after a
+  /// failed CAS.
+  #[inline]
+  pub const fn levels_traversed(&self) -> u64 {
+    self.levels_traversed
+  }
+
+  /// Returns the number of ARENA bytes taken by the new entry (node, key, trailer and value),
+  /// `0` if nothing was inserted.
+  #[inline]
+  pub const fn bytes_allocated(&self) -> u64 {
+    self.bytes_allocated
+  }
+}
+
+/// A snapshot of the ARENA usage and the shape of a [`SkipMap`], see [`SkipMap::stats`].
+#[derive(Debug, Clone)]
+pub struct Stats {
+  capacity: usize,
+  allocated: usize,
+  remaining: usize,
+  nodes: usize,
+  tombstones: usize,
+  height: u8,
+}
+
+impl Stats {
+  /// Returns the capacity of the ARENA.
+  #[inline]
+  pub const fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  /// Returns the number of bytes allocated from the ARENA.
+  #[inline]
+  pub const fn allocated(&self) -> usize {
+    self.allocated
+  }
+
+  /// Returns the number of bytes which can still be allocated from the ARENA.
+  #[inline]
+  pub const fn remaining(&self) -> usize {
+    self.remaining
+  }
+
+  /// Returns the number of nodes in the skiplist, including the removed ones, see [`SkipMap::len`].
+  #[inline]
+  pub const fn nodes(&self) -> usize {
+    self.nodes
+  }
+
+  /// Returns the number of nodes whose value is removed.
+  #[inline]
+  pub const fn tombstones(&self) -> usize {
+    self.tombstones
+  }
+
+  /// Returns the height of the highest tower in use, see [`SkipMap::height`].
+  #[inline]
+  pub const fn height(&self) -> u8 {
+    self.height
+  }
+}
+
+/// A breakdown of the ARENA bytes used by the entries of a [`SkipMap`], see [`SkipMap::memory_footprint`].
+#[derive(Debug, Clone)]
+pub struct MemoryFootprint {
+  node_bytes: usize,
+  key_bytes: usize,
+  value_bytes: usize,
+  discarded_bytes: usize,
+  heights: [usize; MAX_HEIGHT],
+  max_height: u8,
+}
+
+impl MemoryFootprint {
+  /// Returns the number of bytes taken by the nodes, including their towers and trailers.
+  #[inline]
+  pub const fn node_bytes(&self) -> usize {
+    self.node_bytes
+  }
+
+  /// Returns the number of bytes taken by the keys, a key shared by the versions is counted once.
+  #[inline]
+  pub const fn key_bytes(&self) -> usize {
+    self.key_bytes
+  }
+
+  /// Returns the number of bytes taken by the values, the removed entries have no value.
+  #[inline]
+  pub const fn value_bytes(&self) -> usize {
+    self.value_bytes
+  }
+
+  /// Returns the number of bytes discarded by the ARENA, e.g. the overwritten values,
+  /// see [`SkipMap::discarded`].
+  #[inline]
+  pub const fn discarded_bytes(&self) -> usize {
+    self.discarded_bytes
+  }
+
+  /// Returns the number of nodes of each height, the element at index `i` counts the nodes of height `i + 1`,
+  /// up to the maximum height of the map.
+  #[inline]
+  pub fn heights(&self) -> &[usize] {
+    &self.heights[..self.max_height as usize]
+  }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Splice<T> {
   prev: NodePtr<T>,
@@ -2046,6 +3464,16 @@ struct Deallocator {
 }
 
 impl Deallocator {
+  /// Returns the number of bytes which would be deallocated.
+  #[inline]
+  fn size(&self) -> u32 {
+    [&self.node, &self.key, &self.value]
+      .into_iter()
+      .flatten()
+      .map(|ptr| ptr.size)
+      .sum()
+  }
+
   #[inline]
   fn dealloc(self, arena: &Arena) {
     unsafe {
@@ -2067,17 +3495,12 @@ impl Deallocator {
 struct Pointer {
   offset: u32,
   size: u32,
-  height: Option<u8>,
 }
 
 impl Pointer {
   #[inline]
   const fn new(offset: u32, size: u32) -> Self {
-    Self {
-      offset,
-      size,
-      height: None,
-    }
+    Self { offset, size }
   }
 }
 
@@ -2090,6 +3513,100 @@ struct FindResult<T> {
   curr: Option<NodePtr<T>>,
 }
 
+/// The reason of the error returned by the first write of a lazily initialized map whose ARENA
+/// was allocated from before it.
+const LAZY_INIT_ALLOCATED: &str =
+  "the ARENA of a lazily initialized map was allocated from before its first write";
+
+/// The number of shards of the single-flight locks, see
+/// [`SkipMap::get_or_compute_single_flight`].
+#[cfg(feature = "std")]
+const SINGLE_FLIGHT_SHARDS: usize = 64;
+
+/// The sharded single-flight locks of a map.
+#[cfg(feature = "std")]
+struct SingleFlight {
+  locks: Vec<std::sync::Mutex<()>>,
+}
+
+#[cfg(feature = "std")]
+impl SingleFlight {
+  #[inline]
+  fn new() -> Self {
+    Self {
+      locks: (0..SINGLE_FLIGHT_SHARDS)
+        .map(|_| std::sync::Mutex::new(()))
+        .collect(),
+    }
+  }
+
+  /// Locks the shard of the given key.
+  fn lock(&self, key: &[u8]) -> std::sync::MutexGuard<'_, ()> {
+    use core::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    key.hash(&mut hasher);
+    let shard = (hasher.finish() as usize) % SINGLE_FLIGHT_SHARDS;
+    // The lock protects no data, so a poisoned lock (a panicking `compute`) is still usable.
+    self.locks[shard].lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+/// A handle to the [`SingleFlight`] locks shared by the clones of a map, which allocates them
+/// on the first use, so a map which never misses and is never cloned does not allocate them.
+#[cfg(feature = "std")]
+struct LazySingleFlight(AtomicPtr<SingleFlight>);
+
+#[cfg(feature = "std")]
+impl LazySingleFlight {
+  #[inline]
+  const fn new() -> Self {
+    Self(AtomicPtr::new(ptr::null_mut()))
+  }
+
+  /// Returns the locks, allocating them if needed.
+  fn get(&self) -> &SingleFlight {
+    let mut locks = self.0.load(Ordering::Acquire);
+    if locks.is_null() {
+      let new = std::sync::Arc::into_raw(std::sync::Arc::new(SingleFlight::new())) as *mut _;
+      locks =
+        match self
+          .0
+          .compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire)
+        {
+          Ok(_) => new,
+          Err(current) => {
+            // Safety: `new` comes from `Arc::into_raw`, and it is not shared.
+            unsafe { drop(std::sync::Arc::from_raw(new)) };
+            current
+          }
+        };
+    }
+
+    // Safety: the pointer comes from `Arc::into_raw`, and the handle holds a reference to it.
+    unsafe { &*locks }
+  }
+
+  /// Returns another handle to the same locks, allocating them if needed.
+  fn share(&self) -> Self {
+    let locks: *const SingleFlight = self.get();
+    // Safety: the pointer comes from `Arc::into_raw`, and the handle holds a reference to it.
+    unsafe { std::sync::Arc::increment_strong_count(locks) };
+    Self(AtomicPtr::new(locks as *mut _))
+  }
+}
+
+#[cfg(feature = "std")]
+impl Drop for LazySingleFlight {
+  fn drop(&mut self) {
+    let locks = *self.0.get_mut();
+    if !locks.is_null() {
+      // Safety: the pointer comes from `Arc::into_raw`, and the handle holds a reference to it.
+      unsafe { drop(std::sync::Arc::from_raw(locks)) };
+    }
+  }
+}
+
 #[inline]
 const fn encode_value_pointer(offset: u32, val_size: u32) -> u64 {
   (val_size as u64) << 32 | offset as u64
@@ -34,6 +34,69 @@ impl<T> SkipMap<T> {
     Self::with_options_and_comparator(opts, Ascend)
   }
 
+  /// Creates a new skipmap with [`Options`] and upserts all the `(trailer, key, value)` tuples
+  /// of the iterator, see [`SkipMap::try_extend`].
+  ///
+  /// This is the fallible version of [`FromIterator`], the capacity of the options must be
+  /// large enough to hold all the entries.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Options};
+  ///
+  /// let entries = (0..10u32).map(|i| (0, i.to_be_bytes(), i.to_le_bytes()));
+  /// let map = SkipMap::from_iter_with_options(Options::new(), entries).unwrap();
+  /// assert_eq!(map.len(), 10);
+  /// ```
+  pub fn from_iter_with_options<K, V>(
+    opts: Options,
+    iter: impl IntoIterator<Item = (T, K, V)>,
+  ) -> Result<Self, Error>
+  where
+    T: Trailer,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    let map = Self::with_options(opts)?;
+    map.try_extend(iter)?;
+    Ok(map)
+  }
+
+  /// Creates a new skipmap with [`Options`] from the `(trailer, key, value)` tuples of an iterator
+  /// sorted by key, and by descending version for the same key, in one pass.
+  ///
+  /// Unlike [`SkipMap::from_iter_with_options`], no search is done for the entries, each node is
+  /// linked after the last node of every level it reaches, so an entry costs O(height) instead of
+  /// O(log n). A tuple with the same key and version as the previous one is skipped, and
+  /// [`Error::Unsorted`] is returned if the tuples are out of order.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Options};
+  ///
+  /// let entries = (0..100u32).map(|i| (0u64, i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()));
+  /// let map = SkipMap::build_sorted(Options::new().with_capacity(1 << 20), entries).unwrap();
+  /// assert_eq!(map.len(), 100);
+  /// assert_eq!(map.first(0).unwrap().key(), 0u32.to_be_bytes());
+  ///
+  /// let unsorted = [(0u64, b"b", b"b"), (0, b"a", b"a")];
+  /// assert!(SkipMap::build_sorted(Options::new(), unsorted).is_err());
+  /// ```
+  #[inline]
+  pub fn build_sorted<K, V>(
+    opts: Options,
+    iter: impl IntoIterator<Item = (T, K, V)>,
+  ) -> Result<Self, Error>
+  where
+    T: Trailer,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    Self::build_sorted_with_comparator(opts, Ascend, iter)
+  }
+
   /// Create a new memory map file backed with default options.
   ///
   /// **Note:** The capacity stands for how many memory mmaped,
@@ -154,6 +217,61 @@ impl<T, C> SkipMap<T, C> {
     self.data_offset as usize
   }
 
+  /// Returns the reserved region of the ARENA, see [`Options::with_reserved`].
+  ///
+  /// The region is empty if no bytes were reserved.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Options};
+  ///
+  /// let map = SkipMap::<u64>::with_options(Options::new().with_reserved(8)).unwrap();
+  /// assert_eq!(map.reserved_slice(), &[0; 8]);
+  /// ```
+  #[inline]
+  pub fn reserved_slice(&self) -> &[u8] {
+    let meta = self.meta();
+    // Safety: the reserved region is allocated by the ARENA, and it is never deallocated.
+    unsafe {
+      self
+        .arena
+        .get_bytes(meta.reserved_offset as usize, meta.reserved as usize)
+    }
+  }
+
+  /// Returns the reserved region of the ARENA mutably, see [`Options::with_reserved`].
+  ///
+  /// The clones of the map share the region, so like [`clear`](SkipMap::clear), this returns
+  /// [`Error::Shared`] if the ARENA is shared with the clones of the map.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Options};
+  ///
+  /// let mut map = SkipMap::<u64>::with_options(Options::new().with_reserved(8)).unwrap();
+  /// map.reserved_slice_mut().unwrap().copy_from_slice(&42u64.to_le_bytes());
+  /// assert_eq!(map.reserved_slice(), &42u64.to_le_bytes());
+  /// ```
+  #[inline]
+  pub fn reserved_slice_mut(&mut self) -> Result<&mut [u8], Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    if self.refs() != 1 {
+      return Err(Error::Shared);
+    }
+
+    self.invalidate_checksum();
+    let meta = self.meta();
+    let (offset, size) = (meta.reserved_offset as usize, meta.reserved as usize);
+    // Safety: the reserved region is allocated by the ARENA, and it is never deallocated.
+    // The map is not shared, and `&mut self` guarantees that nothing else accesses the region.
+    Ok(unsafe { self.arena.get_bytes_mut(offset, size) })
+  }
+
   /// Returns the version number of the [`SkipMap`].
   #[inline]
   pub const fn version(&self) -> u16 {
@@ -175,6 +293,48 @@ impl<T, C> SkipMap<T, C> {
     self.meta().height()
   }
 
+  /// Returns the number of inserts which increased the height of the skiplist,
+  /// i.e. whose random height exceeded the height of all the nodes allocated before.
+  ///
+  /// The counter is kept in memory and shared by the clones of the map,
+  /// it is not persisted for file backed maps.
+  #[cfg(feature = "stats")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+  #[inline]
+  pub fn height_promotions(&self) -> u64 {
+    self.height_promotions.load(Ordering::Relaxed)
+  }
+
+  /// Returns a snapshot of the ARENA usage and the shape of the map.
+  ///
+  /// The fields are read one by one, so the snapshot is not atomic when the map is written concurrently.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.get_or_insert(0, b"a", b"1").unwrap();
+  /// map.remove(1, b"b").unwrap();
+  ///
+  /// let stats = map.stats();
+  /// assert_eq!(stats.nodes(), 2);
+  /// assert_eq!(stats.tombstones(), 1);
+  /// assert_eq!(stats.capacity(), map.capacity());
+  /// ```
+  pub fn stats(&self) -> Stats {
+    let meta = self.meta();
+    Stats {
+      capacity: self.arena.capacity(),
+      allocated: self.arena.allocated(),
+      remaining: self.arena.remaining(),
+      nodes: meta.len() as usize,
+      tombstones: meta.tombstones() as usize,
+      height: meta.height(),
+    }
+  }
+
   /// Returns the number of remaining bytes can be allocated by the arena.
   #[inline]
   pub fn remaining(&self) -> usize {
@@ -187,6 +347,107 @@ impl<T, C> SkipMap<T, C> {
     self.arena.allocated()
   }
 
+  /// Reserves `bytes` bytes in the ARENA, returns `None` if the map is read-only or sealed,
+  /// or the ARENA does not have enough space.
+  ///
+  /// The space is given back when the [`Reservation`] is dropped without being committed,
+  /// see [`Reservation`] for the strict LIFO constraint of the rollback.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::<u64>::new().unwrap();
+  /// let allocated = map.allocated();
+  ///
+  /// let mut reservation = map.reserve(16).unwrap();
+  /// reservation[..5].copy_from_slice(b"hello");
+  /// assert!(map.allocated() > allocated);
+  ///
+  /// drop(reservation);
+  /// assert_eq!(map.allocated(), allocated);
+  /// ```
+  pub fn reserve(&self, bytes: usize) -> Option<Reservation<'_>> {
+    if self.arena.read_only() || self.meta().is_sealed() {
+      return None;
+    }
+
+    let size = u32::try_from(bytes).ok()?;
+    self.ensure_init().ok()?;
+    self.invalidate_checksum();
+    self.arena.alloc_bytes(size).ok().map(Reservation::new)
+  }
+
+  /// Allocates `len` zeroed bytes in the ARENA, independent of the nodes of the map, and returns
+  /// a handle to write them.
+  ///
+  /// The bytes are never reclaimed and live as long as the ARENA, the [`offset`](AllocatedBytes::offset)
+  /// stays valid across clones and, for a memory-mapped map, across reopens, read them back with
+  /// [`get_bytes`](SkipMap::get_bytes) once the handle is dropped.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::<u64>::new().unwrap();
+  ///
+  /// let mut buf = map.alloc_bytes(5).unwrap();
+  /// buf.copy_from_slice(b"hello");
+  /// let offset = buf.offset();
+  /// drop(buf);
+  ///
+  /// // Safety: the bytes are allocated by `alloc_bytes`, and nothing writes them anymore.
+  /// assert_eq!(unsafe { map.get_bytes(offset, 5) }, b"hello");
+  /// ```
+  pub fn alloc_bytes(&self, len: usize) -> Result<AllocatedBytes<'_>, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    if self.meta().is_sealed() {
+      return Err(Error::Sealed);
+    }
+
+    let size = u32::try_from(len).map_err(|_| Error::ValueTooLarge {
+      size: len as u64,
+      max: u32::MAX as u64,
+    })?;
+    self.ensure_init()?;
+    self.invalidate_checksum();
+    Ok(AllocatedBytes::new(self.arena.alloc_bytes(size)?))
+  }
+
+  /// Returns the `len` bytes at `offset` in the ARENA, e.g. the bytes allocated by
+  /// [`alloc_bytes`](SkipMap::alloc_bytes).
+  ///
+  /// # Safety
+  ///
+  /// - The range must not be written while the returned slice is alive, e.g. by an
+  ///   [`AllocatedBytes`] or a [`Reservation`] over it, or by the writes of the map to its nodes.
+  ///   The bytes allocated by [`alloc_bytes`](SkipMap::alloc_bytes) are safe to read once their
+  ///   handle is dropped.
+  ///
+  /// # Panics
+  ///
+  /// - If the range is out of the allocated part of the ARENA.
+  pub unsafe fn get_bytes(&self, offset: u32, len: usize) -> &[u8] {
+    let offset = offset as usize;
+    match offset.checked_add(len) {
+      Some(end) if end <= self.arena.allocated() => {}
+      _ => panic!(
+        "range {offset}..{} is out of the allocated bytes of the ARENA ({})",
+        offset as u128 + len as u128,
+        self.arena.allocated(),
+      ),
+    }
+
+    // SAFETY: the range is checked to be in bounds of the allocated part of the ARENA, and the
+    // caller guarantees that it is not written concurrently.
+    self.arena.get_bytes(offset, len)
+  }
+
   /// Returns the capacity of the arena.
   #[inline]
   pub const fn capacity(&self) -> usize {
@@ -205,6 +466,34 @@ impl<T, C> SkipMap<T, C> {
     self.len() == 0
   }
 
+  /// Returns an estimate of the serialized size of the entries, in bytes: the sum of the key
+  /// and value sizes, plus the size of a trailer `T` for every entry.
+  ///
+  /// The estimate is read from running counters, so it costs the same regardless of the size of
+  /// the map. Like [`len`](SkipMap::len), the counters cover every version and every tombstone in
+  /// the map, not only the entries visible at a given version: an overwritten value is replaced
+  /// in the estimate, while an older version of a key counts as its own entry. Use
+  /// [`estimate_size_at`](SkipMap::estimate_size_at) for the accurate size at a version.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"bb", b"22").unwrap();
+  /// map.insert(0, b"bb", b"333").unwrap();
+  ///
+  /// let trailer = core::mem::size_of::<u64>() as u64;
+  /// assert_eq!(map.estimate_size(), 1 + 1 + 2 + 3 + 2 * trailer);
+  /// ```
+  #[inline]
+  pub fn estimate_size(&self) -> u64 {
+    let meta = self.meta();
+    meta.key_bytes() + meta.value_bytes() + meta.len() as u64 * mem::size_of::<T>() as u64
+  }
+
   /// Gets the number of pointers to this `SkipMap` similar to [`Arc::strong_count`](std::sync::Arc::strong_count).
   #[inline]
   pub fn refs(&self) -> usize {
@@ -212,24 +501,79 @@ impl<T, C> SkipMap<T, C> {
   }
 
   /// Returns how many bytes are discarded by the ARENA.
+  ///
+  /// When [`insert`](SkipMap::insert) or [`insert_with_value`](SkipMap::insert_with_value)
+  /// replaces the value of an existing entry, the slot of the superseded value (its trailer and
+  /// value bytes) is discarded, unless the freelist reclaims it for later allocations. So with
+  /// [`Freelist::None`](crate::options::Freelist::None), this is the number of bytes occupied by
+  /// superseded values, which is useful to estimate the size of a memtable flush.
+  ///
+  /// The counter is reset by [`clear`](SkipMap::clear).
   #[inline]
-  pub fn discarded(&self) -> u32 {
-    self.arena.discarded()
+  pub fn discarded(&self) -> u64 {
+    self.arena.discarded() as u64
   }
 
-  /// Returns the maximum version of all entries in the map.
+  /// Returns the maximum version of all entries in the map, including the removed ones.
+  ///
+  /// Every write which links a new entry, including the tombstone written by a remove, raises it
+  /// if needed, `0` is returned for an empty map.
   #[inline]
   pub fn max_version(&self) -> u64 {
     self.meta().max_version()
   }
 
-  /// Returns the minimum version of all entries in the map.
+  /// Returns the minimum version of all entries in the map, including the removed ones.
+  ///
+  /// Every write which links a new entry, including the tombstone written by a remove, lowers it
+  /// if needed, `0` is returned for an empty map.
   #[inline]
   pub fn min_version(&self) -> u64 {
     self.meta().min_version()
   }
 
+  /// Returns the minimum and the maximum versions of all entries in the map, see
+  /// [`min_version`](SkipMap::min_version) and [`max_version`](SkipMap::max_version).
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// assert_eq!(map.version_range(), (0, 0));
+  ///
+  /// map.insert(3, b"a", b"a3").unwrap();
+  /// map.insert(5, b"b", b"b5").unwrap();
+  /// assert_eq!(map.version_range(), (3, 5));
+  ///
+  /// map.remove(1, b"a").unwrap();
+  /// assert_eq!(map.version_range(), (1, 5));
+  /// ```
+  #[inline]
+  pub fn version_range(&self) -> (u64, u64) {
+    let meta = self.meta();
+    (meta.min_version(), meta.max_version())
+  }
+
   /// Returns the comparator used to compare keys.
+  ///
+  /// External code which merges or sorts the keys of the map, e.g. a k-way merge over several maps,
+  /// should use it to order the keys the same way as the map does.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Descend, Comparator};
+  ///
+  /// let map = SkipMap::<u64, _>::with_comparator(Descend).unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"b", b"2").unwrap();
+  ///
+  /// let mut keys = vec![b"a".as_slice(), b"c", b"b"];
+  /// keys.sort_by(|a, b| map.comparator().compare(a, b));
+  /// assert_eq!(keys, [b"c".as_slice(), b"b", b"a"]);
+  /// ```
   #[inline]
   pub const fn comparator(&self) -> &C {
     &self.cmp
@@ -237,13 +581,19 @@ impl<T, C> SkipMap<T, C> {
 
   /// Like [`SkipMap::new`], but with a custom [`Comparator`].
   #[inline]
-  pub fn with_comparator(cmp: C) -> Result<Self, Error> {
+  pub fn with_comparator(cmp: C) -> Result<Self, Error>
+  where
+    C: Comparator,
+  {
     Self::with_options_and_comparator(Options::new(), cmp)
   }
 
   /// Like [`SkipMap::new`], but with [`Options`] and a custom [`Comparator`].
   #[inline]
-  pub fn with_options_and_comparator(opts: Options, cmp: C) -> Result<Self, Error> {
+  pub fn with_options_and_comparator(opts: Options, cmp: C) -> Result<Self, Error>
+  where
+    C: Comparator,
+  {
     let arena_opts = ArenaOptions::new()
       .with_capacity(opts.capacity())
       .with_maximum_alignment(Node::<T>::ALIGN as usize)
@@ -254,6 +604,24 @@ impl<T, C> SkipMap<T, C> {
     Self::new_in(arena, cmp, opts)
   }
 
+  /// Like [`SkipMap::build_sorted`], but with a custom [`Comparator`], the tuples must be sorted by
+  /// the comparator.
+  pub fn build_sorted_with_comparator<K, V>(
+    opts: Options,
+    cmp: C,
+    iter: impl IntoIterator<Item = (T, K, V)>,
+  ) -> Result<Self, Error>
+  where
+    T: Trailer,
+    C: Comparator,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    let map = Self::with_options_and_comparator(opts, cmp)?;
+    map.append_sorted(iter)?;
+    Ok(map)
+  }
+
   /// Like [`SkipMap::map_mut`], but with a custom [`Comparator`].
   #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
   #[cfg_attr(docsrs, doc(cfg(all(feature = "memmap", not(target_family = "wasm")))))]
@@ -263,7 +631,10 @@ impl<T, C> SkipMap<T, C> {
     open_options: OpenOptions,
     mmap_options: MmapOptions,
     cmp: C,
-  ) -> std::io::Result<Self> {
+  ) -> std::io::Result<Self>
+  where
+    C: Comparator,
+  {
     Self::map_mut_with_options_and_comparator(path, Options::new(), open_options, mmap_options, cmp)
   }
 
@@ -277,7 +648,10 @@ impl<T, C> SkipMap<T, C> {
     open_options: OpenOptions,
     mmap_options: MmapOptions,
     cmp: C,
-  ) -> std::io::Result<Self> {
+  ) -> std::io::Result<Self>
+  where
+    C: Comparator,
+  {
     let alignment = Node::<T>::ALIGN as usize;
     let arena_opts = ArenaOptions::new()
       .with_maximum_alignment(alignment)
@@ -307,7 +681,10 @@ impl<T, C> SkipMap<T, C> {
     mmap_options: MmapOptions,
     cmp: C,
     magic_version: u16,
-  ) -> std::io::Result<Self> {
+  ) -> std::io::Result<Self>
+  where
+    C: Comparator,
+  {
     let arena = Arena::map(path, open_options, mmap_options, CURRENT_VERSION)?;
     Self::new_in(
       arena,
@@ -332,7 +709,10 @@ impl<T, C> SkipMap<T, C> {
   #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
   #[cfg_attr(docsrs, doc(cfg(all(feature = "memmap", not(target_family = "wasm")))))]
   #[inline]
-  pub fn map_anon_with_comparator(mmap_options: MmapOptions, cmp: C) -> std::io::Result<Self> {
+  pub fn map_anon_with_comparator(mmap_options: MmapOptions, cmp: C) -> std::io::Result<Self>
+  where
+    C: Comparator,
+  {
     Self::map_anon_with_options_and_comparator(Options::new(), mmap_options, cmp)
   }
 
@@ -344,7 +724,10 @@ impl<T, C> SkipMap<T, C> {
     opts: Options,
     mmap_options: MmapOptions,
     cmp: C,
-  ) -> std::io::Result<Self> {
+  ) -> std::io::Result<Self>
+  where
+    C: Comparator,
+  {
     let alignment = Node::<T>::ALIGN as usize;
     let arena_opts = ArenaOptions::new()
       .with_maximum_alignment(alignment)
@@ -356,58 +739,178 @@ impl<T, C> SkipMap<T, C> {
 
   /// Clear the skiplist to empty and re-initialize.
   ///
+  /// `&mut self` guarantees that no entry or iterator borrowing this map is alive, but the clones
+  /// of the map share the same ARENA and may be reading it. So this method returns [`Error::Shared`]
+  /// if the map has clones, see [`refs`](SkipMap::refs).
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, map::Error};
+  /// use std::sync::Arc;
+  ///
+  /// let mut map = Arc::new(SkipMap::new().unwrap());
+  /// map.insert(1, b"hello", b"world").unwrap();
+  ///
+  /// let clone = SkipMap::clone(&map);
+  /// assert_eq!(Arc::get_mut(&mut map).unwrap().clear().unwrap_err(), Error::Shared);
+  ///
+  /// drop(clone);
+  /// Arc::get_mut(&mut map).unwrap().clear().unwrap();
+  /// assert!(map.is_empty());
+  /// ```
+  ///
+  /// The map cannot be cleared through a shared reference:
+  ///
+  /// ```compile_fail
+  /// use skl::SkipMap;
+  /// use std::sync::Arc;
+  ///
+  /// let map = Arc::new(SkipMap::<u64>::new().unwrap());
+  /// let shared = Arc::clone(&map);
+  /// shared.clear().unwrap();
+  /// ```
+  pub fn clear(&mut self) -> Result<(), Error> {
+    if self.refs() != 1 {
+      return Err(Error::Shared);
+    }
+
+    // Safety: the map is not shared, and `&mut self` guarantees that nothing borrows it.
+    unsafe { self.clear_shared() }
+  }
+
+  /// Like [`clear`](SkipMap::clear), but does not check if the ARENA is shared with the clones of the map.
+  ///
   /// # Safety
-  /// - The current pointers get from the ARENA cannot be used anymore after calling this method.
-  /// - This method is not thread-safe.
+  /// - The current pointers get from the ARENA, by this map or any of its clones,
+  ///   cannot be used anymore after calling this method.
+  /// - This method is not thread-safe, no clone of the map may be used concurrently.
   ///
   /// # Example
   ///
   /// Undefine behavior:
   ///
   /// ```ignore
-  /// let map = SkipMap::new(1000).unwrap();
+  /// let mut map = SkipMap::new().unwrap();
+  /// let clone = map.clone();
   ///
   /// map.insert(1, b"hello", b"world").unwrap();
   ///
-  /// let data = map.get(b"hello").unwrap();
+  /// let data = clone.get(1, b"hello").unwrap();
   ///
-  /// map.clear().unwrap();
+  /// unsafe { map.clear_shared().unwrap(); }
   ///
-  /// let w = data[0]; // undefined behavior
+  /// let w = data.value()[0]; // undefined behavior
   /// ```
-  pub unsafe fn clear(&mut self) -> Result<(), Error> {
+  pub unsafe fn clear_shared(&mut self) -> Result<(), Error> {
     self.arena.clear()?;
 
     let meta = if self.opts.unify() {
-      Self::allocate_meta(&self.arena, self.meta().magic_version())?
+      Self::allocate_meta(
+        &self.arena,
+        self.meta().magic_version(),
+        self.meta().comparator(),
+        self.meta().reserved(),
+      )?
     } else {
+      let reserved = self.meta().reserved();
+      let reserved_offset = Self::allocate_reserved(&self.arena, reserved)?;
       unsafe {
         let magic_version = self.meta().magic_version();
+        let comparator = self.meta().comparator();
         let _ = Box::from_raw(self.meta.as_ptr());
-        NonNull::new_unchecked(Box::into_raw(Box::new(Meta::new(magic_version))))
+        NonNull::new_unchecked(Box::into_raw(Box::new(Meta::new(
+          magic_version,
+          comparator,
+          reserved_offset,
+          reserved,
+        ))))
       }
     };
 
     self.meta = meta;
+    self.init_sentinels()
+  }
 
+  /// Allocates the head and the tail nodes after the meta, or only reserves their space if the map
+  /// is initialized lazily.
+  fn init_sentinels(&mut self) -> Result<(), Error> {
     let max_height: u8 = self.opts.max_height().into();
-    let head = Self::allocate_full_node(&self.arena, max_height)?;
-    let tail = Self::allocate_full_node(&self.arena, max_height)?;
+    let (head, tail) = if self.opts.lazy_init() {
+      self.meta().mark_uninit();
+      Self::reserve_sentinels(&self.arena, max_height)
+    } else {
+      Self::allocate_sentinels(&self.arena, max_height)?
+    };
 
-    // Safety:
-    // We will always allocate enough space for the head node and the tail node.
-    unsafe {
-      // Link all head/tail levels together.
-      for i in 0..(max_height as usize) {
-        let head_link = head.tower(&self.arena, i);
-        let tail_link = tail.tower(&self.arena, i);
-        head_link.next_offset.store(tail.offset, Ordering::Relaxed);
-        tail_link.prev_offset.store(head.offset, Ordering::Relaxed);
+    self.head = head;
+    self.tail = tail;
+    Ok(())
+  }
+
+  /// Resets the map to empty, so that its ARENA can be reused, e.g. by a pool of memtables.
+  ///
+  /// Unlike [`clear`](SkipMap::clear), which builds a new meta on the heap when the map is not unified,
+  /// `reset` never allocates: the ARENA's allocation offset is rewound to the start of the data section,
+  /// the meta is rewritten in place and the head and tail nodes are re-created at the same offsets
+  /// with their towers linked to each other again. The cost is `O(max_height)` no matter how many
+  /// entries the map holds. The capacity is unchanged, and a map backed by a memory map keeps its mapping,
+  /// the data is only truncated logically.
+  ///
+  /// The versions, the length and the statistics counters of the map are reset as well.
+  ///
+  /// Like [`clear`](SkipMap::clear), this method returns [`Error::Shared`] if the map has clones.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let mut map = SkipMap::new().unwrap();
+  /// let fresh = map.allocated();
+  /// let cap = map.capacity();
+  ///
+  /// map.insert(1, b"hello", b"world").unwrap();
+  /// map.reset().unwrap();
+  ///
+  /// assert!(map.is_empty());
+  /// assert_eq!(map.max_version(), 0);
+  /// assert_eq!(map.allocated(), fresh);
+  /// assert_eq!(map.capacity(), cap);
+  /// ```
+  pub fn reset(&mut self) -> Result<(), Error> {
+    if self.refs() != 1 {
+      return Err(Error::Shared);
+    }
+
+    let magic_version = self.meta().magic_version();
+    let comparator = self.meta().comparator();
+    let reserved = self.meta().reserved();
+    // Safety: the map is not shared, and `&mut self` guarantees that nothing borrows it.
+    unsafe { self.arena.clear()? };
+
+    if self.opts.unify() {
+      // The meta is the first allocation of the ARENA, so it lands at the same offset again.
+      self.meta = Self::allocate_meta(&self.arena, magic_version, comparator, reserved)?;
+    } else {
+      // The reserved region is the first allocation of the ARENA.
+      let reserved_offset = Self::allocate_reserved(&self.arena, reserved)?;
+      // Safety: the meta is owned by this map, and `&mut self` guarantees that nothing borrows it.
+      unsafe {
+        self.meta.as_ptr().write(Meta::new(
+          magic_version,
+          comparator,
+          reserved_offset,
+          reserved,
+        ));
       }
     }
 
-    self.head = head;
-    self.tail = tail;
+    self.init_sentinels()?;
+
+    #[cfg(feature = "stats")]
+    self.height_promotions.store(0, Ordering::Relaxed);
+
     Ok(())
   }
 
@@ -419,6 +922,7 @@ impl<T, C> SkipMap<T, C> {
   #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
   #[cfg_attr(docsrs, doc(cfg(all(feature = "memmap", not(target_family = "wasm")))))]
   pub fn flush(&self) -> std::io::Result<()> {
+    self.store_checksum();
     self.arena.flush()
   }
 
@@ -430,45 +934,156 @@ impl<T, C> SkipMap<T, C> {
   #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
   #[cfg_attr(docsrs, doc(cfg(all(feature = "memmap", not(target_family = "wasm")))))]
   pub fn flush_async(&self) -> std::io::Result<()> {
+    self.store_checksum();
     self.arena.flush_async()
   }
 
+  /// Stores the checksum of the ARENA in the meta, if [`Options::with_checksum`] is enabled.
+  #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+  #[inline]
+  fn store_checksum(&self) {
+    self.release().store_checksum(&self.arena);
+  }
+
   #[cfg(all(test, feature = "std"))]
   #[inline]
   pub(crate) fn with_yield_now(mut self) -> Self {
     self.yield_now = true;
+    self.opts = self.opts.with_backoff(crate::options::Backoff::Yield);
     self
   }
 }
 
 impl<T: Trailer, C: Comparator> SkipMap<T, C> {
-  /// Upserts a new key-value pair if it does not yet exist, if the key with the given version already exists, it will update the value.
-  /// Unlike [`insert`](SkipMap::insert), this method will update the value if the key with the given version already exists.
+  /// Returns how the ARENA bytes are used by the entries of the map: the nodes, the keys and the values,
+  /// along with the discarded bytes and the number of nodes of each height.
   ///
-  /// - Returns `Ok(None)` if the key was successfully inserted.
-  /// - Returns `Ok(Some(old))` if the key with the given version already exists and the value is successfully updated.
-  pub fn insert<'a, 'b: 'a>(
-    &'a self,
-    trailer: T,
-    key: &'b [u8],
-    value: &'b [u8],
-  ) -> Result<Option<EntryRef<'a, T, C>>, Error> {
-    if self.arena.read_only() {
-      return Err(Error::read_only());
-    }
-
-    let copy = |buf: &mut VacantBuffer| {
-      let _ = buf.write(value);
-      Ok(())
+  /// Unlike [`stats`](SkipMap::stats), this method walks all the nodes of the map, so it is a diagnostic
+  /// `O(n)` call, e.g. for tuning [`Options::with_capacity`]. The walk is not atomic when the map is
+  /// written concurrently.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"hello", b"world").unwrap();
+  /// map.insert(1, b"hello", b"rust").unwrap();
+  ///
+  /// let footprint = map.memory_footprint();
+  /// assert_eq!(footprint.key_bytes(), 5);
+  /// assert_eq!(footprint.value_bytes(), 9);
+  /// assert_eq!(footprint.heights().iter().sum::<usize>(), 2);
+  /// ```
+  pub fn memory_footprint(&self) -> MemoryFootprint {
+    let max_height: u8 = self.opts.max_height().into();
+    let mut footprint = MemoryFootprint {
+      node_bytes: 0,
+      key_bytes: 0,
+      value_bytes: 0,
+      discarded_bytes: self.arena.discarded() as usize,
+      heights: [0; super::MAX_HEIGHT],
+      max_height,
     };
-    let val_len = value.len() as u32;
 
-    self
-      .update::<Infallible>(
-        trailer,
-        Key::Occupied(key),
-        val_len,
-        copy,
+    // Safety: the nodes are allocated by the ARENA.
+    unsafe {
+      let mut last_key_offset = None;
+      let mut nd = self.get_next(self.head, 0);
+      while !nd.is_null() && nd.ptr != self.tail.ptr {
+        let node = nd.as_ref();
+        let height = node.height();
+        footprint.heights[height as usize - 1] += 1;
+        footprint.node_bytes += Node::<T>::size(height) + mem::size_of::<T>();
+
+        // The versions of a key are adjacent, and share the key when it is allocated once.
+        if last_key_offset != Some(node.key_offset) {
+          footprint.key_bytes += node.key_size() as usize;
+          last_key_offset = Some(node.key_offset);
+        }
+
+        let (_, len) = node.value.load(Ordering::Acquire);
+        if len != u32::MAX {
+          footprint.value_bytes += len as usize;
+        }
+        nd = self.get_next(nd, 0);
+      }
+    }
+
+    footprint
+  }
+
+  /// Returns the number of nodes linked at each level, the element at index `i` counts the nodes
+  /// linked at level `i`, up to the [`height`](SkipMap::height) of the map.
+  ///
+  /// The length of level `0` is the number of nodes, including the removed ones, and a node of
+  /// height `h` is counted by the levels below `h`, so the lengths show how the towers are
+  /// spread over the levels, e.g. to debug a skewed skiplist.
+  ///
+  /// Every level is walked along its forward links, so this is a diagnostic call which takes
+  /// `O(n)` time across all the levels. The walk is not atomic when the map is written concurrently.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"b", b"2").unwrap();
+  ///
+  /// let lengths = map.level_lengths();
+  /// assert_eq!(lengths.len(), map.height() as usize);
+  /// assert_eq!(lengths[0], 2);
+  /// ```
+  pub fn level_lengths(&self) -> Vec<usize> {
+    let height = self.height() as usize;
+    let mut lengths = Vec::with_capacity(height);
+
+    // Safety: the nodes are allocated by the ARENA.
+    unsafe {
+      for level in 0..height {
+        let mut len = 0;
+        let mut nd = self.get_next(self.head, level);
+        while !nd.is_null() && nd.ptr != self.tail.ptr {
+          len += 1;
+          nd = self.get_next(nd, level);
+        }
+        lengths.push(len);
+      }
+    }
+
+    lengths
+  }
+
+  /// Upserts a new key-value pair if it does not yet exist, if the key with the given version already exists, it will update the value.
+  /// Unlike [`insert`](SkipMap::insert), this method will update the value if the key with the given version already exists.
+  ///
+  /// - Returns `Ok(None)` if the key was successfully inserted.
+  /// - Returns `Ok(Some(old))` if the key with the given version already exists and the value is successfully updated.
+  pub fn insert<'a, 'b: 'a>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    value: &'b [u8],
+  ) -> Result<Option<EntryRef<'a, T, C>>, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    let copy = |buf: &mut VacantBuffer| {
+      let _ = buf.write(value);
+      Ok(())
+    };
+    let val_len = value.len() as u32;
+
+    self
+      .update::<Infallible>(
+        trailer,
+        Key::Occupied(key),
+        val_len,
+        copy,
         Ordering::Relaxed,
         Ordering::Relaxed,
         &mut Inserter::default(),
@@ -486,6 +1101,403 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       .map_err(|e| e.expect_right("must be map::Error"))
   }
 
+  /// Upserts all the key-value pairs with the same trailer, e.g. to apply a WAL record whose
+  /// writes share one version, see [`insert`](SkipMap::insert).
+  ///
+  /// The pairs are inserted in order. On failure, the index of the pair which failed is returned
+  /// along with the error, the pairs before it are inserted and the ones from it are not, so the
+  /// caller can retry the remainder, e.g. with a larger map. If the remaining ARENA space cannot
+  /// even hold the trailers and the values of all the pairs, the batch fails at index `0` before
+  /// writing anything, passing this check does not guarantee the batch fits.
+  ///
+  /// The search of a pair starts from where the previous pair is inserted, so a batch sorted by
+  /// key is cheaper than inserting the pairs one by one.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert_batch(1, &[(b"a", b"a1"), (b"b", b"b1")]).unwrap();
+  /// assert_eq!(map.get(1, b"b").unwrap().value(), b"b1");
+  ///
+  /// let (idx, _) = map.insert_batch(2, &[(b"c", b"c2"), (b"d", &[0; 1024])]).unwrap_err();
+  /// assert_eq!(idx, 0);
+  /// assert!(map.get(2, b"c").is_none());
+  /// ```
+  pub fn insert_batch(&self, trailer: T, kvs: &[(&[u8], &[u8])]) -> Result<(), (usize, Error)> {
+    if self.arena.read_only() {
+      return Err((0, Error::read_only()));
+    }
+
+    // An upsert of an existing key only allocates the trailer and the value.
+    let requested = kvs.iter().fold(0u64, |acc, (_, value)| {
+      acc + mem::size_of::<T>() as u64 + value.len() as u64
+    });
+    let available = self.arena.remaining();
+    if requested > available as u64 {
+      return Err((
+        0,
+        Error::Arena(rarena_allocator::Error::InsufficientSpace {
+          requested: requested.min(u32::MAX as u64) as u32,
+          available: available as u32,
+        }),
+      ));
+    }
+
+    let mut ins = Inserter::default();
+    for (idx, (key, value)) in kvs.iter().enumerate() {
+      let copy = |buf: &mut VacantBuffer| {
+        let _ = buf.write(value);
+        Ok(())
+      };
+
+      self
+        .update::<Infallible>(
+          trailer,
+          Key::Occupied(key),
+          value.len() as u32,
+          copy,
+          Ordering::Relaxed,
+          Ordering::Relaxed,
+          &mut ins,
+          true,
+        )
+        .map_err(|e| (idx, e.expect_right("must be map::Error")))?;
+    }
+
+    Ok(())
+  }
+
+  /// Overwrites the value of the entry with exactly the given version in place, without allocating
+  /// a new value, e.g. to update a fixed-width counter.
+  ///
+  /// `f` is called with the existing value bytes if the entry exists and is not removed, and
+  /// `Ok(true)` is returned. Otherwise, `f` is not called and `Ok(false)` is returned, so the caller
+  /// can fall back to [`insert`](SkipMap::insert). The length of the value cannot change.
+  ///
+  /// Mutating the bytes in place is not safe with concurrent readers or writers, so like
+  /// [`purge`](SkipMap::purge), it takes `&mut self`, and returns [`Error::Shared`] if the ARENA is
+  /// shared with the clones of the map.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let mut map = SkipMap::new().unwrap();
+  /// map.insert(0, b"counter", &1u64.to_le_bytes()).unwrap();
+  ///
+  /// let updated = map
+  ///   .update_in_place(0, b"counter", |val| {
+  ///     let n = u64::from_le_bytes(val.try_into().unwrap());
+  ///     val.copy_from_slice(&(n + 1).to_le_bytes());
+  ///   })
+  ///   .unwrap();
+  /// assert!(updated);
+  /// assert_eq!(map.get(0, b"counter").unwrap().value(), 2u64.to_le_bytes());
+  /// ```
+  pub fn update_in_place(
+    &mut self,
+    version: u64,
+    key: &[u8],
+    f: impl FnOnce(&mut [u8]),
+  ) -> Result<bool, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    if self.meta().is_sealed() {
+      return Err(Error::Sealed);
+    }
+
+    if self.refs() != 1 {
+      return Err(Error::Shared);
+    }
+
+    if !self.bloom_may_contain(key) {
+      return Ok(false);
+    }
+
+    // Safety: the map is not shared, and `&mut self` guarantees that nothing else reads or
+    // writes the value while `f` runs.
+    unsafe {
+      let (n, eq) = self.find_near(version, key, false, true);
+      let n = match n {
+        Some(n) if eq => n,
+        _ => return Ok(false),
+      };
+
+      let node = n.as_ref();
+      let (offset, size) = node.value.load(Ordering::Acquire);
+      if size == REMOVE {
+        return Ok(false);
+      }
+
+      let value_offset = Node::<T>::align_offset(offset) as usize + mem::size_of::<T>();
+      self.invalidate_checksum();
+      f(self.arena.get_bytes_mut(value_offset, size as usize));
+      Ok(true)
+    }
+  }
+
+  /// Unlinks all the versions of the key from the map, returns `true` if any version was unlinked.
+  ///
+  /// Unlike [`get_or_remove`](SkipMap::get_or_remove) and [`compare_remove`](SkipMap::compare_remove),
+  /// which add a tombstone at a version and keep the older versions readable, the key is invisible
+  /// at all the versions afterwards. The space of the unlinked nodes is not reclaimed.
+  ///
+  /// Unlinking a node is not safe with concurrent writes, so like [`clear`](SkipMap::clear), it takes
+  /// `&mut self`, and returns [`Error::Shared`] if the ARENA is shared with the clones of the map.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let mut map = SkipMap::new().unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  /// map.insert(3, b"a", b"a3").unwrap();
+  ///
+  /// assert!(map.purge(b"a").unwrap());
+  /// assert!(map.get(1, b"a").is_none());
+  /// assert!(!map.purge(b"a").unwrap());
+  /// ```
+  pub fn purge(&mut self, key: &[u8]) -> Result<bool, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    if self.meta().is_sealed() {
+      return Err(Error::Sealed);
+    }
+
+    if self.refs() != 1 {
+      return Err(Error::Shared);
+    }
+
+    // Safety: the map is not shared, and `&mut self` guarantees that nothing else writes to it.
+    unsafe {
+      // The versions of a key are sorted from the newest to the oldest,
+      // so start from the newest version and walk the base level.
+      let (mut nd, _) = self.find_near(u64::MAX, key, false, true);
+      let mut purged = false;
+      while let Some(curr) = nd {
+        if curr.is_null() || curr.ptr == self.tail.ptr {
+          break;
+        }
+
+        let node = curr.as_ref();
+        if self.cmp.compare(node.get_key(&self.arena), key) != cmp::Ordering::Equal {
+          break;
+        }
+
+        let (_, value_size) = node.value.load(Ordering::Acquire);
+        if value_size == REMOVE {
+          self.meta().decrease_tombstones();
+        }
+        self.meta().decrease_bytes(node.key_size(), value_size);
+        nd = Some(self.get_next(curr, 0));
+        self.invalidate_checksum();
+        self.unlink(curr);
+        self.meta().decrease_len();
+        purged = true;
+      }
+
+      #[cfg(feature = "debug-validate")]
+      self.validate_write();
+
+      Ok(purged)
+    }
+  }
+
+  /// Walks every level of the skiplist and checks its invariants, returns [`Error::Corrupted`]
+  /// describing the first violation found.
+  ///
+  /// The checks are:
+  /// - every link points into the allocated memory of the ARENA, and every level ends at the tail,
+  /// - the nodes of every level are sorted by key, and the versions of a key from the newest to the oldest,
+  /// - a node is only linked at the levels lower than its height.
+  ///
+  /// The cost is linear in the number of nodes. With the `debug-validate` feature, this method is
+  /// called after every write, and panics on the first violation, to pinpoint the write which
+  /// broke the structure.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.get_or_insert(1, b"a", b"a1").unwrap();
+  /// map.get_or_insert(1, b"b", b"b1").unwrap();
+  /// assert!(map.validate().is_ok());
+  /// ```
+  pub fn validate(&self) -> Result<(), Error> {
+    let allocated = self.arena.allocated();
+    let height = self.meta().height() as usize;
+    if height == 0 || height > super::MAX_HEIGHT {
+      return Err(Error::Corrupted(
+        "the height of the skiplist is out of range",
+      ));
+    }
+
+    // Safety: every offset is checked to be within the allocated memory before it is dereferenced.
+    unsafe {
+      for level in 0..height {
+        let mut prev: Option<NodePtr<T>> = None;
+        let mut nd = self.get_next(self.head, level);
+        loop {
+          if nd.offset == self.tail.offset {
+            break;
+          }
+
+          if nd.is_null()
+            || nd.offset <= self.tail.offset
+            || nd.offset as usize + Node::<T>::size(1) > allocated
+          {
+            return Err(Error::Corrupted(
+              "a link points out of the allocated memory",
+            ));
+          }
+
+          let node = nd.as_ref();
+          if (node.height() as usize) <= level
+            || nd.offset as usize + Node::<T>::size(node.height()) > allocated
+          {
+            return Err(Error::Corrupted(
+              "a node is linked at a level higher than its height",
+            ));
+          }
+
+          if node.key_offset as usize + node.key_size() as usize > allocated {
+            return Err(Error::Corrupted("a key points out of the allocated memory"));
+          }
+
+          if let Some(prev) = prev {
+            let prev = prev.as_ref();
+            let ord = self
+              .cmp
+              .compare(prev.get_key(&self.arena), node.get_key(&self.arena))
+              .then_with(|| {
+                node
+                  .get_trailer(&self.arena)
+                  .version()
+                  .cmp(&prev.get_trailer(&self.arena).version())
+              });
+            if ord != cmp::Ordering::Less {
+              return Err(Error::Corrupted("the nodes of a level are not sorted"));
+            }
+          }
+
+          prev = Some(nd);
+          nd = self.get_next(nd, level);
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Validates the skiplist after a write, see [`validate`](SkipMap::validate).
+  #[cfg(feature = "debug-validate")]
+  #[inline]
+  pub(super) fn validate_write(&self) {
+    if let Err(e) = self.validate() {
+      panic!("{e} after a write");
+    }
+  }
+
+  /// Upserts all the `(trailer, key, value)` tuples of the iterator, see [`insert`](SkipMap::insert).
+  ///
+  /// Stops at the first error, the tuples inserted before the error are kept.
+  /// This is the fallible version of the [`Extend`] implementation.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.try_extend([(0, b"a", b"a0"), (1, b"b", b"b1")]).unwrap();
+  /// assert_eq!(map.len(), 2);
+  /// ```
+  pub fn try_extend<K, V>(&self, iter: impl IntoIterator<Item = (T, K, V)>) -> Result<(), Error>
+  where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    for (trailer, key, value) in iter {
+      self.insert(trailer, key.as_ref(), value.as_ref())?;
+    }
+    Ok(())
+  }
+
+  /// Copies the entries visible at the given version into a new map created with the options,
+  /// dropping the tombstones, the older versions and the bytes discarded by overwrites.
+  ///
+  /// The trailers of the entries are kept as is, and the new map uses a clone of the comparator.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Options};
+  ///
+  /// let opts = Options::new().with_capacity(1 << 20);
+  /// let map = SkipMap::with_options(opts).unwrap();
+  /// for version in 0..10 {
+  ///   map.get_or_insert(version, b"key", &[version as u8; 64]).unwrap();
+  /// }
+  ///
+  /// let compacted = map.compact(9, opts).unwrap();
+  /// assert_eq!(compacted.len(), 1);
+  /// assert!(compacted.allocated() < map.allocated());
+  /// ```
+  pub fn compact(&self, version: u64, opts: Options) -> Result<SkipMap<T, C>, Error>
+  where
+    C: Clone,
+  {
+    let map = SkipMap::with_options_and_comparator(opts, self.cmp.clone())?;
+    self.compact_into(version, &map)?;
+    Ok(map)
+  }
+
+  /// Like [`compact`](SkipMap::compact), but the new map is backed by the file at the given path,
+  /// see [`map_mut_with_options_and_comparator`](SkipMap::map_mut_with_options_and_comparator).
+  #[cfg(all(feature = "memmap", not(target_family = "wasm")))]
+  #[cfg_attr(docsrs, doc(cfg(all(feature = "memmap", not(target_family = "wasm")))))]
+  pub fn compact_to_file<P: AsRef<std::path::Path>>(
+    &self,
+    version: u64,
+    path: P,
+    opts: Options,
+    open_options: OpenOptions,
+    mmap_options: MmapOptions,
+  ) -> std::io::Result<SkipMap<T, C>>
+  where
+    C: Clone,
+  {
+    let map = SkipMap::map_mut_with_options_and_comparator(
+      path,
+      opts,
+      open_options,
+      mmap_options,
+      self.cmp.clone(),
+    )?;
+    self
+      .compact_into(version, &map)
+      .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    Ok(map)
+  }
+
+  fn compact_into(&self, version: u64, dst: &SkipMap<T, C>) -> Result<(), Error> {
+    for ent in self.iter(version) {
+      dst.get_or_insert(*ent.trailer(), ent.key(), ent.value())?;
+    }
+    Ok(())
+  }
+
   /// Upserts a new key if it does not yet exist, if the key with the given version already exists, it will update the value.
   /// Unlike [`get_or_insert_with_value`](SkipMap::get_or_insert_with_value), this method will update the value if the key with the given version already exists.
   ///
@@ -576,56 +1588,257 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     key: &'b [u8],
     value: &'b [u8],
   ) -> Result<Option<EntryRef<'a, T, C>>, Error> {
-    if self.arena.read_only() {
-      return Err(Error::read_only());
-    }
-
-    let copy = |buf: &mut VacantBuffer| {
-      let _ = buf.write(value);
-      Ok(())
-    };
-    let val_len = value.len() as u32;
-
     self
-      .update::<Infallible>(
-        trailer,
-        Key::Occupied(key),
-        val_len,
-        copy,
-        Ordering::Relaxed,
-        Ordering::Relaxed,
-        &mut Inserter::default(),
-        false,
-      )
-      .map(|old| {
-        old.expect_left("insert must get InsertOk").and_then(|old| {
-          if old.is_removed() {
-            None
-          } else {
-            Some(EntryRef(old))
-          }
-        })
-      })
-      .map_err(|e| e.expect_right("must be map::Error"))
+      .get_or_insert_in(trailer, key, value, &mut Inserter::default())
+      .map(|(old, _)| old)
   }
 
-  /// Inserts a new key if it does not yet exist.
+  /// Inserts a new key-value pair if it does not yet exist like [`get_or_insert`](SkipMap::get_or_insert),
+  /// and also reports if a new entry was created.
   ///
-  /// Unlike [`insert_with_value`](SkipMap::insert_with_value), this method will not update the value if the key with the given version already exists.
+  /// The returned flag is `true` if and only if this call inserted the entry. It tells a new entry apart
+  /// from an existing one which [`get_or_insert`](SkipMap::get_or_insert) reports as `None`, e.g. a removed one,
+  /// without a follow-up [`get`](SkipMap::get).
   ///
-  /// This method is useful when you want to get_or_insert a key and you know the value size but you do not have the value
-  /// at this moment.
+  /// # Example
   ///
-  /// A placeholder will be inserted first, then you will get an [`VacantBuffer`],
-  /// and you must fill the buffer with bytes later in the closure.
+  /// ```
+  /// use skl::SkipMap;
   ///
-  /// - Returns `Ok(None)` if the key was successfully get_or_inserted.
-  /// - Returns `Ok(Some(_))` if the key with the given version already exists.
+  /// let map = SkipMap::new().unwrap();
   ///
-  /// # Example
+  /// let (old, inserted) = map.get_or_insert_detailed(0, b"hello", b"world").unwrap();
+  /// assert!(old.is_none());
+  /// assert!(inserted);
   ///
-  /// ```rust
-  /// use skl::SkipMap;
+  /// let (old, inserted) = map.get_or_insert_detailed(0, b"hello", b"rust").unwrap();
+  /// assert_eq!(old.unwrap().value(), b"world");
+  /// assert!(!inserted);
+  ///
+  /// map.get_or_remove(1, b"removed").unwrap();
+  /// let (old, inserted) = map.get_or_insert_detailed(1, b"removed", b"value").unwrap();
+  /// assert!(old.is_none());
+  /// assert!(!inserted);
+  /// ```
+  pub fn get_or_insert_detailed<'a, 'b: 'a>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    value: &'b [u8],
+  ) -> Result<(Option<EntryRef<'a, T, C>>, bool), Error> {
+    self.get_or_insert_in(trailer, key, value, &mut Inserter::default())
+  }
+
+  /// Inserts a new key-value pair if it does not yet exist like [`get_or_insert`](SkipMap::get_or_insert),
+  /// and reports how the call went in an [`InsertProfile`].
+  ///
+  /// The profile only covers this call, which makes it useful to investigate the tail latency of
+  /// inserts without any global statistics.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  ///
+  /// let (old, profile) = map.get_or_insert_profiled(0, b"hello", b"world").unwrap();
+  /// assert!(old.is_none());
+  /// assert!(profile.bytes_allocated() > 0);
+  ///
+  /// let (old, profile) = map.get_or_insert_profiled(0, b"hello", b"world").unwrap();
+  /// assert_eq!(old.unwrap().value(), b"world");
+  /// assert_eq!(profile.bytes_allocated(), 0);
+  /// ```
+  pub fn get_or_insert_profiled<'a, 'b: 'a>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    value: &'b [u8],
+  ) -> Result<(Option<EntryRef<'a, T, C>>, InsertProfile), Error> {
+    let mut ins = Inserter::default();
+    self
+      .get_or_insert_in(trailer, key, value, &mut ins)
+      .map(|(old, _)| (old, ins.profile))
+  }
+
+  /// Inserts a new key-value pair if it does not yet exist like [`get_or_insert`](SkipMap::get_or_insert),
+  /// but gives up instead of retrying when publishing the new entry fails more than `max_retries` times
+  /// because of concurrent writes around the key.
+  ///
+  /// - Returns `Ok(Err(Contended))` if the insert gave up, nothing is inserted.
+  /// - Returns `Ok(Ok(None))` if the key was successfully get_or_inserted.
+  /// - Returns `Ok(Ok(Some(_)))` if the key with the given version already exists.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  ///
+  /// assert!(map.try_get_or_insert(0, b"hello", b"world", 0).unwrap().unwrap().is_none());
+  /// let old = map.try_get_or_insert(0, b"hello", b"world", 0).unwrap().unwrap();
+  /// assert_eq!(old.unwrap().value(), b"world");
+  /// ```
+  pub fn try_get_or_insert<'a, 'b: 'a>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    value: &'b [u8],
+    max_retries: usize,
+  ) -> Result<Result<Option<EntryRef<'a, T, C>>, Contended>, Error> {
+    let mut ins = Inserter {
+      max_retries: Some(max_retries),
+      ..Default::default()
+    };
+    let (old, _) = self.get_or_insert_in(trailer, key, value, &mut ins)?;
+    if ins.contended {
+      return Ok(Err(Contended));
+    }
+    Ok(Ok(old))
+  }
+
+  /// Rewrites the values of the latest version of all the entries in the map less or equal to the given version,
+  /// without inserting new versions, and returns how many values were rewritten.
+  ///
+  /// `f` is called with the key and a copy of the value of every entry visible at `version`, the removed
+  /// entries are skipped. If `f` returns `true`, the copy is written to a newly allocated value buffer, which
+  /// replaces the value of the entry atomically, so the new value must have the same length as the old one.
+  /// If `f` returns `false`, e.g. because the new value needs a different length, the entry is left untouched.
+  ///
+  /// **Note:** the readers observe either the old or the new value of an entry, never a mix of them, and an
+  /// entry loaded before it is rewritten keeps referencing the old value. If the value of an entry is replaced
+  /// or removed concurrently while `f` runs, the entry is left untouched and not counted. The old value buffers
+  /// are not reclaimed, they are counted as [`discarded`](SkipMap::discarded).
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"hello").unwrap();
+  /// map.insert(0, b"b", b"world").unwrap();
+  ///
+  /// let rewritten = map
+  ///   .rewrite_values(0, |key, value| {
+  ///     if key == b"a" {
+  ///       value.make_ascii_uppercase();
+  ///       true
+  ///     } else {
+  ///       false
+  ///     }
+  ///   })
+  ///   .unwrap();
+  ///
+  /// assert_eq!(rewritten, 1);
+  /// assert_eq!(map.get(0, b"a").unwrap().value(), b"HELLO");
+  /// assert_eq!(map.get(0, b"b").unwrap().value(), b"world");
+  /// ```
+  pub fn rewrite_values(
+    &self,
+    version: u64,
+    mut f: impl FnMut(&[u8], &mut [u8]) -> bool,
+  ) -> Result<usize, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    if self.meta().is_sealed() {
+      return Err(Error::Sealed);
+    }
+
+    let mut buf = std::vec::Vec::new();
+    let mut rewritten = 0;
+    for ent in self.iter(version) {
+      // Safety: the node is allocated by the ARENA.
+      let node = unsafe { ent.0.ptr.as_ref() };
+      let current = node.value.load(Ordering::Acquire);
+      let (trailer, value) = unsafe {
+        (
+          node.get_trailer_by_offset(&self.arena, current.0),
+          node.get_value_by_offset(&self.arena, current.0, current.1),
+        )
+      };
+      let value = match value {
+        Some(value) => value,
+        None => continue,
+      };
+
+      buf.clear();
+      buf.extend_from_slice(value);
+      if !f(ent.key(), &mut buf) {
+        continue;
+      }
+
+      self.invalidate_checksum();
+      if node.replace_value_if(&self.arena, current, trailer, &buf)? {
+        rewritten += 1;
+      }
+    }
+
+    Ok(rewritten)
+  }
+
+  fn get_or_insert_in<'a, 'b: 'a>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    value: &'b [u8],
+    ins: &mut Inserter<'a, T>,
+  ) -> Result<(Option<EntryRef<'a, T, C>>, bool), Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    let copy = |buf: &mut VacantBuffer| {
+      let _ = buf.write(value);
+      Ok(())
+    };
+    let val_len = value.len() as u32;
+
+    self
+      .update::<Infallible>(
+        trailer,
+        Key::Occupied(key),
+        val_len,
+        copy,
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+        ins,
+        false,
+      )
+      .map(|old| {
+        let old = old.expect_left("insert must get InsertOk").and_then(|old| {
+          if old.is_removed() {
+            None
+          } else {
+            Some(EntryRef(old))
+          }
+        });
+        (old, ins.inserted)
+      })
+      .map_err(|e| e.expect_right("must be map::Error"))
+  }
+
+  /// Inserts a new key if it does not yet exist.
+  ///
+  /// Unlike [`insert_with_value`](SkipMap::insert_with_value), this method will not update the value if the key with the given version already exists.
+  ///
+  /// This method is useful when you want to get_or_insert a key and you know the value size but you do not have the value
+  /// at this moment.
+  ///
+  /// A placeholder will be inserted first, then you will get an [`VacantBuffer`],
+  /// and you must fill the buffer with bytes later in the closure.
+  ///
+  /// - Returns `Ok(None)` if the key was successfully get_or_inserted.
+  /// - Returns `Ok(Some(_))` if the key with the given version already exists.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
   ///
   /// struct Person {
   ///   id: u32,
@@ -846,6 +2059,153 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       })
   }
 
+  /// Returns the entry of the key, computing and inserting the value if the key is missing,
+  /// the result is produced by `map_value` from the entry.
+  ///
+  /// Unlike [`get_or_insert`](SkipMap::get_or_insert), concurrent misses on the same key collapse
+  /// to a single computation (single-flight): only one thread runs `compute` and inserts the value,
+  /// the others block until it finishes and then reuse the inserted entry.
+  ///
+  /// # Blocking
+  ///
+  /// On a miss, this method blocks on a lock shared by all the keys of this map (and its clones)
+  /// hashing to the same shard while `compute` runs, so misses on unrelated keys may occasionally
+  /// wait for each other. Hits do not take the lock.
+  ///
+  /// `compute` must not call this method on the same map (or its clones) again, otherwise it may
+  /// deadlock. Calling it on other maps is fine.
+  ///
+  /// # Errors
+  ///
+  /// Returns [`Error::Contended`] if the entry is removed by concurrent writes right after the
+  /// computed value is written, and again after it is written over the removal.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  ///
+  /// let val = map
+  ///   .get_or_compute_single_flight(0, b"a", || b"1".to_vec(), |ent| ent.value().to_vec())
+  ///   .unwrap();
+  /// assert_eq!(val, b"1");
+  ///
+  /// // the key exists, so `compute` is not called
+  /// let val = map
+  ///   .get_or_compute_single_flight(0, b"a", || -> Vec<u8> { unreachable!() }, |ent| ent.value().to_vec())
+  ///   .unwrap();
+  /// assert_eq!(val, b"1");
+  /// ```
+  #[cfg(feature = "std")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+  pub fn get_or_compute_single_flight<'a, 'b: 'a, V, R>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    compute: impl FnOnce() -> V,
+    map_value: impl FnOnce(EntryRef<'a, T, C>) -> R,
+  ) -> Result<R, Error>
+  where
+    V: AsRef<[u8]>,
+  {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    let version = trailer.version();
+    if let Some(ent) = self.get(version, key) {
+      return Ok(map_value(ent));
+    }
+
+    let _guard = self.single_flight.get().lock(key);
+
+    // Another thread may have inserted the key while we were waiting for the lock.
+    if let Some(ent) = self.get(version, key) {
+      return Ok(map_value(ent));
+    }
+
+    let value = compute();
+    let value = value.as_ref();
+    let copy = |buf: &mut VacantBuffer| {
+      let _ = buf.write(value);
+      Ok(())
+    };
+    let val_len = value.len() as u32;
+
+    if let Some(ent) = self
+      .get_or_insert_with_value::<Infallible>(trailer, key, val_len, copy)
+      .map_err(|e| e.expect_right("must be map::Error"))?
+    {
+      return Ok(map_value(ent));
+    }
+
+    if let Some(ent) = self.get(version, key) {
+      return Ok(map_value(ent));
+    }
+
+    // The key with the given version is marked as removed, either before this call or right
+    // after the insertion, so write the computed value over the tombstone once.
+    self
+      .insert_with_value::<Infallible>(trailer, key, val_len, copy)
+      .map_err(|e| e.expect_right("must be map::Error"))?;
+    self
+      .get(version, key)
+      .map(map_value)
+      .ok_or(Error::Contended)
+  }
+
+  /// Removes the key with the given version, and returns `true` if a value of the key with the
+  /// given version was removed by this call.
+  ///
+  /// This is a convenience over [`compare_remove`](SkipMap::compare_remove): if the entry is updated by
+  /// another thread during the removal, the removal is retried, so the key is always marked as removed
+  /// when this method returns `Ok`. If the key with the given version does not exist, a removed entry
+  /// is inserted to shadow the older versions, and `false` is returned, as when the key is already removed.
+  ///
+  /// The removed value is not returned, because its memory is released back to the ARENA by the removal.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"hello", b"world").unwrap();
+  ///
+  /// assert!(map.remove(0, b"hello").unwrap());
+  /// assert!(!map.remove(0, b"hello").unwrap());
+  /// assert!(map.get(0, b"hello").is_none());
+  /// ```
+  pub fn remove<'a, 'b: 'a>(&'a self, trailer: T, key: &'b [u8]) -> Result<bool, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    loop {
+      let mut ins = Inserter::default();
+      let res = self
+        .update(
+          trailer,
+          Key::Remove(key),
+          0,
+          noop::<Infallible>,
+          Ordering::AcqRel,
+          Ordering::Acquire,
+          &mut ins,
+          true,
+        )
+        .map_err(|e| e.expect_right("must be map::Error"))?;
+
+      // The value was updated by another thread before it could be removed, try again.
+      if let Either::Right(Err(_)) = res {
+        continue;
+      }
+      return Ok(ins.removed);
+    }
+  }
+
   /// Removes the key-value pair if it exists. A CAS operation will be used to ensure the operation is atomic.
   ///
   /// Unlike [`get_or_remove`](SkipMap::get_or_remove), this method will remove the value if the key with the given version already exists.
@@ -894,6 +2254,108 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
       .map_err(|e| e.expect_right("must be map::Error"))
   }
 
+  /// Removes the key at the given version, only if its value visible at the version equals `expected`.
+  ///
+  /// - Returns `Ok(Either::Left(true))` if the value matched and the key is removed by this call.
+  /// - Returns `Ok(Either::Left(false))` if the key has no visible value at the version, nothing is written.
+  /// - Returns `Ok(Either::Right(current))` if the visible value does not match `expected`.
+  ///
+  /// If the visible value is at the given version, it is compared and replaced by a tombstone with a CAS
+  /// on the value pointer, which is retried if the value is updated concurrently. If it is an older
+  /// version, a removed entry is inserted at the given version to shadow it, a concurrent insert of a
+  /// version in between is not detected. Like [`remove`](SkipMap::remove), the removed value is not
+  /// returned, because its memory is released back to the ARENA by the removal.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, either::Either};
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"hello", b"world").unwrap();
+  ///
+  /// let current = map.compare_remove_if(0, b"hello", b"earth").unwrap().unwrap_right();
+  /// assert_eq!(current.value(), b"world");
+  ///
+  /// assert_eq!(map.compare_remove_if(0, b"hello", b"world").unwrap().unwrap_left(), true);
+  /// assert!(map.get(0, b"hello").is_none());
+  /// assert_eq!(map.compare_remove_if(0, b"hello", b"world").unwrap().unwrap_left(), false);
+  /// ```
+  pub fn compare_remove_if<'a, 'b: 'a>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    expected: &[u8],
+  ) -> Result<Either<bool, EntryRef<'a, T, C>>, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    let version = trailer.version();
+    loop {
+      let current = match self.get(version, key) {
+        Some(current) => current,
+        None => return Ok(Either::Left(false)),
+      };
+
+      let node_ptr = current.0.ptr;
+      // Safety: the node is allocated by the ARENA, and the entry borrows the map.
+      let node = unsafe { node_ptr.as_ref() };
+      let (offset, len) = node.value.load(Ordering::Acquire);
+      // Safety: the pointer is loaded from the node.
+      match unsafe { node.get_value_by_offset(&self.arena, offset, len) } {
+        // Removed concurrently, look for the visible value again.
+        None => continue,
+        Some(value) if value != expected => return Ok(Either::Right(current)),
+        Some(_) => {}
+      }
+
+      if current.version() != version {
+        // The tombstone must be linked right before the compared entry, otherwise a version
+        // inserted between them meanwhile would be removed without being compared.
+        let mut ins = Inserter {
+          expected_next: Some(node_ptr.offset),
+          ..Default::default()
+        };
+        self
+          .update(
+            trailer,
+            Key::Remove(key),
+            0,
+            noop::<Infallible>,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            &mut ins,
+            false,
+          )
+          .map_err(|e| e.expect_right("must be map::Error"))?;
+
+        // Another thread inserted the key at the same version or between the versions,
+        // compare with the visible value instead.
+        if !ins.inserted {
+          continue;
+        }
+        return Ok(Either::Left(true));
+      }
+
+      if self.meta().is_sealed() {
+        return Err(Error::Sealed);
+      }
+
+      self.invalidate_checksum();
+      if let Ok(old_size) = node.clear_value_if(
+        &self.arena,
+        (offset, len),
+        Ordering::AcqRel,
+        Ordering::Acquire,
+      ) {
+        self.meta().increase_tombstones();
+        self.meta().decrease_bytes(0, old_size);
+        return Ok(Either::Left(true));
+      }
+    }
+  }
+
   /// Gets or removes the key-value pair if it exists.
   /// Unlike [`compare_remove`](SkipMap::compare_remove), this method will not remove the value if the key with the given version already exists.
   ///
@@ -1016,6 +2478,80 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     self.get(version, key).is_some()
   }
 
+  /// Seals the map, after which the key set is fixed and all the writes fail with [`Error::Sealed`].
+  ///
+  /// If a Bloom filter is configured by [`Options::with_bloom_filter`], it is built over all the keys
+  /// now and stored in the ARENA. After that, [`get`](SkipMap::get) and [`contains_key`](SkipMap::contains_key)
+  /// consult the filter first, and return `None` without searching the skiplist for most of the absent keys.
+  /// For a memory-mapped map, the filter is persisted with the map (after a flush), and reopening
+  /// the map restores it.
+  ///
+  /// Sealing must not race with writers. Sealing a sealed map does nothing.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::{SkipMap, Options, map::Error};
+  ///
+  /// let map = SkipMap::with_options(Options::new().with_bloom_filter(10)).unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.seal().unwrap();
+  ///
+  /// assert!(map.is_sealed());
+  /// assert_eq!(map.get(0, b"a").unwrap().value(), b"1");
+  /// assert!(map.get(0, b"b").is_none());
+  /// assert_eq!(map.insert(0, b"b", b"2").unwrap_err(), Error::Sealed);
+  /// ```
+  pub fn seal(&self) -> Result<(), Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    if self.meta().is_sealed() {
+      return Ok(());
+    }
+
+    let bits_per_key = self.opts.bloom_filter();
+    if bits_per_key != 0 {
+      self.ensure_init()?;
+      // Safety: the head and all the nodes linked at level 0 are allocated by the ARENA.
+      let hashes = bloom::hashes(unsafe {
+        core::iter::successors(Some(self.get_next(self.head, 0)), |nd| {
+          Some(self.get_next(*nd, 0))
+        })
+        .take_while(|nd| nd.ptr != self.tail.ptr)
+        .map(|nd| nd.as_ref().get_key(&self.arena))
+      });
+
+      let len = bloom::encoded_len(hashes.len(), bits_per_key);
+      self.invalidate_checksum();
+      let mut buf = self.arena.alloc_bytes(len as u32)?;
+      // Safety: the buffer has `len` bytes of capacity.
+      let filter = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), len) };
+      bloom::build(&hashes, bits_per_key, filter);
+      buf.detach();
+
+      // The tail node has no value, so its value pointer records the filter.
+      // Safety: the tail node is allocated by the ARENA.
+      unsafe {
+        self
+          .tail
+          .as_ref()
+          .value
+          .swap(buf.offset() as u32, len as u32);
+      }
+    }
+
+    self.meta().seal();
+    Ok(())
+  }
+
+  /// Returns `true` if the map is sealed, see [`seal`](SkipMap::seal).
+  #[inline]
+  pub fn is_sealed(&self) -> bool {
+    self.meta().is_sealed()
+  }
+
   /// Returns the first entry in the map.
   pub fn first(&self, version: u64) -> Option<EntryRef<'_, T, C>> {
     self.iter(version).seek_lower_bound(Bound::Unbounded)
@@ -1026,28 +2562,83 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     self.iter(version).seek_upper_bound(Bound::Unbounded)
   }
 
-  /// Returns the value associated with the given key, if it exists.
-  pub fn get<'a, 'b: 'a>(&'a self, version: u64, key: &'b [u8]) -> Option<EntryRef<'a, T, C>> {
-    unsafe {
-      let (n, eq) = self.find_near(version, key, false, true); // findLessOrEqual.
-
-      let n = n?;
-      let node = n.as_ref();
-      let node_key = node.get_key(&self.arena);
-      let (trailer, value) = node.get_value_and_trailer(&self.arena);
-      if eq {
-        return value.map(|val| {
-          EntryRef(VersionedEntryRef {
-            map: self,
-            key: node_key,
-            trailer,
-            value: Some(val),
-            ptr: n,
-          })
-        });
-      }
+  /// Returns the key of the first entry in the map.
+  ///
+  /// This is the same as `first(version).map(|e| e.key())`, but only the key is read from the arena.
+  pub fn first_key(&self, version: u64) -> Option<&[u8]> {
+    let mut nd = self.first_in()?;
 
-      if !matches!(self.cmp.compare(key, node_key), cmp::Ordering::Equal) {
+    loop {
+      if nd.is_null() || nd.ptr == self.tail.ptr {
+        return None;
+      }
+
+      unsafe {
+        let node = nd.as_ref();
+        let (offset, len) = node.value.load(Ordering::Acquire);
+        if node.get_trailer_by_offset(&self.arena, offset).version() <= version {
+          if len != REMOVE {
+            return Some(node.get_key(&self.arena));
+          }
+
+          // The tombstone hides the older versions of the key.
+          nd = self.oldest_version(nd);
+        }
+
+        nd = self.get_next(nd, 0);
+      }
+    }
+  }
+
+  /// Returns the key of the last entry in the map.
+  ///
+  /// This is the same as `last(version).map(|e| e.key())`, but only the key is read from the arena.
+  pub fn last_key(&self, version: u64) -> Option<&[u8]> {
+    let mut nd = self.last_in(version)?;
+
+    loop {
+      if nd.is_null() || nd.ptr == self.head.ptr {
+        return None;
+      }
+
+      unsafe {
+        let node = nd.as_ref();
+        let (offset, len) = node.value.load(Ordering::Acquire);
+        if len != REMOVE && node.get_trailer_by_offset(&self.arena, offset).version() <= version {
+          return Some(node.get_key(&self.arena));
+        }
+
+        nd = self.get_prev(nd, 0);
+      }
+    }
+  }
+
+  /// Returns the value associated with the given key, if it exists.
+  pub fn get<'a, 'b: 'a>(&'a self, version: u64, key: &'b [u8]) -> Option<EntryRef<'a, T, C>> {
+    if !self.bloom_may_contain(key) {
+      return None;
+    }
+
+    unsafe {
+      let (n, eq) = self.find_near(version, key, false, true); // findLessOrEqual.
+
+      let n = n?;
+      let node = n.as_ref();
+      let node_key = node.get_key(&self.arena);
+      let (trailer, value) = node.get_value_and_trailer(&self.arena);
+      if eq {
+        return value.map(|val| {
+          EntryRef(VersionedEntryRef {
+            map: self,
+            key: node_key,
+            trailer,
+            value: Some(val),
+            ptr: n,
+          })
+        });
+      }
+
+      if !matches!(self.cmp.compare(key, node_key), cmp::Ordering::Equal) {
         return None;
       }
 
@@ -1067,24 +2658,663 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     }
   }
 
+  /// Returns the value associated with the given key like [`get`](SkipMap::get), unless the entry
+  /// is expired at `now`, see [`Trailer::is_expired`].
+  ///
+  /// An expired entry hides the older versions of the key like a removed one does.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{ExpirableTrailer, SkipMap};
+  ///
+  /// let map = SkipMap::<ExpirableTrailer>::new().unwrap();
+  /// map.insert(ExpirableTrailer::new(0, 100), b"a", b"a0").unwrap();
+  ///
+  /// assert_eq!(map.get_unexpired(0, 99, b"a").unwrap().value(), b"a0");
+  /// assert!(map.get_unexpired(0, 100, b"a").is_none());
+  /// ```
+  pub fn get_unexpired<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    now: u64,
+    key: &'b [u8],
+  ) -> Option<EntryRef<'a, T, C>> {
+    self
+      .get(version, key)
+      .filter(|ent| !ent.trailer().is_expired(now))
+  }
+
+  /// Returns the value associated with the given key visible at the given version, if it exists,
+  /// together with how stale it is.
+  ///
+  /// The staleness is the gap between the latest version of the key, regardless of the given version,
+  /// and the version of the returned entry, i.e. `0` means the entry is the latest one. The latest version
+  /// includes the removed versions, so an entry shadowed by a newer tombstone is stale as well.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  /// map.insert(5, b"a", b"a5").unwrap();
+  ///
+  /// let (ent, staleness) = map.get_with_staleness(3, b"a").unwrap();
+  /// assert_eq!(ent.value(), b"a1");
+  /// assert_eq!(staleness, 4);
+  ///
+  /// let (ent, staleness) = map.get_with_staleness(5, b"a").unwrap();
+  /// assert_eq!(ent.value(), b"a5");
+  /// assert_eq!(staleness, 0);
+  /// ```
+  pub fn get_with_staleness<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    key: &'b [u8],
+  ) -> Option<(EntryRef<'a, T, C>, u64)> {
+    if !self.bloom_may_contain(key) {
+      return None;
+    }
+
+    unsafe {
+      // The versions of a key are sorted from the newest to the oldest,
+      // so the first node of the key holds its latest version.
+      let (n, _) = self.find_near(u64::MAX, key, false, true);
+      let mut nd = n?;
+      let mut latest = None;
+
+      loop {
+        if nd.is_null() || nd.ptr == self.tail.ptr {
+          return None;
+        }
+
+        let node = nd.as_ref();
+        let node_key = node.get_key(&self.arena);
+        if !matches!(self.cmp.compare(key, node_key), cmp::Ordering::Equal) {
+          return None;
+        }
+
+        let (trailer, value) = node.get_value_and_trailer(&self.arena);
+        let latest = *latest.get_or_insert(trailer.version());
+        if trailer.version() <= version {
+          return value.map(|val| {
+            (
+              EntryRef(VersionedEntryRef {
+                map: self,
+                key: node_key,
+                trailer,
+                value: Some(val),
+                ptr: nd,
+              }),
+              latest - trailer.version(),
+            )
+          });
+        }
+
+        nd = self.get_next(nd, 0);
+      }
+    }
+  }
+
+  /// Returns the entry of the given key written at exactly the given version, if it exists.
+  ///
+  /// Unlike [`get`](SkipMap::get), which returns the latest version less or equal to `version`,
+  /// this ignores the MVCC visibility and only matches `version` itself. A removed entry is returned
+  /// as well, see [`VersionedEntryRef::is_removed`].
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  /// map.insert(3, b"a", b"a3").unwrap();
+  ///
+  /// assert!(map.get_exact(2, b"a").is_none());
+  /// assert_eq!(map.get_exact(1, b"a").unwrap().value().unwrap(), b"a1");
+  /// ```
+  pub fn get_exact<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    key: &'b [u8],
+  ) -> Option<VersionedEntryRef<'a, T, C>> {
+    if !self.bloom_may_contain(key) {
+      return None;
+    }
+
+    unsafe {
+      let (n, eq) = self.find_near(version, key, false, true);
+      if !eq {
+        return None;
+      }
+
+      let n = n?;
+      let node = n.as_ref();
+      let (trailer, value) = node.get_value_and_trailer(&self.arena);
+      Some(VersionedEntryRef {
+        map: self,
+        key: node.get_key(&self.arena),
+        trailer,
+        value,
+        ptr: n,
+      })
+    }
+  }
+
+  /// Returns the number of versions stored for the key, including the removed ones.
+  ///
+  /// The versions of the key are counted by walking them, so the cost is linear in the number of versions.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  /// map.insert(2, b"a", b"a2").unwrap();
+  /// map.get_or_remove(3, b"a").unwrap();
+  ///
+  /// assert_eq!(map.version_count(b"a"), 3);
+  /// assert_eq!(map.version_count(b"b"), 0);
+  /// ```
+  pub fn version_count(&self, key: &[u8]) -> usize {
+    if !self.bloom_may_contain(key) {
+      return 0;
+    }
+
+    let mut count = 0;
+    // Safety: the nodes returned by find_near and get_next are allocated by self.arena.
+    unsafe {
+      // The versions of a key are sorted from the newest to the oldest.
+      let (mut nd, _) = self.find_near(u64::MAX, key, false, true);
+      while let Some(curr) = nd {
+        if curr.is_null() || curr.ptr == self.tail.ptr {
+          break;
+        }
+
+        let nk = curr.as_ref().get_key(&self.arena);
+        if self.cmp.compare(nk, key) != cmp::Ordering::Equal {
+          break;
+        }
+
+        count += 1;
+        nd = Some(self.get_next(curr, 0));
+      }
+    }
+    count
+  }
+
+  /// Copies the value associated with the given key into the uninitialized buffer, if it exists,
+  /// and returns the initialized prefix of the buffer.
+  ///
+  /// This avoids zeroing the destination before the copy. If the buffer is shorter than the value,
+  /// only the first `buf.len()` bytes of the value are copied, like `std::io::Read::read`.
+  ///
+  /// The bytes of `buf` after the returned prefix are left untouched, and must not be assumed
+  /// to be initialized by this call.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  /// use core::mem::MaybeUninit;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.get_or_insert(0, b"hello", b"world").unwrap();
+  ///
+  /// let mut buf = [MaybeUninit::<u8>::uninit(); 16];
+  /// assert_eq!(map.read_value_uninit(0, b"hello", &mut buf).unwrap(), b"world");
+  /// ```
+  pub fn read_value_uninit<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    key: &'b [u8],
+    buf: &'a mut [mem::MaybeUninit<u8>],
+  ) -> Option<&'a [u8]> {
+    let ent = self.get(version, key)?;
+    let value = ent.value();
+    let len = value.len().min(buf.len());
+
+    // Safety: the value and the buffer do not overlap, the buffer is borrowed mutably, and the
+    // first `len` bytes of the buffer are initialized by the copy.
+    unsafe {
+      ptr::copy_nonoverlapping(value.as_ptr(), buf.as_mut_ptr().cast::<u8>(), len);
+      Some(core::slice::from_raw_parts(buf.as_ptr().cast::<u8>(), len))
+    }
+  }
+
+  /// Returns the undecoded bytes of the trailer associated with the given key, if it exists.
+  ///
+  /// The returned slice is the in-memory representation of the trailer, `mem::size_of::<T>()` bytes,
+  /// which can be decoded by tools that do not know the exact trailer type.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.get_or_insert(7, b"hello", b"world").unwrap();
+  ///
+  /// let raw = map.raw_trailer_bits(7, b"hello").unwrap();
+  /// assert_eq!(u64::from_ne_bytes(raw.try_into().unwrap()), 7);
+  /// ```
+  pub fn raw_trailer_bits<'a, 'b: 'a>(&'a self, version: u64, key: &'b [u8]) -> Option<&'a [u8]> {
+    let ent = self.get(version, key)?;
+
+    // Safety: the node was allocated by self.arena, and the trailer is stored at the aligned
+    // offset of the value pointer.
+    unsafe {
+      let (offset, _) = ent.0.ptr.as_ref().value.load(Ordering::Acquire);
+      Some(self.arena.get_bytes(
+        Node::<T>::align_offset(offset) as usize,
+        mem::size_of::<T>(),
+      ))
+    }
+  }
+
+  /// Builds an in-memory secondary index over the values visible at the given version,
+  /// `extract` maps a value to the key it is indexed by.
+  ///
+  /// The index is not updated by later writes, it must be rebuilt after mutating the map,
+  /// or only be built over a sealed map, see [`seal`](SkipMap::seal).
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.get_or_insert(0, b"alice", &30u32.to_be_bytes()).unwrap();
+  /// map.get_or_insert(0, b"bob", &25u32.to_be_bytes()).unwrap();
+  /// map.get_or_insert(0, b"carol", &30u32.to_be_bytes()).unwrap();
+  ///
+  /// let index = map.build_value_index(0, |v| u32::from_be_bytes(v.try_into().unwrap()) as u64);
+  /// let keys: Vec<_> = index.lookup(30).map(|ent| ent.key().to_vec()).collect();
+  /// assert_eq!(keys, [b"alice".to_vec(), b"carol".to_vec()]);
+  /// ```
+  pub fn build_value_index(
+    &self,
+    version: u64,
+    extract: impl Fn(&[u8]) -> u64,
+  ) -> ValueIndex<'_, T, C> {
+    ValueIndex::build(self, version, extract)
+  }
+
+  /// Returns the entry of the node at the given offset, which is obtained by [`EntryRef::node_offset`].
+  ///
+  /// The offset of a node never changes once the node is inserted, and the file bytes are
+  /// not changed by flushing or reopening, so for a file backed map, the offset is a durable
+  /// identifier of the node which can be stored by external indexes and resolved after
+  /// reopening the same file.
+  ///
+  /// Returns `None` if the offset is not within the allocated memory, does not point at the
+  /// start of a node which is linked into the map, or the node is marked as removed. The node is
+  /// validated by searching its key and version, so resolving costs as much as a [`get`](SkipMap::get).
+  pub fn resolve(&self, offset: u32) -> Option<EntryRef<'_, T, C>> {
+    let ptr = self.node_at(offset)?;
+    let ent = VersionedEntryRef::from_node(ptr, self);
+    if ent.is_removed() {
+      return None;
+    }
+
+    Some(EntryRef(ent))
+  }
+
+  /// Like [`resolve`](SkipMap::resolve), but the offset is not validated, so re-reading an entry
+  /// costs a few loads instead of checking the node is linked into the map.
+  ///
+  /// Returns `None` if the node is marked as removed.
+  ///
+  /// # Safety
+  ///
+  /// - The offset must be obtained by [`EntryRef::node_offset`] from an entry of this map, or of
+  ///   a map backed by the same file.
+  /// - The map must not be [`clear`](SkipMap::clear)ed, [`reset`](SkipMap::reset) or
+  ///   [`purge`](SkipMap::purge)d since the offset was obtained, which may reuse the memory of the node.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"hello", b"world").unwrap();
+  ///
+  /// let offset = map.get(0, b"hello").unwrap().node_offset();
+  /// let ent = unsafe { map.entry_at_offset(offset) }.unwrap();
+  /// assert_eq!(ent.key(), b"hello");
+  /// assert_eq!(ent.value(), b"world");
+  /// ```
+  #[inline]
+  pub unsafe fn entry_at_offset(&self, offset: u32) -> Option<EntryRef<'_, T, C>> {
+    let ptr = NodePtr::new(self.arena.get_pointer(offset as usize) as _, offset);
+    let ent = VersionedEntryRef::from_node(ptr, self);
+    if ent.is_removed() {
+      return None;
+    }
+
+    Some(EntryRef(ent))
+  }
+
+  /// Returns an iterator over all the entries of the map in the order their nodes are allocated
+  /// in the ARENA, which follows the order they are written in, e.g. to export the write sequence.
+  ///
+  /// Every version of a key is yielded, the removed entries are skipped. Nodes are allocated
+  /// sequentially, so the order is the insertion order, except for the nodes which reuse the
+  /// space freed by other allocations, see [`Options::with_freelist`](crate::Options::with_freelist).
+  ///
+  /// The values, the keys and the other allocations are interleaved with the nodes in the ARENA,
+  /// so the offsets of the nodes are collected by walking the base level and sorted up front,
+  /// which takes `O(n log n)` time and `O(n)` memory.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"c", b"c").unwrap();
+  /// map.insert(0, b"a", b"a").unwrap();
+  /// map.insert(0, b"b", b"b").unwrap();
+  ///
+  /// let keys: Vec<_> = map.iter_by_offset().map(|ent| ent.key().to_vec()).collect();
+  /// assert_eq!(keys, [b"c".to_vec(), b"a".to_vec(), b"b".to_vec()]);
+  /// ```
+  pub fn iter_by_offset(&self) -> impl Iterator<Item = EntryRef<'_, T, C>> + '_ {
+    let mut nodes = Vec::with_capacity(self.len());
+    // Safety: the head, the tail and the linked nodes were allocated by self.arena.
+    unsafe {
+      let mut nd = self.get_next(self.head, 0);
+      while !nd.is_null() && nd.ptr != self.tail.ptr {
+        nodes.push(nd);
+        nd = self.get_next(nd, 0);
+      }
+    }
+    nodes.sort_unstable_by_key(|nd| nd.offset);
+
+    nodes.into_iter().filter_map(move |nd| {
+      let ent = VersionedEntryRef::from_node(nd, self);
+      if ent.is_removed() {
+        None
+      } else {
+        Some(EntryRef(ent))
+      }
+    })
+  }
+
   /// Returns an `EntryRef` pointing to the highest element whose key is below the given bound.
   /// If no such element is found then `None` is returned.
   pub fn upper_bound<'a, 'b: 'a>(
     &'a self,
     version: u64,
-    upper: Bound<&'b [u8]>,
-  ) -> Option<EntryRef<'a, T, C>> {
-    self.iter(version).seek_upper_bound(upper)
+    upper: Bound<&'b [u8]>,
+  ) -> Option<EntryRef<'a, T, C>> {
+    self.iter(version).seek_upper_bound(upper)
+  }
+
+  /// Returns an `EntryRef` pointing to the lowest element whose key is above the given bound.
+  /// If no such element is found then `None` is returned.
+  pub fn lower_bound<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    lower: Bound<&'b [u8]>,
+  ) -> Option<EntryRef<'a, T, C>> {
+    self.iter(version).seek_lower_bound(lower)
+  }
+
+  /// Returns the key of the highest element whose key is below the given bound, like
+  /// [`upper_bound`](SkipMap::upper_bound) but without the entry.
+  /// If no such element is found then `None` is returned.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  /// use core::ops::Bound;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"c", b"3").unwrap();
+  ///
+  /// assert_eq!(map.upper_bound_key(0, Bound::Excluded(b"c")), Some(&b"a"[..]));
+  /// assert_eq!(map.upper_bound_key(0, Bound::Unbounded), Some(&b"c"[..]));
+  /// assert_eq!(map.upper_bound_key(0, Bound::Excluded(b"a")), None);
+  /// ```
+  pub fn upper_bound_key<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    upper: Bound<&'b [u8]>,
+  ) -> Option<&'a [u8]> {
+    iterator::AllVersionsIter::new(version, self, false).seek_upper_bound_key(upper)
+  }
+
+  /// Returns the key of the lowest element whose key is above the given bound, like
+  /// [`lower_bound`](SkipMap::lower_bound) but without the entry.
+  /// If no such element is found then `None` is returned.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  /// use core::ops::Bound;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"c", b"3").unwrap();
+  ///
+  /// assert_eq!(map.lower_bound_key(0, Bound::Excluded(b"a")), Some(&b"c"[..]));
+  /// assert_eq!(map.lower_bound_key(0, Bound::Unbounded), Some(&b"a"[..]));
+  /// assert_eq!(map.lower_bound_key(0, Bound::Excluded(b"c")), None);
+  /// ```
+  pub fn lower_bound_key<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    lower: Bound<&'b [u8]>,
+  ) -> Option<&'a [u8]> {
+    iterator::AllVersionsIter::new(version, self, false).seek_lower_bound_key(lower)
+  }
+
+  /// Returns up to `n` entries whose keys are the closest to the given key, in ascending order.
+  ///
+  /// The search starts from the floor and the ceil of `key` and expands outward, alternately
+  /// taking one entry from the right side and one from the left side. If `key` exists in the map,
+  /// the matching entry is included. Near either end of the map, the missing entries on one side
+  /// are taken from the other side, so fewer than `n` entries are returned only when the map
+  /// contains fewer than `n` entries visible at `version`.
+  pub fn closest<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    key: &'b [u8],
+    n: usize,
+  ) -> Vec<EntryRef<'a, T, C>> {
+    if n == 0 {
+      return Vec::new();
+    }
+
+    let mut right_iter = self.iter(version);
+    let mut left_iter = self.iter(version);
+    let mut right = right_iter.seek_lower_bound(Bound::Included(key));
+    let mut left = left_iter.seek_upper_bound(Bound::Excluded(key));
+
+    let mut lefts = Vec::new();
+    let mut rights = Vec::new();
+    let mut take_right = true;
+    while lefts.len() + rights.len() < n {
+      match (left.is_some(), right.is_some()) {
+        (false, false) => break,
+        (true, false) => take_right = false,
+        (false, true) => take_right = true,
+        _ => {}
+      }
+
+      if take_right {
+        rights.extend(right.take());
+        right = right_iter.next();
+      } else {
+        lefts.extend(left.take());
+        left = left_iter.next_back();
+      }
+      take_right = !take_right;
+    }
+
+    lefts.reverse();
+    lefts.extend(rights);
+    lefts
+  }
+
+  /// Splits the key space into at most `n` contiguous, non-overlapping ranges, e.g. to scan the map
+  /// in parallel by feeding each range to [`range`](SkipMap::range).
+  ///
+  /// The pivots are picked evenly from the nodes of the highest level which has at least `n - 1`
+  /// nodes visible at `version`, so the ranges are only roughly balanced. The first range is
+  /// unbounded below, the last one is unbounded above, and each pivot is the excluded end of one
+  /// range and the included start of the next one, see [`SliceBounds`](crate::SliceBounds) to pass them
+  /// to the range methods. Fewer than `n` ranges are returned if the map does not have enough distinct
+  /// keys, at least one range is always returned.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Options, SliceBounds};
+  ///
+  /// let map = SkipMap::<u64>::with_options(Options::new().with_capacity(1 << 20)).unwrap();
+  /// for i in 0..100u32 {
+  ///   map.get_or_insert(0, &i.to_be_bytes(), b"value").unwrap();
+  /// }
+  ///
+  /// let ranges = map.split_ranges(0, 4);
+  /// let total: usize = ranges
+  ///   .iter()
+  ///   .map(|range| map.range(0, range.slice_bounds()).count())
+  ///   .sum();
+  /// assert_eq!(total, 100);
+  /// ```
+  pub fn split_ranges(&self, version: u64, n: usize) -> Vec<(Bound<Vec<u8>>, Bound<Vec<u8>>)> {
+    let pivots = self.split_keys(version, n);
+    let mut ranges = Vec::with_capacity(pivots.len() + 1);
+    let mut start = Bound::Unbounded;
+    for pivot in pivots {
+      let pivot = pivot.to_vec();
+      ranges.push((start, Bound::Excluded(pivot.clone())));
+      start = Bound::Included(pivot);
+    }
+    ranges.push((start, Bound::Unbounded));
+    ranges
+  }
+
+  /// Returns the pivots splitting the key space into at most `n` ranges, see
+  /// [`split_ranges`](SkipMap::split_ranges).
+  fn split_keys(&self, version: u64, n: usize) -> Vec<&[u8]> {
+    let wanted = n.saturating_sub(1);
+    let mut keys: Vec<&[u8]> = Vec::new();
+    if wanted > 0 {
+      for level in (0..self.meta().height() as usize).rev() {
+        keys.clear();
+        // Safety: the head, the tail and the linked nodes were allocated by self.arena.
+        unsafe {
+          let mut nd = self.get_next(self.head, level);
+          while !nd.is_null() && nd.ptr != self.tail.ptr {
+            let node = nd.as_ref();
+            let key = node.get_key(&self.arena);
+            let distinct = keys.last().map_or(true, |last| {
+              self.cmp.compare(last, key) != cmp::Ordering::Equal
+            });
+            if distinct && node.get_trailer(&self.arena).version() <= version {
+              keys.push(key);
+            }
+            nd = self.get_next(nd, level);
+          }
+        }
+
+        if keys.len() >= wanted {
+          break;
+        }
+      }
+    }
+
+    let pivots = wanted.min(keys.len());
+    (1..=pivots)
+      .map(|i| keys[i * keys.len() / (pivots + 1)])
+      .collect()
+  }
+
+  /// Returns a parallel iterator over the latest version of all entries in the map less or equal to
+  /// the given version, the same entries as [`iter`](SkipMap::iter) yields.
+  ///
+  /// The key space is split like [`split_ranges`](SkipMap::split_ranges) into a few ranges per thread
+  /// of the rayon pool, and each range is scanned sequentially, so the entries of a range are
+  /// yielded in order. Order-preserving adapters such as `collect` keep the order of the map.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use rayon::prelude::*;
+  /// use skl::{SkipMap, Options};
+  ///
+  /// let map = SkipMap::<u64>::with_options(Options::new().with_capacity(1 << 20)).unwrap();
+  /// for i in 0..100u32 {
+  ///   map.insert(0, &i.to_be_bytes(), b"value").unwrap();
+  /// }
+  ///
+  /// let len: usize = map.par_iter(0).map(|ent| ent.value().len()).sum();
+  /// assert_eq!(len, 500);
+  /// ```
+  #[cfg(feature = "rayon")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+  pub fn par_iter(
+    &self,
+    version: u64,
+  ) -> impl rayon::iter::ParallelIterator<Item = EntryRef<'_, T, C>> + '_
+  where
+    T: Send + Sync,
+    C: Sync,
+  {
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    let pivots = self.split_keys(version, rayon::current_num_threads() * 4);
+    (0..=pivots.len()).into_par_iter().flat_map_iter(move |i| {
+      let start = match i {
+        0 => Bound::Unbounded,
+        i => Bound::Included(pivots[i - 1]),
+      };
+      let end = match pivots.get(i) {
+        Some(pivot) => Bound::Excluded(*pivot),
+        None => Bound::Unbounded,
+      };
+      self.range(version, (start, end))
+    })
   }
 
-  /// Returns an `EntryRef` pointing to the lowest element whose key is above the given bound.
-  /// If no such element is found then `None` is returned.
-  pub fn lower_bound<'a, 'b: 'a>(
-    &'a self,
-    version: u64,
-    lower: Bound<&'b [u8]>,
-  ) -> Option<EntryRef<'a, T, C>> {
-    self.iter(version).seek_lower_bound(lower)
+  /// Returns a read view of the map at the given version.
+  ///
+  /// The snapshot holds a clone of the map, see [`Snapshot`] for its consistency guarantees.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(3, b"a", b"a3").unwrap();
+  ///
+  /// let snapshot = map.snapshot(3);
+  /// map.insert(5, b"a", b"a5").unwrap();
+  /// map.get_or_remove(5, b"b").unwrap();
+  ///
+  /// assert_eq!(snapshot.get(b"a").unwrap().value(), b"a3");
+  /// assert_eq!(map.get(5, b"a").unwrap().value(), b"a5");
+  /// ```
+  #[inline]
+  pub fn snapshot(&self, version: u64) -> Snapshot<T, C>
+  where
+    C: Clone,
+  {
+    Snapshot::new(self.clone(), version)
   }
 
   /// Returns a new iterator, this iterator will yield the latest version of all entries in the map less or equal to the given version.
@@ -1093,6 +3323,33 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     iterator::Iter::new(version, self)
   }
 
+  /// Returns a new iterator like [`iter`](SkipMap::iter), which stops yielding entries once the
+  /// given `token` is cancelled.
+  ///
+  /// The token is checked before yielding each entry, so the holder of the token (or its clones)
+  /// can abort a long scan from another thread, e.g. when the query times out.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::{SkipMap, map::CancelToken};
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"b", b"2").unwrap();
+  ///
+  /// let token = CancelToken::new();
+  /// let mut iter = map.iter_cancellable(0, token.clone());
+  /// assert_eq!(iter.next().unwrap().key(), b"a");
+  ///
+  /// token.cancel();
+  /// assert!(iter.next().is_none());
+  /// ```
+  #[inline]
+  pub fn iter_cancellable(&self, version: u64, token: CancelToken) -> CancellableIter<'_, T, C> {
+    CancellableIter::new(version, self, token)
+  }
+
   /// Returns a new iterator, this iterator will yield all versions for all entries in the map less or equal to the given version.
   #[inline]
   pub const fn iter_all_versions(&self, version: u64) -> iterator::AllVersionsIter<T, C> {
@@ -1110,6 +3367,247 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     iterator::Iter::range(version, self, range)
   }
 
+  /// Returns a iterator over the keys starting with the given prefix, this iterator will yield the latest
+  /// version of all the entries with the prefix less or equal to the given version.
+  ///
+  /// The keys are the same as the ones of `range(version, prefix..upper)`, where `upper` is the
+  /// exclusive upper bound of the prefix in lexicographic order, without computing `upper`: the scan
+  /// starts at the prefix and stops at the first key without the prefix. An empty prefix scans all
+  /// the entries, and a prefix of `0xFF` bytes, which has no upper bound, scans to the end of the map.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// for key in [&b"app"[..], b"apple", b"application", b"apq", b"banana"] {
+  ///   map.insert(0, key, b"").unwrap();
+  /// }
+  ///
+  /// let keys: Vec<_> = map.scan_prefix(0, b"app").map(|ent| ent.key().to_vec()).collect();
+  /// assert_eq!(keys, [&b"app"[..], b"apple", b"application"]);
+  /// ```
+  pub fn scan_prefix<'a>(
+    &'a self,
+    version: u64,
+    prefix: &'a [u8],
+  ) -> impl Iterator<Item = EntryRef<'a, T, C>> + 'a {
+    // The keys with the prefix are contiguous, the ones before them are skipped for the
+    // comparators which do not sort the prefix first, e.g. Descend.
+    self
+      .range(version, (Bound::Included(prefix), Bound::Unbounded))
+      .skip_while(move |ent| !ent.key().starts_with(prefix))
+      .take_while(move |ent| ent.key().starts_with(prefix))
+  }
+
+  /// Returns a new iterator, this iterator will yield the latest version of all entries in the map less or
+  /// equal to the given version, in the reverse order of the comparator.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"b", b"2").unwrap();
+  /// map.insert(0, b"c", b"3").unwrap();
+  ///
+  /// let keys: Vec<_> = map.iter_rev(0).take(2).map(|ent| ent.key().to_vec()).collect();
+  /// assert_eq!(keys, [b"c".to_vec(), b"b".to_vec()]);
+  /// ```
+  #[inline]
+  pub const fn iter_rev(&self, version: u64) -> iterator::RevIter<T, C> {
+    iterator::RevIter::new(version, self)
+  }
+
+  /// Returns a cursor over the latest version of all entries in the map less or equal to the given version,
+  /// which can be moved in both directions and re-positioned, see [`Cursor`](super::Cursor).
+  ///
+  /// The cursor starts before the first entry.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"b", b"2").unwrap();
+  /// map.insert(0, b"c", b"3").unwrap();
+  ///
+  /// let mut cursor = map.cursor(0);
+  /// assert!(!cursor.is_valid());
+  /// assert_eq!(cursor.seek(b"b").unwrap().key(), b"b");
+  /// assert_eq!(cursor.next().unwrap().key(), b"c");
+  /// assert_eq!(cursor.prev().unwrap().key(), b"b");
+  /// assert_eq!(cursor.current().unwrap().value(), b"2");
+  /// ```
+  #[inline]
+  pub const fn cursor(&self, version: u64) -> iterator::Cursor<T, C> {
+    iterator::Cursor::new(version, self)
+  }
+
+  /// Returns an iterator over the keys removed at the given version, i.e. the keys whose latest
+  /// version less or equal to the given version is a tombstone, which [`iter`](SkipMap::iter) skips.
+  ///
+  /// The keys whose latest visible version has a value are skipped, even if an older version
+  /// is removed.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"b", b"2").unwrap();
+  /// map.remove(1, b"a").unwrap();
+  /// map.remove(1, b"b").unwrap();
+  /// map.insert(2, b"b", b"3").unwrap();
+  ///
+  /// let removed: Vec<_> = map.iter_tombstones(2).map(|ent| ent.key().to_vec()).collect();
+  /// assert_eq!(removed, [b"a"]);
+  ///
+  /// let ent = map.iter_tombstones(1).nth(1).unwrap();
+  /// assert_eq!(ent.key(), b"b");
+  /// assert!(ent.value().is_none());
+  /// assert_eq!(ent.version(), 1);
+  /// ```
+  #[inline]
+  pub const fn iter_tombstones(&self, version: u64) -> iterator::TombstonesIter<T, C> {
+    iterator::TombstonesIter::new(version, self)
+  }
+
+  /// Returns a iterator that within the range, this iterator will yield the latest version of all entries in
+  /// the range less or equal to the given version, in the reverse order of the comparator.
+  #[inline]
+  pub fn range_rev<'a, Q, R>(&'a self, version: u64, range: R) -> iterator::RevIter<'a, T, C, Q, R>
+  where
+    &'a [u8]: PartialOrd<Q>,
+    Q: ?Sized + PartialOrd<&'a [u8]>,
+    R: RangeBounds<Q> + 'a,
+  {
+    iterator::RevIter::range(version, self, range)
+  }
+
+  /// Returns the number of entries visible at the given version within the range,
+  /// the same as `range(version, range).count()`.
+  ///
+  /// The entries are counted by walking the range, so the cost is linear in the number of
+  /// versions stored within the range. A per-link span count cannot answer this faster: which
+  /// nodes are visible depends on the version being read, and the count of visible keys
+  /// changes with every newer version and tombstone of a key.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// for i in 0..10u8 {
+  ///   map.get_or_insert(0, &[i], &[i]).unwrap();
+  /// }
+  /// assert_eq!(map.range_count(0, [2u8].as_slice()..[5u8].as_slice()), 3);
+  /// ```
+  pub fn range_count<'a, Q, R>(&'a self, version: u64, range: R) -> usize
+  where
+    &'a [u8]: PartialOrd<Q>,
+    Q: ?Sized + PartialOrd<&'a [u8]>,
+    R: RangeBounds<Q> + 'a,
+  {
+    self.range(version, range).count()
+  }
+
+  /// Returns the number of entries visible at the given version.
+  ///
+  /// Unlike [`len`](SkipMap::len), which counts every inserted version and tombstone,
+  /// this only counts the latest version of the keys which are not removed at the given version.
+  /// The entries are counted by walking the map, see [`range_count`](SkipMap::range_count).
+  pub fn len_at(&self, version: u64) -> usize {
+    self.iter(version).count()
+  }
+
+  /// Returns the serialized size of the entries visible at the given version, in bytes, counted
+  /// the same way as [`estimate_size`](SkipMap::estimate_size).
+  ///
+  /// Only the latest version of the keys which are not removed at the given version is counted,
+  /// which takes a walk over the map, see [`for_each_visible`](SkipMap::for_each_visible).
+  pub fn estimate_size_at(&self, version: u64) -> u64 {
+    let mut size = 0;
+    let _ = self.for_each_visible(version, |ent| {
+      size += (ent.key().len() + ent.value().len() + mem::size_of::<T>()) as u64;
+      ControlFlow::Continue(())
+    });
+    size
+  }
+
+  /// Calls `f` with the latest version of all entries in the map less or equal to the given version,
+  /// in the order of the comparator, the same entries as [`iter`](SkipMap::iter) yields.
+  ///
+  /// The scan stops early when `f` returns [`ControlFlow::Break`], in which case `Break` is returned.
+  /// Unlike the iterators, the entries are borrowed from the map directly, so the scan does not
+  /// keep any state between the calls.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use core::ops::ControlFlow;
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"1").unwrap();
+  /// map.insert(0, b"b", b"22").unwrap();
+  /// map.insert(0, b"c", b"333").unwrap();
+  ///
+  /// let mut len = 0;
+  /// let flow = map.for_each_visible(0, |ent| {
+  ///   len += ent.value().len();
+  ///   if ent.key() == b"b" {
+  ///     ControlFlow::Break(())
+  ///   } else {
+  ///     ControlFlow::Continue(())
+  ///   }
+  /// });
+  /// assert!(flow.is_break());
+  /// assert_eq!(len, 3);
+  /// ```
+  pub fn for_each_visible<'a>(
+    &'a self,
+    version: u64,
+    mut f: impl FnMut(EntryRef<'a, T, C>) -> ControlFlow<()>,
+  ) -> ControlFlow<()> {
+    // Safety: the nodes are allocated by the ARENA, and the entries borrow the map.
+    unsafe {
+      let mut nd = self.get_next(self.head, 0);
+      while !nd.is_null() && nd.ptr != self.tail.ptr {
+        let node = nd.as_ref();
+        let (trailer, value) = node.get_value_and_trailer(&self.arena);
+        if trailer.version() > version {
+          nd = self.get_next(nd, 0);
+          continue;
+        }
+
+        // The versions of a key are ordered from the newest to the oldest, so this is the latest
+        // visible version, which hides the older ones whether it is removed or not.
+        if value.is_some() {
+          let ent = VersionedEntryRef {
+            map: self,
+            key: node.get_key(&self.arena),
+            trailer,
+            value,
+            ptr: nd,
+          };
+          f(EntryRef(ent))?;
+        }
+        nd = self.get_next(self.oldest_version(nd), 0);
+      }
+    }
+
+    ControlFlow::Continue(())
+  }
+
   /// Returns a iterator that within the range, this iterator will yield all versions for all entries in the range less or equal to the given version.
   #[inline]
   pub fn range_all_versions<'a, Q, R>(
@@ -1125,3 +3623,185 @@ impl<T: Trailer, C: Comparator> SkipMap<T, C> {
     iterator::AllVersionsIter::range(version, self, range, true)
   }
 }
+
+impl<C> SkipMap<u64, C> {
+  /// Starts a read-your-writes session at the given version, see [`Session`].
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  ///
+  /// let session = map.session(1);
+  /// assert_eq!(session.get(b"a").unwrap().value(), b"a1");
+  ///
+  /// session.insert(b"a", b"a2").unwrap();
+  /// assert_eq!(session.version(), 2);
+  /// assert_eq!(session.get(b"a").unwrap().value(), b"a2");
+  ///
+  /// session.remove(b"a").unwrap();
+  /// assert!(session.get(b"a").is_none());
+  /// ```
+  #[inline]
+  pub fn session(&self, version: u64) -> Session<'_, C> {
+    Session::new(self, version)
+  }
+}
+
+/// Methods for the maps which do not use MVCC.
+///
+/// With the zero-sized `()` trailer, every entry is stored at version `0`, and no ARENA space is
+/// spent on the trailer. These methods are the same as the versioned ones, without the version.
+impl<C: Comparator> SkipMap<(), C> {
+  /// Returns the value associated with the given key, if it exists, see [`get`](SkipMap::get).
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::<()>::new().unwrap();
+  /// map.insert1(b"hello", b"world").unwrap();
+  /// assert_eq!(map.get1(b"hello").unwrap().value(), b"world");
+  ///
+  /// map.remove1(b"hello").unwrap();
+  /// assert!(map.get1(b"hello").is_none());
+  /// ```
+  #[inline]
+  pub fn get1<'a, 'b: 'a>(&'a self, key: &'b [u8]) -> Option<EntryRef<'a, (), C>> {
+    self.get(0, key)
+  }
+
+  /// Upserts a new key-value pair, see [`insert`](SkipMap::insert).
+  ///
+  /// Returns `Ok(Some(old))` if the key already exists and the value is updated.
+  #[inline]
+  pub fn insert1<'a, 'b: 'a>(
+    &'a self,
+    key: &'b [u8],
+    value: &'b [u8],
+  ) -> Result<Option<EntryRef<'a, (), C>>, Error> {
+    self.insert((), key, value)
+  }
+
+  /// Removes the key, see [`compare_remove`](SkipMap::compare_remove).
+  ///
+  /// Returns `Ok(None)` if the key is removed, or `Ok(Some(current))` if the entry was updated
+  /// by another thread concurrently, so it is not removed.
+  #[inline]
+  pub fn remove1<'a, 'b: 'a>(
+    &'a self,
+    key: &'b [u8],
+  ) -> Result<Option<EntryRef<'a, (), C>>, Error> {
+    self.compare_remove((), key, Ordering::AcqRel, Ordering::Relaxed)
+  }
+
+  /// Returns a new iterator over all the entries in the map, see [`iter`](SkipMap::iter).
+  #[inline]
+  pub const fn iter1(&self) -> iterator::Iter<(), C> {
+    self.iter(0)
+  }
+
+  /// Returns a iterator over the entries within the range, see [`range`](SkipMap::range).
+  #[inline]
+  pub fn range1<'a, Q, R>(&'a self, range: R) -> iterator::Iter<'a, (), C, Q, R>
+  where
+    &'a [u8]: PartialOrd<Q>,
+    Q: ?Sized + PartialOrd<&'a [u8]>,
+    R: RangeBounds<Q> + 'a,
+  {
+    self.range(0, range)
+  }
+}
+
+impl<T: Trailer, C: TryComparator> SkipMap<T, Fallible<C>> {
+  /// Like [`get`](SkipMap::get), but returns the error of the [`TryComparator`] if the key cannot be
+  /// compared with a key of the map on the way to the entry.
+  pub fn try_get<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    key: &'b [u8],
+  ) -> Result<Option<EntryRef<'a, T, Fallible<C>>>, CompareError> {
+    if !self.bloom_may_contain(key) {
+      return Ok(None);
+    }
+
+    let compare = |a: &[u8], b: &[u8]| self.cmp.inner().try_compare(a, b);
+    unsafe {
+      let (n, eq) = self.try_find_near(version, key, false, true, compare)?;
+
+      let n = match n {
+        Some(n) => n,
+        None => return Ok(None),
+      };
+      let node = n.as_ref();
+      let node_key = node.get_key(&self.arena);
+      let (trailer, value) = node.get_value_and_trailer(&self.arena);
+      if !eq && (compare(key, node_key)? != cmp::Ordering::Equal || trailer.version() > version) {
+        return Ok(None);
+      }
+
+      Ok(value.map(|val| {
+        EntryRef(VersionedEntryRef {
+          map: self,
+          key: node_key,
+          trailer,
+          value: Some(val),
+          ptr: n,
+        })
+      }))
+    }
+  }
+
+  /// Like [`insert`](SkipMap::insert), but returns [`Error::Compare`] instead of inserting the entry if
+  /// the key cannot be compared with itself or with a key of the map on its search path.
+  ///
+  /// The keys are compared while searching for the position of the entry, so a key inserted by
+  /// another thread meanwhile fails the insertion as well if it cannot be compared with the new key.
+  pub fn try_insert<'a, 'b: 'a>(
+    &'a self,
+    trailer: T,
+    key: &'b [u8],
+    value: &'b [u8],
+  ) -> Result<Option<EntryRef<'a, T, Fallible<C>>>, Error> {
+    if self.arena.read_only() {
+      return Err(Error::read_only());
+    }
+
+    let compare = |a: &[u8], b: &[u8]| self.cmp.inner().try_compare(a, b);
+    // The search path of an empty map compares nothing.
+    compare(key, key)?;
+
+    let copy = |buf: &mut VacantBuffer| {
+      let _ = buf.write(value);
+      Ok(())
+    };
+    let val_len = value.len() as u32;
+
+    self
+      .try_update::<Infallible>(
+        trailer,
+        Key::Occupied(key),
+        val_len,
+        copy,
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+        &mut Inserter::default(),
+        true,
+        compare,
+      )
+      .map(|old| {
+        old.expect_left("insert must get InsertOk").and_then(|old| {
+          if old.is_removed() {
+            None
+          } else {
+            Some(EntryRef(old))
+          }
+        })
+      })
+      .map_err(|e| e.expect_right("must be map::Error"))
+  }
+}
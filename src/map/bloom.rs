@@ -0,0 +1,101 @@
+use std::vec::Vec;
+
+/// The smallest number of bits of a filter, so that a filter over a few keys still has a
+/// reasonable false positive rate.
+const MIN_BITS: usize = 64;
+
+/// Returns the number of bytes of a filter over `keys` keys with `bits_per_key` bits per key,
+/// including the trailing byte which stores the number of probes.
+pub(super) fn encoded_len(keys: usize, bits_per_key: u32) -> usize {
+  let bits = (keys * bits_per_key as usize).max(MIN_BITS);
+  (bits + 7) / 8 + 1
+}
+
+/// Builds the filter over the hashes of the keys into `buf`, whose length must be
+/// [`encoded_len`] of the number of hashes.
+pub(super) fn build(hashes: &[u32], bits_per_key: u32, buf: &mut [u8]) {
+  // Rounding down `bits_per_key * ln(2)` reduces the probing cost a little bit.
+  let probes = ((bits_per_key as f64 * 0.69) as u32).clamp(1, 30);
+
+  let (bits, tail) = buf.split_at_mut(buf.len() - 1);
+  bits.iter_mut().for_each(|b| *b = 0);
+  tail[0] = probes as u8;
+
+  let nbits = bits.len() * 8;
+  for &h in hashes {
+    // Double hashing, see [Kirsch, Mitzenmacher 2006].
+    let mut h = h;
+    let delta = h.rotate_left(15);
+    for _ in 0..probes {
+      let pos = h as usize % nbits;
+      bits[pos / 8] |= 1 << (pos % 8);
+      h = h.wrapping_add(delta);
+    }
+  }
+}
+
+/// Returns `false` if the key is surely not in the filter.
+pub(super) fn may_contain(filter: &[u8], key: &[u8]) -> bool {
+  let (probes, bits) = match filter.split_last() {
+    Some((probes, bits)) => (*probes, bits),
+    None => return true,
+  };
+
+  let nbits = bits.len() * 8;
+  if nbits == 0 || probes > 30 {
+    // Treat a malformed filter as a match, it can only cost a lookup.
+    return true;
+  }
+
+  let mut h = hash(key);
+  let delta = h.rotate_left(15);
+  for _ in 0..probes {
+    let pos = h as usize % nbits;
+    if bits[pos / 8] & (1 << (pos % 8)) == 0 {
+      return false;
+    }
+    h = h.wrapping_add(delta);
+  }
+  true
+}
+
+/// A 32-bit murmur-like hash of the key, the same one used by LevelDB's Bloom filter.
+pub(super) fn hash(key: &[u8]) -> u32 {
+  const SEED: u32 = 0xbc9f_1d34;
+  const M: u32 = 0xc6a4_a793;
+
+  let mut h = SEED ^ (key.len() as u32).wrapping_mul(M);
+
+  let mut chunks = key.chunks_exact(4);
+  for chunk in &mut chunks {
+    let w = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    h = h.wrapping_add(w).wrapping_mul(M);
+    h ^= h >> 16;
+  }
+
+  let rest = chunks.remainder();
+  if !rest.is_empty() {
+    for (i, b) in rest.iter().enumerate() {
+      h = h.wrapping_add((*b as u32) << (8 * i));
+    }
+    h = h.wrapping_mul(M);
+    h ^= h >> 24;
+  }
+
+  h
+}
+
+/// Collects the hashes of the distinct keys, the keys must be sorted so that the
+/// versions of a key are adjacent.
+pub(super) fn hashes<'a>(keys: impl Iterator<Item = &'a [u8]>) -> Vec<u32> {
+  let mut hashes = Vec::new();
+  let mut prev: Option<&[u8]> = None;
+  for key in keys {
+    if prev == Some(key) {
+      continue;
+    }
+    hashes.push(hash(key));
+    prev = Some(key);
+  }
+  hashes
+}
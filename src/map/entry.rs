@@ -1,11 +1,10 @@
-use core::cmp;
+use core::{cmp, ops::Deref};
 
-use super::{Comparator, NodePtr, SkipMap, Trailer};
+use super::{Arena, Comparator, NodePtr, Release, SkipMap, Trailer};
 
 /// A versioned entry reference of the skipmap.
 ///
 /// Compared to the [`EntryRef`], this one's value can be `None` which means the entry is removed.
-#[derive(Debug)]
 pub struct VersionedEntryRef<'a, T, C> {
   pub(super) map: &'a SkipMap<T, C>,
   pub(super) key: &'a [u8],
@@ -28,6 +27,16 @@ impl<'a, T: Clone, C> Clone for VersionedEntryRef<'a, T, C> {
 
 impl<'a, T: Copy, C> Copy for VersionedEntryRef<'a, T, C> {}
 
+impl<'a, T: core::fmt::Debug, C> core::fmt::Debug for VersionedEntryRef<'a, T, C> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("VersionedEntryRef")
+      .field("key", &self.key)
+      .field("value", &self.value)
+      .field("trailer", &self.trailer)
+      .finish()
+  }
+}
+
 impl<'a, T, C> VersionedEntryRef<'a, T, C> {
   /// Returns the reference to the key
   #[inline]
@@ -53,6 +62,29 @@ impl<'a, T, C> VersionedEntryRef<'a, T, C> {
     self.value.is_none()
   }
 
+  /// Returns the offset of the entry's node in the ARENA.
+  ///
+  /// The offset is stable for the lifetime of the map, including reopening a file backed map,
+  /// and can be turned back into an entry by [`SkipMap::resolve`].
+  #[inline]
+  pub const fn node_offset(&self) -> u32 {
+    self.ptr.offset
+  }
+
+  /// Returns the key and the value as [`IoSlice`](std::io::IoSlice)s borrowing the ARENA,
+  /// which can be passed to [`Write::write_vectored`](std::io::Write::write_vectored) without copying.
+  ///
+  /// The value slice of a removed entry is empty.
+  #[cfg(feature = "std")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+  #[inline]
+  pub fn as_io_slices(&self) -> [std::io::IoSlice<'a>; 2] {
+    [
+      std::io::IoSlice::new(self.key),
+      std::io::IoSlice::new(self.value.unwrap_or(&[])),
+    ]
+  }
+
   /// Returns the owned versioned entry,
   /// feel free to clone the entry if needed, no allocation and no deep clone will be made.
   #[inline]
@@ -132,10 +164,21 @@ impl<'a, T: Trailer, C: Comparator> Ord for VersionedEntryRef<'a, T, C> {
   }
 }
 
+/// Hashes the key and the version, consistent with the [`Eq`] implementation as long as the comparator
+/// only considers equal the keys with the same bytes, which is not the case of e.g. a
+/// [`PrefixComparator`](crate::PrefixComparator).
+///
+/// The map is not hashed, so the entries of different maps with the same key and version have the same hash.
+impl<'a, T: Trailer, C> core::hash::Hash for VersionedEntryRef<'a, T, C> {
+  fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+    self.key.hash(state);
+    self.version().hash(state);
+  }
+}
+
 /// An owned versioned entry of the skipmap.
 ///
 /// Compared to the [`Entry`], this one's value can be `None` which means the entry is removed.
-#[derive(Debug)]
 pub struct VersionedEntry<T, C> {
   pub(super) map: SkipMap<T, C>,
   pub(super) trailer: T,
@@ -152,6 +195,16 @@ impl<T: Clone, C: Clone> Clone for VersionedEntry<T, C> {
   }
 }
 
+impl<T: core::fmt::Debug, C> core::fmt::Debug for VersionedEntry<T, C> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("VersionedEntry")
+      .field("key", &self.key())
+      .field("value", &self.value())
+      .field("trailer", &self.trailer)
+      .finish()
+  }
+}
+
 impl<'a, T: Clone, C> From<&'a VersionedEntry<T, C>> for VersionedEntryRef<'a, T, C> {
   fn from(entry: &'a VersionedEntry<T, C>) -> VersionedEntryRef<'a, T, C> {
     entry.borrow()
@@ -268,6 +321,22 @@ impl<T, C> Entry<T, C> {
   }
 }
 
+impl<T: Trailer, C: Comparator> PartialEq for Entry<T, C> {
+  fn eq(&self, other: &Self) -> bool {
+    self.borrow().eq(&other.borrow())
+  }
+}
+
+impl<T: Trailer, C: Comparator> Eq for Entry<T, C> {}
+
+/// Hashes the key and the version, see the [`Hash`](core::hash::Hash) implementation of [`VersionedEntryRef`].
+impl<T: Trailer, C> core::hash::Hash for Entry<T, C> {
+  fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+    self.key().hash(state);
+    self.version().hash(state);
+  }
+}
+
 /// An entry reference to the skipmap's entry.
 ///
 /// Compared to the [`VersionedEntryRef`], this one's value cannot be `None`.
@@ -310,6 +379,38 @@ impl<'a, T, C> EntryRef<'a, T, C> {
     self.0.trailer()
   }
 
+  /// Returns the offset of the entry's node in the ARENA.
+  ///
+  /// See [`SkipMap::resolve`] for turning the offset back into an entry.
+  #[inline]
+  pub const fn node_offset(&self) -> u32 {
+    self.0.node_offset()
+  }
+
+  /// Returns the key and the value as [`IoSlice`](std::io::IoSlice)s borrowing the ARENA,
+  /// which can be passed to [`Write::write_vectored`](std::io::Write::write_vectored) without copying.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  /// use std::io::Write;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"hello", b"world").unwrap();
+  ///
+  /// let ent = map.get(0, b"hello").unwrap();
+  /// let mut buf = Vec::new();
+  /// buf.write_vectored(&ent.as_io_slices()).unwrap();
+  /// assert_eq!(buf, b"helloworld");
+  /// ```
+  #[cfg(feature = "std")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+  #[inline]
+  pub fn as_io_slices(&self) -> [std::io::IoSlice<'a>; 2] {
+    self.0.as_io_slices()
+  }
+
   /// Returns the owned entry, feel free to clone the entry if needed, no allocation and no deep clone will be made.
   #[inline]
   pub fn to_owned(&self) -> Entry<T, C>
@@ -328,6 +429,265 @@ impl<'a, T, C> EntryRef<'a, T, C> {
   {
     self.0.version()
   }
+
+  /// Returns the key as an [`ArenaBytes`], which shares the ARENA instead of borrowing the map.
+  #[inline]
+  pub fn key_bytes(&self) -> ArenaBytes {
+    ArenaBytes::new(self.0.map, self.key())
+  }
+
+  /// Returns the value as an [`ArenaBytes`], which shares the ARENA instead of borrowing the map.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"hello", b"world").unwrap();
+  ///
+  /// let value = map.get(0, b"hello").unwrap().value_bytes();
+  /// let handle = std::thread::spawn(move || value.to_vec());
+  /// drop(map);
+  /// assert_eq!(handle.join().unwrap(), b"world");
+  /// ```
+  #[inline]
+  pub fn value_bytes(&self) -> ArenaBytes {
+    ArenaBytes::new(self.0.map, self.value())
+  }
+}
+
+/// A key or a value in the ARENA of a [`SkipMap`], returned by [`EntryRef::key_bytes`] and
+/// [`EntryRef::value_bytes`].
+///
+/// The handle holds a reference to the ARENA like a clone of the map does, so it can outlive the
+/// entry and the map, and be sent to other threads. While it is alive, the ARENA is shared, so
+/// [`SkipMap::clear`] returns [`Error::Shared`](super::Error::Shared). Like the entry it comes from,
+/// it reads the bytes in place: a value replaced by [`SkipMap::insert`] may be reused by the
+/// freelist of the ARENA.
+///
+/// If the handle outlives all the clones of the map, dropping it releases the map instead, e.g.
+/// flushes a file backed map if [`Options::with_flush_on_drop`](crate::Options::with_flush_on_drop)
+/// is enabled.
+#[derive(Clone)]
+pub struct ArenaBytes {
+  arena: Arena,
+  release: Release,
+  offset: usize,
+  len: usize,
+}
+
+impl ArenaBytes {
+  #[inline]
+  fn new<T, C>(map: &SkipMap<T, C>, bytes: &[u8]) -> Self {
+    let arena = &map.arena;
+    // An empty slice is not allocated by the ARENA, offset 0 reads as an empty slice.
+    let offset = if bytes.is_empty() {
+      0
+    } else {
+      // Safety: the bytes are allocated by the ARENA.
+      unsafe { arena.offset(bytes.as_ptr()) }
+    };
+
+    Self {
+      arena: arena.clone(),
+      release: map.release(),
+      offset,
+      len: bytes.len(),
+    }
+  }
+}
+
+impl Drop for ArenaBytes {
+  fn drop(&mut self) {
+    // Safety: the meta belongs to the map of the ARENA.
+    unsafe { self.release.run(&self.arena) }
+  }
+}
+
+impl Deref for ArenaBytes {
+  type Target = [u8];
+
+  #[inline]
+  fn deref(&self) -> &[u8] {
+    // Safety: the bytes were allocated by the ARENA, which is kept alive by the handle.
+    unsafe { self.arena.get_bytes(self.offset, self.len) }
+  }
+}
+
+impl AsRef<[u8]> for ArenaBytes {
+  #[inline]
+  fn as_ref(&self) -> &[u8] {
+    self
+  }
+}
+
+impl core::fmt::Debug for ArenaBytes {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_tuple("ArenaBytes").field(&self.deref()).finish()
+  }
+}
+
+impl<'a, T: Trailer, C: Comparator> EntryRef<'a, T, C> {
+  /// Compares the keys of the entries with the comparator of the map, ignoring the versions.
+  ///
+  /// Unlike the [`Ord`] implementation, which orders the versions of the same key from the newest
+  /// to the oldest, this method considers all the versions of a key equal, e.g. sorting with
+  /// `sort_by(|a, b| a.key_cmp(b))` groups the entries by key and keeps the order of the versions.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"a0").unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  ///
+  /// let (old, new) = (map.get(0, b"a").unwrap(), map.get(1, b"a").unwrap());
+  /// assert!(old.key_cmp(&new).is_eq());
+  /// assert!(old > new);
+  /// ```
+  #[inline]
+  pub fn key_cmp(&self, other: &Self) -> cmp::Ordering {
+    self.0.map.cmp.compare(self.0.key, other.0.key)
+  }
+
+  /// Returns `true` if the entries have the same key for the comparator of the map, whatever
+  /// their versions are.
+  ///
+  /// Unlike `==`, which also compares the versions, this method is `true` for all the versions
+  /// of a key, see [`key_cmp`](EntryRef::key_cmp).
+  #[inline]
+  pub fn key_eq(&self, other: &Self) -> bool {
+    self.key_cmp(other).is_eq()
+  }
+
+  /// Returns the entry following this one, as the iterator created by [`SkipMap::iter`] with the
+  /// same `version` would return it, i.e. the latest version of the next key visible at `version`,
+  /// skipping the removed keys.
+  ///
+  /// The search starts from the entry's node, so moving to a neighbor does not search from the head.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"a0").unwrap();
+  /// map.insert(0, b"b", b"b0").unwrap();
+  /// map.insert(0, b"c", b"c0").unwrap();
+  /// map.get_or_remove(1, b"b").unwrap();
+  ///
+  /// let a = map.get(1, b"a").unwrap();
+  /// let c = a.next(1).unwrap();
+  /// assert_eq!(c.key(), b"c");
+  /// assert!(c.next(1).is_none());
+  /// assert_eq!(a.next(0).unwrap().key(), b"b");
+  /// ```
+  pub fn next(&self, version: u64) -> Option<EntryRef<'a, T, C>> {
+    let map = self.0.map;
+    // Safety: the node of the entry and its neighbors are allocated by the ARENA of the map.
+    unsafe {
+      // Skips the older versions of the entry's key.
+      let mut nd = map.oldest_version(self.0.ptr);
+      loop {
+        nd = map.get_next(nd, 0);
+        if nd.is_null() || nd.ptr == map.tail.ptr {
+          return None;
+        }
+
+        let node = nd.as_ref();
+        let (trailer, value) = node.get_value_and_trailer(&map.arena);
+        if trailer.version() > version {
+          continue;
+        }
+
+        // The first visible version of a key is its latest one.
+        let ent = VersionedEntryRef {
+          map,
+          key: node.get_key(&map.arena),
+          trailer,
+          value,
+          ptr: nd,
+        };
+        nd = map.oldest_version(nd);
+        if !ent.is_removed() {
+          return Some(EntryRef(ent));
+        }
+      }
+    }
+  }
+
+  /// Returns the entry preceding this one, as the iterator created by [`SkipMap::iter`] with the
+  /// same `version` would return it, i.e. the latest version of the previous key visible at `version`,
+  /// skipping the removed keys.
+  ///
+  /// The search starts from the entry's node, so moving to a neighbor does not search from the head.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::SkipMap;
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(0, b"a", b"a0").unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  /// map.insert(0, b"b", b"b0").unwrap();
+  ///
+  /// let b = map.get(1, b"b").unwrap();
+  /// assert_eq!(b.prev(1).unwrap().value(), b"a1");
+  /// assert_eq!(b.prev(0).unwrap().value(), b"a0");
+  /// assert!(b.prev(1).unwrap().prev(1).is_none());
+  /// ```
+  pub fn prev(&self, version: u64) -> Option<EntryRef<'a, T, C>> {
+    let map = self.0.map;
+    // Safety: the node of the entry and its neighbors are allocated by the ARENA of the map.
+    unsafe {
+      let mut nd = map.get_prev(self.0.ptr, 0);
+      // Skips the newer versions of the entry's key.
+      while !nd.is_null()
+        && nd.ptr != map.head.ptr
+        && map.cmp.compare(nd.as_ref().get_key(&map.arena), self.0.key) == cmp::Ordering::Equal
+      {
+        nd = map.get_prev(nd, 0);
+      }
+
+      loop {
+        if nd.is_null() || nd.ptr == map.head.ptr {
+          return None;
+        }
+
+        // The versions of a key are walked from the oldest to the newest, the last visible one
+        // is the latest version of the key.
+        let key = nd.as_ref().get_key(&map.arena);
+        let mut visible = None;
+        while !nd.is_null()
+          && nd.ptr != map.head.ptr
+          && map.cmp.compare(nd.as_ref().get_key(&map.arena), key) == cmp::Ordering::Equal
+        {
+          let (trailer, value) = nd.as_ref().get_value_and_trailer(&map.arena);
+          if trailer.version() <= version {
+            visible = Some(VersionedEntryRef {
+              map,
+              key,
+              trailer,
+              value,
+              ptr: nd,
+            });
+          }
+          nd = map.get_prev(nd, 0);
+        }
+
+        if let Some(ent) = visible {
+          if !ent.is_removed() {
+            return Some(EntryRef(ent));
+          }
+        }
+      }
+    }
+  }
 }
 
 impl<'a, T: Trailer, C: Comparator> PartialEq for EntryRef<'a, T, C> {
@@ -349,3 +709,25 @@ impl<'a, T: Trailer, C: Comparator> Ord for EntryRef<'a, T, C> {
     self.0.cmp(&other.0)
   }
 }
+
+/// Hashes the key and the version, see the [`Hash`](core::hash::Hash) implementation of [`VersionedEntryRef`].
+///
+/// # Example
+///
+/// ```rust
+/// use skl::SkipMap;
+/// use std::collections::HashSet;
+///
+/// let (a, b) = (SkipMap::new().unwrap(), SkipMap::new().unwrap());
+/// a.insert(0, b"hello", b"world").unwrap();
+/// b.insert(0, b"hello", b"world").unwrap();
+/// b.insert(1, b"hello", b"rust").unwrap();
+///
+/// let set: HashSet<_> = a.iter(0).chain(b.iter(0)).chain(b.iter(1)).collect();
+/// assert_eq!(set.len(), 2);
+/// ```
+impl<'a, T: Trailer, C> core::hash::Hash for EntryRef<'a, T, C> {
+  fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+    self.0.hash(state);
+  }
+}
@@ -6,27 +6,105 @@ pub enum Error {
   /// Indicates that the arena is full
   Arena(rarena_allocator::Error),
 
-  /// Indicates that the value is too large to be stored in the [`SkipMap`](super::SkipMap).
-  ValueTooLarge(u64),
+  /// Indicates that the value is too large to be stored in the [`SkipMap`](super::SkipMap),
+  /// see [`Options::with_max_value_size`](crate::Options::with_max_value_size).
+  ValueTooLarge {
+    /// The size of the value.
+    size: u64,
+    /// The maximum size of a value.
+    max: u64,
+  },
 
-  /// Indicates that the key is too large to be stored in the [`SkipMap`](super::SkipMap).
-  KeyTooLarge(u64),
+  /// Indicates that the key is too large to be stored in the [`SkipMap`](super::SkipMap),
+  /// see [`Options::with_max_key_size`](crate::Options::with_max_key_size).
+  KeyTooLarge {
+    /// The size of the key.
+    size: u64,
+    /// The maximum size of a key.
+    max: u64,
+  },
 
   /// Indicates that the entry is too large to be stored in the [`SkipMap`](super::SkipMap).
   EntryTooLarge(u64),
 
   /// Arena too small
   ArenaTooSmall,
+
+  /// Indicates that the branching probability is not in the range `(0.0, 1.0)`.
+  InvalidBranchingProbability,
+
+  /// Indicates that a Bloom filter is configured by [`Options::with_bloom_filter`](crate::Options::with_bloom_filter),
+  /// but the [`Comparator`](crate::Comparator) does not order the raw key bytes, see [`Ascend`](crate::Ascend)
+  /// and [`Descend`](crate::Descend).
+  UnsupportedBloomFilter,
+
+  /// Indicates that the [`SkipMap`](super::SkipMap) is sealed, so it does not accept writes.
+  Sealed,
+
+  /// Indicates that the [`SkipMap`](super::SkipMap) was created with a different [`Comparator`](crate::Comparator)
+  /// than the one it is reopened with.
+  IncompatibleFormat,
+
+  /// Indicates that the checksum of the [`SkipMap`](super::SkipMap) does not match the one stored
+  /// by the last flush, see [`Options::with_checksum`](crate::Options::with_checksum).
+  ChecksumMismatch,
+
+  /// Indicates that the ARENA of the [`SkipMap`](super::SkipMap) is shared with its clones,
+  /// so it cannot be cleared.
+  Shared,
+
+  /// Indicates that an invariant of the [`SkipMap`](super::SkipMap) is violated,
+  /// see [`SkipMap::validate`](super::SkipMap::validate).
+  Corrupted(&'static str),
+
+  /// Indicates that a key is not resolved within the maximum number of layers,
+  /// see [`get_layered_timed`](super::get_layered_timed).
+  TooManyLayers,
+
+  /// Indicates that the key cannot be compared with the keys in the [`SkipMap`](super::SkipMap),
+  /// see [`TryComparator`](crate::TryComparator).
+  Compare(crate::CompareError),
+
+  /// Indicates that the entries are not sorted by key, and by descending version for the same key,
+  /// see [`SkipMap::build_sorted`](super::SkipMap::build_sorted).
+  Unsorted,
+
+  /// Indicates that concurrent writes kept removing the entry written by
+  /// [`SkipMap::get_or_compute_single_flight`](super::SkipMap::get_or_compute_single_flight).
+  Contended,
 }
 
 impl core::fmt::Display for Error {
   fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     match self {
       Self::Arena(e) => write!(f, "{e}"),
-      Self::ValueTooLarge(size) => write!(f, "value size {} is too large", size),
-      Self::KeyTooLarge(size) => write!(f, "key size {} is too large", size),
+      Self::ValueTooLarge { size, max } => {
+        write!(f, "value size {size} is too large, the maximum is {max}")
+      }
+      Self::KeyTooLarge { size, max } => {
+        write!(f, "key size {size} is too large, the maximum is {max}")
+      }
       Self::EntryTooLarge(size) => write!(f, "entry size {size} is too large",),
       Self::ArenaTooSmall => write!(f, "ARENA capacity is too small"),
+      Self::InvalidBranchingProbability => {
+        write!(f, "branching probability must be in the range (0.0, 1.0)")
+      }
+      Self::UnsupportedBloomFilter => write!(
+        f,
+        "Bloom filter requires a comparator which orders the raw key bytes"
+      ),
+      Self::Sealed => write!(f, "skipmap is sealed"),
+      Self::IncompatibleFormat => write!(
+        f,
+        "skipmap was created with a different comparator than the one it is reopened with"
+      ),
+      Self::ChecksumMismatch => write!(f, "skipmap checksum mismatch"),
+      Self::Shared => write!(f, "skipmap is shared with its clones"),
+      Self::Corrupted(reason) => write!(f, "skipmap is corrupted: {reason}"),
+      Self::TooManyLayers => write!(f, "key is not resolved within the maximum number of layers"),
+      Self::Compare(e) => write!(f, "{e}"),
+      Self::Unsorted => write!(f, "entries are not sorted by key and descending version"),
+      Self::Contended => write!(f, "entry is removed by concurrent writes"),
     }
   }
 }
@@ -34,12 +112,22 @@ impl core::fmt::Display for Error {
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
+/// Without `std`, the error implements [`core::error::Error`] when the compiler is Rust 1.81 or later.
+#[cfg(all(not(feature = "std"), core_error))]
+impl core::error::Error for Error {}
+
 impl From<rarena_allocator::Error> for Error {
   fn from(e: rarena_allocator::Error) -> Self {
     Self::Arena(e)
   }
 }
 
+impl From<crate::CompareError> for Error {
+  fn from(e: crate::CompareError) -> Self {
+    Self::Compare(e)
+  }
+}
+
 impl Error {
   /// Returns a read only error.
   #[inline]
@@ -67,17 +155,60 @@ pub(super) fn bad_version() -> std::io::Error {
 #[test]
 fn test_fmt() {
   assert_eq!(
-    std::format!("{}", Error::KeyTooLarge(10)),
-    "key size 10 is too large"
+    std::format!("{}", Error::KeyTooLarge { size: 10, max: 5 }),
+    "key size 10 is too large, the maximum is 5"
   );
   assert_eq!(
-    std::format!("{}", Error::ValueTooLarge(10)),
-    "value size 10 is too large"
+    std::format!("{}", Error::ValueTooLarge { size: 10, max: 5 }),
+    "value size 10 is too large, the maximum is 5"
   );
   assert_eq!(
     std::format!("{}", Error::EntryTooLarge(10)),
     "entry size 10 is too large"
   );
+  assert_eq!(
+    std::format!("{}", Error::InvalidBranchingProbability),
+    "branching probability must be in the range (0.0, 1.0)"
+  );
+  assert_eq!(
+    std::format!("{}", Error::UnsupportedBloomFilter),
+    "Bloom filter requires a comparator which orders the raw key bytes"
+  );
+  assert_eq!(std::format!("{}", Error::Sealed), "skipmap is sealed");
+  assert_eq!(
+    std::format!("{}", Error::IncompatibleFormat),
+    "skipmap was created with a different comparator than the one it is reopened with"
+  );
+  assert_eq!(
+    std::format!("{}", Error::ChecksumMismatch),
+    "skipmap checksum mismatch"
+  );
+  assert_eq!(
+    std::format!("{}", Error::Shared),
+    "skipmap is shared with its clones"
+  );
+  assert_eq!(
+    std::format!(
+      "{}",
+      Error::Corrupted("the nodes of a level are not sorted")
+    ),
+    "skipmap is corrupted: the nodes of a level are not sorted"
+  );
+  assert_eq!(
+    std::format!("{}", Error::TooManyLayers),
+    "key is not resolved within the maximum number of layers"
+  );
+  assert_eq!(
+    std::format!(
+      "{}",
+      Error::Compare(crate::CompareError::new("the key is too short"))
+    ),
+    "failed to compare keys: the key is too short"
+  );
+  assert_eq!(
+    std::format!("{}", Error::Unsorted),
+    "entries are not sorted by key and descending version"
+  );
   assert_eq!(
     std::format!(
       "{}",
@@ -7,3 +7,18 @@ pub use all_versions::*;
 
 mod iter;
 pub use iter::*;
+
+mod rev;
+pub use rev::*;
+
+mod cancellable;
+pub use cancellable::*;
+
+mod merge;
+pub use merge::*;
+
+mod cursor;
+pub use cursor::*;
+
+mod tombstones;
+pub use tombstones::*;
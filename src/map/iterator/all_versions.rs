@@ -8,10 +8,35 @@ pub struct AllVersionsIter<'a, T, C, Q: ?Sized = &'static [u8], R = core::ops::R
   pub(super) version: u64,
   pub(super) range: R,
   pub(super) all_versions: bool,
+  /// The entries expired at this timestamp are skipped, see [`skip_expired`](Self::skip_expired).
+  pub(super) now: Option<u64>,
   pub(super) last: Option<VersionedEntryRef<'a, T, C>>,
+  pub(super) peeked: Option<Peeked<'a, T, C>>,
   pub(super) _phantom: core::marker::PhantomData<Q>,
 }
 
+/// The result of a peek, which is returned by the next call to `next` (or `next_back`)
+/// without computing it again.
+pub(super) struct Peeked<'a, T, C> {
+  /// `true` if the entry is peeked by `peek_back`.
+  back: bool,
+  /// The position the iterator moves to when the peeked entry is consumed.
+  nd: NodePtr<T>,
+  ent: Option<VersionedEntryRef<'a, T, C>>,
+}
+
+impl<'a, T: Clone, C> Clone for Peeked<'a, T, C> {
+  fn clone(&self) -> Self {
+    Self {
+      back: self.back,
+      nd: self.nd,
+      ent: self.ent.clone(),
+    }
+  }
+}
+
+impl<'a, T: Copy, C> Copy for Peeked<'a, T, C> {}
+
 impl<'a, R: Clone, Q: Clone, T: Clone, C> Clone for AllVersionsIter<'a, T, C, Q, R> {
   fn clone(&self) -> Self {
     Self {
@@ -20,7 +45,9 @@ impl<'a, R: Clone, Q: Clone, T: Clone, C> Clone for AllVersionsIter<'a, T, C, Q,
       version: self.version,
       range: self.range.clone(),
       last: self.last.clone(),
+      peeked: self.peeked.clone(),
       all_versions: self.all_versions,
+      now: self.now,
       _phantom: core::marker::PhantomData,
     }
   }
@@ -40,7 +67,9 @@ where
       version,
       range: RangeFull,
       last: None,
+      peeked: None,
       all_versions,
+      now: None,
       _phantom: core::marker::PhantomData,
     }
   }
@@ -59,7 +88,9 @@ where
       version,
       range: r,
       last: None,
+      peeked: None,
       all_versions,
+      now: None,
       _phantom: core::marker::PhantomData,
     }
   }
@@ -72,11 +103,38 @@ impl<'a, Q: ?Sized, R, T, C> AllVersionsIter<'a, T, C, Q, R> {
     &self.range
   }
 
+  /// Returns the comparator of the map, which defines the order of the yielded keys.
+  #[inline]
+  pub const fn comparator(&self) -> &'a C {
+    &self.map.cmp
+  }
+
   /// Returns the entry at the current position of the iterator.
   #[inline]
   pub const fn entry(&self) -> Option<&VersionedEntryRef<'a, T, C>> {
     self.last.as_ref()
   }
+
+  /// Skips the entries which are expired at `now`, see [`Trailer::is_expired`].
+  ///
+  /// An expired entry hides the older versions of its key like a removed one does, unless the iterator
+  /// yields all the versions, in which case only the expired versions are skipped.
+  #[inline]
+  pub fn skip_expired(mut self, now: u64) -> Self {
+    self.now = Some(now);
+    self
+  }
+}
+
+impl<'a, Q: ?Sized, R, T: Trailer, C> AllVersionsIter<'a, T, C, Q, R> {
+  /// Returns `true` if the trailer is expired at the timestamp of the iterator.
+  #[inline]
+  fn is_expired(&self, trailer: &T) -> bool {
+    match self.now {
+      Some(now) => trailer.is_expired(now),
+      None => false,
+    }
+  }
 }
 
 impl<'a, Q, R, T, C> AllVersionsIter<'a, T, C, Q, R>
@@ -90,6 +148,7 @@ where
   /// Moves the iterator to the highest element whose key is below the given bound.
   /// If no such element is found then `None` is returned.
   pub fn seek_upper_bound(&mut self, upper: Bound<&[u8]>) -> Option<VersionedEntryRef<'a, T, C>> {
+    self.peeked = None;
     match upper {
       Bound::Included(key) => self.seek_le(key).map(|n| {
         let ent = VersionedEntryRef::from_node(n, self.map);
@@ -108,6 +167,7 @@ where
   /// Moves the iterator to the lowest element whose key is above the given bound.
   /// If no such element is found then `None` is returned.
   pub fn seek_lower_bound(&mut self, lower: Bound<&[u8]>) -> Option<VersionedEntryRef<'a, T, C>> {
+    self.peeked = None;
     match lower {
       Bound::Included(key) => self.seek_ge(key).map(|n| {
         let ent = VersionedEntryRef::from_node(n, self.map);
@@ -123,11 +183,124 @@ where
     }
   }
 
+  /// Like [`seek_upper_bound`](Self::seek_upper_bound), but returns only the key of the found node.
+  pub(crate) fn seek_upper_bound_key(&mut self, upper: Bound<&[u8]>) -> Option<&'a [u8]> {
+    self.peeked = None;
+    let nd = match upper {
+      Bound::Included(key) => self.seek_le(key)?,
+      Bound::Excluded(key) => self.seek_lt(key)?,
+      Bound::Unbounded => return self.last().map(|ent| ent.key),
+    };
+    // Safety: the node is allocated by the map's arena, so the key is valid
+    Some(unsafe { nd.as_ref().get_key(&self.map.arena) })
+  }
+
+  /// Like [`seek_lower_bound`](Self::seek_lower_bound), but returns only the key of the found node.
+  pub(crate) fn seek_lower_bound_key(&mut self, lower: Bound<&[u8]>) -> Option<&'a [u8]> {
+    self.peeked = None;
+    let nd = match lower {
+      Bound::Included(key) => self.seek_ge(key)?,
+      Bound::Excluded(key) => self.seek_gt(key)?,
+      Bound::Unbounded => return self.first().map(|ent| ent.key),
+    };
+    // Safety: the node is allocated by the map's arena, so the key is valid
+    Some(unsafe { nd.as_ref().get_key(&self.map.arena) })
+  }
+
+  /// Moves the iterator to the lowest element whose key is greater than or equal to the given key,
+  /// starting the search from an entry previously returned by an iterator of the same map.
+  ///
+  /// This is a finger search: when `key` is greater than the key of `from`, the search descends from
+  /// `from`'s node instead of from the head of the map, which makes a sequence of monotonically
+  /// increasing lookups much cheaper than repeated [`seek_lower_bound`](Self::seek_lower_bound) calls.
+  /// If `key` is less than or equal to the key of `from`, or `from` belongs to another map,
+  /// this falls back to a full search.
+  ///
+  /// The result is always the same as `seek_lower_bound(Bound::Included(key))`.
+  pub fn seek_from(
+    &mut self,
+    from: &VersionedEntryRef<'a, T, C>,
+    key: &[u8],
+  ) -> Option<VersionedEntryRef<'a, T, C>> {
+    self.peeked = None;
+    if from.map.head.ptr != self.map.head.ptr
+      || self.map.cmp.compare(from.key, key) != cmp::Ordering::Less
+    {
+      return self.seek_lower_bound(Bound::Included(key));
+    }
+
+    // Safety: from is a node of this map and its key is less than key.
+    self.nd = unsafe { self.map.ge_from(self.version, from.ptr, key) }?;
+    self.seek_ge_in().map(|n| {
+      let ent = VersionedEntryRef::from_node(n, self.map);
+      self.last = Some(ent);
+      ent
+    })
+  }
+
+  /// Returns the entry which the next call to [`next`](Iterator::next) returns, without advancing
+  /// the iterator.
+  ///
+  /// The entry is cached until it is consumed by `next`, or discarded by a call to `next_back`,
+  /// [`peek_back`](Self::peek_back) or one of the `seek_*` methods.
+  pub fn peek(&mut self) -> Option<VersionedEntryRef<'a, T, C>> {
+    self.peek_in(false)
+  }
+
+  /// Returns the entry which the next call to [`next_back`](DoubleEndedIterator::next_back) returns,
+  /// without moving the iterator, see [`peek`](Self::peek).
+  pub fn peek_back(&mut self) -> Option<VersionedEntryRef<'a, T, C>> {
+    self.peek_in(true)
+  }
+
+  fn peek_in(&mut self, back: bool) -> Option<VersionedEntryRef<'a, T, C>> {
+    if let Some(peeked) = self.peeked {
+      if peeked.back == back {
+        return peeked.ent;
+      }
+    }
+
+    let (nd, last) = (self.nd, self.last);
+    let ent = self.step(back);
+    self.peeked = Some(Peeked {
+      back,
+      nd: self.nd,
+      ent,
+    });
+    self.nd = nd;
+    self.last = last;
+    ent
+  }
+
+  /// Moves the iterator forward (or backward if `back` is `true`), consuming the peeked entry if any.
+  fn step(&mut self, back: bool) -> Option<VersionedEntryRef<'a, T, C>> {
+    if let Some(peeked) = self.peeked.take() {
+      if peeked.back == back {
+        self.nd = peeked.nd;
+        if peeked.ent.is_some() {
+          self.last = peeked.ent;
+        }
+        return peeked.ent;
+      }
+    }
+
+    let ent = if back { self.prev() } else { self.next_in() };
+    ent.map(|v| {
+      // Safety: the EntryRef holds a reference to the map, so it is always valid.
+      unsafe { core::mem::transmute(v) }
+    })
+  }
+
   /// Advances to the next position. Returns the key and value if the
   /// iterator is pointing at a valid entry, and `None` otherwise.
   fn next_in(&mut self) -> Option<VersionedEntryRef<T, C>> {
     loop {
       unsafe {
+        // The tail has no successor, stay at the end once it is reached.
+        if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
+          return None;
+        }
+
         self.nd = self.map.get_next(self.nd, 0);
 
         if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
@@ -140,7 +313,17 @@ where
           continue;
         }
 
+        if self.is_expired(&trailer) {
+          if !self.all_versions {
+            // The expired entry hides the older versions of the key.
+            self.nd = self.map.oldest_version(self.nd);
+          }
+          continue;
+        }
+
         if !self.all_versions && value.is_none() {
+          // The tombstone hides the older versions of the key.
+          self.nd = self.map.oldest_version(self.nd);
           continue;
         }
 
@@ -169,38 +352,106 @@ where
     }
   }
 
-  /// Advances to the prev position. Returns the key and value if the
-  /// iterator is pointing at a valid entry, and `None` otherwise.
-  fn prev(&mut self) -> Option<VersionedEntryRef<T, C>> {
+  /// Counts the entries [`next`](Iterator::next) would yield from the current position, walking
+  /// level 0 the same way without reading the values or building the entries.
+  fn count_remaining(mut self) -> usize {
+    let mut count = 0;
+    let mut last = self.last.map(|ent| ent.key);
+    if let Some(peeked) = self.peeked.take() {
+      if !peeked.back {
+        match peeked.ent {
+          Some(ent) => {
+            self.nd = peeked.nd;
+            last = Some(ent.key);
+            count += 1;
+          }
+          None => return 0,
+        }
+      }
+    }
+
     loop {
       unsafe {
-        self.nd = self.map.get_prev(self.nd, 0);
+        if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
+          return count;
+        }
 
-        if self.nd.is_null() || self.nd.ptr == self.map.head.ptr {
-          return None;
+        self.nd = self.map.get_next(self.nd, 0);
+
+        if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
+          return count;
         }
 
         let node = self.nd.as_ref();
-        let (trailer, value) = node.get_value_and_trailer(&self.map.arena);
+        let (offset, len) = node.value.load(Ordering::Acquire);
+        let trailer = node.get_trailer_by_offset(&self.map.arena, offset);
         if trailer.version() > self.version {
           continue;
         }
 
-        if !self.all_versions && value.is_none() {
+        if self.is_expired(&trailer) {
+          if !self.all_versions {
+            self.nd = self.map.oldest_version(self.nd);
+          }
+          continue;
+        }
+
+        if !self.all_versions && len == u32::MAX {
+          self.nd = self.map.oldest_version(self.nd);
           continue;
         }
 
         let nk = node.get_key(&self.map.arena);
 
         if !self.all_versions {
-          if let Some(last) = self.last {
-            if self.map.cmp.compare(last.key, nk) == cmp::Ordering::Equal {
+          if let Some(last) = last {
+            if self.map.cmp.compare(last, nk) == cmp::Ordering::Equal {
               continue;
             }
           }
         }
 
         if self.map.cmp.contains(&self.range, nk) {
+          last = Some(nk);
+          count += 1;
+        }
+      }
+    }
+  }
+
+  /// Advances to the prev position. Returns the key and value if the
+  /// iterator is pointing at a valid entry, and `None` otherwise.
+  fn prev(&mut self) -> Option<VersionedEntryRef<T, C>> {
+    // A fresh iterator is positioned before the first entry, moving backward starts from the end.
+    if self.nd.ptr == self.map.head.ptr && self.last.is_none() {
+      self.nd = self.map.tail;
+    }
+
+    loop {
+      unsafe {
+        // The head has no predecessor, stay at the start once it is reached.
+        if self.nd.is_null() || self.nd.ptr == self.map.head.ptr {
+          return None;
+        }
+
+        self.nd = self.map.get_prev(self.nd, 0);
+
+        if self.nd.is_null() || self.nd.ptr == self.map.head.ptr {
+          return None;
+        }
+
+        let node = self.nd.as_ref();
+        let nk = node.get_key(&self.map.arena);
+
+        if self.all_versions {
+          let (trailer, value) = node.get_value_and_trailer(&self.map.arena);
+          if trailer.version() > self.version
+            || self.is_expired(&trailer)
+            || !self.map.cmp.contains(&self.range, nk)
+          {
+            continue;
+          }
+
           let ent = VersionedEntryRef {
             map: self.map,
             key: nk,
@@ -211,8 +462,106 @@ where
           self.last = Some(ent);
           return Some(ent);
         }
+
+        if let Some(last) = self.last {
+          if self.map.cmp.compare(last.key, nk) == cmp::Ordering::Equal {
+            continue;
+          }
+        }
+
+        // The versions of a key are walked from the oldest to the newest, the last visible one
+        // is the latest version of the key, which is hidden if it is removed or expired.
+        let mut visible = None;
+        loop {
+          let (trailer, value) = self.nd.as_ref().get_value_and_trailer(&self.map.arena);
+          if trailer.version() <= self.version {
+            visible = Some((self.nd, trailer, value));
+          }
+
+          let prev = self.map.get_prev(self.nd, 0);
+          if prev.is_null()
+            || prev.ptr == self.map.head.ptr
+            || self
+              .map
+              .cmp
+              .compare(prev.as_ref().get_key(&self.map.arena), nk)
+              != cmp::Ordering::Equal
+          {
+            break;
+          }
+          self.nd = prev;
+        }
+
+        if let Some((nd, trailer, value @ Some(_))) = visible {
+          if !self.is_expired(&trailer) && self.map.cmp.contains(&self.range, nk) {
+            self.nd = nd;
+            let ent = VersionedEntryRef {
+              map: self.map,
+              key: nk,
+              trailer,
+              value,
+              ptr: nd,
+            };
+            self.last = Some(ent);
+            return Some(ent);
+          }
+        }
+      }
+    }
+  }
+
+  /// Returns `true` if the iterator yields the entry of the node, regardless of the range.
+  ///
+  /// ## Safety
+  /// - `nd` must be a node (not the head or the tail) allocated by the map.
+  unsafe fn is_visible(&self, nd: NodePtr<T>) -> bool {
+    let (trailer, value) = nd.as_ref().get_value_and_trailer(&self.map.arena);
+    trailer.version() <= self.version
+      && !self.is_expired(&trailer)
+      && (self.all_versions || value.is_some())
+  }
+
+  /// Returns the next node to look at when seeking forward from `nd`, which is the newest version
+  /// visible at the version of the iterator of the next key, or of the same key if the iterator
+  /// yields all the versions.
+  ///
+  /// ## Safety
+  /// - `nd` must be a node (not the head or the tail) allocated by the map.
+  unsafe fn seek_next(&self, nd: NodePtr<T>) -> Option<NodePtr<T>> {
+    if self.all_versions {
+      self
+        .map
+        .find_next_max_version(self.map.get_next(nd, 0), self.version)
+    } else {
+      // A removed or expired entry hides the older versions of its key.
+      self
+        .map
+        .gt(self.version, nd.as_ref().get_key(&self.map.arena))
+    }
+  }
+
+  /// Returns the next node to look at when seeking backward from `nd`, see [`seek_next`](Self::seek_next).
+  ///
+  /// ## Safety
+  /// - `nd` must be a node (not the head or the tail) allocated by the map.
+  unsafe fn seek_prev(&self, nd: NodePtr<T>) -> Option<NodePtr<T>> {
+    let key = nd.as_ref().get_key(&self.map.arena);
+    if self.all_versions {
+      // The older versions of the key follow `nd`.
+      let next = self.map.get_next(nd, 0);
+      if !next.is_null()
+        && next.ptr != self.map.tail.ptr
+        && self
+          .map
+          .cmp
+          .compare(next.as_ref().get_key(&self.map.arena), key)
+          == cmp::Ordering::Equal
+      {
+        return Some(next);
       }
     }
+
+    self.map.lt(self.version, key)
   }
 
   /// Moves the iterator to the first entry whose key is greater than or
@@ -220,11 +569,16 @@ where
   /// pointing at a valid entry, and `None` otherwise.
   fn seek_ge(&mut self, key: &[u8]) -> Option<NodePtr<T>> {
     self.nd = self.map.ge(self.version, key)?;
-    if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
-      return None;
-    }
+    self.seek_ge_in()
+  }
 
+  /// Moves the iterator forward from the current node to the first visible entry within the range.
+  fn seek_ge_in(&mut self) -> Option<NodePtr<T>> {
     loop {
+      if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
+        return None;
+      }
+
       unsafe {
         // Safety: the nd is valid, we already check this
         let node = self.nd.as_ref();
@@ -232,7 +586,9 @@ where
         let nk = node.get_key(&self.map.arena);
 
         if self.map.cmp.contains(&self.range, nk) {
-          return Some(self.nd);
+          if self.is_visible(self.nd) {
+            return Some(self.nd);
+          }
         } else {
           let upper = self.range.end_bound();
           match upper {
@@ -248,9 +604,9 @@ where
             }
             Bound::Unbounded => {}
           }
-
-          self.nd = self.map.get_next(self.nd, 0);
         }
+
+        self.nd = self.seek_next(self.nd)?;
       }
     }
   }
@@ -260,40 +616,7 @@ where
   /// pointing at a valid entry, and `None` otherwise.
   fn seek_gt(&mut self, key: &[u8]) -> Option<NodePtr<T>> {
     self.nd = self.map.gt(self.version, key)?;
-
-    if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
-      return None;
-    }
-
-    loop {
-      unsafe {
-        // Safety: the nd is valid, we already check this
-        let node = self.nd.as_ref();
-        // Safety: the node is allocated by the map's arena, so the key is valid
-        let nk = node.get_key(&self.map.arena);
-
-        if self.map.cmp.contains(&self.range, nk) {
-          return Some(self.nd);
-        } else {
-          let upper = self.range.end_bound();
-          match upper {
-            Bound::Included(upper) => {
-              if upper.lt(&nk) {
-                return None;
-              }
-            }
-            Bound::Excluded(upper) => {
-              if upper.le(&nk) {
-                return None;
-              }
-            }
-            Bound::Unbounded => {}
-          }
-
-          self.nd = self.map.get_next(self.nd, 0);
-        }
-      }
-    }
+    self.seek_ge_in()
   }
 
   /// Moves the iterator to the first entry whose key is less than or
@@ -301,37 +624,7 @@ where
   /// pointing at a valid entry, and `None` otherwise.
   fn seek_le(&mut self, key: &[u8]) -> Option<NodePtr<T>> {
     self.nd = self.map.le(self.version, key)?;
-
-    loop {
-      unsafe {
-        // Safety: the nd is valid, we already check this on line 75
-        let node = self.nd.as_ref();
-
-        // Safety: the node is allocated by the map's arena, so the key is valid
-        let nk = node.get_key(&self.map.arena);
-
-        if self.map.cmp.contains(&self.range, nk) {
-          return Some(self.nd);
-        } else {
-          let lower = self.range.start_bound();
-          match lower {
-            Bound::Included(lower) => {
-              if lower.gt(&nk) {
-                return None;
-              }
-            }
-            Bound::Excluded(lower) => {
-              if lower.ge(&nk) {
-                return None;
-              }
-            }
-            Bound::Unbounded => {}
-          }
-
-          self.nd = self.map.get_prev(self.nd, 0);
-        }
-      }
-    }
+    self.seek_le_in()
   }
 
   /// Moves the iterator to the last entry whose key is less than the given
@@ -341,16 +634,26 @@ where
     // NB: the top-level AllVersionsIter has already adjusted key based on
     // the upper-bound.
     self.nd = self.map.lt(self.version, key)?;
+    self.seek_le_in()
+  }
 
+  /// Moves the iterator backward from the current node to the first visible entry within the range.
+  fn seek_le_in(&mut self) -> Option<NodePtr<T>> {
     loop {
+      if self.nd.is_null() || self.nd.ptr == self.map.head.ptr {
+        return None;
+      }
+
       unsafe {
-        // Safety: the nd is valid, we already check this on line 75
+        // Safety: the nd is valid, we already check this
         let node = self.nd.as_ref();
         // Safety: the node is allocated by the map's arena, so the key is valid
         let nk = node.get_key(&self.map.arena);
 
         if self.map.cmp.contains(&self.range, nk) {
-          return Some(self.nd);
+          if self.is_visible(self.nd) {
+            return Some(self.nd);
+          }
         } else {
           let lower = self.range.start_bound();
           match lower {
@@ -366,9 +669,9 @@ where
             }
             Bound::Unbounded => {}
           }
-
-          self.nd = self.map.get_prev(self.nd, 0);
         }
+
+        self.nd = self.seek_prev(self.nd)?;
       }
     }
   }
@@ -376,7 +679,7 @@ where
   /// Seeks position at the first entry in map. Returns the key and value
   /// if the iterator is pointing at a valid entry, and `None` otherwise.
   fn first(&mut self) -> Option<VersionedEntryRef<'a, T, C>> {
-    self.nd = self.map.first_in(self.version)?;
+    self.nd = self.map.first_in()?;
 
     loop {
       if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
@@ -393,7 +696,13 @@ where
           continue;
         }
 
-        if !self.all_versions && value.is_none() {
+        if !self.all_versions && (value.is_none() || self.is_expired(&trailer)) {
+          // The tombstone or the expired entry hides the older versions of the key.
+          self.nd = self.map.get_next(self.map.oldest_version(self.nd), 0);
+          continue;
+        }
+
+        if self.is_expired(&trailer) {
           self.nd = self.map.get_next(self.nd, 0);
           continue;
         }
@@ -419,41 +728,9 @@ where
   /// the iterator is pointing at a valid entry, and `None` otherwise.
   fn last(&mut self) -> Option<VersionedEntryRef<'a, T, C>> {
     self.nd = self.map.last_in(self.version)?;
-
-    loop {
-      unsafe {
-        if self.nd.is_null() || self.nd.ptr == self.map.head.ptr {
-          return None;
-        }
-
-        let node = self.nd.as_ref();
-        let (trailer, value) = node.get_value_and_trailer(&self.map.arena);
-
-        if trailer.version() > self.version {
-          self.nd = self.map.get_prev(self.nd, 0);
-          continue;
-        }
-
-        if !self.all_versions && value.is_none() {
-          self.nd = self.map.get_prev(self.nd, 0);
-          continue;
-        }
-
-        let nk = node.get_key(&self.map.arena);
-        if self.map.cmp.contains(&self.range, nk) {
-          let ent = VersionedEntryRef {
-            map: self.map,
-            key: nk,
-            trailer,
-            value,
-            ptr: self.nd,
-          };
-          return Some(ent);
-        }
-
-        self.nd = self.map.get_prev(self.nd, 0);
-      }
-    }
+    self
+      .seek_le_in()
+      .map(|n| VersionedEntryRef::from_node(n, self.map))
   }
 }
 
@@ -469,10 +746,15 @@ where
 
   #[inline]
   fn next(&mut self) -> Option<Self::Item> {
-    self.next_in().map(|v| {
-      // Safety: the EntryRef holds a reference to the map, so it is always valid.
-      unsafe { core::mem::transmute(v) }
-    })
+    self.step(false)
+  }
+
+  #[inline]
+  fn count(self) -> usize
+  where
+    Self: Sized,
+  {
+    self.count_remaining()
   }
 
   #[inline]
@@ -517,9 +799,16 @@ where
   R: RangeBounds<Q>,
 {
   fn next_back(&mut self) -> Option<Self::Item> {
-    self.prev().map(|v| {
-      // Safety: the EntryRef holds a reference to the map, so it is always valid.
-      unsafe { core::mem::transmute(v) }
-    })
+    self.step(true)
   }
 }
+
+impl<'a, Q, R, T, C> core::iter::FusedIterator for AllVersionsIter<'a, T, C, Q, R>
+where
+  C: Comparator,
+  T: Trailer,
+  &'a [u8]: PartialOrd<Q>,
+  Q: ?Sized + PartialOrd<&'a [u8]>,
+  R: RangeBounds<Q>,
+{
+}
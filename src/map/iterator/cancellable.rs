@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use crate::sync::{AtomicBool, Ordering};
+
+use super::*;
+
+/// A token which can be used to cancel a [`CancellableIter`] from another thread.
+///
+/// Cloning a token is cheap, all the clones share the same cancellation state.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+  /// Creates a new token which is not cancelled.
+  #[inline]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Signals the cancellation, all the iterators holding this token (or its clones) will stop
+  /// yielding entries.
+  #[inline]
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::Release);
+  }
+
+  /// Returns `true` if the cancellation has been signaled.
+  #[inline]
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::Acquire)
+  }
+}
+
+/// An iterator over the skipmap which stops yielding entries once its [`CancelToken`] is cancelled.
+pub struct CancellableIter<'a, T, C> {
+  iter: Iter<'a, T, C>,
+  token: CancelToken,
+}
+
+impl<'a, T, C> CancellableIter<'a, T, C>
+where
+  C: Comparator,
+{
+  #[inline]
+  pub(crate) fn new(version: u64, map: &'a SkipMap<T, C>, token: CancelToken) -> Self {
+    Self {
+      iter: Iter::new(version, map),
+      token,
+    }
+  }
+}
+
+impl<'a, T, C> CancellableIter<'a, T, C> {
+  /// Returns the token of the iterator.
+  #[inline]
+  pub const fn token(&self) -> &CancelToken {
+    &self.token
+  }
+
+  /// Returns the comparator of the map, which defines the order of the yielded keys.
+  #[inline]
+  pub const fn comparator(&self) -> &'a C {
+    self.iter.comparator()
+  }
+}
+
+impl<'a, T: Clone, C> CancellableIter<'a, T, C> {
+  /// Returns the entry at the current position of the iterator.
+  #[inline]
+  pub fn entry(&self) -> Option<EntryRef<'a, T, C>> {
+    self.iter.entry()
+  }
+}
+
+impl<'a, T, C> Iterator for CancellableIter<'a, T, C>
+where
+  C: Comparator,
+  T: Trailer,
+{
+  type Item = EntryRef<'a, T, C>;
+
+  #[inline]
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.token.is_cancelled() {
+      return None;
+    }
+
+    self.iter.next()
+  }
+}
+
+impl<'a, T, C> DoubleEndedIterator for CancellableIter<'a, T, C>
+where
+  C: Comparator,
+  T: Trailer,
+{
+  #[inline]
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.token.is_cancelled() {
+      return None;
+    }
+
+    self.iter.next_back()
+  }
+}
+
+impl<'a, T, C> core::iter::FusedIterator for CancellableIter<'a, T, C>
+where
+  C: Comparator,
+  T: Trailer,
+{
+}
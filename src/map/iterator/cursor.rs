@@ -0,0 +1,98 @@
+use super::*;
+
+/// A cursor over the latest version of the entries in the skipmap, created by [`SkipMap::cursor`].
+///
+/// Unlike the iterators, a cursor is meant to be kept around and moved in both directions, or
+/// re-positioned with [`seek`](Cursor::seek), as many times as needed. It is valid while it points
+/// at an entry, see [`current`](Cursor::current). Moving past the last entry with
+/// [`next`](Iterator::next) invalidates the cursor, and a following [`prev`](Cursor::prev) moves it
+/// back to the last entry, the same holds for the first entry in the other direction.
+pub struct Cursor<'a, T, C> {
+  iter: AllVersionsIter<'a, T, C>,
+}
+
+impl<'a, T: Clone, C> Clone for Cursor<'a, T, C> {
+  fn clone(&self) -> Self {
+    Self {
+      iter: self.iter.clone(),
+    }
+  }
+}
+
+impl<'a, T, C> Cursor<'a, T, C>
+where
+  C: Comparator,
+{
+  #[inline]
+  pub(crate) const fn new(version: u64, map: &'a SkipMap<T, C>) -> Self {
+    Self {
+      iter: AllVersionsIter::new(version, map, false),
+    }
+  }
+}
+
+impl<'a, T: Clone, C> Cursor<'a, T, C> {
+  /// Returns the entry the cursor points at, `None` if the cursor is not valid.
+  #[inline]
+  pub fn current(&self) -> Option<EntryRef<'a, T, C>> {
+    self.iter.last.clone().map(EntryRef)
+  }
+
+  /// Returns `true` if the cursor points at an entry.
+  #[inline]
+  pub fn is_valid(&self) -> bool {
+    self.iter.last.is_some()
+  }
+}
+
+impl<'a, T, C> Cursor<'a, T, C>
+where
+  C: Comparator,
+  T: Trailer,
+{
+  /// Moves the cursor to the first entry whose key is greater than or equal to the given key,
+  /// and returns it.
+  ///
+  /// If there is no such entry, the cursor is moved past the last entry and `None` is returned.
+  pub fn seek(&mut self, key: &[u8]) -> Option<EntryRef<'a, T, C>> {
+    match self.iter.seek_lower_bound(Bound::Included(key)) {
+      Some(ent) => Some(EntryRef(ent)),
+      None => {
+        self.iter.nd = self.iter.map.tail;
+        self.iter.last = None;
+        None
+      }
+    }
+  }
+
+  /// Moves the cursor to the previous entry, and returns it.
+  ///
+  /// If the cursor is at the first entry, it is moved before the first entry and `None` is returned.
+  pub fn prev(&mut self) -> Option<EntryRef<'a, T, C>> {
+    let ent = self.iter.next_back();
+    if ent.is_none() {
+      self.iter.last = None;
+    }
+    ent.map(EntryRef)
+  }
+}
+
+impl<'a, T, C> Iterator for Cursor<'a, T, C>
+where
+  C: Comparator,
+  T: Trailer,
+{
+  type Item = EntryRef<'a, T, C>;
+
+  /// Moves the cursor to the next entry, and returns it.
+  ///
+  /// If the cursor is at the last entry, it is moved past the last entry and `None` is returned.
+  #[inline]
+  fn next(&mut self) -> Option<Self::Item> {
+    let ent = self.iter.next();
+    if ent.is_none() {
+      self.iter.last = None;
+    }
+    ent.map(EntryRef)
+  }
+}
@@ -41,6 +41,18 @@ impl<'a, Q: ?Sized, R, T, C> Iter<'a, T, C, Q, R> {
   pub const fn bounds(&self) -> &R {
     &self.0.range
   }
+
+  /// Returns the comparator of the map, which defines the order of the yielded keys.
+  #[inline]
+  pub const fn comparator(&self) -> &'a C {
+    self.0.comparator()
+  }
+
+  /// Skips the entries which are expired at `now`, see [`AllVersionsIter::skip_expired`].
+  #[inline]
+  pub fn skip_expired(self, now: u64) -> Self {
+    Self(self.0.skip_expired(now))
+  }
 }
 
 impl<'a, Q: ?Sized, R, T: Clone, C> Iter<'a, T, C, Q, R> {
@@ -59,6 +71,18 @@ where
   Q: ?Sized + PartialOrd<&'a [u8]>,
   R: RangeBounds<Q>,
 {
+  /// Returns the entry which the next call to [`next`](Iterator::next) returns, without advancing
+  /// the iterator, see [`AllVersionsIter::peek`].
+  pub fn peek(&mut self) -> Option<EntryRef<'a, T, C>> {
+    self.0.peek().map(EntryRef)
+  }
+
+  /// Returns the entry which the next call to [`next_back`](DoubleEndedIterator::next_back) returns,
+  /// without moving the iterator, see [`AllVersionsIter::peek_back`].
+  pub fn peek_back(&mut self) -> Option<EntryRef<'a, T, C>> {
+    self.0.peek_back().map(EntryRef)
+  }
+
   /// Moves the iterator to the highest element whose key is below the given bound.
   /// If no such element is found then `None` is returned.
   pub fn seek_upper_bound(&mut self, upper: Bound<&[u8]>) -> Option<EntryRef<'a, T, C>> {
@@ -70,6 +94,15 @@ where
   pub fn seek_lower_bound(&mut self, lower: Bound<&[u8]>) -> Option<EntryRef<'a, T, C>> {
     self.0.seek_lower_bound(lower).map(EntryRef)
   }
+
+  /// Moves the iterator to the lowest element whose key is greater than or equal to the given key,
+  /// starting the search from an entry previously returned by an iterator of the same map.
+  ///
+  /// See [`AllVersionsIter::seek_from`] for the details and the monotonic precondition
+  /// under which the finger search applies.
+  pub fn seek_from(&mut self, from: &EntryRef<'a, T, C>, key: &[u8]) -> Option<EntryRef<'a, T, C>> {
+    self.0.seek_from(&from.0, key).map(EntryRef)
+  }
 }
 
 impl<'a, Q, R, T, C> Iterator for Iter<'a, T, C, Q, R>
@@ -87,6 +120,14 @@ where
     self.0.next().map(EntryRef)
   }
 
+  #[inline]
+  fn count(self) -> usize
+  where
+    Self: Sized,
+  {
+    self.0.count()
+  }
+
   #[inline]
   fn last(self) -> Option<Self::Item>
   where
@@ -126,3 +167,13 @@ where
     self.0.next_back().map(EntryRef)
   }
 }
+
+impl<'a, Q, R, T, C> core::iter::FusedIterator for Iter<'a, T, C, Q, R>
+where
+  C: Comparator,
+  T: Trailer,
+  &'a [u8]: PartialOrd<Q>,
+  Q: ?Sized + PartialOrd<&'a [u8]>,
+  R: RangeBounds<Q>,
+{
+}
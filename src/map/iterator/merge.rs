@@ -0,0 +1,142 @@
+use std::{collections::BinaryHeap, vec::Vec};
+
+use super::*;
+
+/// The head entry of one of the merged maps.
+struct Head<'a, T, C> {
+  ent: VersionedEntryRef<'a, T, C>,
+  /// The index of the map in the slice passed to [`MergeIterator::new`].
+  idx: usize,
+}
+
+impl<'a, T: Trailer, C: Comparator> Head<'a, T, C> {
+  /// Returns the order of the heads, the head which should be yielded first is the smallest one.
+  #[inline]
+  fn order(&self, other: &Self) -> cmp::Ordering {
+    self
+      .ent
+      .map
+      .cmp
+      .compare(self.ent.key, other.ent.key)
+      .then_with(|| other.ent.trailer.version().cmp(&self.ent.trailer.version()))
+      .then_with(|| self.idx.cmp(&other.idx))
+  }
+}
+
+impl<'a, T: Trailer, C: Comparator> PartialEq for Head<'a, T, C> {
+  #[inline]
+  fn eq(&self, other: &Self) -> bool {
+    self.order(other) == cmp::Ordering::Equal
+  }
+}
+
+impl<'a, T: Trailer, C: Comparator> Eq for Head<'a, T, C> {}
+
+impl<'a, T: Trailer, C: Comparator> PartialOrd for Head<'a, T, C> {
+  #[inline]
+  fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl<'a, T: Trailer, C: Comparator> Ord for Head<'a, T, C> {
+  #[inline]
+  fn cmp(&self, other: &Self) -> cmp::Ordering {
+    // BinaryHeap is a max-heap, reverse the order to pop the smallest head first.
+    other.order(self)
+  }
+}
+
+/// An iterator which merges multiple skipmaps into a single sorted stream.
+///
+/// For every distinct key, only the entry with the highest version less or equal to the given version
+/// across all the maps is yielded. If the same key has the same version in several maps,
+/// the entry of the map which comes first in the slice wins.
+///
+/// All the maps must use the same comparator, otherwise the order of the yielded entries is unspecified.
+pub struct MergeIterator<'a, T, C> {
+  iters: Vec<AllVersionsIter<'a, T, C>>,
+  heap: BinaryHeap<Head<'a, T, C>>,
+  with_tombstones: bool,
+}
+
+impl<'a, T: Trailer, C: Comparator> MergeIterator<'a, T, C> {
+  /// Creates a new merging iterator over the maps, which yields the entries visible at the given version.
+  ///
+  /// If `with_tombstones` is `true`, a key whose winning entry is removed is yielded as a removed entry,
+  /// see [`VersionedEntryRef::is_removed`], otherwise the key is skipped.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, map::MergeIterator};
+  ///
+  /// let older = SkipMap::new().unwrap();
+  /// older.get_or_insert(1, b"a", b"a1").unwrap();
+  /// older.get_or_insert(1, b"b", b"b1").unwrap();
+  ///
+  /// let newer = SkipMap::new().unwrap();
+  /// newer.get_or_insert(2, b"a", b"a2").unwrap();
+  /// newer.get_or_remove(2, b"b").unwrap();
+  ///
+  /// let mut iter = MergeIterator::new(&[&older, &newer], 2, false);
+  /// let ent = iter.next().unwrap();
+  /// assert_eq!(ent.key(), b"a");
+  /// assert_eq!(ent.value().unwrap(), b"a2");
+  /// assert!(iter.next().is_none());
+  /// ```
+  pub fn new(maps: &[&'a SkipMap<T, C>], version: u64, with_tombstones: bool) -> Self {
+    let mut iters: Vec<_> = maps.iter().map(|m| m.iter_all_versions(version)).collect();
+    let heap = iters
+      .iter_mut()
+      .enumerate()
+      .filter_map(|(idx, iter)| iter.next().map(|ent| Head { ent, idx }))
+      .collect();
+
+    Self {
+      iters,
+      heap,
+      with_tombstones,
+    }
+  }
+
+  /// Advances the iterator of the map at `idx` past all the versions of `key`,
+  /// and pushes its next entry to the heap.
+  fn advance(&mut self, idx: usize, key: &[u8]) {
+    let iter = &mut self.iters[idx];
+    for ent in iter.by_ref() {
+      if ent.map.cmp.compare(ent.key, key) != cmp::Ordering::Equal {
+        self.heap.push(Head { ent, idx });
+        return;
+      }
+    }
+  }
+}
+
+impl<'a, T: Trailer, C: Comparator> Iterator for MergeIterator<'a, T, C> {
+  type Item = VersionedEntryRef<'a, T, C>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let Head { ent, idx } = self.heap.pop()?;
+
+      // Drop the older versions of the key in all the maps.
+      self.advance(idx, ent.key);
+      while let Some(head) = self.heap.peek() {
+        if head.ent.map.cmp.compare(head.ent.key, ent.key) != cmp::Ordering::Equal {
+          break;
+        }
+
+        let idx = head.idx;
+        self.heap.pop();
+        self.advance(idx, ent.key);
+      }
+
+      if self.with_tombstones || !ent.is_removed() {
+        return Some(ent);
+      }
+    }
+  }
+}
+
+impl<'a, T: Trailer, C: Comparator> core::iter::FusedIterator for MergeIterator<'a, T, C> {}
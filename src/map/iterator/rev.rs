@@ -0,0 +1,142 @@
+use super::*;
+
+/// An iterator over the skipmap in the reverse order of its comparator, created by
+/// [`SkipMap::iter_rev`] and [`SkipMap::range_rev`].
+///
+/// Unlike [`Iter::rev`](Iterator::rev), the primary direction of this iterator is backward, so
+/// [`next`](Iterator::next) yields the greatest entry first, and the seek methods are oriented
+/// accordingly. The current state of the iterator can be cloned by simply value copying the struct.
+pub struct RevIter<'a, T, C, Q: ?Sized = &'static [u8], R = core::ops::RangeFull>(
+  Iter<'a, T, C, Q, R>,
+);
+
+impl<'a, R: Clone, Q: Clone, T: Clone, C> Clone for RevIter<'a, T, C, Q, R> {
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+impl<'a, R: Copy, Q: Copy, T: Copy, C> Copy for RevIter<'a, T, C, Q, R> {}
+
+impl<'a, T, C> RevIter<'a, T, C>
+where
+  C: Comparator,
+{
+  #[inline]
+  pub(crate) const fn new(version: u64, map: &'a SkipMap<T, C>) -> Self {
+    Self(Iter::new(version, map))
+  }
+}
+
+impl<'a, Q, R, T, C> RevIter<'a, T, C, Q, R>
+where
+  &'a [u8]: PartialOrd<Q>,
+  Q: ?Sized + PartialOrd<&'a [u8]>,
+{
+  #[inline]
+  pub(crate) fn range(version: u64, map: &'a SkipMap<T, C>, r: R) -> Self {
+    Self(Iter::range(version, map, r))
+  }
+}
+
+impl<'a, Q: ?Sized, R, T, C> RevIter<'a, T, C, Q, R> {
+  /// Returns the bounds of the iterator.
+  #[inline]
+  pub const fn bounds(&self) -> &R {
+    self.0.bounds()
+  }
+
+  /// Returns the comparator of the map, the iterator yields the keys in its reverse order.
+  #[inline]
+  pub const fn comparator(&self) -> &'a C {
+    self.0.comparator()
+  }
+
+  /// Skips the entries which are expired at `now`, see [`AllVersionsIter::skip_expired`].
+  #[inline]
+  pub fn skip_expired(self, now: u64) -> Self {
+    Self(self.0.skip_expired(now))
+  }
+}
+
+impl<'a, Q: ?Sized, R, T: Clone, C> RevIter<'a, T, C, Q, R> {
+  /// Returns the entry at the current position of the iterator.
+  #[inline]
+  pub fn entry(&self) -> Option<EntryRef<'a, T, C>> {
+    self.0.entry()
+  }
+}
+
+impl<'a, Q, R, T, C> RevIter<'a, T, C, Q, R>
+where
+  C: Comparator,
+  T: Trailer,
+  &'a [u8]: PartialOrd<Q>,
+  Q: ?Sized + PartialOrd<&'a [u8]>,
+  R: RangeBounds<Q>,
+{
+  /// Returns the entry which the next call to [`next`](Iterator::next) returns, without moving
+  /// the iterator, see [`AllVersionsIter::peek`].
+  pub fn peek(&mut self) -> Option<EntryRef<'a, T, C>> {
+    self.0.peek_back()
+  }
+
+  /// Returns the entry which the next call to [`next_back`](DoubleEndedIterator::next_back) returns,
+  /// without moving the iterator, see [`AllVersionsIter::peek_back`].
+  pub fn peek_back(&mut self) -> Option<EntryRef<'a, T, C>> {
+    self.0.peek()
+  }
+
+  /// Moves the iterator to the first element in the iteration order whose key is not past the
+  /// given bound, i.e. the highest element whose key is below the bound.
+  /// If no such element is found then `None` is returned.
+  pub fn seek_lower_bound(&mut self, lower: Bound<&[u8]>) -> Option<EntryRef<'a, T, C>> {
+    self.0.seek_upper_bound(lower)
+  }
+
+  /// Moves the iterator to the last element in the iteration order whose key is not before the
+  /// given bound, i.e. the lowest element whose key is above the bound.
+  /// If no such element is found then `None` is returned.
+  pub fn seek_upper_bound(&mut self, upper: Bound<&[u8]>) -> Option<EntryRef<'a, T, C>> {
+    self.0.seek_lower_bound(upper)
+  }
+}
+
+impl<'a, Q, R, T, C> Iterator for RevIter<'a, T, C, Q, R>
+where
+  C: Comparator,
+  T: Trailer,
+  &'a [u8]: PartialOrd<Q>,
+  Q: ?Sized + PartialOrd<&'a [u8]>,
+  R: RangeBounds<Q>,
+{
+  type Item = EntryRef<'a, T, C>;
+
+  #[inline]
+  fn next(&mut self) -> Option<Self::Item> {
+    self.0.next_back()
+  }
+}
+
+impl<'a, Q, R, T, C> DoubleEndedIterator for RevIter<'a, T, C, Q, R>
+where
+  C: Comparator,
+  T: Trailer,
+  &'a [u8]: PartialOrd<Q>,
+  Q: ?Sized + PartialOrd<&'a [u8]>,
+  R: RangeBounds<Q>,
+{
+  fn next_back(&mut self) -> Option<Self::Item> {
+    self.0.next()
+  }
+}
+
+impl<'a, Q, R, T, C> core::iter::FusedIterator for RevIter<'a, T, C, Q, R>
+where
+  C: Comparator,
+  T: Trailer,
+  &'a [u8]: PartialOrd<Q>,
+  Q: ?Sized + PartialOrd<&'a [u8]>,
+  R: RangeBounds<Q>,
+{
+}
@@ -0,0 +1,75 @@
+use super::*;
+
+/// An iterator over the removed entries of the skipmap, created by [`SkipMap::iter_tombstones`].
+///
+/// A key is yielded when its latest version less or equal to the read version is removed, the
+/// entries are [`VersionedEntryRef`]s whose [`value`](VersionedEntryRef::value) is always `None`.
+pub struct TombstonesIter<'a, T, C> {
+  map: &'a SkipMap<T, C>,
+  nd: NodePtr<T>,
+  version: u64,
+}
+
+impl<'a, T, C> Clone for TombstonesIter<'a, T, C> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<'a, T, C> Copy for TombstonesIter<'a, T, C> {}
+
+impl<'a, T, C> TombstonesIter<'a, T, C> {
+  #[inline]
+  pub(crate) const fn new(version: u64, map: &'a SkipMap<T, C>) -> Self {
+    Self {
+      map,
+      nd: map.head,
+      version,
+    }
+  }
+}
+
+impl<'a, T, C> Iterator for TombstonesIter<'a, T, C>
+where
+  C: Comparator,
+  T: Trailer,
+{
+  type Item = VersionedEntryRef<'a, T, C>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      unsafe {
+        // The tail has no successor, stay at the end once it is reached.
+        if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
+          return None;
+        }
+
+        self.nd = self.map.get_next(self.nd, 0);
+
+        if self.nd.is_null() || self.nd.ptr == self.map.tail.ptr {
+          return None;
+        }
+
+        let nd = self.nd;
+        let node = nd.as_ref();
+        let (trailer, value) = node.get_value_and_trailer(&self.map.arena);
+        if trailer.version() > self.version {
+          continue;
+        }
+
+        // This is the latest visible version of the key, which hides the older versions whether
+        // it is removed or not.
+        self.nd = self.map.oldest_version(nd);
+        if value.is_none() {
+          return Some(VersionedEntryRef {
+            map: self.map,
+            key: node.get_key(&self.map.arena),
+            trailer,
+            value,
+            ptr: nd,
+          });
+        }
+      }
+    }
+  }
+}
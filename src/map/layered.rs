@@ -0,0 +1,85 @@
+use super::*;
+
+/// Returns the value associated with the given key from a chain of maps, e.g. the levels of an
+/// LSM tree or the overflow maps of a spilled map, consulting at most `max_layers` of them.
+///
+/// The maps are ordered from the newest layer to the oldest one, the first layer which holds a
+/// version of the key visible at `version` resolves it, a removed entry resolves the key to `None`
+/// without consulting the older layers.
+///
+/// Returns [`Error::TooManyLayers`] if the key is not resolved by the first `max_layers` maps and
+/// there are more layers left, so a pathological chain fails fast instead of scanning all of them.
+///
+/// # Example
+///
+/// ```rust
+/// use skl::{get_layered_timed, map::Error, SkipMap};
+///
+/// let layers = (0..4).map(|_| SkipMap::<u64>::new().unwrap()).collect::<Vec<_>>();
+/// layers[3].insert(0, b"a", b"a0").unwrap();
+/// layers[1].insert(1, b"b", b"b1").unwrap();
+///
+/// let ent = get_layered_timed(1, b"b", &layers, 2).unwrap().unwrap();
+/// assert_eq!(ent.value(), b"b1");
+/// assert!(matches!(get_layered_timed(1, b"a", &layers, 2), Err(Error::TooManyLayers)));
+/// assert_eq!(get_layered_timed(1, b"a", &layers, 4).unwrap().unwrap().value(), b"a0");
+/// ```
+pub fn get_layered_timed<'a, 'b: 'a, T: Trailer, C: Comparator>(
+  version: u64,
+  key: &'b [u8],
+  maps: &'a [SkipMap<T, C>],
+  max_layers: usize,
+) -> Result<Option<EntryRef<'a, T, C>>, Error> {
+  for (idx, map) in maps.iter().enumerate() {
+    if idx == max_layers {
+      return Err(Error::TooManyLayers);
+    }
+
+    if let Some(ent) = map.get_visible(version, key) {
+      if ent.is_removed() {
+        return Ok(None);
+      }
+      return Ok(Some(EntryRef(ent)));
+    }
+  }
+
+  Ok(None)
+}
+
+impl<T: Trailer, C: Comparator> SkipMap<T, C> {
+  /// Returns the latest version of the key visible at the given version, including a removed one.
+  fn get_visible<'a, 'b: 'a>(
+    &'a self,
+    version: u64,
+    key: &'b [u8],
+  ) -> Option<VersionedEntryRef<'a, T, C>> {
+    if !self.bloom_may_contain(key) {
+      return None;
+    }
+
+    unsafe {
+      let (n, eq) = self.find_near(version, key, false, true);
+      let n = n?;
+      let node = n.as_ref();
+      let node_key = node.get_key(&self.arena);
+      let (trailer, value) = node.get_value_and_trailer(&self.arena);
+      if !eq {
+        if !matches!(self.cmp.compare(key, node_key), cmp::Ordering::Equal) {
+          return None;
+        }
+
+        if trailer.version() > version {
+          return None;
+        }
+      }
+
+      Some(VersionedEntryRef {
+        map: self,
+        key: node_key,
+        trailer,
+        value,
+        ptr: n,
+      })
+    }
+  }
+}
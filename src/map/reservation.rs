@@ -0,0 +1,123 @@
+use core::{fmt, ops, slice};
+
+use rarena_allocator::BytesRefMut;
+
+/// Space reserved in the ARENA of a [`SkipMap`](super::SkipMap), returned by
+/// [`SkipMap::reserve`](super::SkipMap::reserve).
+///
+/// Dropping the reservation without [`commit`](Reservation::commit)ting it gives the space back.
+///
+/// The ARENA is append-only, so the space can only be rolled back if the reservation is still
+/// the most recent allocation when it is dropped. That is, reservations must be dropped in the
+/// LIFO order, and no other allocation, including the writes of other threads, may happen in
+/// between. Otherwise, the space goes to the freelist, or is counted as
+/// [`discarded`](super::SkipMap::discarded) if the freelist is disabled.
+#[must_use = "the reserved space is given back when the reservation is dropped"]
+pub struct Reservation<'a> {
+  buf: BytesRefMut<'a>,
+}
+
+impl<'a> Reservation<'a> {
+  #[inline]
+  pub(super) fn new(buf: BytesRefMut<'a>) -> Self {
+    Self { buf }
+  }
+
+  /// Returns the offset of the reserved space in the ARENA.
+  #[inline]
+  pub fn offset(&self) -> usize {
+    self.buf.offset()
+  }
+
+  /// Returns the number of reserved bytes.
+  #[inline]
+  pub fn capacity(&self) -> usize {
+    self.buf.capacity()
+  }
+
+  /// Commits the reservation, the space is kept in the ARENA after the reservation is dropped.
+  ///
+  /// Returns the offset of the reserved space in the ARENA.
+  #[inline]
+  pub fn commit(mut self) -> usize {
+    self.buf.detach();
+    self.buf.offset()
+  }
+}
+
+impl<'a> ops::Deref for Reservation<'a> {
+  type Target = [u8];
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    // Safety: the reserved space is allocated by the ARENA, and it is valid for `capacity` bytes.
+    unsafe { slice::from_raw_parts(self.buf.as_ptr(), self.buf.capacity()) }
+  }
+}
+
+impl<'a> ops::DerefMut for Reservation<'a> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    // Safety: the reserved space is allocated by the ARENA, and it is valid for `capacity` bytes.
+    unsafe { slice::from_raw_parts_mut(self.buf.as_mut_ptr(), self.buf.capacity()) }
+  }
+}
+
+impl<'a> fmt::Debug for Reservation<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Reservation")
+      .field("offset", &self.offset())
+      .field("capacity", &self.capacity())
+      .finish()
+  }
+}
+
+/// Bytes allocated in the ARENA of a [`SkipMap`](super::SkipMap), returned by
+/// [`SkipMap::alloc_bytes`](super::SkipMap::alloc_bytes).
+///
+/// Unlike a [`Reservation`], the bytes are kept in the ARENA when the handle is dropped, and the
+/// handle is the only way to write them.
+pub struct AllocatedBytes<'a> {
+  buf: BytesRefMut<'a>,
+}
+
+impl<'a> AllocatedBytes<'a> {
+  #[inline]
+  pub(super) fn new(mut buf: BytesRefMut<'a>) -> Self {
+    buf.detach();
+    Self { buf }
+  }
+
+  /// Returns the offset of the bytes in the ARENA.
+  #[inline]
+  pub fn offset(&self) -> u32 {
+    self.buf.offset() as u32
+  }
+}
+
+impl<'a> ops::Deref for AllocatedBytes<'a> {
+  type Target = [u8];
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    // Safety: the bytes are allocated by the ARENA, and they are valid for `capacity` bytes.
+    unsafe { slice::from_raw_parts(self.buf.as_ptr(), self.buf.capacity()) }
+  }
+}
+
+impl<'a> ops::DerefMut for AllocatedBytes<'a> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    // Safety: the bytes are allocated by the ARENA, and they are valid for `capacity` bytes.
+    unsafe { slice::from_raw_parts_mut(self.buf.as_mut_ptr(), self.buf.capacity()) }
+  }
+}
+
+impl<'a> fmt::Debug for AllocatedBytes<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("AllocatedBytes")
+      .field("offset", &self.offset())
+      .field("len", &self.len())
+      .finish()
+  }
+}
@@ -0,0 +1,122 @@
+use std::vec::Vec;
+
+use serde::{
+  de::{DeserializeOwned, Error as _, SeqAccess, Visitor},
+  ser::SerializeSeq,
+  Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::*;
+
+/// A record of an entry, the value is `None` for a removed entry.
+#[derive(Serialize)]
+struct RecordRef<'a, T> {
+  trailer: T,
+  key: &'a [u8],
+  value: Option<&'a [u8]>,
+}
+
+#[derive(Deserialize)]
+struct Record<T> {
+  trailer: T,
+  key: Vec<u8>,
+  value: Option<Vec<u8>>,
+}
+
+/// Serializes all the versions of all the entries, including the removed ones, as a sequence of
+/// `{ trailer, key, value }` records, the value is `None` for a removed entry.
+///
+/// The map cannot implement `Deserialize` because the ARENA cannot be sized from the records,
+/// see [`SkipMap::from_records`] and [`SkipMap::deserialize_into`] to rebuild a map.
+impl<T: Trailer + Serialize, C: Comparator> Serialize for SkipMap<T, C> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    // Collects the entries first, so the length of the sequence is known and consistent with
+    // the records even if there are concurrent writes.
+    let entries = self.iter_all_versions(u64::MAX).collect::<Vec<_>>();
+    let mut seq = serializer.serialize_seq(Some(entries.len()))?;
+    for ent in &entries {
+      seq.serialize_element(&RecordRef {
+        trailer: *ent.trailer(),
+        key: ent.key(),
+        value: ent.value(),
+      })?;
+    }
+    seq.end()
+  }
+}
+
+impl<T: Trailer + DeserializeOwned> SkipMap<T> {
+  /// Creates a new map with the given options, and inserts the records serialized from a map,
+  /// see [`deserialize_into`](SkipMap::deserialize_into).
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{SkipMap, Options};
+  /// use bincode::{DefaultOptions, Deserializer, Options as _};
+  ///
+  /// let map = SkipMap::new().unwrap();
+  /// map.insert(1, b"a", b"a1").unwrap();
+  /// map.get_or_remove(2, b"a").unwrap();
+  ///
+  /// let bytes = DefaultOptions::new().serialize(&map).unwrap();
+  /// let mut deserializer = Deserializer::from_slice(&bytes, DefaultOptions::new());
+  /// let restored = SkipMap::<u64>::from_records(Options::new(), &mut deserializer).unwrap();
+  ///
+  /// assert!(restored.get(2, b"a").is_none());
+  /// assert_eq!(restored.get(1, b"a").unwrap().value(), b"a1");
+  /// ```
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn from_records<'de, D: Deserializer<'de>>(
+    opts: Options,
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    let map = Self::with_options(opts).map_err(D::Error::custom)?;
+    map.deserialize_into(deserializer)?;
+    Ok(map)
+  }
+}
+
+impl<T: Trailer + DeserializeOwned, C: Comparator> SkipMap<T, C> {
+  /// Inserts the records serialized from a map into this map, a removed entry is inserted as
+  /// a tombstone at its version.
+  ///
+  /// The records are inserted one by one as they are deserialized, an error, e.g. the ARENA
+  /// running out of space, stops the deserialization, the records inserted before are kept.
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn deserialize_into<'de, D: Deserializer<'de>>(
+    &self,
+    deserializer: D,
+  ) -> Result<(), D::Error> {
+    struct RecordsVisitor<'a, T, C>(&'a SkipMap<T, C>);
+
+    impl<'de, 'a, T: Trailer + DeserializeOwned, C: Comparator> Visitor<'de>
+      for RecordsVisitor<'a, T, C>
+    {
+      type Value = ();
+
+      fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("a sequence of skipmap records")
+      }
+
+      fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(record) = seq.next_element::<Record<T>>()? {
+          match record.value {
+            Some(value) => self
+              .0
+              .insert(record.trailer, &record.key, &value)
+              .map(|_| ()),
+            None => self
+              .0
+              .get_or_remove(record.trailer, &record.key)
+              .map(|_| ()),
+          }
+          .map_err(A::Error::custom)?;
+        }
+        Ok(())
+      }
+    }
+
+    deserializer.deserialize_seq(RecordsVisitor(self))
+  }
+}
@@ -0,0 +1,82 @@
+use super::*;
+
+/// A read-your-writes session over a [`SkipMap`], created by [`SkipMap::session`].
+///
+/// Every write of the session is done at the next session version, and every read is done at the
+/// current session version, so the reads always see the writes done before them through the session.
+///
+/// The session version is advanced atomically, so a session can be shared across threads, each write
+/// gets a distinct version. A read sees all the session writes which have completed before it,
+/// but a concurrent write which has taken its version and is not yet linked may be missed.
+pub struct Session<'a, C> {
+  map: &'a SkipMap<u64, C>,
+  version: AtomicU64,
+}
+
+impl<'a, C: Comparator> core::fmt::Debug for Session<'a, C> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Session")
+      .field("version", &self.version())
+      .field("map", self.map)
+      .finish()
+  }
+}
+
+impl<'a, C> Session<'a, C> {
+  #[inline]
+  pub(super) fn new(map: &'a SkipMap<u64, C>, version: u64) -> Self {
+    Self {
+      map,
+      version: AtomicU64::new(version),
+    }
+  }
+
+  /// Returns the current version of the session, which is the version of the last write.
+  #[inline]
+  pub fn version(&self) -> u64 {
+    self.version.load(Ordering::Acquire)
+  }
+
+  /// Returns the map of the session.
+  #[inline]
+  pub const fn map(&self) -> &'a SkipMap<u64, C> {
+    self.map
+  }
+
+  #[inline]
+  fn next_version(&self) -> u64 {
+    self.version.fetch_add(1, Ordering::AcqRel) + 1
+  }
+}
+
+impl<'a, C: Comparator> Session<'a, C> {
+  /// Inserts the key-value pair at the next version of the session, see [`SkipMap::insert`].
+  #[inline]
+  pub fn insert<'b: 'a>(
+    &self,
+    key: &'b [u8],
+    value: &'b [u8],
+  ) -> Result<Option<EntryRef<'a, u64, C>>, Error> {
+    self.map.insert(self.next_version(), key, value)
+  }
+
+  /// Removes the key at the next version of the session, see [`SkipMap::get_or_remove`].
+  #[inline]
+  pub fn remove<'b: 'a>(&self, key: &'b [u8]) -> Result<Option<EntryRef<'a, u64, C>>, Error> {
+    self.map.get_or_remove(self.next_version(), key)
+  }
+
+  /// Returns the value associated with the given key at the current version of the session,
+  /// see [`SkipMap::get`].
+  #[inline]
+  pub fn get<'b: 'a>(&self, key: &'b [u8]) -> Option<EntryRef<'a, u64, C>> {
+    self.map.get(self.version(), key)
+  }
+
+  /// Returns `true` if the key exists at the current version of the session,
+  /// see [`SkipMap::contains_key`].
+  #[inline]
+  pub fn contains_key<'b: 'a>(&self, key: &'b [u8]) -> bool {
+    self.map.contains_key(self.version(), key)
+  }
+}
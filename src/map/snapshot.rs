@@ -0,0 +1,99 @@
+use super::*;
+
+/// A read view of a [`SkipMap`] at a fixed version, created by [`SkipMap::snapshot`].
+///
+/// The snapshot holds a clone of the map, which shares the same ARENA, so it can outlive the map
+/// it is created from, and be sent to other threads. Only the writes at versions higher than the
+/// version of the snapshot do not affect it: inserts, upserts and removals at or below it,
+/// [`SkipMap::rewrite_values`] and [`SkipMap::clear_shared`] change what it sees, and so would
+/// [`SkipMap::update_in_place`] and [`SkipMap::purge`], which fail while the snapshot shares the map.
+pub struct Snapshot<T, C> {
+  map: SkipMap<T, C>,
+  version: u64,
+}
+
+impl<T: Trailer, C: Comparator> core::fmt::Debug for Snapshot<T, C> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Snapshot")
+      .field("version", &self.version)
+      .field("map", &self.map)
+      .finish()
+  }
+}
+
+impl<T, C: Clone> Clone for Snapshot<T, C> {
+  #[inline]
+  fn clone(&self) -> Self {
+    Self {
+      map: self.map.clone(),
+      version: self.version,
+    }
+  }
+}
+
+impl<T, C> Snapshot<T, C> {
+  #[inline]
+  pub(super) const fn new(map: SkipMap<T, C>, version: u64) -> Self {
+    Self { map, version }
+  }
+
+  /// Returns the version of the snapshot.
+  #[inline]
+  pub const fn version(&self) -> u64 {
+    self.version
+  }
+
+  /// Returns the map of the snapshot.
+  #[inline]
+  pub const fn map(&self) -> &SkipMap<T, C> {
+    &self.map
+  }
+}
+
+impl<T: Trailer, C: Comparator> Snapshot<T, C> {
+  /// Returns the value associated with the given key at the version of the snapshot, if it exists,
+  /// see [`SkipMap::get`].
+  #[inline]
+  pub fn get<'a, 'b: 'a>(&'a self, key: &'b [u8]) -> Option<EntryRef<'a, T, C>> {
+    self.map.get(self.version, key)
+  }
+
+  /// Returns `true` if the key exists at the version of the snapshot, see [`SkipMap::contains_key`].
+  #[inline]
+  pub fn contains_key<'a, 'b: 'a>(&'a self, key: &'b [u8]) -> bool {
+    self.map.contains_key(self.version, key)
+  }
+
+  /// Returns an `EntryRef` pointing to the highest element whose key is below the given bound,
+  /// see [`SkipMap::upper_bound`].
+  #[inline]
+  pub fn upper_bound<'a, 'b: 'a>(&'a self, upper: Bound<&'b [u8]>) -> Option<EntryRef<'a, T, C>> {
+    self.map.upper_bound(self.version, upper)
+  }
+
+  /// Returns an `EntryRef` pointing to the lowest element whose key is above the given bound,
+  /// see [`SkipMap::lower_bound`].
+  #[inline]
+  pub fn lower_bound<'a, 'b: 'a>(&'a self, lower: Bound<&'b [u8]>) -> Option<EntryRef<'a, T, C>> {
+    self.map.lower_bound(self.version, lower)
+  }
+
+  /// Returns a new iterator over the entries visible at the version of the snapshot,
+  /// see [`SkipMap::iter`].
+  #[inline]
+  pub const fn iter(&self) -> iterator::Iter<T, C> {
+    self.map.iter(self.version)
+  }
+
+  /// Returns a iterator over the entries within the range visible at the version of the snapshot,
+  /// see [`SkipMap::range`].
+  #[inline]
+  pub fn range<'a, Q, R>(&'a self, range: R) -> iterator::Iter<'a, T, C, Q, R>
+  where
+    &'a [u8]: PartialOrd<Q>,
+    Q: ?Sized + PartialOrd<&'a [u8]>,
+    R: RangeBounds<Q> + 'a,
+  {
+    self.map.range(self.version, range)
+  }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use crate::Descend;
+use crate::{CompareError, Descend, Fallible, PrefixComparator, TryComparator};
 
 use std::format;
 
@@ -27,6 +27,28 @@ fn run(f: impl Fn() + Send + Sync + 'static) {
   f();
 }
 
+// The map is shared across threads by reference, the entries and the iterators borrowing it
+// must be able to cross the threads as well.
+const _: fn() = || {
+  fn assert_send<T: Send>() {}
+  fn assert_sync<T: Sync>() {}
+
+  assert_send::<SkipMap<u64, Ascend>>();
+  assert_sync::<SkipMap<u64, Ascend>>();
+  assert_send::<SkipMap<u64, Descend>>();
+  assert_sync::<SkipMap<u64, Descend>>();
+  assert_send::<EntryRef<'static, u64, Ascend>>();
+  assert_sync::<EntryRef<'static, u64, Ascend>>();
+  assert_send::<VersionedEntryRef<'static, u64, Ascend>>();
+  assert_sync::<VersionedEntryRef<'static, u64, Ascend>>();
+  assert_send::<AllVersionsIter<'static, u64, Ascend>>();
+  assert_sync::<AllVersionsIter<'static, u64, Ascend>>();
+  assert_send::<Iter<'static, u64, Ascend>>();
+  assert_sync::<Iter<'static, u64, Ascend>>();
+  assert_send::<MergeIterator<'static, u64, Ascend>>();
+  assert_sync::<MergeIterator<'static, u64, Ascend>>();
+};
+
 /// Only used for testing
 
 pub fn key(i: usize) -> std::vec::Vec<u8> {
@@ -337,9 +359,7 @@ fn basic_in(mut l: SkipMap) {
     assert_eq!(arr, [a2, a1, b2, b1]);
   }
 
-  unsafe {
-    l.clear().unwrap();
-  }
+  l.clear().unwrap();
 
   let l = l.clone();
   {
@@ -400,6 +420,193 @@ fn test_basic_map_anon_unify() {
   })
 }
 
+/// Counts the comparisons of the keys.
+#[derive(Debug, Default, Clone)]
+struct CountingComparator(Arc<core::sync::atomic::AtomicUsize>);
+
+impl Comparator for CountingComparator {
+  fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+    self.0.fetch_add(1, Ordering::Relaxed);
+    a.cmp(b)
+  }
+
+  fn contains<'a, Q>(&self, range: &impl RangeBounds<Q>, key: &'a [u8]) -> bool
+  where
+    &'a [u8]: PartialOrd<Q>,
+    Q: ?Sized + PartialOrd<&'a [u8]>,
+  {
+    range.contains(&key)
+  }
+}
+
+fn search_complexity(opts: Options) {
+  const N: usize = 1000;
+
+  let cmp = CountingComparator::default();
+  let l = SkipMap::<u64, _>::with_options_and_comparator(opts, cmp.clone()).unwrap();
+  for i in 0..N {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+
+  // The search of every level continues from where the upper level stopped, so an insert
+  // compares O(log N) keys, restarting every level from the head compares O(N) keys.
+  let comparisons = cmp.0.load(Ordering::Relaxed);
+  assert!(comparisons < N * 100, "comparisons {comparisons}");
+}
+
+#[test]
+fn test_search_complexity() {
+  run(|| search_complexity(TEST_OPTIONS));
+}
+
+#[test]
+fn test_search_complexity_unify() {
+  run(|| search_complexity(UNIFY_TEST_OPTIONS));
+}
+
+fn tombstone_hides_older_versions(l: SkipMap) {
+  for k in [b"a", b"b", b"c"] {
+    l.get_or_insert(1, k, k).unwrap();
+  }
+  l.get_or_remove(2, b"a").unwrap();
+  l.get_or_remove(2, b"b").unwrap();
+
+  let keys = |version| {
+    l.iter(version)
+      .map(|ent| ent.key().to_vec())
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(keys(1), [b"a", b"b", b"c"]);
+  // The versions below the tombstones must not be yielded.
+  assert_eq!(keys(2), [b"c"]);
+  assert_eq!(l.first(2).unwrap().key(), b"c");
+  assert_eq!(l.iter_all_versions(2).count(), 5);
+}
+
+#[test]
+fn test_tombstone_hides_older_versions() {
+  run(|| tombstone_hides_older_versions(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_tombstone_hides_older_versions_unify() {
+  run(|| tombstone_hides_older_versions(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_tombstone_hides_older_versions_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    tombstone_hides_older_versions(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+/// Inserts the entry like `get_or_insert`, but the search starts from the splice cached by `ins`,
+/// returns the value of the existing entry.
+fn get_or_insert_reusing<'a>(
+  l: &'a SkipMap,
+  ins: &mut Inserter<u64>,
+  version: u64,
+  key: &'a [u8],
+  value: &[u8],
+) -> Option<Vec<u8>> {
+  let copy = |buf: &mut VacantBuffer| {
+    let _ = buf.write(value);
+    Ok::<_, Infallible>(())
+  };
+  l.update(
+    version,
+    Key::Occupied(key),
+    value.len() as u32,
+    copy,
+    Ordering::Relaxed,
+    Ordering::Relaxed,
+    ins,
+    false,
+  )
+  .unwrap()
+  .expect_left("insert must get InsertOk")
+  .map(|old| old.value().unwrap().to_vec())
+}
+
+fn reused_splice(l: SkipMap) {
+  const N: usize = 100;
+
+  let keys = (0..N * 3).map(key).collect::<Vec<_>>();
+  let values = (0..N * 4).map(new_value).collect::<Vec<_>>();
+
+  // The inserts share one inserter, so each search starts from the splice cached by the previous one.
+  let mut ins = Inserter::default();
+
+  // `keys[3]` lies after the next node of the splice cached by `keys[1]`.
+  for i in [2, 0, 1, 3] {
+    assert!(get_or_insert_reusing(&l, &mut ins, 0, &keys[i], &values[i]).is_none());
+  }
+  l.validate().unwrap();
+  assert_eq!(l.len(), 4);
+
+  // `keys[11]` is the next node of the splice cached by `keys[10]`, so it must be found.
+  let mut ins = Inserter::default();
+  for i in [11, 10] {
+    get_or_insert_reusing(&l, &mut ins, 0, &keys[i], &values[i]);
+  }
+  let old = get_or_insert_reusing(&l, &mut ins, 0, &keys[11], &values[0]);
+  assert_eq!(old.unwrap(), values[11]);
+  assert_eq!(l.len(), 6);
+
+  // An existing entry found at an upper level returns before the lower levels are searched,
+  // so the next insert must not reuse the splice cached below that level.
+  let mut ins = Inserter::default();
+  for i in 0..N {
+    assert!(get_or_insert_reusing(&l, &mut ins, 0, &keys[N * 2 + i], &values[i]).is_none());
+    let old = get_or_insert_reusing(&l, &mut ins, 0, &keys[N * 2 + i / 2], &values[0]);
+    assert_eq!(old.unwrap(), values[i / 2]);
+  }
+  l.validate().unwrap();
+  assert_eq!(l.len(), 6 + N);
+
+  // Scrambled keys and versions, every pair is inserted several times.
+  let mut ins = Inserter::default();
+  let mut first = std::collections::HashMap::new();
+  for i in 0..N * 4 {
+    let k = N + i * 37 % N;
+    let version = (i % 3) as u64;
+    let old = get_or_insert_reusing(&l, &mut ins, version, &keys[k], &values[i]);
+    match first.get(&(k, version)) {
+      Some(&j) => assert_eq!(old.unwrap(), values[j]),
+      None => {
+        assert!(old.is_none());
+        first.insert((k, version), i);
+      }
+    }
+  }
+  l.validate().unwrap();
+  assert_eq!(l.len(), 6 + N + first.len());
+  for (&(k, version), &i) in first.iter() {
+    assert_eq!(l.get(version, &keys[k]).unwrap().value(), values[i]);
+  }
+}
+
+#[test]
+fn test_reused_splice() {
+  run(|| reused_splice(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_reused_splice_unify() {
+  run(|| reused_splice(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_reused_splice_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    reused_splice(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
 fn iter_all_versions_mvcc(l: SkipMap) {
   l.get_or_insert(1, b"a", b"a1").unwrap();
   l.get_or_insert(3, b"a", b"a2").unwrap();
@@ -540,2506 +747,8492 @@ fn test_iter_all_versions_mvcc_map_anon_unify() {
   })
 }
 
-fn ordering() {
-  let l = SkipMap::with_options_and_comparator(TEST_OPTIONS, Descend).unwrap();
-
-  l.get_or_insert(1, b"a1", b"a1").unwrap();
-  l.get_or_insert(2, b"a2", b"a2").unwrap();
-  l.get_or_insert(3, b"a3", b"a3").unwrap();
-
-  let mut it = l.iter_all_versions(3);
-  for i in (1..=3).rev() {
-    let ent = it.next().unwrap();
-    assert_eq!(ent.key(), format!("a{i}").as_bytes());
-    assert_eq!(ent.value().unwrap(), format!("a{i}").as_bytes());
-  }
-}
-
-#[test]
-fn test_ordering() {
-  run(ordering);
-}
-
-fn get_mvcc(l: SkipMap) {
+fn range_all_versions_mvcc(l: SkipMap) {
   l.get_or_insert(1, b"a", b"a1").unwrap();
   l.get_or_insert(3, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"b", b"b1").unwrap();
+  l.get_or_insert(2, b"b", b"b2").unwrap();
   l.get_or_insert(1, b"c", b"c1").unwrap();
   l.get_or_insert(3, b"c", b"c2").unwrap();
 
-  let ent = l.get(1, b"a").unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  // Formats the entries as `key@version=value`.
+  fn collect<'a>(
+    it: impl Iterator<Item = VersionedEntryRef<'a, u64, Ascend>>,
+  ) -> std::vec::Vec<std::string::String> {
+    it.map(|ent| {
+      format!(
+        "{}@{}={}",
+        core::str::from_utf8(ent.key()).unwrap(),
+        ent.trailer().version(),
+        core::str::from_utf8(ent.value().unwrap()).unwrap()
+      )
+    })
+    .collect()
+  }
 
-  let ent = l.get(2, b"a").unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  let range = b"a".as_slice()..=b"b".as_slice();
+  assert!(collect(l.range_all_versions(0, range.clone())).is_empty());
+  assert_eq!(
+    collect(l.range_all_versions(1, range.clone())),
+    ["a@1=a1", "b@1=b1"]
+  );
+  assert_eq!(
+    collect(l.range_all_versions(2, range.clone())),
+    ["a@1=a1", "b@2=b2", "b@1=b1"]
+  );
+  assert_eq!(
+    collect(l.range_all_versions(3, range)),
+    ["a@3=a2", "a@1=a1", "b@2=b2", "b@1=b1"]
+  );
+
+  // A bound in the middle of the keys still yields all versions of the boundary keys.
+  let range = (
+    Bound::Excluded(b"a".as_slice()),
+    Bound::Included(b"b".as_slice()),
+  );
+  assert_eq!(
+    collect(l.range_all_versions(3, range)),
+    ["b@2=b2", "b@1=b1"]
+  );
+  assert_eq!(
+    collect(l.range_all_versions(3, b"b".as_slice()..)),
+    ["b@2=b2", "b@1=b1", "c@3=c2", "c@1=c1"]
+  );
+
+  let mut it = l.range_all_versions(3, b"a".as_slice()..=b"b".as_slice());
+  let ent = it.seek_lower_bound(Bound::Excluded(b"a")).unwrap();
+  assert_eq!(ent.key(), b"b");
+  assert_eq!(ent.trailer().version(), 2);
+  let ent = it.seek_upper_bound(Bound::Unbounded).unwrap();
+  assert_eq!(ent.key(), b"b");
+  assert!(it.seek_lower_bound(Bound::Excluded(b"b")).is_none());
+}
 
-  let ent = l.get(3, b"a").unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_range_all_versions_mvcc() {
+  run(|| range_all_versions_mvcc(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
 
-  let ent = l.get(4, b"a").unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_range_all_versions_mvcc_unify() {
+  run(|| range_all_versions_mvcc(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
 
-  assert!(l.get(0, b"b").is_none());
-  assert!(l.get(1, b"b").is_none());
-  assert!(l.get(2, b"b").is_none());
-  assert!(l.get(3, b"b").is_none());
-  assert!(l.get(4, b"b").is_none());
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_all_versions_mvcc_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    range_all_versions_mvcc(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  let ent = l.get(1, b"c").unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+fn first_last_key(l: SkipMap) {
+  assert!(l.first_key(0).is_none());
+  assert!(l.last_key(0).is_none());
 
-  let ent = l.get(2, b"c").unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  l.get_or_insert(1, b"b", b"b1").unwrap();
+  l.get_or_insert(1, b"c", b"c1").unwrap();
+  l.get_or_remove(2, b"a").unwrap();
+  l.get_or_remove(2, b"d").unwrap();
+  l.get_or_insert(3, b"a", b"a3").unwrap();
 
-  let ent = l.get(3, b"c").unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+  for version in 0..=4 {
+    assert_eq!(
+      l.first_key(version),
+      l.first(version).as_ref().map(|e| e.key()),
+      "version {version}"
+    );
+    assert_eq!(
+      l.last_key(version),
+      l.last(version).as_ref().map(|e| e.key()),
+      "version {version}"
+    );
+  }
 
-  let ent = l.get(4, b"c").unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+  assert!(l.first_key(0).is_none());
+  assert_eq!(l.first_key(1), Some(b"b".as_slice()));
+  assert_eq!(l.last_key(1), Some(b"c".as_slice()));
+  // The tombstoned boundary keys are skipped.
+  assert_eq!(l.first_key(2), Some(b"b".as_slice()));
+  assert_eq!(l.last_key(2), Some(b"c".as_slice()));
+  assert_eq!(l.first_key(3), Some(b"a".as_slice()));
+  assert_eq!(l.last_key(3), Some(b"c".as_slice()));
 
-  assert!(l.get(5, b"d").is_none());
+  // A tombstone hides the older versions of its key.
+  l.get_or_insert(4, b"0", b"04").unwrap();
+  l.get_or_remove(5, b"0").unwrap();
+  assert_eq!(l.first_key(4), Some(b"0".as_slice()));
+  assert_eq!(l.first_key(5), Some(b"a".as_slice()));
+  assert_eq!(l.first(5).unwrap().key(), b"a");
 }
 
 #[test]
-fn test_get_mvcc() {
-  run(|| get_mvcc(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+fn test_first_last_key() {
+  run(|| first_last_key(SkipMap::with_options(TEST_OPTIONS).unwrap()));
 }
 
 #[test]
-fn test_get_mvcc_unify() {
-  run(|| get_mvcc(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+fn test_first_last_key_unify() {
+  run(|| first_last_key(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_get_mvcc_map_mut() {
+fn test_first_last_key_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_get_mvcc_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    get_mvcc(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    first_last_key(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+/// A trailer which packs a 56-bit version and 8 bits of flags into one word.
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+struct VersionedFlags(u64);
+
+impl VersionedFlags {
+  const VERSION_MASK: u64 = (1 << 56) - 1;
+
+  fn new(version: u64, flags: u8) -> Self {
+    Self(version | ((flags as u64) << 56))
+  }
+}
+
+unsafe impl Trailer for VersionedFlags {
+  fn version(&self) -> u64 {
+    self.0 & Self::VERSION_MASK
+  }
+}
+
+fn raw_trailer_bits(l: SkipMap<VersionedFlags>) {
+  l.get_or_insert(VersionedFlags::new(1, 0b101), b"a", b"a1")
+    .unwrap();
+  l.get_or_insert(VersionedFlags::new(3, 0xff), b"a", b"a3")
+    .unwrap();
+  l.get_or_remove(VersionedFlags::new(4, 0), b"b").unwrap();
+
+  let decode = |raw: &[u8]| {
+    let bits = u64::from_ne_bytes(raw.try_into().unwrap());
+    (bits & VersionedFlags::VERSION_MASK, (bits >> 56) as u8)
+  };
+
+  assert!(l.raw_trailer_bits(0, b"a").is_none());
+  assert_eq!(decode(l.raw_trailer_bits(2, b"a").unwrap()), (1, 0b101));
+  assert_eq!(decode(l.raw_trailer_bits(3, b"a").unwrap()), (3, 0xff));
+  assert!(l.raw_trailer_bits(4, b"b").is_none());
+  assert!(l.raw_trailer_bits(4, b"c").is_none());
+}
+
+#[test]
+fn test_raw_trailer_bits() {
+  run(|| raw_trailer_bits(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_raw_trailer_bits_unify() {
+  run(|| raw_trailer_bits(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_get_mvcc_map_anon() {
+fn test_raw_trailer_bits_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_mvcc(SkipMap::map_anon(map_options).unwrap());
+    raw_trailer_bits(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn extend(mut l: SkipMap) {
+  l.extend((0..1000u32).map(|i| (i as u64 % 3, key(i as usize), new_value(i as usize))));
+  assert_eq!(l.len(), 1000);
+
+  for i in [0usize, 1, 499, 998, 999] {
+    let k = key(i);
+    let ent = l.get(2, &k).unwrap();
+    assert_eq!(ent.value(), new_value(i));
+    assert_eq!(ent.trailer().version(), i as u64 % 3);
+  }
+
+  // The existing entries are updated.
+  l.try_extend([(0, key(0), make_value(0))]).unwrap();
+  assert_eq!(l.len(), 1000);
+  assert_eq!(l.get(0, &key(0)).unwrap().value(), make_value(0));
+}
+
+#[test]
+fn test_extend() {
+  run(|| extend(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_extend_unify() {
+  run(|| extend(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_get_mvcc_map_anon_unify() {
+fn test_extend_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_mvcc(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    extend(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn gt_in(l: SkipMap) {
-  l.get_or_insert(1, b"a", b"a1").unwrap();
-  l.get_or_insert(3, b"a", b"a2").unwrap();
-  l.get_or_insert(1, b"c", b"c1").unwrap();
-  l.get_or_insert(3, b"c", b"c2").unwrap();
-  l.get_or_insert(5, b"c", b"c3").unwrap();
-
-  assert!(l.lower_bound(0, Bound::Excluded(b"a")).is_none());
-  assert!(l.lower_bound(0, Bound::Excluded(b"b")).is_none());
-  assert!(l.lower_bound(0, Bound::Excluded(b"c")).is_none());
+#[test]
+fn test_from_iter_with_options() {
+  let l = SkipMap::from_iter_with_options(
+    TEST_OPTIONS,
+    (0..1000).map(|i| (0u64, key(i), new_value(i))),
+  )
+  .unwrap();
+  assert_eq!(l.len(), 1000);
+  assert_eq!(l.get(0, &key(42)).unwrap().value(), new_value(42));
 
-  let ent = l.lower_bound(1, Bound::Excluded(b"")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  let err = SkipMap::from_iter_with_options(
+    Options::new().with_capacity(1024),
+    (0..1000).map(|i| (0u64, key(i), new_value(i))),
+  )
+  .unwrap_err();
+  assert!(matches!(
+    err,
+    Error::Arena(ArenaError::InsufficientSpace { .. })
+  ));
+}
 
-  let ent = l.lower_bound(2, Bound::Excluded(b"")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+#[should_panic(expected = "failed to extend the skipmap")]
+fn test_extend_panics_on_insufficient_space() {
+  let mut l = SkipMap::with_options(Options::new().with_capacity(1024)).unwrap();
+  l.extend((0..1000).map(|i| (0u64, key(i), new_value(i))));
+}
+
+#[test]
+fn test_merge_iterator() {
+  let l1 = SkipMap::with_options(TEST_OPTIONS).unwrap();
+  let l2 = SkipMap::with_options(TEST_OPTIONS).unwrap();
+  let l3 = SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap();
+
+  l1.get_or_insert(1, b"a", b"a1").unwrap();
+  l1.get_or_insert(1, b"b", b"b1").unwrap();
+  l1.get_or_insert(1, b"c", b"c1").unwrap();
+  l1.get_or_insert(4, b"e", b"e4").unwrap();
+
+  l2.get_or_insert(2, b"a", b"a2").unwrap();
+  l2.get_or_remove(3, b"b").unwrap();
+  l2.get_or_insert(2, b"b", b"b2").unwrap();
+  l2.get_or_insert(2, b"d", b"d2").unwrap();
+
+  l3.get_or_insert(3, b"a", b"a3").unwrap();
+  l3.get_or_insert(1, b"c", b"c1'").unwrap();
+  l3.get_or_insert(1, b"d", b"d1").unwrap();
+  l3.get_or_remove(5, b"d").unwrap();
+
+  fn collect(iter: MergeIterator<'_, u64, Ascend>) -> std::vec::Vec<std::string::String> {
+    iter
+      .map(|ent| {
+        format!(
+          "{}@{}={}",
+          core::str::from_utf8(ent.key()).unwrap(),
+          ent.trailer().version(),
+          ent
+            .value()
+            .map(|v| core::str::from_utf8(v).unwrap())
+            .unwrap_or("-")
+        )
+      })
+      .collect()
+  }
 
-  let ent = l.lower_bound(3, Bound::Excluded(b"")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+  let maps = [&l1, &l2, &l3];
+  assert!(collect(MergeIterator::new(&maps, 0, true)).is_empty());
+  assert_eq!(
+    collect(MergeIterator::new(&maps, 1, false)),
+    ["a@1=a1", "b@1=b1", "c@1=c1", "d@1=d1"]
+  );
+  assert_eq!(
+    collect(MergeIterator::new(&maps, 2, false)),
+    ["a@2=a2", "b@2=b2", "c@1=c1", "d@2=d2"]
+  );
+  assert_eq!(
+    collect(MergeIterator::new(&maps, 3, false)),
+    ["a@3=a3", "c@1=c1", "d@2=d2"]
+  );
+  assert_eq!(
+    collect(MergeIterator::new(&maps, 3, true)),
+    ["a@3=a3", "b@3=-", "c@1=c1", "d@2=d2"]
+  );
+  assert_eq!(
+    collect(MergeIterator::new(&maps, 5, false)),
+    ["a@3=a3", "c@1=c1", "e@4=e4"]
+  );
+  assert_eq!(
+    collect(MergeIterator::new(&maps, 5, true)),
+    ["a@3=a3", "b@3=-", "c@1=c1", "d@5=-", "e@4=e4"]
+  );
+
+  // The same version of a key in several maps resolves to the first map.
+  assert_eq!(
+    collect(MergeIterator::new(&[&l3, &l1], 1, false)),
+    ["a@1=a1", "b@1=b1", "c@1=c1'", "d@1=d1"]
+  );
+  assert!(collect(MergeIterator::new(&[], 5, true)).is_empty());
+}
+
+fn value_index(l: SkipMap) {
+  // The value is a big endian age followed by a name.
+  for (i, age) in [30u32, 25, 30, 40, 25, 30].into_iter().enumerate() {
+    let mut value = age.to_be_bytes().to_vec();
+    value.extend_from_slice(format!("person{i}").as_bytes());
+    l.get_or_insert(1, &key(i), &value).unwrap();
+  }
+  l.get_or_insert(2, &key(6), &30u32.to_be_bytes()).unwrap();
 
-  let ent = l.lower_bound(1, Bound::Excluded(b"a")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  let age = |v: &[u8]| u32::from_be_bytes(v[..4].try_into().unwrap()) as u64;
+  let index = l.build_value_index(1, age);
+  assert_eq!(index.len(), 3);
 
-  let ent = l.lower_bound(2, Bound::Excluded(b"a")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  let lookup = |age| {
+    index
+      .lookup(age)
+      .map(|ent| ent.key().to_vec())
+      .collect::<std::vec::Vec<_>>()
+  };
+  assert_eq!(lookup(30), [key(0), key(2), key(5)]);
+  assert_eq!(lookup(25), [key(1), key(4)]);
+  assert_eq!(lookup(40), [key(3)]);
+  assert!(lookup(50).is_empty());
 
-  let ent = l.lower_bound(3, Bound::Excluded(b"a")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+  // The entries visible at a newer version are not in the index until it is rebuilt.
+  let index = l.build_value_index(2, age);
+  let keys: std::vec::Vec<_> = index.lookup(30).map(|ent| ent.key().to_vec()).collect();
+  assert_eq!(keys, [key(0), key(2), key(5), key(6)]);
+  let ent = index.lookup(40).next().unwrap();
+  assert_eq!(&ent.value()[4..], b"person3");
+}
 
-  let ent = l.lower_bound(1, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+fn test_value_index() {
+  run(|| value_index(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
 
-  let ent = l.lower_bound(2, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+fn test_value_index_unify() {
+  run(|| value_index(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
 
-  let ent = l.lower_bound(3, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+#[cfg(feature = "memmap")]
+fn test_value_index_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    value_index(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  let ent = l.lower_bound(4, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+fn range_count(l: SkipMap) {
+  const N: usize = 10000;
 
-  let ent = l.lower_bound(5, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c3");
-  assert_eq!(ent.trailer().version(), 5);
+  for i in 0..N {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  // Newer versions do not change the number of keys, a tombstone hides its key.
+  for i in (0..N).step_by(10) {
+    l.get_or_insert(1, &key(i), &make_value(i)).unwrap();
+  }
+  for i in (5..N).step_by(100) {
+    l.get_or_remove(2, &key(i)).unwrap();
+  }
 
-  let ent = l.lower_bound(6, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c3");
-  assert_eq!(ent.trailer().version(), 5);
+  assert_eq!(l.len_at(0), N);
+  assert_eq!(l.len_at(1), N);
+  assert_eq!(l.len_at(2), N - N / 100);
+  assert_eq!(l.len_at(2), l.iter(2).count());
+
+  let (k100, k2500, k9999) = (key(100), key(2500), key(9999));
+  let bounds = [
+    (Bound::Unbounded, Bound::Unbounded),
+    (
+      Bound::Included(k100.as_slice()),
+      Bound::Excluded(k2500.as_slice()),
+    ),
+    (
+      Bound::Excluded(k100.as_slice()),
+      Bound::Included(k2500.as_slice()),
+    ),
+    (Bound::Included(k2500.as_slice()), Bound::Unbounded),
+    (Bound::Unbounded, Bound::Included(k9999.as_slice())),
+    (
+      Bound::Included(k2500.as_slice()),
+      Bound::Included(k2500.as_slice()),
+    ),
+    (Bound::Excluded(k9999.as_slice()), Bound::Unbounded),
+  ];
+  for version in 0..=2 {
+    for range in bounds {
+      assert_eq!(
+        l.range_count(version, range),
+        l.range(version, range).count(),
+        "version {version}, range {range:?}"
+      );
+    }
+  }
 
-  assert!(l.lower_bound(1, Bound::Excluded(b"c")).is_none());
-  assert!(l.lower_bound(2, Bound::Excluded(b"c")).is_none());
-  assert!(l.lower_bound(3, Bound::Excluded(b"c")).is_none());
-  assert!(l.lower_bound(4, Bound::Excluded(b"c")).is_none());
-  assert!(l.lower_bound(5, Bound::Excluded(b"c")).is_none());
-  assert!(l.lower_bound(6, Bound::Excluded(b"c")).is_none());
+  assert_eq!(l.range_count(0, k100.as_slice()..k2500.as_slice()), 2400);
+  assert_eq!(l.range_count(2, k100.as_slice()..k2500.as_slice()), 2376);
 }
 
 #[test]
-fn test_gt() {
-  run(|| gt_in(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+#[cfg(feature = "std")]
+fn test_range_count() {
+  run(|| range_count(SkipMap::with_options(BIG_TEST_OPTIONS).unwrap()));
 }
 
 #[test]
-fn test_gt_unify() {
-  run(|| gt_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+#[cfg(feature = "std")]
+fn test_range_count_unify() {
+  run(|| range_count(SkipMap::with_options(UNIFY_BIG_TEST_OPTIONS).unwrap()));
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_gt_map_mut() {
+#[cfg(all(feature = "memmap", feature = "std"))]
+fn test_range_count_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_gt_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    gt_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(BIG_ARENA_SIZE as u32);
+    range_count(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn compact(l: SkipMap) {
+  for version in 0..10 {
+    for i in 0..100 {
+      l.get_or_insert(version, &key(i), &make_value(i * 10 + version as usize))
+        .unwrap();
+    }
+  }
+  for i in (0..100).step_by(10) {
+    l.get_or_remove(10, &key(i)).unwrap();
+  }
+  assert_eq!(l.len(), 1010);
+
+  let compacted = l.compact(9, TEST_OPTIONS).unwrap();
+  assert_eq!(compacted.len(), 100);
+  assert!(compacted.allocated() < l.allocated());
+  for i in 0..100 {
+    let k = key(i);
+    let ent = compacted.get(9, &k).unwrap();
+    assert_eq!(ent.value(), make_value(i * 10 + 9));
+    assert_eq!(ent.trailer().version(), 9);
+  }
+
+  // The tombstones visible at the version are dropped.
+  let compacted = l.compact(10, TEST_OPTIONS).unwrap();
+  assert_eq!(compacted.len(), 90);
+  assert!(compacted.get(10, &key(10)).is_none());
+  assert_eq!(
+    compacted.get(10, &key(11)).unwrap().value(),
+    make_value(119)
+  );
+}
+
+#[test]
+fn test_compact() {
+  run(|| compact(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_compact_unify() {
+  run(|| compact(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_gt_map_anon() {
+fn test_compact_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    gt_in(SkipMap::map_anon(map_options).unwrap());
+    compact(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_gt_map_anon_unify() {
+#[cfg_attr(miri, ignore)]
+fn test_compact_to_file() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    gt_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("compact_skipmap");
+
+    let l = SkipMap::with_options(TEST_OPTIONS).unwrap();
+    for version in 0..3 {
+      for i in 0..100 {
+        l.get_or_insert(version, &key(i), &new_value(i)).unwrap();
+      }
+    }
+
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let compacted = l
+        .compact_to_file(2, &p, Options::new(), open_options, map_options)
+        .unwrap();
+      assert_eq!(compacted.len(), 100);
+      compacted.flush().unwrap();
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let compacted = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    assert_eq!(compacted.len(), 100);
+    for i in 0..100 {
+      let k = key(i);
+      let ent = compacted.get(2, &k).unwrap();
+      assert_eq!(ent.value(), new_value(i));
+      assert_eq!(ent.trailer().version(), 2);
+    }
   })
 }
 
-fn ge_in(l: SkipMap) {
-  l.get_or_insert(1, b"a", b"a1").unwrap();
-  l.get_or_insert(3, b"a", b"a2").unwrap();
-  l.get_or_insert(1, b"c", b"c1").unwrap();
-  l.get_or_insert(3, b"c", b"c2").unwrap();
-
-  assert!(l.lower_bound(0, Bound::Included(b"a")).is_none());
-  assert!(l.lower_bound(0, Bound::Included(b"b")).is_none());
-  assert!(l.lower_bound(0, Bound::Included(b"c")).is_none());
+fn read_value_uninit(l: SkipMap) {
+  use core::mem::MaybeUninit;
 
-  let ent = l.lower_bound(1, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  let value = make_value(42);
+  l.get_or_insert(2, b"a", &value).unwrap();
 
-  let ent = l.lower_bound(2, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  let mut buf = std::vec::Vec::<MaybeUninit<u8>>::with_capacity(64);
+  // Safety: MaybeUninit does not require initialization.
+  unsafe { buf.set_len(64) };
 
-  let ent = l.lower_bound(3, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+  assert!(l.read_value_uninit(0, b"a", &mut buf).is_none());
+  assert!(l.read_value_uninit(2, b"b", &mut buf).is_none());
+  assert_eq!(l.read_value_uninit(1, b"a", &mut buf).unwrap(), b"a1");
+  assert_eq!(l.read_value_uninit(2, b"a", &mut buf).unwrap(), value);
 
-  let ent = l.lower_bound(4, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+  // A short buffer gets the prefix of the value.
+  let mut short = [MaybeUninit::<u8>::uninit(); 3];
+  assert_eq!(
+    l.read_value_uninit(2, b"a", &mut short).unwrap(),
+    &value[..3]
+  );
+}
 
-  let ent = l.lower_bound(1, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+fn test_read_value_uninit() {
+  run(|| read_value_uninit(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
 
-  let ent = l.lower_bound(2, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+fn test_read_value_uninit_unify() {
+  run(|| read_value_uninit(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
 
-  let ent = l.lower_bound(3, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+#[cfg(feature = "memmap")]
+fn test_read_value_uninit_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    read_value_uninit(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  let ent = l.lower_bound(4, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+fn iter_fused(l: SkipMap) {
+  fn assert_fused<I: core::iter::FusedIterator>(_: &I) {}
 
-  let ent = l.lower_bound(1, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  for i in 0..10 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
 
-  let ent = l.lower_bound(2, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  let mut it = l.iter(0);
+  assert_fused(&it);
+  assert_eq!(it.by_ref().count(), 10);
+  for _ in 0..3 {
+    assert!(it.next().is_none());
+  }
 
-  let ent = l.lower_bound(3, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+  let (k3, k6) = (key(3), key(6));
+  let mut it = l.range(0, k3.as_slice()..k6.as_slice());
+  assert_eq!(it.by_ref().count(), 3);
+  for _ in 0..3 {
+    assert!(it.next().is_none());
+  }
 
-  let ent = l.lower_bound(4, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+  let mut it = l.iter_all_versions(0);
+  assert_fused(&it);
+  assert_eq!(it.by_ref().count(), 10);
+  for _ in 0..3 {
+    assert!(it.next().is_none());
+  }
 
-  assert!(l.lower_bound(0, Bound::Included(b"d")).is_none());
-  assert!(l.lower_bound(1, Bound::Included(b"d")).is_none());
-  assert!(l.lower_bound(2, Bound::Included(b"d")).is_none());
-  assert!(l.lower_bound(3, Bound::Included(b"d")).is_none());
-  assert!(l.lower_bound(4, Bound::Included(b"d")).is_none());
+  let mut it = l.iter(0);
+  it.seek_upper_bound(Bound::Unbounded).unwrap();
+  assert_eq!(it.by_ref().rev().count(), 9);
+  for _ in 0..3 {
+    assert!(it.next_back().is_none());
+  }
 }
 
 #[test]
-fn test_ge() {
-  run(|| ge_in(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+fn test_iter_fused() {
+  run(|| iter_fused(SkipMap::with_options(TEST_OPTIONS).unwrap()));
 }
 
 #[test]
-fn test_ge_unify() {
-  run(|| ge_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+fn test_iter_fused_unify() {
+  run(|| iter_fused(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_ge_map_mut() {
+fn test_iter_fused_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_ge_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    ge_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_fused(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+#[cfg(feature = "stats")]
+fn height_promotions(l: SkipMap) {
+  assert_eq!(l.height(), 1);
+  assert_eq!(l.height_promotions(), 0);
+
+  let mut promotions = 0;
+  for i in 0..1000 {
+    let height = l.height();
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+    if l.height() > height {
+      promotions += 1;
+    }
+  }
+
+  assert!(l.height() > 1);
+  assert!(promotions > 0);
+  assert_eq!(l.height_promotions(), promotions);
+  // The clones share the counter.
+  assert_eq!(l.clone().height_promotions(), promotions);
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_ge_map_anon() {
+#[cfg(feature = "stats")]
+fn test_height_promotions() {
+  run(|| height_promotions(SkipMap::with_options(TEST_OPTIONS.with_height_seed(42)).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "stats")]
+fn test_height_promotions_unify() {
+  run(|| {
+    height_promotions(SkipMap::with_options(UNIFY_TEST_OPTIONS.with_height_seed(42)).unwrap())
+  });
+}
+
+#[test]
+#[cfg(all(feature = "stats", feature = "memmap"))]
+fn test_height_promotions_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    ge_in(SkipMap::map_anon(map_options).unwrap());
+    height_promotions(
+      SkipMap::map_anon_with_options(Options::new().with_height_seed(42), map_options).unwrap(),
+    );
   })
 }
 
+fn get_exact(l: SkipMap) {
+  let k = key(0);
+  l.insert(1, &k, &new_value(1)).unwrap();
+  l.insert(3, &k, &new_value(3)).unwrap();
+  l.get_or_remove(5, &k).unwrap();
+
+  assert!(l.get_exact(2, &k).is_none());
+  assert!(l.get_exact(4, &k).is_none());
+  assert!(l.get_exact(3, &key(1)).is_none());
+
+  let ent = l.get_exact(3, &k).unwrap();
+  assert_eq!(ent.key(), k);
+  assert_eq!(ent.trailer().version(), 3);
+  assert_eq!(ent.value().unwrap(), new_value(3));
+
+  let ent = l.get_exact(1, &k).unwrap();
+  assert_eq!(ent.value().unwrap(), new_value(1));
+
+  let ent = l.get_exact(5, &k).unwrap();
+  assert!(ent.is_removed());
+  assert_eq!(ent.trailer().version(), 5);
+}
+
+#[test]
+fn test_get_exact() {
+  run(|| get_exact(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_get_exact_unify() {
+  run(|| get_exact(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_ge_map_anon_unify() {
+fn test_get_exact_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    ge_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    get_exact(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn le_in(l: SkipMap) {
-  l.get_or_insert(1, b"a", b"a1").unwrap();
-  l.get_or_insert(3, b"a", b"a2").unwrap();
-  l.get_or_insert(1, b"c", b"c1").unwrap();
-  l.get_or_insert(3, b"c", b"c2").unwrap();
+fn reserve(l: SkipMap) {
+  l.get_or_insert(0, &key(0), &new_value(0)).unwrap();
+  let allocated = l.allocated();
 
-  assert!(l.upper_bound(0, Bound::Included(b"a")).is_none());
-  assert!(l.upper_bound(0, Bound::Included(b"b")).is_none());
-  assert!(l.upper_bound(0, Bound::Included(b"c")).is_none());
+  let mut r = l.reserve(100).unwrap();
+  assert_eq!(r.capacity(), 100);
+  r.copy_from_slice(&[1; 100]);
+  assert!(l.allocated() >= allocated + 100);
+  drop(r);
+  assert_eq!(l.allocated(), allocated);
 
-  let ent = l.upper_bound(1, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  // Nested reservations are rolled back in the LIFO order.
+  let outer = l.reserve(64).unwrap();
+  let inner = l.reserve(32).unwrap();
+  drop(inner);
+  drop(outer);
+  assert_eq!(l.allocated(), allocated);
 
-  let ent = l.upper_bound(2, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  // A committed reservation keeps the space.
+  let r = l.reserve(100).unwrap();
+  let offset = r.commit();
+  assert!(offset >= allocated);
+  assert!(l.allocated() >= allocated + 100);
 
-  let ent = l.upper_bound(3, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+  assert!(l.reserve(l.capacity()).is_none());
+}
 
-  let ent = l.upper_bound(4, Bound::Included(b"a")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_reserve() {
+  run(|| reserve(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
 
-  let ent = l.upper_bound(1, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+fn test_reserve_unify() {
+  run(|| reserve(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
 
-  let ent = l.upper_bound(2, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+#[cfg(feature = "memmap")]
+fn test_reserve_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    reserve(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  let ent = l.upper_bound(3, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+fn stats(l: SkipMap) {
+  let stats = l.stats();
+  assert_eq!(stats.nodes(), 0);
+  assert_eq!(stats.tombstones(), 0);
+  assert_eq!(stats.capacity(), l.capacity());
+  assert_eq!(stats.allocated() + stats.remaining(), stats.capacity());
 
-  let ent = l.upper_bound(4, Bound::Included(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+  for i in 0..10 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  // New tombstone nodes.
+  for i in 0..3 {
+    l.get_or_remove(1, &key(i)).unwrap();
+  }
+  // Removes the value of an existing node.
+  l.compare_remove(0, &key(5), Ordering::SeqCst, Ordering::Acquire)
+    .unwrap();
 
-  let ent = l.upper_bound(1, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  let stats = l.stats();
+  assert_eq!(stats.nodes(), 13);
+  assert_eq!(stats.nodes(), l.len());
+  assert_eq!(stats.tombstones(), 4);
+  assert_eq!(stats.height(), l.height());
+  assert_eq!(stats.allocated(), l.allocated());
+  assert_eq!(stats.remaining(), l.remaining());
 
-  let ent = l.upper_bound(2, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  // Writing a value to a removed node.
+  l.insert(1, &key(0), &new_value(0)).unwrap();
+  assert_eq!(l.stats().tombstones(), 3);
+  assert_eq!(l.stats().nodes(), 13);
+}
 
-  let ent = l.upper_bound(3, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_stats() {
+  run(|| stats(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
 
-  let ent = l.upper_bound(4, Bound::Included(b"c")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_stats_unify() {
+  run(|| stats(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
 
-  let ent = l.upper_bound(1, Bound::Included(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+#[cfg(feature = "memmap")]
+fn test_stats_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    stats(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  let ent = l.upper_bound(2, Bound::Included(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+fn unversioned(l: SkipMap<()>) {
+  for i in 0..10 {
+    assert!(l.insert1(&key(i), &new_value(i)).unwrap().is_none());
+  }
 
-  let ent = l.upper_bound(3, Bound::Included(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+  let k = key(3);
+  assert_eq!(l.get1(&k).unwrap().value(), new_value(3));
+  let old = l.insert1(&k, b"updated").unwrap().unwrap();
+  assert_eq!(old.value(), new_value(3));
+  assert_eq!(l.get1(&k).unwrap().value(), b"updated");
+  assert_eq!(l.len(), 10);
 
-  let ent = l.upper_bound(4, Bound::Included(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
-}
+  assert!(l.remove1(&k).unwrap().is_none());
+  assert!(l.get1(&k).is_none());
+  assert!(l.remove1(&key(100)).unwrap().is_none());
+  assert!(l.get1(&key(100)).is_none());
 
-#[test]
-fn test_le() {
-  run(|| le_in(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+  assert_eq!(l.iter1().count(), 9);
+  let keys: std::vec::Vec<_> = l
+    .range1(key(2).as_slice()..key(6).as_slice())
+    .map(|ent| ent.key().to_vec())
+    .collect();
+  assert_eq!(keys, [key(2), key(4), key(5)]);
 }
 
 #[test]
-fn test_le_unify() {
-  run(|| le_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+fn test_unversioned() {
+  run(|| unversioned(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_le_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_le_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    gt_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
-  })
+fn test_unversioned_unify() {
+  run(|| unversioned(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_le_map_anon() {
+fn test_unversioned_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    gt_in(SkipMap::map_anon(map_options).unwrap());
+    unversioned(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_le_map_anon_unify() {
+fn test_unversioned_entry_size() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    gt_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    let versioned = SkipMap::<u64>::with_options(TEST_OPTIONS.with_max_height(u5::new(1))).unwrap();
+    let unversioned =
+      SkipMap::<()>::with_options(TEST_OPTIONS.with_max_height(u5::new(1))).unwrap();
+
+    let before = versioned.allocated();
+    versioned.insert(0, &key(0), &new_value(0)).unwrap();
+    let versioned_size = versioned.allocated() - before;
+
+    let before = unversioned.allocated();
+    unversioned.insert1(&key(0), &new_value(0)).unwrap();
+    let unversioned_size = unversioned.allocated() - before;
+
+    assert!(unversioned_size < versioned_size);
   })
 }
 
-fn lt_in(l: SkipMap) {
-  l.get_or_insert(1, b"a", b"a1").unwrap();
-  l.get_or_insert(3, b"a", b"a2").unwrap();
-  l.get_or_insert(1, b"c", b"c1").unwrap();
-  l.get_or_insert(3, b"c", b"c2").unwrap();
+fn get_with_staleness(l: SkipMap) {
+  let k = key(1);
+  l.insert(1, &k, &new_value(1)).unwrap();
+  l.insert(5, &k, &new_value(5)).unwrap();
+  l.insert(3, &key(0), &new_value(0)).unwrap();
+  l.insert(3, &key(2), &new_value(2)).unwrap();
 
-  assert!(l.upper_bound(0, Bound::Excluded(b"a")).is_none());
-  assert!(l.upper_bound(0, Bound::Excluded(b"b")).is_none());
-  assert!(l.upper_bound(0, Bound::Excluded(b"c")).is_none());
-  assert!(l.upper_bound(1, Bound::Excluded(b"a")).is_none());
-  assert!(l.upper_bound(2, Bound::Excluded(b"a")).is_none());
+  assert!(l.get_with_staleness(0, &k).is_none());
+  assert!(l.get_with_staleness(10, &key(3)).is_none());
 
-  let ent = l.upper_bound(1, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
+  let (ent, staleness) = l.get_with_staleness(3, &k).unwrap();
+  assert_eq!(ent.value(), new_value(1));
   assert_eq!(ent.trailer().version(), 1);
+  assert_eq!(staleness, 4);
 
-  let ent = l.upper_bound(2, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+  let (ent, staleness) = l.get_with_staleness(10, &k).unwrap();
+  assert_eq!(ent.value(), new_value(5));
+  assert_eq!(staleness, 0);
 
-  let ent = l.upper_bound(3, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+  // A newer tombstone makes the older versions stale.
+  l.get_or_remove(7, &k).unwrap();
+  assert!(l.get_with_staleness(7, &k).is_none());
+  let (ent, staleness) = l.get_with_staleness(6, &k).unwrap();
+  assert_eq!(ent.value(), new_value(5));
+  assert_eq!(staleness, 2);
+}
 
-  let ent = l.upper_bound(4, Bound::Excluded(b"b")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_get_with_staleness() {
+  run(|| get_with_staleness(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
 
-  let ent = l.upper_bound(1, Bound::Excluded(b"c")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+fn test_get_with_staleness_unify() {
+  run(|| get_with_staleness(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
 
-  let ent = l.upper_bound(2, Bound::Excluded(b"c")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a1");
-  assert_eq!(ent.trailer().version(), 1);
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_with_staleness_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_with_staleness(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  let ent = l.upper_bound(3, Bound::Excluded(b"c")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+fn clear(mut l: SkipMap) {
+  for i in 0..100 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
 
-  let ent = l.upper_bound(4, Bound::Excluded(b"c")).unwrap();
-  assert_eq!(ent.key(), b"a");
-  assert_eq!(ent.value(), b"a2");
-  assert_eq!(ent.trailer().version(), 3);
+  let clone = l.clone();
+  assert_eq!(l.clear().unwrap_err(), Error::Shared);
+  assert_eq!(clone.len(), 100);
+  drop(clone);
 
-  let ent = l.upper_bound(1, Bound::Excluded(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  l.clear().unwrap();
+  assert!(l.is_empty());
+  assert!(l.iter(0).next().is_none());
+  assert!(l.get(0, &key(0)).is_none());
 
-  let ent = l.upper_bound(2, Bound::Excluded(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c1");
-  assert_eq!(ent.trailer().version(), 1);
+  l.get_or_insert(0, &key(0), &new_value(0)).unwrap();
+  assert_eq!(l.get(0, &key(0)).unwrap().value(), new_value(0));
+  assert_eq!(l.len(), 1);
+}
 
-  let ent = l.upper_bound(3, Bound::Excluded(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_clear() {
+  run(|| clear(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
 
-  let ent = l.upper_bound(4, Bound::Excluded(b"d")).unwrap();
-  assert_eq!(ent.key(), b"c");
-  assert_eq!(ent.value(), b"c2");
-  assert_eq!(ent.trailer().version(), 3);
+#[test]
+fn test_clear_unify() {
+  run(|| clear(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-fn test_lt() {
-  run(|| lt_in(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+#[cfg(feature = "memmap")]
+fn test_clear_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    clear(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn snapshot(l: SkipMap) {
+  for i in 0..10 {
+    l.get_or_insert(3, &key(i), &new_value(i)).unwrap();
+  }
+
+  let snapshot = l.snapshot(3);
+  assert_eq!(snapshot.version(), 3);
+
+  #[cfg(feature = "std")]
+  let snapshot = std::thread::spawn({
+    let l = l.clone();
+    move || {
+      for i in 0..10 {
+        l.get_or_insert(5, &key(i), &new_value(i + 100)).unwrap();
+      }
+      l.get_or_insert(5, &key(10), &new_value(10)).unwrap();
+      l.compare_remove(5, &key(0), Ordering::SeqCst, Ordering::Acquire)
+        .unwrap();
+      snapshot
+    }
+  })
+  .join()
+  .unwrap();
+
+  let k = key(1);
+  assert_eq!(snapshot.get(&k).unwrap().value(), new_value(1));
+  assert!(snapshot.contains_key(&key(0)));
+  assert!(!snapshot.contains_key(&key(10)));
+  assert_eq!(snapshot.iter().count(), 10);
+  for (i, ent) in snapshot.iter().enumerate() {
+    assert_eq!(ent.value(), new_value(i));
+    assert_eq!(ent.trailer().version(), 3);
+  }
+  assert_eq!(
+    snapshot.range(key(2).as_slice()..key(5).as_slice()).count(),
+    3
+  );
+  assert!(snapshot.lower_bound(Bound::Excluded(&key(9))).is_none());
+  let k = key(9);
+  let ent = snapshot.upper_bound(Bound::Included(&k)).unwrap();
+  assert_eq!(ent.key(), key(9));
+  assert_eq!(ent.value(), new_value(9));
+
+  #[cfg(feature = "std")]
+  {
+    let k = key(1);
+    assert_eq!(l.get(5, &k).unwrap().value(), new_value(101));
+    assert!(l.get(5, &key(0)).is_none());
+  }
 }
 
 #[test]
-fn test_lt_unify() {
-  run(|| lt_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_snapshot() {
+  run(|| snapshot(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_lt_map_mut() {
-  let dir = tempfile::tempdir().unwrap();
-  let p = dir.path().join("test_skipmap_lt_map_mut");
-  let open_options = OpenOptions::default()
-    .create_new(Some(ARENA_SIZE as u32))
-    .read(true)
-    .write(true);
-  let map_options = MmapOptions::default();
-  lt_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+fn test_snapshot_unify() {
+  run(|| snapshot(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-
-fn test_lt_map_anon() {
+fn test_snapshot_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    lt_in(SkipMap::map_anon(map_options).unwrap());
+    snapshot(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_lt_map_anon_unify() {
+fn test_comparator_matches_iteration_order() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    lt_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    let l = SkipMap::with_options_and_comparator(TEST_OPTIONS, Descend).unwrap();
+    let mut keys = std::vec::Vec::new();
+    for i in [3, 7, 1, 9, 5, 0] {
+      l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      keys.push(key(i));
+    }
+
+    let iter = l.iter(0);
+    let cmp = iter.comparator();
+    keys.sort_by(|a, b| cmp.compare(a, b));
+    let ordered: std::vec::Vec<_> = iter.map(|ent| ent.key().to_vec()).collect();
+    assert_eq!(keys, ordered);
+
+    keys.sort_by(|a, b| l.comparator().compare(b, a));
+    keys.sort_by(|a, b| l.iter_all_versions(0).comparator().compare(a, b));
+    assert_eq!(keys, ordered);
   })
 }
 
-fn test_basic_large_testcases_in(l: Arc<SkipMap>) {
-  let n = 1000;
+#[cfg(feature = "std")]
+fn as_io_slices(l: SkipMap) {
+  use std::io::Write;
 
-  for i in 0..n {
-    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
-  }
+  l.get_or_insert(0, b"hello", b"world").unwrap();
+  l.get_or_remove(1, b"hello").unwrap();
 
-  for i in 0..n {
-    let k = key(i);
-    let ent = l.get(0, &k).unwrap();
-    assert_eq!(new_value(i), ent.value());
-    assert_eq!(ent.trailer().version(), 0);
-    assert_eq!(ent.key(), k);
-  }
+  let ent = l.get(0, b"hello").unwrap();
+  let mut buf = std::vec::Vec::new();
+  // Writing to a Vec never writes partially.
+  let n = buf.write_vectored(&ent.as_io_slices()).unwrap();
+  assert_eq!(n, 10);
+  assert_eq!(buf, b"helloworld");
 
-  assert_eq!(n, l.len());
+  // A tombstone only has the key.
+  let ent = l.iter_all_versions(1).next().unwrap();
+  assert!(ent.is_removed());
+  let [key, value] = ent.as_io_slices();
+  assert_eq!(&*key, b"hello");
+  assert!(value.is_empty());
 }
 
 #[test]
-fn test_basic_large_testcases() {
-  run(|| {
-    let l = Arc::new(SkipMap::with_options(TEST_OPTIONS).unwrap());
-    test_basic_large_testcases_in(l);
-  })
+#[cfg(feature = "std")]
+fn test_as_io_slices() {
+  run(|| as_io_slices(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-fn test_basic_large_testcases_unify() {
+#[cfg(feature = "memmap")]
+fn test_as_io_slices_map_anon() {
   run(|| {
-    let l = Arc::new(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
-    test_basic_large_testcases_in(l);
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    as_io_slices(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn debug(l: SkipMap) {
+  for i in 0..3 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+
+  let out = format!("{:?}", l);
+  assert!(out.contains("len: 3"));
+  for i in 0..3 {
+    let k = key(i);
+    assert!(out.contains(&format!("{:?}", k.as_slice())));
+  }
+  assert!(!out.contains("..."));
+
+  for i in 3..20 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+
+  let out = format!("{:?}", l);
+  assert!(out.contains("..."));
+  let last = key(15);
+  assert!(out.contains(&format!("{:?}", last.as_slice())));
+  let hidden = key(16);
+  assert!(!out.contains(&format!("{:?}", hidden.as_slice())));
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_basic_large_testcases_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_basic_large_testcases_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    let l = Arc::new(SkipMap::map_mut(p, open_options, map_options).unwrap());
-    test_basic_large_testcases_in(l);
-  })
+fn test_debug() {
+  run(|| debug(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_basic_large_testcases_map_anon() {
+fn test_debug_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    let l = Arc::new(SkipMap::map_anon(map_options).unwrap());
-    test_basic_large_testcases_in(l);
+    debug(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn alloc_bytes(l: SkipMap) {
+  let k = key(0);
+  l.get_or_insert(0, &k, &new_value(0)).unwrap();
+
+  let blob = (0..=255u8).collect::<std::vec::Vec<_>>();
+  let mut buf = l.alloc_bytes(blob.len()).unwrap();
+  assert!(buf.iter().all(|b| *b == 0));
+  buf.copy_from_slice(&blob);
+  let offset = buf.offset();
+  drop(buf);
+
+  for i in 1..10 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+
+  // Safety: the bytes are allocated by `alloc_bytes`, and their handle is dropped.
+  unsafe {
+    assert_eq!(l.get_bytes(offset, blob.len()), blob.as_slice());
+    assert!(l.get_bytes(offset, 0).is_empty());
+  }
+  assert_eq!(l.get(0, &k).unwrap().value(), new_value(0));
+
+  let end = l.allocated() as u32;
+  assert!(
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+      l.get_bytes(end, 1);
+    }))
+    .is_err()
+  );
+
+  l.seal().unwrap();
+  assert_eq!(l.alloc_bytes(8).unwrap_err(), Error::Sealed);
+}
+
+#[test]
+fn test_alloc_bytes() {
+  run(|| alloc_bytes(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_basic_large_testcases_map_anon_unify() {
+fn test_alloc_bytes_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    let l = Arc::new(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
-    test_basic_large_testcases_in(l);
+    alloc_bytes(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-#[cfg(feature = "std")]
-fn test_concurrent_basic_runner(l: Arc<SkipMap>) {
-  #[cfg(not(any(miri, feature = "loom")))]
-  const N: usize = 1000;
-  #[cfg(any(miri, feature = "loom"))]
-  const N: usize = 5;
+fn split_ranges(l: SkipMap) {
+  assert_eq!(
+    l.split_ranges(0, 4),
+    std::vec![(Bound::Unbounded, Bound::Unbounded)]
+  );
 
-  let wg = Arc::new(());
-  for i in 0..N {
-    let w = wg.clone();
-    let l = l.clone();
-    std::thread::spawn(move || {
-      l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
-      drop(w);
-    });
+  for i in 0..1000 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
   }
-  while Arc::strong_count(&wg) > 1 {}
-  for i in 0..N {
-    let w = wg.clone();
-    let l = l.clone();
-    std::thread::spawn(move || {
-      let k = key(i);
-      assert_eq!(l.get(0, &k).unwrap().value(), new_value(i), "broken: {i}");
-      drop(w);
-    });
+
+  let all = l
+    .iter(0)
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  for n in [0, 1, 2, 4, 7, 16] {
+    let ranges = l.split_ranges(0, n);
+    assert!(!ranges.is_empty() && ranges.len() <= n.max(1));
+    assert_eq!(ranges.first().unwrap().0, Bound::Unbounded);
+    assert_eq!(ranges.last().unwrap().1, Bound::Unbounded);
+
+    let mut keys = std::vec::Vec::new();
+    for (start, end) in &ranges {
+      let start = start.as_ref().map(|k| k.as_slice());
+      let end = end.as_ref().map(|k| k.as_slice());
+      keys.extend(l.range(0, (start, end)).map(|ent| ent.key().to_vec()));
+    }
+    assert_eq!(keys, all);
   }
+
+  assert!(l.split_ranges(0, 4).len() > 1);
 }
 
 #[test]
-#[cfg(feature = "std")]
-fn test_concurrent_basic() {
-  run(|| {
-    let l = Arc::new(
-      SkipMap::with_options(TEST_OPTIONS)
-        .unwrap()
-        .with_yield_now(),
-    );
-    test_concurrent_basic_runner(l);
-  })
+fn test_split_ranges() {
+  run(|| split_ranges(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "std")]
-fn test_concurrent_basic_unify() {
+#[cfg(feature = "memmap")]
+fn test_split_ranges_map_anon() {
   run(|| {
-    let l = Arc::new(
-      SkipMap::with_options(UNIFY_TEST_OPTIONS)
-        .unwrap()
-        .with_yield_now(),
-    );
-    test_concurrent_basic_runner(l);
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    split_ranges(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn update_in_place(mut l: SkipMap) {
+  let k1 = key(1);
+  let k2 = key(2);
+  let k3 = key(3);
+  l.get_or_insert(1, &k1, &new_value(1)).unwrap();
+  l.get_or_insert(2, &k1, &new_value(2)).unwrap();
+  l.get_or_insert(1, &k2, b"abc").unwrap();
+  let allocated = l.allocated();
+
+  // Hit.
+  let replaced = new_value(99);
+  assert!(l
+    .update_in_place(1, &k1, |val| {
+      assert_eq!(val, new_value(1).as_slice());
+      val.copy_from_slice(&replaced);
+    })
+    .unwrap());
+  assert_eq!(l.get(1, &k1).unwrap().value(), replaced);
+  assert_eq!(l.get(2, &k1).unwrap().value(), new_value(2));
+  assert_eq!(l.allocated(), allocated);
+
+  // Missing key and missing version misses.
+  assert!(!l.update_in_place(1, &k3, |_| unreachable!()).unwrap());
+  assert!(!l.update_in_place(3, &k1, |_| unreachable!()).unwrap());
+
+  // Removed entry miss.
+  l.compare_remove(1, &k2, Ordering::SeqCst, Ordering::Acquire)
+    .unwrap();
+  assert!(!l.update_in_place(1, &k2, |_| unreachable!()).unwrap());
+
+  // A shared map is rejected.
+  let clone = l.clone();
+  assert_eq!(
+    l.update_in_place(1, &k1, |_| unreachable!()).unwrap_err(),
+    Error::Shared
+  );
+  drop(clone);
+  assert!(l.update_in_place(1, &k1, |_| {}).unwrap());
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_concurrent_basic_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_concurrent_basic_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    let l = Arc::new(
-      SkipMap::map_mut(p, open_options, map_options)
-        .unwrap()
-        .with_yield_now(),
-    );
-    test_concurrent_basic_runner(l);
-  })
+fn test_update_in_place() {
+  run(|| update_in_place(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_concurrent_basic_map_anon() {
+fn test_update_in_place_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    test_concurrent_basic_runner(Arc::new(
-      SkipMap::map_anon(map_options).unwrap().with_yield_now(),
-    ));
+    update_in_place(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn iter_by_offset(l: SkipMap) {
+  let order = [7, 2, 9, 0, 5, 3, 8, 1, 6, 4];
+  for i in order {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  l.get_or_insert(1, &key(9), &new_value(19)).unwrap();
+
+  let written = l
+    .iter_by_offset()
+    .map(|ent| (ent.key().to_vec(), ent.version()))
+    .collect::<std::vec::Vec<_>>();
+  let mut expected = order
+    .iter()
+    .map(|i| (key(*i), 0))
+    .collect::<std::vec::Vec<_>>();
+  expected.push((key(9), 1));
+  assert_eq!(written, expected);
+
+  let sorted = l
+    .iter(0)
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  assert_eq!(sorted, (0..10).map(key).collect::<std::vec::Vec<_>>());
+}
+
+#[test]
+fn test_iter_by_offset() {
+  run(|| iter_by_offset(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_concurrent_basic_map_anon_unify() {
+fn test_iter_by_offset_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    test_concurrent_basic_runner(Arc::new(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options)
-        .unwrap()
-        .with_yield_now(),
-    ));
+    iter_by_offset(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-#[cfg(feature = "std")]
-fn test_concurrent_basic_big_values_runner(l: Arc<SkipMap>) {
-  #[cfg(not(any(miri, feature = "loom")))]
-  const N: usize = 100;
-  #[cfg(any(miri, feature = "loom"))]
-  const N: usize = 5;
-
-  for i in 0..N {
-    let l = l.clone();
-    std::thread::spawn(move || {
-      l.get_or_insert(0, &key(i), &big_value(i)).unwrap();
-    });
+fn purge(mut l: SkipMap) {
+  let a = key(1);
+  for version in [1, 3, 5] {
+    l.get_or_insert(version, &a, &new_value(version as usize))
+      .unwrap();
   }
-  while Arc::strong_count(&l) > 1 {}
-  // assert_eq!(N, l.len());
-  for i in 0..N {
-    let l = l.clone();
-    std::thread::spawn(move || {
-      let k = key(i);
-      assert_eq!(l.get(0, &k).unwrap().value(), big_value(i), "broken: {i}");
-    });
+  for i in [0, 2] {
+    l.get_or_insert(2, &key(i), &new_value(i)).unwrap();
   }
-  while Arc::strong_count(&l) > 1 {}
+  l.get_or_remove(7, &key(2)).unwrap();
+  assert_eq!(l.len(), 6);
+
+  assert!(l.purge(&a).unwrap());
+  assert!(l.get(u64::MAX, &a).is_none());
+  for version in 0..=6 {
+    assert!(l.get(version, &a).is_none());
+    assert!(l.get_exact(version, &a).is_none());
+  }
+  assert_eq!(
+    l.iter_all_versions(u64::MAX)
+      .filter(|ent| ent.key() == a)
+      .count(),
+    0
+  );
+  assert_eq!(l.len(), 3);
+  assert!(!l.purge(&a).unwrap());
+
+  // The neighbours are intact at every level.
+  assert_eq!(l.get(2, &key(0)).unwrap().value(), new_value(0));
+  assert_eq!(l.get(2, &key(2)).unwrap().value(), new_value(2));
+  let keys = l
+    .iter(2)
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  assert_eq!(keys, [key(0), key(2)]);
+
+  // Purging the tombstoned key drops the tombstone as well.
+  assert_eq!(l.stats().tombstones(), 1);
+  assert!(l.purge(&key(2)).unwrap());
+  assert_eq!(l.stats().tombstones(), 0);
+  assert_eq!(l.len(), 1);
+
+  let k = key(1);
+  l.get_or_insert(9, &k, &new_value(9)).unwrap();
+  assert_eq!(l.get(9, &k).unwrap().value(), new_value(9));
+
+  let clone = l.clone();
+  assert_eq!(l.purge(&k).unwrap_err(), Error::Shared);
+  drop(clone);
+  assert!(l.purge(&k).unwrap());
 }
 
 #[test]
-#[cfg(feature = "std")]
-fn test_concurrent_basic_big_values() {
-  run(|| {
-    test_concurrent_basic_big_values_runner(Arc::new(
-      SkipMap::with_options(BIG_TEST_OPTIONS)
-        .unwrap()
-        .with_yield_now(),
-    ));
-  })
+fn test_purge() {
+  run(|| purge(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "std")]
-fn test_concurrent_basic_big_values_unify() {
+#[cfg(feature = "memmap")]
+fn test_purge_map_anon() {
   run(|| {
-    test_concurrent_basic_big_values_runner(Arc::new(
-      SkipMap::with_options(UNIFY_BIG_TEST_OPTIONS)
-        .unwrap()
-        .with_yield_now(),
-    ));
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    purge(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn validate(l: SkipMap) {
+  assert!(l.validate().is_ok());
+  for i in 0..100 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+    l.get_or_insert(1, &key(i), &new_value(i)).unwrap();
+  }
+  assert!(l.validate().is_ok());
+
+  // Break the order by rewriting the key of a node in place.
+  let k = key(50);
+  let ent = l.get_exact(0, &k).unwrap();
+  unsafe {
+    let node = ent.ptr.as_ref();
+    let bytes = l
+      .arena
+      .get_bytes_mut(node.key_offset as usize, node.key_size() as usize);
+    bytes.copy_from_slice(&key(99));
+  }
+
+  assert!(matches!(l.validate(), Err(Error::Corrupted(_))));
+}
+
+#[test]
+fn test_validate() {
+  run(|| validate(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_concurrent_basic_big_values_map_mut() {
+fn test_validate_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_concurrent_basic_big_values_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(120 << 20))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    test_concurrent_basic_big_values_runner(Arc::new(
-      SkipMap::map_mut(p, open_options, map_options)
-        .unwrap()
-        .with_yield_now(),
-    ));
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    validate(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+#[cfg(feature = "debug-validate")]
+fn validate_every_write(mut l: SkipMap) {
+  for i in (0..100).rev() {
+    l.get_or_insert(i as u64, &key(i), &new_value(i)).unwrap();
+    l.insert(i as u64 + 1, &key(i), &new_value(i + 1)).unwrap();
+    if i % 3 == 0 {
+      l.get_or_remove(i as u64 + 2, &key(i)).unwrap();
+    }
+    if i % 5 == 0 {
+      let k = key(i);
+      l.compare_remove(i as u64, &k, Ordering::SeqCst, Ordering::Acquire)
+        .unwrap();
+    }
+    if i % 7 == 0 {
+      l.purge(&key(i)).unwrap();
+    }
+  }
+
+  assert!(l.validate().is_ok());
+  for i in 0..100 {
+    let k = key(i);
+    if i % 7 == 0 || i % 3 == 0 {
+      assert!(l.get(u64::MAX, &k).is_none());
+    } else {
+      assert_eq!(l.get(u64::MAX, &k).unwrap().value(), new_value(i + 1));
+    }
+  }
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_concurrent_basic_big_values_map_anon() {
+#[cfg(feature = "debug-validate")]
+fn test_validate_every_write() {
+  run(|| validate_every_write(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(all(feature = "debug-validate", feature = "memmap"))]
+fn test_validate_every_write_map_anon() {
   run(|| {
-    let map_options = MmapOptions::default().len(120 << 20);
-    test_concurrent_basic_big_values_runner(Arc::new(
-      SkipMap::map_anon(map_options).unwrap().with_yield_now(),
-    ));
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    validate_every_write(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn peek(l: SkipMap) {
+  for i in 0..10 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+    l.get_or_insert(1, &key(i), &new_value(i + 10)).unwrap();
+  }
+
+  // Interleaving peek and next neither skips nor repeats an entry.
+  let mut it = l.iter(1);
+  let mut keys = std::vec::Vec::new();
+  for i in 0.. {
+    let peeked = it.peek().map(|ent| ent.key().to_vec());
+    if i % 2 == 0 {
+      assert_eq!(it.peek().map(|ent| ent.key().to_vec()), peeked);
+    }
+    let next = it.next().map(|ent| ent.key().to_vec());
+    assert_eq!(peeked, next);
+    match next {
+      Some(k) => keys.push(k),
+      None => break,
+    }
+  }
+  assert_eq!(keys, (0..10).map(key).collect::<std::vec::Vec<_>>());
+  assert!(it.peek().is_none());
+
+  // The same over all the versions.
+  let mut it = l.iter_all_versions(1);
+  let mut count = 0;
+  while let Some(peeked) = it.peek() {
+    let ent = it.next().unwrap();
+    assert_eq!(peeked.key(), ent.key());
+    assert_eq!(peeked.version(), ent.version());
+    assert_eq!(it.entry().unwrap().version(), ent.version());
+    count += 1;
+  }
+  assert_eq!(count, 20);
+
+  // A peek does not move the current entry, and a peek in the other direction discards it.
+  let mut it = l.iter(1);
+  it.next().unwrap();
+  let ent = it.next().unwrap();
+  assert_eq!(ent.key(), key(1));
+  assert_eq!(it.peek().unwrap().key(), key(2));
+  assert_eq!(it.entry().unwrap().key(), key(1));
+  assert_eq!(it.peek_back().unwrap().key(), key(0));
+  assert_eq!(it.peek().unwrap().key(), key(2));
+  assert_eq!(it.next().unwrap().key(), key(2));
+  assert_eq!(it.peek_back().unwrap().key(), key(1));
+  assert_eq!(it.next_back().unwrap().key(), key(1));
+  assert_eq!(it.entry().unwrap().key(), key(1));
+
+  // Seeking discards the peeked entry.
+  let mut it = l.iter(1);
+  assert_eq!(it.peek().unwrap().key(), key(0));
+  let k = key(5);
+  assert_eq!(
+    it.seek_lower_bound(Bound::Included(&k)).unwrap().key(),
+    key(5)
+  );
+  assert_eq!(it.next().unwrap().key(), key(6));
+  assert_eq!(it.peek().unwrap().key(), key(7));
+  assert_eq!(
+    it.seek_upper_bound(Bound::Included(&k)).unwrap().key(),
+    key(5)
+  );
+  assert_eq!(it.next().unwrap().key(), key(6));
+}
+
+#[test]
+fn test_peek() {
+  run(|| peek(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_concurrent_basic_big_values_map_anon_unify() {
+fn test_peek_map_anon() {
   run(|| {
-    let map_options = MmapOptions::default().len(120 << 20);
-    test_concurrent_basic_big_values_runner(Arc::new(
-      SkipMap::map_anon_with_options(UNIFY_BIG_TEST_OPTIONS, map_options)
-        .unwrap()
-        .with_yield_now(),
-    ));
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    peek(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-#[cfg(feature = "std")]
-fn concurrent_one_key(l: Arc<SkipMap>) {
-  #[cfg(not(any(miri, feature = "loom")))]
-  const N: usize = 100;
-  #[cfg(any(miri, feature = "loom"))]
-  const N: usize = 5;
+fn vec_ranges(l: SkipMap) {
+  use crate::SliceBounds;
 
-  let wg = WaitGroup::new();
-  for i in 0..N {
-    let wg = wg.add(1);
-    let l = l.clone();
-    std::thread::spawn(move || {
-      let _ = l.get_or_insert(0, b"thekey", &make_value(i));
-      wg.done();
-    });
+  for i in 0..10 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
   }
 
-  wg.wait();
+  let collect = |it: Iter<'_, u64, Ascend, &[u8], (Bound<&[u8]>, Bound<&[u8]>)>| {
+    it.map(|ent| ent.key().to_vec())
+      .collect::<std::vec::Vec<_>>()
+  };
+  let keys = |r: core::ops::Range<usize>| r.map(key).collect::<std::vec::Vec<_>>();
 
-  let saw_value = Arc::new(crate::sync::AtomicU32::new(0));
-  for _ in 0..N {
-    let wg = wg.add(1);
-    let l = l.clone();
-    let saw_value = saw_value.clone();
-    std::thread::spawn(move || {
-      let ent = l.get(0, b"thekey").unwrap();
-      let val = ent.value();
-      let num: usize = core::str::from_utf8(&val[1..]).unwrap().parse().unwrap();
-      assert!((0..N).contains(&num));
+  let all = l
+    .range(0, ..)
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  assert_eq!(all, keys(0..10));
 
-      let mut it = l.iter_all_versions(0);
-      let ent = it.seek_lower_bound(Bound::Included(b"thekey")).unwrap();
-      let val = ent.value().unwrap();
-      let num: usize = core::str::from_utf8(&val[1..]).unwrap().parse().unwrap();
-      assert!((0..N).contains(&num));
-      assert_eq!(ent.key(), b"thekey");
-      saw_value.fetch_add(1, Ordering::SeqCst);
-      wg.done();
-    });
-  }
+  let range = key(3)..key(6);
+  assert_eq!(collect(l.range(0, range.slice_bounds())), keys(3..6));
 
-  wg.wait();
+  let range = key(3)..=key(6);
+  assert_eq!(collect(l.range(0, range.slice_bounds())), keys(3..7));
 
-  assert_eq!(N, saw_value.load(Ordering::SeqCst) as usize);
-  assert_eq!(l.len(), 1);
-}
+  let range = ..=key(4);
+  assert_eq!(collect(l.range(0, range.slice_bounds())), keys(0..5));
 
-#[test]
-#[cfg(feature = "std")]
-fn test_concurrent_one_key() {
-  run(|| {
-    concurrent_one_key(Arc::new(
-      SkipMap::with_options(TEST_OPTIONS)
-        .unwrap()
-        .with_yield_now(),
-    ));
-  })
+  let range = key(7)..;
+  assert_eq!(collect(l.range(0, range.slice_bounds())), keys(7..10));
+
+  let range = (Bound::Excluded(key(2)), Bound::Included(key(4)));
+  assert_eq!(collect(l.range(0, range.slice_bounds())), keys(3..5));
+  assert_eq!(l.range_count(0, range.slice_bounds()), 2);
 }
 
 #[test]
-#[cfg(feature = "std")]
-fn test_concurrent_one_key_unify() {
-  run(|| {
-    concurrent_one_key(Arc::new(
-      SkipMap::with_options(UNIFY_TEST_OPTIONS)
-        .unwrap()
-        .with_yield_now(),
-    ));
-  })
+fn test_vec_ranges() {
+  run(|| vec_ranges(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_concurrent_one_key_map_mut() {
+fn test_vec_ranges_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_concurrent_one_key_map_mut");
-    let open_options = OpenOptions::default()
-      .create(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    concurrent_one_key(Arc::new(
-      SkipMap::map_mut(p, open_options, map_options)
-        .unwrap()
-        .with_yield_now(),
-    ));
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    vec_ranges(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn version_count(l: SkipMap) {
+  let a = key(1);
+  let b = key(2);
+  assert_eq!(l.version_count(&a), 0);
+
+  for version in 1..=4 {
+    l.get_or_insert(version, &a, &new_value(version as usize))
+      .unwrap();
+  }
+  l.get_or_remove(5, &a).unwrap();
+  l.get_or_insert(3, &b, &new_value(3)).unwrap();
+  l.get_or_insert(1, &key(0), &new_value(0)).unwrap();
+  l.get_or_insert(1, &key(3), &new_value(3)).unwrap();
+
+  assert_eq!(l.version_count(&a), 5);
+  assert_eq!(l.version_count(&b), 1);
+  assert_eq!(l.version_count(&key(4)), 0);
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_concurrent_one_key_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    concurrent_one_key(Arc::new(
-      SkipMap::map_anon(map_options).unwrap().with_yield_now(),
-    ));
-  })
+fn test_version_count() {
+  run(|| version_count(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_concurrent_one_key_map_anon_unify() {
+fn test_version_count_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    concurrent_one_key(Arc::new(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options)
-        .unwrap()
-        .with_yield_now(),
-    ));
+    version_count(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn iter_all_versions_next(l: SkipMap) {
-  const N: usize = 100;
-
-  for i in (0..N).rev() {
-    l.get_or_insert(0, &make_int_key(i), &make_value(i))
-      .unwrap();
-  }
-
-  let mut it = l.iter_all_versions(0);
-  let mut ent = it.seek_lower_bound(Bound::Unbounded).unwrap();
-  for i in 0..N {
-    assert_eq!(ent.key(), make_int_key(i));
-    assert_eq!(ent.value().unwrap(), make_value(i));
-    if i != N - 1 {
-      ent = it.next().unwrap();
+#[cfg(feature = "serde")]
+fn serde_round_trip(l: SkipMap) {
+  for i in 0..100 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+    if i % 3 == 0 {
+      l.insert(1, &key(i), &new_value(i + 100)).unwrap();
+    }
+    if i % 5 == 0 {
+      l.get_or_remove(2, &key(i)).unwrap();
     }
   }
+  l.insert(3, b"", b"").unwrap();
+
+  use bincode::{DefaultOptions, Deserializer, Options as _};
+
+  let bytes = DefaultOptions::new().serialize(&l).unwrap();
+  let mut deserializer = Deserializer::from_slice(&bytes, DefaultOptions::new());
+  let restored = SkipMap::<u64>::from_records(TEST_OPTIONS, &mut deserializer).unwrap();
+
+  let collect = |l: &SkipMap| {
+    l.iter_all_versions(u64::MAX)
+      .map(|ent| {
+        (
+          ent.key().to_vec(),
+          ent.version(),
+          ent.value().map(|v| v.to_vec()),
+        )
+      })
+      .collect::<std::vec::Vec<_>>()
+  };
+  assert_eq!(collect(&restored), collect(&l));
+  assert_eq!(restored.len(), l.len());
+  assert_eq!(DefaultOptions::new().serialize(&restored).unwrap(), bytes);
+
+  // The records can be inserted into an existing map.
+  let other = SkipMap::with_options(TEST_OPTIONS).unwrap();
+  other
+    .deserialize_into(&mut Deserializer::from_slice(&bytes, DefaultOptions::new()))
+    .unwrap();
+  assert_eq!(collect(&other), collect(&l));
 
-  assert!(it.next().is_none());
+  // A truncated record is rejected.
+  let truncated = &bytes[..bytes.len() - 1];
+  let mut deserializer = Deserializer::from_slice(truncated, DefaultOptions::new());
+  assert!(SkipMap::<u64>::from_records(TEST_OPTIONS, &mut deserializer).is_err());
 }
 
 #[test]
-fn test_iter_all_versions_next() {
-  run(|| iter_all_versions_next(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+#[cfg(feature = "serde")]
+fn test_serde_round_trip() {
+  run(|| serde_round_trip(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-fn test_iter_all_versions_next_unify() {
-  run(|| iter_all_versions_next(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+#[cfg(all(feature = "serde", feature = "memmap"))]
+fn test_serde_round_trip_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    serde_round_trip(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn get_layered_timed(layers: &[SkipMap]) {
+  let deep = key(0);
+  let shallow = key(1);
+  let removed = key(2);
+  layers[layers.len() - 1]
+    .insert(0, &deep, &new_value(0))
+    .unwrap();
+  layers[1].insert(0, &shallow, &new_value(1)).unwrap();
+  layers[3].insert(0, &removed, &new_value(2)).unwrap();
+  layers[0].get_or_remove(1, &removed).unwrap();
+
+  // The key is only present in the deepest layer, which is beyond max_layers.
+  let max_layers = layers.len() - 1;
+  assert!(matches!(
+    super::get_layered_timed(0, &deep, layers, max_layers),
+    Err(Error::TooManyLayers)
+  ));
+  let ent = super::get_layered_timed(0, &deep, layers, layers.len())
+    .unwrap()
+    .unwrap();
+  assert_eq!(ent.value(), new_value(0));
+
+  // A shallow chain resolves the key.
+  let ent = super::get_layered_timed(0, &shallow, &layers[..2], 2)
+    .unwrap()
+    .unwrap();
+  assert_eq!(ent.value(), new_value(1));
+  let ent = super::get_layered_timed(0, &shallow, layers, 2)
+    .unwrap()
+    .unwrap();
+  assert_eq!(ent.value(), new_value(1));
+
+  // A tombstone in a newer layer resolves the key to nothing.
+  assert!(super::get_layered_timed(1, &removed, layers, 1)
+    .unwrap()
+    .is_none());
+  let ent = super::get_layered_timed(0, &removed, layers, layers.len())
+    .unwrap()
+    .unwrap();
+  assert_eq!(ent.value(), new_value(2));
+
+  // A key absent from every layer of a chain within max_layers is not found.
+  assert!(super::get_layered_timed(0, &key(3), layers, layers.len())
+    .unwrap()
+    .is_none());
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_iter_all_versions_next_map_mut() {
+fn test_get_layered_timed() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_iter_all_versions_next_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    iter_all_versions_next(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let layers = (0..8)
+      .map(|_| SkipMap::with_options(TEST_OPTIONS).unwrap())
+      .collect::<std::vec::Vec<_>>();
+    get_layered_timed(&layers)
   })
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_iter_all_versions_next_map_anon() {
+fn test_get_layered_timed_map_anon() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_next(SkipMap::map_anon(map_options).unwrap());
+    let layers = (0..8)
+      .map(|_| {
+        let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+        SkipMap::map_anon(map_options).unwrap()
+      })
+      .collect::<std::vec::Vec<_>>();
+    get_layered_timed(&layers)
   })
 }
 
+fn entry_neighbors(l: SkipMap) {
+  l.insert(0, b"a", b"a0").unwrap();
+  l.insert(0, b"b", b"b0").unwrap();
+  l.insert(2, b"b", b"b2").unwrap();
+  l.insert(0, b"c", b"c0").unwrap();
+  l.insert(1, b"d", b"d1").unwrap();
+  l.get_or_remove(2, b"d").unwrap();
+  l.insert(0, b"e", b"e0").unwrap();
+  l.insert(3, b"e", b"e3").unwrap();
+
+  let forward = |version: u64| {
+    let mut keys = std::vec::Vec::new();
+    let mut ent = l.first(version);
+    while let Some(e) = ent {
+      keys.push((e.key().to_vec(), e.value().to_vec()));
+      ent = e.next(version);
+    }
+    keys
+  };
+  let backward = |version: u64| {
+    let mut keys = std::vec::Vec::new();
+    let mut ent = l.last(version);
+    while let Some(e) = ent {
+      keys.push((e.key().to_vec(), e.value().to_vec()));
+      ent = e.prev(version);
+    }
+    keys.reverse();
+    keys
+  };
+  let collect = |version: u64| {
+    l.iter(version)
+      .map(|e| (e.key().to_vec(), e.value().to_vec()))
+      .collect::<std::vec::Vec<_>>()
+  };
+
+  for version in 0..5 {
+    assert_eq!(forward(version), collect(version));
+    assert_eq!(backward(version), collect(version));
+  }
+
+  let c = l.get(1, b"c").unwrap();
+  let d = c.next(1).unwrap();
+  assert_eq!(d.value(), b"d1");
+  assert_eq!(d.next(1).unwrap().value(), b"e0");
+  assert_eq!(c.next(2).unwrap().value(), b"e0");
+  assert_eq!(c.next(3).unwrap().value(), b"e3");
+  assert_eq!(c.prev(1).unwrap().value(), b"b0");
+  assert_eq!(c.prev(2).unwrap().value(), b"b2");
+  assert_eq!(c.prev(2).unwrap().prev(2).unwrap().value(), b"a0");
+  assert!(l.get(0, b"a").unwrap().prev(0).is_none());
+  assert!(l.get(3, b"e").unwrap().next(3).is_none());
+}
+
+#[test]
+fn test_entry_neighbors() {
+  run(|| entry_neighbors(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_iter_all_versions_next_map_anon_unify() {
+fn test_entry_neighbors_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_next(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
-    );
+    entry_neighbors(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn range_next(l: SkipMap) {
-  const N: usize = 100;
+#[test]
+fn test_max_key_value_size() {
+  let opts = TEST_OPTIONS
+    .with_max_key_size(u27::new(8))
+    .with_max_value_size(16);
+  let l = SkipMap::with_options(opts).unwrap();
 
-  for i in (0..N).rev() {
-    l.get_or_insert(0, &make_int_key(i), &make_value(i))
-      .unwrap();
-  }
+  // The sizes at the boundary are accepted.
+  l.insert(0, &[b'k'; 8], &[b'v'; 16]).unwrap();
+  l.get_or_insert(0, &[b'a'; 8], &[b'v'; 16]).unwrap();
+  l.get_or_insert_with_value::<()>(0, b"b", 16, |val| {
+    val.write(&[b'v'; 16]).unwrap();
+    Ok(())
+  })
+  .unwrap();
+  assert_eq!(l.get(0, &[b'k'; 8]).unwrap().value(), &[b'v'; 16]);
 
-  let upper = make_int_key(50);
-  let mut it = l.range(0, ..=upper.as_slice());
-  let mut ent = it.seek_lower_bound(Bound::Unbounded);
-  for i in 0..N {
-    if i <= 50 {
-      {
-        let ent = ent.unwrap();
-        assert_eq!(ent.key(), make_int_key(i));
-        assert_eq!(ent.value(), make_value(i));
-      }
-      ent = it.next();
-    } else {
-      assert!(ent.is_none());
-      ent = it.next();
-    }
+  assert!(matches!(
+    l.insert(0, &[b'k'; 9], b"v"),
+    Err(Error::KeyTooLarge { size: 9, max: 8 })
+  ));
+  assert!(matches!(
+    l.get_or_insert(0, &[b'a'; 9], b"v"),
+    Err(Error::KeyTooLarge { size: 9, max: 8 })
+  ));
+  assert!(matches!(
+    l.get_or_remove(1, &[b'a'; 9]),
+    Err(Error::KeyTooLarge { size: 9, max: 8 })
+  ));
+  assert!(matches!(
+    l.insert(0, b"k", &[b'v'; 17]),
+    Err(Error::ValueTooLarge { size: 17, max: 16 })
+  ));
+  assert!(matches!(
+    l.get_or_insert(0, b"c", &[b'v'; 17]),
+    Err(Error::ValueTooLarge { size: 17, max: 16 })
+  ));
+  assert!(matches!(
+    l.insert_with_value::<()>(0, b"c", 17, |_| Ok(())),
+    Err(Either::Right(Error::ValueTooLarge { size: 17, max: 16 }))
+  ));
+
+  // The rejected writes allocate nothing.
+  let allocated = l.allocated();
+  assert!(l.insert(0, &[b'k'; 9], b"v").is_err());
+  assert!(l.insert(0, b"k", &[b'v'; 17]).is_err());
+  assert_eq!(l.allocated(), allocated);
+  assert_eq!(l.len(), 3);
+}
+
+fn scan_prefix(l: SkipMap) {
+  for k in [
+    &b"ap"[..],
+    b"app",
+    b"apple",
+    b"application",
+    b"apq",
+    b"banana",
+    b"\xff",
+    b"\xff\xff",
+    b"\xff\xff\x00",
+  ] {
+    l.insert(0, k, k).unwrap();
   }
+  l.insert(1, b"apply", b"apply").unwrap();
+  l.get_or_remove(2, b"apple").unwrap();
+
+  let scan = |version: u64, prefix: &[u8]| {
+    l.scan_prefix(version, prefix)
+      .map(|ent| {
+        assert_eq!(ent.key(), ent.value());
+        ent.key().to_vec()
+      })
+      .collect::<std::vec::Vec<_>>()
+  };
 
-  assert!(it.next().is_none());
-}
+  assert_eq!(scan(0, b"app"), [&b"app"[..], b"apple", b"application"]);
+  assert_eq!(
+    scan(1, b"app"),
+    [&b"app"[..], b"apple", b"application", b"apply"]
+  );
+  assert_eq!(scan(2, b"app"), [&b"app"[..], b"application", b"apply"]);
+  assert_eq!(scan(2, b"appl"), [&b"application"[..], b"apply"]);
+  assert_eq!(scan(0, b"b"), [&b"banana"[..]]);
+  assert!(scan(0, b"c").is_empty());
+  assert!(scan(0, b"applications").is_empty());
 
-#[test]
-fn test_range_next() {
-  run(|| range_next(SkipMap::with_options(TEST_OPTIONS).unwrap()));
-}
+  // A prefix of 0xFF bytes has no upper bound.
+  assert_eq!(
+    scan(0, b"\xff"),
+    [&b"\xff"[..], b"\xff\xff", b"\xff\xff\x00"]
+  );
+  assert_eq!(scan(0, b"\xff\xff"), [&b"\xff\xff"[..], b"\xff\xff\x00"]);
 
-#[test]
-fn test_range_next_unify() {
-  run(|| range_next(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+  // An empty prefix scans everything.
+  let all = l
+    .iter(2)
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  assert_eq!(scan(2, b""), all);
+  assert_eq!(l.scan_prefix(2, b"ap").count(), 5);
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_range_next_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_range_next_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    iter_all_versions_next(SkipMap::map_mut(p, open_options, map_options).unwrap());
-  })
+fn test_scan_prefix() {
+  run(|| scan_prefix(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_range_next_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_next(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_scan_prefix_descend() {
+  let l = SkipMap::with_options_and_comparator(TEST_OPTIONS, Descend).unwrap();
+  for k in [
+    &b"ap"[..],
+    b"app",
+    b"apple",
+    b"application",
+    b"apq",
+    b"banana",
+  ] {
+    l.insert(0, k, k).unwrap();
+  }
+
+  let keys = l
+    .scan_prefix(0, b"app")
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  assert_eq!(keys, [&b"application"[..], b"apple", b"app"]);
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_range_next_map_anon_unify() {
+fn test_scan_prefix_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_next(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
-    );
+    scan_prefix(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn iter_all_versions_prev(l: SkipMap) {
+fn iter_rev(l: SkipMap) {
   const N: usize = 100;
 
   for i in 0..N {
-    l.get_or_insert(0, &make_int_key(i), &make_value(i))
-      .unwrap();
+    let k = key(i);
+    l.insert(0, &k, &new_value(i)).unwrap();
+    if i % 3 == 0 {
+      l.insert(2, &k, &new_value(i + N)).unwrap();
+    }
+    if i % 4 == 0 {
+      l.get_or_remove(1, &k).unwrap();
+    }
+    if i % 7 == 0 {
+      l.insert(3, &k, &new_value(i + 2 * N)).unwrap();
+    }
   }
 
-  let mut it = l.iter_all_versions(0);
-  let mut ent = it.seek_upper_bound(Bound::Unbounded).unwrap();
-  for i in (0..N).rev() {
-    assert_eq!(ent.key(), make_int_key(i));
-    assert_eq!(ent.value().unwrap(), make_value(i));
-    if i != 0 {
-      ent = it.next_back().unwrap();
-    }
+  let entries = |it: &mut dyn Iterator<Item = EntryRef<'_, u64, Ascend>>| {
+    it.map(|ent| (ent.key().to_vec(), ent.version(), ent.value().to_vec()))
+      .collect::<std::vec::Vec<_>>()
+  };
+
+  for version in 0..5 {
+    let mut forward = entries(&mut l.iter(version));
+    assert!(!forward.is_empty());
+    forward.reverse();
+    assert_eq!(entries(&mut l.iter_rev(version)), forward);
+    assert_eq!(entries(&mut l.iter(version).rev()), forward);
+    assert_eq!(entries(&mut l.iter_rev(version).rev()).len(), forward.len());
+
+    let lower = key(10);
+    let upper = key(50);
+    let mut forward = entries(&mut l.range(version, lower.as_slice()..upper.as_slice()));
+    forward.reverse();
+    assert_eq!(
+      entries(&mut l.range_rev(version, lower.as_slice()..upper.as_slice())),
+      forward
+    );
+    assert_eq!(
+      entries(&mut l.range(version, lower.as_slice()..upper.as_slice()).rev()),
+      forward
+    );
+
+    let mut forward = l
+      .iter_all_versions(version)
+      .map(|ent| (ent.key().to_vec(), ent.version()))
+      .collect::<std::vec::Vec<_>>();
+    forward.reverse();
+    let backward = l
+      .iter_all_versions(version)
+      .rev()
+      .map(|ent| (ent.key().to_vec(), ent.version()))
+      .collect::<std::vec::Vec<_>>();
+    assert_eq!(backward, forward);
   }
 
-  assert!(it.next_back().is_none());
-}
+  // The reverse iterator composes with the adapters in its primary direction.
+  let last_two = l
+    .iter_rev(3)
+    .take(2)
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  assert_eq!(last_two, [key(N - 1), key(N - 2)]);
 
-#[test]
-fn test_iter_all_versions_next_back() {
-  run(|| iter_all_versions_prev(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+  // The seeks are oriented backward.
+  let mut it = l.iter_rev(0);
+  let k = key(50);
+  assert_eq!(it.seek_lower_bound(Bound::Included(&k)).unwrap().key(), k);
+  assert_eq!(it.next().unwrap().key(), key(49));
+  assert_eq!(it.peek().unwrap().key(), key(48));
+  assert_eq!(it.next().unwrap().key(), key(48));
+  let k = key(10);
+  assert_eq!(
+    it.seek_upper_bound(Bound::Excluded(&k)).unwrap().key(),
+    key(11)
+  );
+  assert_eq!(it.next().unwrap().key(), key(10));
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_iter_all_versions_prev_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_iter_all_versions_prev_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    iter_all_versions_prev(SkipMap::map_mut(p, open_options, map_options).unwrap());
-  })
+fn test_iter_rev() {
+  run(|| iter_rev(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_iter_all_versions_prev_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_prev(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_iter_rev_unify() {
+  run(|| iter_rev(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_iter_all_versions_prev_map_anon_unify() {
+fn test_iter_rev_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_prev(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
-    );
+    iter_rev(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn range_prev(l: SkipMap) {
-  const N: usize = 100;
+fn expirable(l: SkipMap<ExpirableTrailer>) {
+  const N: usize = 50;
 
+  // (key, version, expire_at, value)
+  let mut model = std::vec::Vec::new();
   for i in 0..N {
-    l.get_or_insert(0, &make_int_key(i), &make_value(i))
-      .unwrap();
+    let k = key(i);
+    for version in 0..3u64 {
+      let expire_at = (i as u64 * 37 + version * 11) % 200;
+      let trailer = ExpirableTrailer::new(version, expire_at);
+      if (i as u64 + version) % 5 == 0 {
+        l.get_or_remove(trailer, &k).unwrap();
+        model.push((k.clone(), version, expire_at, None));
+      } else {
+        let v = new_value(i * 3 + version as usize);
+        l.insert(trailer, &k, &v).unwrap();
+        model.push((k.clone(), version, expire_at, Some(v)));
+      }
+    }
   }
 
-  let lower = make_int_key(50);
-  let mut it = l.range(0, lower.as_slice()..);
-  let mut ent = it.seek_upper_bound(Bound::Unbounded);
-  for i in (0..N).rev() {
-    if i >= 50 {
-      {
-        let ent = ent.unwrap();
-        assert_eq!(ent.key(), make_int_key(i));
-        assert_eq!(ent.value(), make_value(i));
+  for version in 0..4 {
+    for now in [0, 50, 100, 150, 200] {
+      let mut expected = std::vec::Vec::new();
+      for i in 0..N {
+        let k = key(i);
+        let latest = model
+          .iter()
+          .filter(|(mk, v, _, _)| *mk == k && *v <= version)
+          .max_by_key(|(_, v, _, _)| *v);
+        let expected_value = match latest {
+          Some((_, _, expire_at, Some(value))) if *expire_at > now => Some(value.clone()),
+          _ => None,
+        };
+
+        assert_eq!(
+          l.get_unexpired(version, now, &k)
+            .map(|ent| ent.value().to_vec()),
+          expected_value
+        );
+        if let Some(value) = expected_value {
+          expected.push((k, value));
+        }
       }
-      ent = it.next_back();
-    } else {
-      assert!(ent.is_none());
-      ent = it.next_back();
+
+      let collect = |it: &mut dyn Iterator<Item = EntryRef<'_, ExpirableTrailer, Ascend>>| {
+        it.map(|ent| (ent.key().to_vec(), ent.value().to_vec()))
+          .collect::<std::vec::Vec<_>>()
+      };
+      assert_eq!(collect(&mut l.iter(version).skip_expired(now)), expected);
+      let mut backward = collect(&mut l.iter(version).skip_expired(now).rev());
+      backward.reverse();
+      assert_eq!(backward, expected);
+      let mut backward = collect(&mut l.iter_rev(version).skip_expired(now));
+      backward.reverse();
+      assert_eq!(backward, expected);
+
+      let mut all_versions = model
+        .iter()
+        .filter(|(_, v, expire_at, _)| *v <= version && *expire_at > now)
+        .map(|(k, v, _, value)| (k.clone(), *v, value.clone()))
+        .collect::<std::vec::Vec<_>>();
+      all_versions.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+      let actual = l
+        .iter_all_versions(version)
+        .skip_expired(now)
+        .map(|ent| {
+          (
+            ent.key().to_vec(),
+            ent.version(),
+            ent.value().map(|v| v.to_vec()),
+          )
+        })
+        .collect::<std::vec::Vec<_>>();
+      assert_eq!(actual, all_versions);
     }
   }
 
-  assert!(it.next_back().is_none());
+  // Without the flag, the expired entries are returned.
+  assert!(l.iter(3).skip_expired(200).next().is_none());
+  assert!(l.iter(3).count() > 0);
 }
 
 #[test]
-fn test_range_prev() {
-  run(|| range_prev(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+fn test_expirable() {
+  run(|| expirable(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
-#[test]
-fn test_range_prev_unify() {
-  run(|| range_prev(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+fn reset(mut l: SkipMap) {
+  let fresh = l.allocated();
+  let cap = l.capacity();
+
+  for i in 0..100 {
+    l.get_or_insert(i as u64, &key(i), &new_value(i)).unwrap();
+  }
+  l.get_or_remove(100, &key(0)).unwrap();
+
+  let clone = l.clone();
+  assert_eq!(l.reset().unwrap_err(), Error::Shared);
+  assert_eq!(clone.len(), 101);
+  drop(clone);
+
+  l.reset().unwrap();
+  assert!(l.is_empty());
+  assert_eq!(l.max_version(), 0);
+  let allocated = l.allocated();
+  assert!(allocated <= fresh);
+  assert_eq!(l.capacity(), cap);
+  assert!(l.iter(u64::MAX).next().is_none());
+  assert!(l.get(u64::MAX, &key(1)).is_none());
+
+  for i in (0..100).rev() {
+    l.get_or_insert(0, &key(i), &new_value(i + 1)).unwrap();
+  }
+  assert_eq!(l.len(), 100);
+  assert_eq!(l.capacity(), cap);
+  for (i, ent) in l.iter(0).enumerate() {
+    assert_eq!(ent.key(), key(i));
+    assert_eq!(ent.value(), new_value(i + 1));
+  }
+  assert_eq!(l.iter(0).count(), 100);
+
+  l.reset().unwrap();
+  assert!(l.is_empty());
+  assert_eq!(l.allocated(), allocated);
+  assert_eq!(l.capacity(), cap);
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_range_prev_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_range_prev_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    range_prev(SkipMap::map_mut(p, open_options, map_options).unwrap());
-  })
+fn test_reset() {
+  reset(SkipMap::with_options(TEST_OPTIONS).unwrap());
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_range_prev_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    range_prev(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_reset_unify() {
+  reset(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_range_prev_map_anon_unify() {
+fn test_reset_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    range_prev(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    reset(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn iter_all_versions_seek_ge(l: SkipMap) {
-  const N: usize = 100;
-
-  for i in (0..N).rev() {
-    let v = i * 10 + 1000;
-    l.get_or_insert(0, &make_int_key(v), &make_value(v))
-      .unwrap();
-  }
+fn get_or_insert_detailed(l: SkipMap) {
+  let (k0, k1) = (key(0), key(1));
+  let (v0, v1) = (new_value(0), new_value(1));
 
-  let mut it = l.iter_all_versions(0);
-  let ent = it.seek_lower_bound(Bound::Included(b"")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1000));
-  assert_eq!(ent.value().unwrap(), make_value(1000));
+  let (old, inserted) = l.get_or_insert_detailed(0, &k0, &v0).unwrap();
+  assert!(old.is_none());
+  assert!(inserted);
 
-  let ent = it.seek_lower_bound(Bound::Included(b"01000")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1000));
-  assert_eq!(ent.value().unwrap(), make_value(1000));
+  let (old, inserted) = l.get_or_insert_detailed(0, &k0, &v1).unwrap();
+  assert_eq!(old.unwrap().value(), v0);
+  assert!(!inserted);
 
-  let ent = it.seek_lower_bound(Bound::Included(b"01005")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1010));
-  assert_eq!(ent.value().unwrap(), make_value(1010));
+  // a newer version of the same key is a new entry
+  let (old, inserted) = l.get_or_insert_detailed(1, &k0, &v1).unwrap();
+  assert!(old.is_none());
+  assert!(inserted);
 
-  let ent = it.seek_lower_bound(Bound::Included(b"01010")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1010));
-  assert_eq!(ent.value().unwrap(), make_value(1010));
+  l.get_or_remove(0, &k1).unwrap();
+  let (old, inserted) = l.get_or_insert_detailed(0, &k1, &v1).unwrap();
+  assert!(old.is_none());
+  assert!(!inserted);
 
-  let ent = it.seek_lower_bound(Bound::Included(b"01020")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1020));
-  assert_eq!(ent.value().unwrap(), make_value(1020));
+  assert_eq!(l.get(1, &k0).unwrap().value(), v1);
+  assert!(l.get(0, &k1).is_none());
+  assert_eq!(l.len(), 3);
+}
 
-  let ent = it.seek_lower_bound(Bound::Included(b"01200")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1200));
-  assert_eq!(ent.value().unwrap(), make_value(1200));
+#[test]
+fn test_get_or_insert_detailed() {
+  get_or_insert_detailed(SkipMap::with_options(TEST_OPTIONS).unwrap());
+}
 
-  let ent = it.seek_lower_bound(Bound::Included(b"01100")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1100));
-  assert_eq!(ent.value().unwrap(), make_value(1100));
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_or_insert_detailed_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_or_insert_detailed(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  let ent = it.seek_lower_bound(Bound::Included(b"99999"));
-  assert!(ent.is_none());
+fn rewrite_values(l: SkipMap) {
+  const MASK: u8 = 0x5A;
 
-  l.get_or_insert(0, &[], &[]).unwrap();
-  let ent = it.seek_lower_bound(Bound::Included(b"")).unwrap();
-  assert_eq!(ent.key(), &[]);
-  assert_eq!(ent.value().unwrap(), &[]);
+  for i in 0..100 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  // a newer version is not visible at version 0 and must be kept as is
+  l.get_or_insert(1, &key(0), &new_value(1000)).unwrap();
+  l.get_or_remove(0, &key(100)).unwrap();
+
+  // an entry loaded before the rewrite keeps the old value
+  let k = key(1);
+  let held = l.get(0, &k).unwrap();
+  let discarded = l.discarded();
+
+  let rewritten = l
+    .rewrite_values(0, |_, value| {
+      value.iter_mut().for_each(|b| *b ^= MASK);
+      true
+    })
+    .unwrap();
+  assert_eq!(rewritten, 100);
+  assert_eq!(l.len(), 102);
+  assert_eq!(held.value(), new_value(1));
+  assert!(l.discarded() > discarded);
 
-  let ent = it.seek_lower_bound(Bound::Included(b"")).unwrap();
-  assert_eq!(ent.key(), &[]);
-  assert_eq!(ent.value().unwrap(), &[]);
-}
+  let masked = |i: usize| new_value(i).iter().map(|b| b ^ MASK).collect::<Vec<_>>();
+  for i in 0..100 {
+    assert_eq!(l.get(0, &key(i)).unwrap().value(), masked(i));
+  }
+  assert_eq!(l.get(1, &key(0)).unwrap().value(), new_value(1000));
+  assert!(l.get(0, &key(100)).is_none());
+
+  // a closure returning false leaves the entries untouched
+  let rewritten = l
+    .rewrite_values(0, |key, value| {
+      value.fill(0);
+      key.ends_with(b"1")
+    })
+    .unwrap();
+  assert_eq!(rewritten, 10);
+  for i in 0..100 {
+    let k = key(i);
+    let ent = l.get(0, &k).unwrap();
+    if i % 10 == 1 {
+      assert_eq!(ent.value(), [0; 5]);
+    } else {
+      assert_eq!(ent.value(), masked(i));
+    }
+  }
 
-#[test]
-fn test_iter_all_versions_seek_ge() {
-  run(|| iter_all_versions_seek_ge(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+  l.seal().unwrap();
+  assert!(matches!(
+    l.rewrite_values(0, |_, _| true),
+    Err(Error::Sealed)
+  ));
 }
 
 #[test]
-fn test_iter_all_versions_seek_ge_unify() {
-  run(|| iter_all_versions_seek_ge(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+fn test_rewrite_values() {
+  rewrite_values(SkipMap::with_options(TEST_OPTIONS).unwrap());
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_iter_all_versions_seek_ge_map_mut() {
+fn test_rewrite_values_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_iter_all_versions_seek_ge_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    iter_all_versions_seek_ge(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    rewrite_values(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+/// Fills the ARENA with nodes which are almost all of the maximum height, until an allocation
+/// fails. The towers are allocated with the exact size of their height, so the last allocation must
+/// either fit completely or fail with `InsufficientSpace`, leaving the map valid.
+fn tall_nodes_at_boundary(l: SkipMap) {
+  let cap = l.capacity();
+  let mut inserted = 0;
+  loop {
+    match l.get_or_insert(0, &key(inserted), &new_value(inserted)) {
+      Ok(_) => inserted += 1,
+      Err(e) => {
+        assert!(matches!(
+          e,
+          Error::Arena(ArenaError::InsufficientSpace { .. })
+        ));
+        break;
+      }
+    }
+  }
+
+  assert!(inserted > 0);
+  assert!(l.allocated() <= cap);
+  assert_eq!(l.capacity(), cap);
+  assert_eq!(l.len(), inserted);
+  l.validate().unwrap();
+  for i in 0..inserted {
+    assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+  }
+}
+
+fn tall_node_options(cap: u32) -> Options {
+  Options::new()
+    .with_capacity(cap)
+    .with_max_height(u5::new(20))
+    .with_branching_probability(0.99)
+    .with_freelist(Freelist::None)
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_iter_all_versions_seek_ge_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_seek_ge(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_tall_nodes_at_boundary() {
+  // Every few bytes of capacity moves the boundary to another point of the last node.
+  for cap in (4096..4096 + 256).step_by(4) {
+    tall_nodes_at_boundary(SkipMap::with_options(tall_node_options(cap)).unwrap());
+    tall_nodes_at_boundary(SkipMap::with_options(tall_node_options(cap).with_unify(true)).unwrap());
+  }
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_iter_all_versions_seek_ge_map_anon_unify() {
+fn test_tall_nodes_at_boundary_map_anon() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_seek_ge(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
-    );
+    for cap in (4096..4096 + 256).step_by(4) {
+      let map_options = MmapOptions::default().len(cap);
+      tall_nodes_at_boundary(
+        SkipMap::map_anon_with_options(tall_node_options(cap), map_options).unwrap(),
+      );
+    }
   })
 }
 
-fn iter_all_versions_seek_lt(l: SkipMap) {
-  const N: usize = 100;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+struct SeqTrailer {
+  version: u64,
+  seq: u32,
+}
 
-  for i in (0..N).rev() {
-    let v = i * 10 + 1000;
-    l.get_or_insert(0, &make_int_key(v), &make_value(v))
-      .unwrap();
+unsafe impl Trailer for SeqTrailer {
+  fn version(&self) -> u64 {
+    self.version
   }
+}
 
-  let mut it = l.iter_all_versions(0);
-  assert!(it.seek_upper_bound(Bound::Excluded(b"")).is_none());
-
-  let ent = it.seek_upper_bound(Bound::Excluded(b"01000"));
-  assert!(ent.is_none());
-
-  let ent = it.seek_upper_bound(Bound::Excluded(b"01001")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1000));
-  assert_eq!(ent.value().unwrap(), make_value(1000));
-
-  let ent = it.seek_upper_bound(Bound::Excluded(b"01991")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1990));
-  assert_eq!(ent.value().unwrap(), make_value(1990));
+fn custom_trailer(l: SkipMap<SeqTrailer>) {
+  let t = |version, seq| SeqTrailer { version, seq };
+  let (k0, k1) = (key(0), key(1));
+  let (v0, v1) = (new_value(0), new_value(1));
 
-  let ent = it.seek_upper_bound(Bound::Excluded(b"99999")).unwrap();
-  assert_eq!(ent.key(), make_int_key(1990));
-  assert_eq!(ent.value().unwrap(), make_value(1990));
+  assert!(l.get_or_insert(t(1, 10), &k0, &v0).unwrap().is_none());
+  let old = l.get_or_insert(t(1, 11), &k0, &v1).unwrap().unwrap();
+  assert_eq!(*old.trailer(), t(1, 10));
 
-  l.get_or_insert(0, &[], &[]).unwrap();
-  assert!(l.lt(0, &[]).is_none());
+  assert!(l.insert(t(3, 30), &k0, &v1).unwrap().is_none());
+  let old = l.insert(t(3, 31), &k0, &v0).unwrap().unwrap();
+  assert_eq!(*old.trailer(), t(3, 30));
+  assert!(l.insert(t(2, 20), &k1, &v1).unwrap().is_none());
 
-  let ent = it.seek_upper_bound(Bound::Excluded(b""));
-  assert!(ent.is_none());
+  // the versions of the trailers order the entries
+  let ent = l.get(2, &k0).unwrap();
+  assert_eq!(*ent.trailer(), t(1, 10));
+  assert_eq!(ent.version(), 1);
+  assert_eq!(ent.value(), v0);
+  let ent = l.get(3, &k0).unwrap();
+  assert_eq!(*ent.trailer(), t(3, 31));
+  assert_eq!(ent.value(), v0);
+  assert!(l.get(1, &k1).is_none());
+  assert_eq!(*l.get(2, &k1).unwrap().trailer(), t(2, 20));
 
-  let ent = it.seek_upper_bound(Bound::Excluded(b"\x01")).unwrap();
-  assert_eq!(ent.key(), &[]);
-  assert_eq!(ent.value().unwrap(), &[]);
+  let trailers = l
+    .iter_all_versions(3)
+    .map(|ent| *ent.trailer())
+    .collect::<Vec<_>>();
+  assert_eq!(trailers, [t(3, 31), t(1, 10), t(2, 20)]);
 }
 
 #[test]
-fn test_iter_all_versions_seek_lt() {
-  run(|| iter_all_versions_seek_lt(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+fn test_custom_trailer() {
+  custom_trailer(SkipMap::with_options(TEST_OPTIONS).unwrap());
 }
 
 #[test]
-fn test_iter_all_versions_seek_lt_unify() {
-  run(|| iter_all_versions_seek_lt(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_custom_trailer_unify() {
+  custom_trailer(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_iter_all_versions_seek_lt_map_mut() {
+fn test_custom_trailer_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_iter_all_versions_seek_lt_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    iter_all_versions_seek_lt(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    custom_trailer(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn into_iter(l: SkipMap) {
+  for i in 0..100 {
+    l.get_or_insert(i as u64, &key(i), &new_value(i)).unwrap();
+  }
+  for i in (0..100).step_by(3) {
+    l.get_or_insert(u64::MAX, &key(i), &new_value(i + 1000))
+      .unwrap();
+  }
+  l.get_or_remove(200, &key(50)).unwrap();
+
+  let mut count = 0;
+  for (ent, expected) in (&l).into_iter().zip(l.iter(u64::MAX)) {
+    assert_eq!(ent.key(), expected.key());
+    assert_eq!(ent.value(), expected.value());
+    assert_eq!(ent.version(), expected.version());
+    count += 1;
+  }
+  assert_eq!(count, 99);
+
+  let mut keys = Vec::new();
+  for ent in &l {
+    keys.push(ent.key().to_vec());
+  }
+  assert_eq!(keys.len(), 99);
+  assert!(!keys.contains(&key(50)));
+  assert_eq!(l.get(u64::MAX, &key(3)).unwrap().value(), new_value(1003));
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_iter_all_versions_seek_lt_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_seek_lt(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_into_iter() {
+  into_iter(SkipMap::with_options(TEST_OPTIONS).unwrap());
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_iter_all_versions_seek_lt_map_anon_unify() {
+fn test_into_iter_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_all_versions_seek_lt(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
-    );
+    into_iter(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn range(l: SkipMap) {
-  for i in 1..10 {
-    l.get_or_insert(0, &make_int_key(i), &make_value(i))
-      .unwrap();
-  }
-
-  let k3 = make_int_key(3);
-  let k7 = make_int_key(7);
-  let mut it = l.range(0, k3.as_slice()..k7.as_slice()).clone();
-  assert_eq!(it.bounds(), &(k3.as_slice()..k7.as_slice()));
-
-  for i in 3..=6 {
-    let k = make_int_key(i);
-    let ent = it.seek_lower_bound(Bound::Included(&k)).unwrap();
-    assert_eq!(ent.key(), make_int_key(i));
-    assert_eq!(ent.value(), make_value(i));
+fn remove_simple(l: SkipMap) {
+  for i in 0..100 {
+    let v = new_value(i);
+    l.insert(0, &key(i), &v).unwrap();
   }
 
-  for i in 1..3 {
-    let k = make_int_key(i);
-    let ent = it.seek_lower_bound(Bound::Included(&k)).unwrap();
-    assert_eq!(ent.key(), make_int_key(3));
-    assert_eq!(ent.value(), make_value(3));
-  }
+  for i in 0..100 {
+    let k = key(i);
+    // no race, remove should succeed
+    assert!(l.remove(0, &k).unwrap());
 
-  for i in 7..10 {
-    let k = make_int_key(i);
-    assert!(it.seek_lower_bound(Bound::Included(&k)).is_none());
+    // key already removed
+    assert!(!l.remove(0, &k).unwrap());
   }
 
-  for i in 7..10 {
-    let k = make_int_key(i);
-    let ent = it.seek_upper_bound(Bound::Included(&k)).unwrap();
-    assert_eq!(ent.key(), make_int_key(6));
-    assert_eq!(ent.value(), make_value(6));
+  for i in 0..100 {
+    let k = key(i);
+    let ent = l.get(0, &k);
+    assert!(ent.is_none());
   }
+}
 
-  let ent = it
-    .seek_lower_bound(Bound::Included(&make_int_key(6)))
-    .unwrap();
-  assert_eq!(ent.key(), make_int_key(6));
-  assert_eq!(ent.value(), make_value(6));
-
-  assert!(it.next().is_none());
+#[test]
+fn test_remove_simple() {
+  run(|| remove_simple(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
 
-  let ent = it
-    .seek_upper_bound(Bound::Included(&make_int_key(6)))
-    .unwrap();
-  assert_eq!(ent.key(), make_int_key(6));
-  assert_eq!(ent.value(), make_value(6));
+#[test]
+fn test_remove_simple_unify() {
+  run(|| remove_simple(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
 
-  assert!(it.next().is_none());
+#[test]
+#[cfg(feature = "memmap")]
+fn test_remove_simple_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    remove_simple(SkipMap::map_anon(map_options).unwrap());
+  })
+}
 
-  for i in 4..=7 {
-    let k = make_int_key(i);
-    let ent = it.seek_upper_bound(Bound::Excluded(&k)).unwrap();
-    assert_eq!(ent.key(), make_int_key(i - 1));
-    assert_eq!(ent.value(), make_value(i - 1));
+fn remove_simple2(l: SkipMap) {
+  for i in 0..100 {
+    let v = new_value(i);
+    l.insert(0, &key(i), &v).unwrap();
   }
 
-  for i in 7..10 {
-    let k = make_int_key(i);
-    let ent = it.seek_upper_bound(Bound::Excluded(&k)).unwrap();
-    assert_eq!(ent.key(), make_int_key(6));
-    assert_eq!(ent.value(), make_value(6));
-  }
+  for i in 0..100 {
+    let k = key(i);
+    // not found, a removed entry shadows the older version
+    assert!(!l.remove(1, &k).unwrap());
+    assert!(l.get(1, &k).is_none());
+    assert_eq!(l.get(0, &k).unwrap().value(), new_value(i));
 
-  for i in 1..3 {
-    let k = make_int_key(i);
-    let ent = it.seek_lower_bound(Bound::Excluded(&k)).unwrap();
-    assert_eq!(ent.key(), make_int_key(3));
-    assert_eq!(ent.value(), make_value(3));
+    // no race, remove should succeed
+    assert!(l.remove(0, &k).unwrap());
   }
 
-  for i in 1..4 {
-    let k = make_int_key(i);
-    assert!(it.seek_upper_bound(Bound::Excluded(&k)).is_none());
+  for i in 0..100 {
+    let k = key(i);
+    let ent = l.get(0, &k);
+    assert!(ent.is_none());
   }
-
-  let ent = it
-    .seek_upper_bound(Bound::Excluded(&make_int_key(4)))
-    .unwrap();
-  assert_eq!(ent.key(), make_int_key(3));
-  assert_eq!(ent.value(), make_value(3));
-
-  assert!(it.next_back().is_none());
+  assert_eq!(l.len(), 200);
 }
 
 #[test]
-fn test_range() {
-  run(|| range(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+fn test_remove_simple2() {
+  run(|| remove_simple2(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-fn test_range_unify() {
-  run(|| range(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_remove_simple2_unify() {
+  run(|| remove_simple2(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_range_map_mut() {
+fn test_remove_simple2_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_range_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    range(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    remove_simple2(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn bound_keys(l: SkipMap) {
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  l.get_or_insert(3, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"c", b"c1").unwrap();
+  l.get_or_insert(3, b"c", b"c2").unwrap();
+  l.get_or_insert(5, b"c", b"c3").unwrap();
+  l.get_or_remove(4, b"a").unwrap();
+
+  let keys: [&[u8]; 5] = [b"", b"a", b"b", b"c", b"d"];
+  for version in 0..=6 {
+    let mut bounds = vec![Bound::Unbounded];
+    for k in keys {
+      bounds.push(Bound::Included(k));
+      bounds.push(Bound::Excluded(k));
+    }
+
+    for bound in bounds {
+      assert_eq!(
+        l.lower_bound_key(version, bound),
+        l.lower_bound(version, bound).as_ref().map(|ent| ent.key()),
+        "lower bound {bound:?} at version {version}",
+      );
+      assert_eq!(
+        l.upper_bound_key(version, bound),
+        l.upper_bound(version, bound).as_ref().map(|ent| ent.key()),
+        "upper bound {bound:?} at version {version}",
+      );
+    }
+  }
+
+  assert_eq!(l.lower_bound_key(1, Bound::Excluded(b"a")), Some(&b"c"[..]));
+  assert_eq!(l.upper_bound_key(3, Bound::Unbounded), Some(&b"c"[..]));
+  assert!(l.lower_bound_key(0, Bound::Unbounded).is_none());
+}
+
+#[test]
+fn test_bound_keys() {
+  bound_keys(SkipMap::with_options(TEST_OPTIONS).unwrap());
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_range_map_anon() {
+fn test_bound_keys_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    range(SkipMap::map_anon(map_options).unwrap());
+    bound_keys(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn prefix_comparator(l: SkipMap<u64, PrefixComparator>) {
+  assert_eq!(l.comparator().prefix_len(), 4);
+
+  assert!(l.get_or_insert(0, b"ten1-a", b"a0").unwrap().is_none());
+  // the same prefix and version is the same entry
+  let old = l.get_or_insert(0, b"ten1-b", b"b0").unwrap().unwrap();
+  assert_eq!(old.key(), b"ten1-a");
+  assert_eq!(old.value(), b"a0");
+  // a newer version of the prefix is a newer version of the same key
+  assert!(l.get_or_insert(1, b"ten1-c", b"c1").unwrap().is_none());
+  assert!(l.get_or_insert(0, b"ten2-a", b"a0").unwrap().is_none());
+  assert!(l.get_or_insert(0, b"ten0", b"0").unwrap().is_none());
+  assert!(l.get_or_insert(0, b"te", b"short").unwrap().is_none());
+
+  assert!(l.contains_key(0, b"ten1"));
+  assert!(l.contains_key(0, b"ten1-zzz"));
+  assert!(!l.contains_key(0, b"ten3-a"));
+  assert_eq!(l.get(0, b"ten1-x").unwrap().value(), b"a0");
+  assert_eq!(l.get(1, b"ten1-x").unwrap().value(), b"c1");
+  assert_eq!(l.get(0, b"te").unwrap().value(), b"short");
+  assert!(l.get(0, b"t").is_none());
+
+  // the newer version shares the key of the first inserted entry
+  let keys = l.iter(1).map(|ent| ent.key().to_vec()).collect::<Vec<_>>();
+  assert_eq!(
+    keys,
+    [&b"te"[..], b"ten0", b"ten1-a", b"ten2-a"]
+      .iter()
+      .map(|k| k.to_vec())
+      .collect::<Vec<_>>()
+  );
+
+  let upper: &[u8] = b"ten2";
+  let values = l
+    .range(1, &b"ten1"[..]..upper)
+    .map(|ent| ent.value().to_vec())
+    .collect::<Vec<_>>();
+  assert_eq!(values, [b"c1".to_vec()]);
+  assert_eq!(l.len(), 5);
+}
+
+#[test]
+fn test_prefix_comparator() {
+  prefix_comparator(
+    SkipMap::with_options_and_comparator(TEST_OPTIONS, PrefixComparator::new(Ascend, 4)).unwrap(),
+  );
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_range_map_anon_unify() {
+fn test_prefix_comparator_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    range(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    prefix_comparator(
+      SkipMap::map_anon_with_options_and_comparator(
+        TEST_OPTIONS,
+        map_options,
+        PrefixComparator::new(Ascend, 4),
+      )
+      .unwrap(),
+    );
   })
 }
 
-fn iter_latest(l: SkipMap) {
-  const N: usize = 100;
+/// Orders the keys by a big endian `u64` header, and rejects the keys shorter than the header.
+#[derive(Debug, Clone, Copy)]
+struct HeaderComparator;
 
-  for i in 0..N {
-    l.get_or_insert(0, &make_int_key(i), &make_value(i))
-      .unwrap();
+impl TryComparator for HeaderComparator {
+  fn try_compare(&self, a: &[u8], b: &[u8]) -> Result<cmp::Ordering, CompareError> {
+    const ERR: CompareError = CompareError::new("the key is shorter than 8 bytes");
+    if a.len() < 8 || b.len() < 8 {
+      return Err(ERR);
+    }
+    Ok(a[..8].cmp(&b[..8]).then_with(|| a[8..].cmp(&b[8..])))
   }
+}
 
-  for i in 50..N {
-    l.get_or_insert(1, &make_int_key(i), &make_value(i + 1000))
-      .unwrap();
+fn try_comparator(l: SkipMap<u64, Fallible<HeaderComparator>>) {
+  let err = CompareError::new("the key is shorter than 8 bytes");
+  let k = |i: u64| i.to_be_bytes().to_vec();
+
+  // a key which cannot be compared is rejected even when the map is empty
+  assert_eq!(
+    l.try_insert(0, b"short", b"v").unwrap_err(),
+    Error::Compare(err)
+  );
+  assert!(l.is_empty());
+
+  for i in 0..100 {
+    l.try_insert(0, &k(i), &new_value(i as usize)).unwrap();
   }
 
-  for i in 0..50 {
-    l.get_or_insert(2, &make_int_key(i), &make_value(i + 1000))
-      .unwrap();
+  for i in 0..100 {
+    let key = k(i);
+    let ent = l.try_get(0, &key).unwrap().unwrap();
+    assert_eq!(ent.value(), new_value(i as usize));
   }
+  assert!(l.try_get(0, &k(1000)).unwrap().is_none());
+
+  assert_eq!(l.try_get(0, b"short").unwrap_err(), err);
+  assert_eq!(
+    l.try_insert(0, b"short", b"v").unwrap_err(),
+    Error::Compare(err)
+  );
+  assert_eq!(l.len(), 100);
+
+  // the infallible methods still work
+  assert!(l.get(0, b"short").is_none());
+  assert_eq!(l.iter(0).count(), 100);
+  l.validate().unwrap();
+
+  // the keys which cannot be compared are ordered before the others, by their bytes
+  l.insert(0, b"short", b"s").unwrap();
+  l.insert(0, b"bad", b"b").unwrap();
+  assert_eq!(l.get(0, b"short").unwrap().value(), b"s");
+  let keys = l
+    .iter(0)
+    .take(3)
+    .map(|ent| ent.key().to_vec())
+    .collect::<Vec<_>>();
+  assert_eq!(keys, [b"bad".to_vec(), b"short".to_vec(), k(0)]);
+  assert_eq!(l.range(0, ..k(1).as_slice()).count(), 3);
+  assert_eq!(l.range(0, k(0).as_slice()..).count(), 100);
+  l.validate().unwrap();
+
+  // a key on the search path which cannot be compared fails the insertion
+  assert_eq!(
+    l.try_insert(1, &k(0), b"v").unwrap_err(),
+    Error::Compare(err)
+  );
+  assert_eq!(l.get(1, &k(0)).unwrap().value(), new_value(0));
+}
+
+#[test]
+fn test_try_comparator() {
+  try_comparator(
+    SkipMap::with_options_and_comparator(TEST_OPTIONS, Fallible::new(HeaderComparator)).unwrap(),
+  );
+}
 
-  let mut it = l.iter(4);
-  let mut num = 0;
-  for i in 0..N {
-    let ent = it.next().unwrap();
-    assert_eq!(ent.key(), make_int_key(i));
-    assert_eq!(ent.value(), make_value(i + 1000));
+#[test]
+#[cfg(feature = "memmap")]
+fn test_try_comparator_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    try_comparator(
+      SkipMap::map_anon_with_options_and_comparator(
+        TEST_OPTIONS,
+        map_options,
+        Fallible::new(HeaderComparator),
+      )
+      .unwrap(),
+    );
+  })
+}
 
-    num += 1;
+fn entry_key_cmp(l: SkipMap) {
+  for version in 0..3 {
+    for i in 0..10 {
+      l.get_or_insert(version, &key(i), &new_value(i)).unwrap();
+    }
+  }
+
+  let (k0, k1) = (key(0), key(1));
+  let v0 = l.get(0, &k0).unwrap();
+  let v2 = l.get(2, &k0).unwrap();
+  assert!(v0.key_eq(&v2));
+  assert_eq!(v0.key_cmp(&v2), cmp::Ordering::Equal);
+  assert!(v0 != v2);
+  assert!(v0 > v2);
+
+  let other = l.get(0, &k1).unwrap();
+  assert!(!v0.key_eq(&other));
+  assert_eq!(v0.key_cmp(&other), cmp::Ordering::Less);
+  assert_eq!(other.key_cmp(&v2), cmp::Ordering::Greater);
+
+  // sorting by key keeps the versions of a key together and in their original order
+  let mut ents = (0..3)
+    .rev()
+    .flat_map(|version| l.iter(version).collect::<Vec<_>>())
+    .collect::<Vec<_>>();
+  ents.sort_by(|a, b| a.key_cmp(b));
+  ents.dedup_by(|a, b| a.key_eq(b));
+  assert_eq!(ents.len(), 10);
+  for (i, ent) in ents.iter().enumerate() {
+    assert_eq!(ent.key(), key(i));
+    assert_eq!(ent.version(), 2);
   }
-  assert_eq!(num, N);
 }
 
 #[test]
-fn test_iter_latest() {
-  run(|| iter_latest(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+fn test_entry_key_cmp() {
+  entry_key_cmp(SkipMap::with_options(TEST_OPTIONS).unwrap());
 }
 
 #[test]
-fn test_iter_latest_unify() {
-  run(|| iter_latest(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_entry_key_cmp_descend() {
+  let l = SkipMap::with_options_and_comparator(TEST_OPTIONS, Descend).unwrap();
+  l.get_or_insert(0, b"a", b"a0").unwrap();
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  l.get_or_insert(0, b"b", b"b0").unwrap();
+
+  let (a0, a1, b0) = (
+    l.get(0, b"a").unwrap(),
+    l.get(1, b"a").unwrap(),
+    l.get(0, b"b").unwrap(),
+  );
+  assert!(a0.key_eq(&a1));
+  assert_eq!(a0.key_cmp(&b0), cmp::Ordering::Greater);
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_iter_latest_map_mut() {
+fn test_entry_key_cmp_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_iter_latest_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    iter_latest(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    entry_key_cmp(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+// The hash of an entry only covers its key and version, which never change.
+#[allow(clippy::mutable_key_type)]
+fn entry_hash(l1: SkipMap, l2: SkipMap) {
+  use std::collections::HashSet;
+
+  for i in 0..100 {
+    l1.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  // the first half duplicates l1, the second half has newer versions
+  for i in 0..100 {
+    let version = if i < 50 { 0 } else { 1 };
+    l2.get_or_insert(version, &key(i), &new_value(i)).unwrap();
+  }
+
+  let set = l1.iter(1).chain(l2.iter(1)).collect::<HashSet<_>>();
+  assert_eq!(set.len(), 150);
+  for ent in l2.iter(1) {
+    assert!(set.contains(&ent));
+  }
+
+  let set = l1
+    .iter(1)
+    .chain(l2.iter(1))
+    .map(|ent| ent.to_owned())
+    .collect::<HashSet<_>>();
+  assert_eq!(set.len(), 150);
+
+  let set = l1
+    .iter_all_versions(1)
+    .chain(l2.iter_all_versions(1))
+    .collect::<HashSet<_>>();
+  assert_eq!(set.len(), 150);
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_iter_latest_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_latest(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_entry_hash() {
+  entry_hash(
+    SkipMap::with_options(TEST_OPTIONS).unwrap(),
+    SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap(),
+  );
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_iter_latest_map_anon_unify() {
+fn test_entry_hash_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    iter_latest(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    entry_hash(
+      SkipMap::map_anon(map_options).unwrap(),
+      SkipMap::with_options(TEST_OPTIONS).unwrap(),
+    );
   })
 }
 
-fn range_latest(l: SkipMap) {
-  const N: usize = 100;
-
-  for i in 0..N {
-    l.get_or_insert(0, &make_int_key(i), &make_value(i))
-      .unwrap();
-  }
-
-  for i in 50..N {
-    l.get_or_insert(1, &make_int_key(i), &make_value(i + 1000))
-      .unwrap();
+fn memory_footprint(l: SkipMap) {
+  let empty = l.memory_footprint();
+  assert_eq!(empty.node_bytes(), 0);
+  assert_eq!(empty.key_bytes(), 0);
+  assert_eq!(empty.value_bytes(), 0);
+  assert_eq!(empty.heights().len(), 20);
+  assert!(empty.heights().iter().all(|&n| n == 0));
+
+  let mut key_bytes = 0;
+  let mut value_bytes = 0;
+  for i in 0..1000 {
+    let k = format!("key-{i}");
+    let v = vec![b'v'; i % 37];
+    l.get_or_insert(0, k.as_bytes(), &v).unwrap();
+    key_bytes += k.len();
+    value_bytes += v.len();
   }
-
-  for i in 0..50 {
-    l.get_or_insert(2, &make_int_key(i), &make_value(i + 1000))
-      .unwrap();
+  // the newer versions share the key
+  for i in 0..100 {
+    let k = format!("key-{i}");
+    l.get_or_insert(1, k.as_bytes(), b"new").unwrap();
+    value_bytes += 3;
   }
+  // a removed entry has a key but no value
+  l.get_or_remove(0, b"removed").unwrap();
+  key_bytes += 7;
 
-  let mut it = l.range(4, ..);
-  let mut num = 0;
-  for i in 0..N {
-    let ent = it.next().unwrap();
-    assert_eq!(ent.key(), make_int_key(i));
-    assert_eq!(ent.value(), make_value(i + 1000));
-
-    num += 1;
-  }
-  assert_eq!(num, N);
+  let footprint = l.memory_footprint();
+  assert_eq!(footprint.key_bytes(), key_bytes);
+  assert_eq!(footprint.value_bytes(), value_bytes);
+  assert_eq!(footprint.heights().iter().sum::<usize>(), l.len());
+  assert!(footprint.heights()[0] > 0);
+  assert!(footprint.node_bytes() >= l.len() * (mem::size_of::<Node<u64>>() + 8));
+  assert!(
+    footprint.node_bytes() + footprint.key_bytes() + footprint.value_bytes() <= l.allocated()
+  );
+  assert_eq!(footprint.discarded_bytes(), l.discarded() as usize);
 }
 
 #[test]
-fn test_range_latest() {
-  run(|| range_latest(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+fn test_memory_footprint() {
+  memory_footprint(SkipMap::with_options(TEST_OPTIONS).unwrap());
 }
 
 #[test]
-fn test_range_latest_unify() {
-  run(|| range_latest(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_memory_footprint_unify() {
+  memory_footprint(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_range_latest_map_mut() {
+fn test_memory_footprint_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_range_latest_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    range_latest(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    memory_footprint(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn iter_count(l: SkipMap) {
+  for i in 0..100 {
+    l.get_or_insert(1, &key(i), &new_value(i)).unwrap();
+  }
+  for i in (0..100).step_by(3) {
+    l.get_or_insert(2, &key(i), &new_value(i + 1)).unwrap();
+  }
+  for i in (0..100).step_by(5) {
+    l.get_or_remove(3, &key(i)).unwrap();
+  }
+  for i in (0..100).step_by(7) {
+    l.get_or_insert(4, &key(i), &new_value(i + 2)).unwrap();
+  }
+
+  let (lo, hi) = (key(10), key(60));
+  for version in 0..=5 {
+    assert_eq!(
+      l.iter(version).count(),
+      l.iter(version).fold(0, |n, _| n + 1),
+      "latest at version {version}",
+    );
+    assert_eq!(
+      l.iter_all_versions(version).count(),
+      l.iter_all_versions(version).fold(0, |n, _| n + 1),
+      "all versions at version {version}",
+    );
+    assert_eq!(
+      l.range(version, lo.as_slice()..hi.as_slice()).count(),
+      l.range(version, lo.as_slice()..hi.as_slice())
+        .fold(0, |n, _| n + 1),
+      "range at version {version}",
+    );
+
+    // The count starts from the current position of the iterator, including a peeked entry.
+    let mut iter = l.iter(version);
+    let total = iter.fold(0, |n, _| n + 1);
+    if total > 2 {
+      iter.next();
+      iter.peek();
+      assert_eq!(iter.count(), total - 1);
+    }
+  }
+
+  assert_eq!(l.iter(0).count(), 0);
+  assert_eq!(l.iter(1).count(), 100);
+  assert_eq!(l.iter(3).count(), 80);
+  assert_eq!(l.iter_all_versions(3).count(), 154);
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_range_latest_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    range_latest(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_iter_count() {
+  run(|| iter_count(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_iter_count_unify() {
+  run(|| iter_count(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_range_latest_map_anon_unify() {
+fn test_iter_count_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    range_latest(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    iter_count(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-#[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_reopen_mmap() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("reopen_skipmap");
-    {
-      let open_options = OpenOptions::default()
-        .create(Some(ARENA_SIZE as u32))
-        .read(true)
-        .write(true);
-      let map_options = MmapOptions::default();
-      let l = SkipMap::map_mut(&p, open_options, map_options).unwrap();
-      for i in 0..1000 {
-        l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
-      }
-      l.flush().unwrap();
-    }
+fn for_each_visible(l: SkipMap) {
+  for i in 0..100 {
+    l.get_or_insert(1, &key(i), &new_value(i)).unwrap();
+  }
+  for i in (0..100).step_by(3) {
+    l.get_or_insert(2, &key(i), &key(i)).unwrap();
+  }
+  for i in (0..100).step_by(4) {
+    l.get_or_remove(3, &key(i)).unwrap();
+  }
 
-    let open_options = OpenOptions::default().read(true);
-    let map_options = MmapOptions::default();
-    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
-    assert_eq!(1000, l.len());
-    for i in 0..1000 {
-      let k = key(i);
-      let ent = l.get(0, &k).unwrap();
-      assert_eq!(new_value(i), ent.value());
-      assert_eq!(ent.trailer().version(), 0);
-      assert_eq!(ent.key(), k);
+  for version in 0..=4 {
+    let mut len = 0;
+    let mut keys = vec![];
+    let flow = l.for_each_visible(version, |ent| {
+      len += ent.value().len();
+      keys.push(ent.key().to_vec());
+      ControlFlow::Continue(())
+    });
+    assert!(flow.is_continue());
+    assert_eq!(
+      len,
+      l.iter(version).map(|ent| ent.value().len()).sum::<usize>(),
+      "version {version}",
+    );
+    assert_eq!(
+      keys,
+      l.iter(version)
+        .map(|ent| ent.key().to_vec())
+        .collect::<Vec<_>>(),
+      "version {version}",
+    );
+  }
+
+  let mut seen = 0;
+  let flow = l.for_each_visible(3, |_| {
+    seen += 1;
+    if seen == 10 {
+      ControlFlow::Break(())
+    } else {
+      ControlFlow::Continue(())
     }
-  })
+  });
+  assert!(flow.is_break());
+  assert_eq!(seen, 10);
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_reopen_mmap2() {
-  run(|| {
-    use rand::seq::SliceRandom;
+fn test_for_each_visible() {
+  run(|| for_each_visible(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
 
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("reopen_skipmap2");
-    {
-      let open_options = OpenOptions::default()
-        .create(Some(ARENA_SIZE as u32))
-        .read(true)
-        .write(true);
-      let map_options = MmapOptions::default();
-      let l = SkipMap::map_mut_with_comparator(&p, open_options, map_options, Ascend).unwrap();
-      let mut data = (0..1000).collect::<Vec<usize>>();
-      data.shuffle(&mut rand::thread_rng());
-      for i in data {
-        l.get_or_insert(i as u64, &key(i), &new_value(i)).unwrap();
-      }
-      l.flush_async().unwrap();
-      assert_eq!(l.max_version(), 999);
-      assert_eq!(l.min_version(), 0);
-    }
+#[test]
+fn test_for_each_visible_unify() {
+  run(|| for_each_visible(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
 
-    let open_options = OpenOptions::default().read(true);
-    let map_options = MmapOptions::default();
-    let l = SkipMap::<u64, Ascend>::map_with_comparator(&p, open_options, map_options, Ascend, 0)
-      .unwrap();
-    assert_eq!(1000, l.len());
-    let mut data = (0..1000).collect::<Vec<usize>>();
-    data.shuffle(&mut rand::thread_rng());
-    for i in data {
-      let k = key(i);
-      let ent = l.get(i as u64, &k).unwrap();
-      assert_eq!(new_value(i), ent.value());
-      assert_eq!(ent.trailer().version(), i as u64);
-      assert_eq!(ent.key(), k);
-    }
-    assert_eq!(l.max_version(), 999);
-    assert_eq!(l.min_version(), 0);
+#[test]
+#[cfg(feature = "memmap")]
+fn test_for_each_visible_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    for_each_visible(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-struct Person {
-  id: u32,
-  name: std::string::String,
-}
+fn estimate_size(mut l: SkipMap) {
+  let trailer = mem::size_of::<u64>() as u64;
+  let scan = |l: &SkipMap| {
+    l.iter_all_versions(u64::MAX)
+      .map(|ent| (ent.key().len() + ent.value().map_or(0, |v| v.len())) as u64 + trailer)
+      .sum::<u64>()
+  };
 
-impl Person {
-  fn encoded_size(&self) -> usize {
-    4 + self.name.len()
+  assert_eq!(l.estimate_size(), 0);
+  assert_eq!(l.estimate_size_at(0), 0);
+
+  for i in 0..100 {
+    l.get_or_insert(1, &key(i), &new_value(i)).unwrap();
   }
-}
+  assert_eq!(l.estimate_size(), scan(&l));
+  assert_eq!(l.estimate_size(), l.estimate_size_at(1));
 
-fn get_or_insert_with_value(l: SkipMap) {
-  let alice = Person {
-    id: 1,
-    name: std::string::String::from("Alice"),
-  };
+  // Overwrites replace the value bytes, removes drop them.
+  for i in (0..100).step_by(2) {
+    l.insert(1, &key(i), &make_value(i)).unwrap();
+  }
+  for i in (0..100).step_by(5) {
+    l.remove(1, &key(i)).unwrap();
+  }
+  assert_eq!(l.estimate_size(), scan(&l));
 
-  let encoded_size = alice.encoded_size() as u32;
+  // Newer versions and tombstones count as their own entries.
+  for i in (0..100).step_by(3) {
+    l.get_or_insert(2, &key(i), &key(i)).unwrap();
+  }
+  for i in (0..100).step_by(7) {
+    l.get_or_remove(3, &key(i)).unwrap();
+  }
+  assert_eq!(l.estimate_size(), scan(&l));
+
+  for version in 0..=4 {
+    let visible = l
+      .iter(version)
+      .map(|ent| (ent.key().len() + ent.value().len()) as u64 + trailer)
+      .sum::<u64>();
+    assert_eq!(l.estimate_size_at(version), visible, "version {version}");
+    assert!(l.estimate_size_at(version) <= l.estimate_size());
+  }
 
-  l.get_or_insert_with_value::<()>(1, b"alice", encoded_size, |val| {
-    assert_eq!(val.capacity(), encoded_size as usize);
-    assert!(val.is_empty());
-    val.write(&alice.id.to_le_bytes()).unwrap();
-    assert_eq!(val.len(), 4);
-    assert_eq!(val.remaining(), encoded_size as usize - 4);
-    assert_eq!(&*val, alice.id.to_le_bytes());
-    val[..4].copy_from_slice(&alice.id.to_be_bytes());
-    assert_eq!(&*val, alice.id.to_be_bytes());
-    val.write(alice.name.as_bytes()).unwrap();
-    assert_eq!(val.len(), encoded_size as usize);
-    let err = val.write(&[1]).unwrap_err();
-    assert_eq!(
-      std::string::ToString::to_string(&err),
-      "buffer does not have enough space (remaining 0, want 1)"
-    );
-    Ok(())
-  })
-  .unwrap();
+  assert!(l.purge(&key(0)).unwrap());
+  assert!(l.purge(&key(3)).unwrap());
+  assert_eq!(l.estimate_size(), scan(&l));
+
+  l.clear().unwrap();
+  assert_eq!(l.estimate_size(), 0);
 }
 
 #[test]
-fn test_get_or_insert_with_value() {
-  run(|| {
-    get_or_insert_with_value(SkipMap::with_options(TEST_OPTIONS).unwrap());
-  })
+fn test_estimate_size() {
+  run(|| estimate_size(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-fn test_get_or_insert_with_value_unify() {
-  run(|| {
-    get_or_insert_with_value(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
-  })
+fn test_estimate_size_unify() {
+  run(|| estimate_size(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_get_or_insert_with_value_map_mut() {
+fn test_estimate_size_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_get_or_insert_with_value_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    get_or_insert_with_value(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    estimate_size(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn arena_bytes(l: SkipMap) {
+  for i in 0..100 {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  l.get_or_insert(0, b"empty", b"").unwrap();
+
+  let clone = l.clone();
+  let (keys, values): (Vec<_>, Vec<_>) = {
+    let k = key(7);
+    let ent = l.get(0, &k).unwrap();
+    let value = ent.value_bytes();
+    let values = vec![value.clone(), value];
+    let keys = vec![ent.key_bytes()];
+    (keys, values)
+  };
+
+  let empty = l.get(0, b"empty").unwrap().value_bytes();
+  assert!(empty.is_empty());
+
+  drop(l);
+  drop(clone);
+
+  assert_eq!(keys[0].as_ref(), key(7).as_slice());
+  for value in &values {
+    assert_eq!(&**value, new_value(7).as_slice());
+  }
+  assert_eq!(&*empty, b"");
+
+  let value = values[0].clone();
+  let handle = std::thread::spawn(move || value.to_vec());
+  assert_eq!(handle.join().unwrap(), new_value(7));
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_get_or_insert_with_value_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_or_insert_with_value(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_arena_bytes() {
+  run(|| arena_bytes(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_arena_bytes_unify() {
+  run(|| arena_bytes(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_get_or_insert_with_value_map_anon_unify() {
+fn test_arena_bytes_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_or_insert_with_value(
-      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
-    );
+    arena_bytes(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn get_or_insert_with(l: SkipMap) {
-  let alice = Person {
-    id: 1,
-    name: std::string::String::from("Alice"),
+fn cursor(l: SkipMap) {
+  for i in 0..100 {
+    l.get_or_insert(1, &key(i), &new_value(i)).unwrap();
+  }
+  for i in (0..100).step_by(3) {
+    l.get_or_insert(2, &key(i), &make_value(i)).unwrap();
+  }
+  for i in (0..100).step_by(10) {
+    l.get_or_remove(3, &key(i)).unwrap();
+  }
+  l.get_or_insert(5, &key(50), &new_value(50)).unwrap();
+
+  let visible: Vec<(Vec<u8>, Vec<u8>)> = l
+    .iter(3)
+    .map(|ent| (ent.key().to_vec(), ent.value().to_vec()))
+    .collect();
+  assert_eq!(visible.len(), 90);
+  let at = |ent: Option<EntryRef<'_, u64, Ascend>>| {
+    ent.map(|ent| (ent.key().to_vec(), ent.value().to_vec()))
   };
 
-  let encoded_size = alice.encoded_size() as u32;
+  let mut cursor = l.cursor(3);
+  assert!(!cursor.is_valid());
+  assert!(cursor.current().is_none());
 
-  l.get_or_insert_with::<()>(
-    1,
-    u27::new(5),
-    |key| {
-      key.write(b"alice").unwrap();
-      Ok(())
-    },
-    encoded_size,
-    |val| {
-      assert_eq!(val.capacity(), encoded_size as usize);
-      assert!(val.is_empty());
-      val.write(&alice.id.to_le_bytes()).unwrap();
-      assert_eq!(val.len(), 4);
-      assert_eq!(val.remaining(), encoded_size as usize - 4);
-      assert_eq!(&*val, alice.id.to_le_bytes());
-      val[..4].copy_from_slice(&alice.id.to_be_bytes());
-      assert_eq!(&*val, alice.id.to_be_bytes());
-      val.write(alice.name.as_bytes()).unwrap();
-      assert_eq!(val.len(), encoded_size as usize);
-      let err = val.write(&[1]).unwrap_err();
-      assert_eq!(
-        std::string::ToString::to_string(&err),
-        "buffer does not have enough space (remaining 0, want 1)"
-      );
-      Ok(())
-    },
-  )
-  .unwrap();
-}
+  // Seek to every key, then move around it in both directions.
+  for i in 0..100 {
+    let k = key(i);
+    let pos = visible
+      .iter()
+      .position(|(vk, _)| vk.as_slice() >= k.as_slice());
+    assert_eq!(
+      at(cursor.seek(&k)),
+      pos.map(|p| visible[p].clone()),
+      "seek {i}"
+    );
+    assert_eq!(at(cursor.current()), pos.map(|p| visible[p].clone()));
+    let pos = match pos {
+      Some(pos) => pos,
+      None => {
+        assert!(!cursor.is_valid());
+        continue;
+      }
+    };
 
-#[test]
-fn test_get_or_insert_with() {
-  run(|| get_or_insert_with(SkipMap::with_options(TEST_OPTIONS).unwrap()))
-}
+    assert_eq!(at(cursor.next()), visible.get(pos + 1).cloned());
+    if pos + 1 < visible.len() {
+      assert_eq!(at(cursor.prev()), Some(visible[pos].clone()));
+    } else {
+      assert!(!cursor.is_valid());
+      assert_eq!(at(cursor.prev()), Some(visible[pos].clone()));
+    }
+    let prev = pos.checked_sub(1).map(|p| visible[p].clone());
+    assert_eq!(at(cursor.prev()), prev);
+    assert_eq!(cursor.is_valid(), prev.is_some());
+  }
 
-#[test]
-fn test_get_or_insert_with_unify() {
-  run(|| get_or_insert_with(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+  // Walk forward past the end, then backward past the start.
+  cursor.seek(b"").unwrap();
+  let mut forward = vec![at(cursor.current()).unwrap()];
+  for ent in cursor.by_ref() {
+    forward.push(at(Some(ent)).unwrap());
+  }
+  assert_eq!(forward, visible);
+  assert!(!cursor.is_valid());
+
+  let mut backward = vec![];
+  while let Some(ent) = cursor.prev() {
+    backward.push(at(Some(ent)).unwrap());
+  }
+  backward.reverse();
+  assert_eq!(backward, visible);
+  assert!(!cursor.is_valid());
+  assert_eq!(at(cursor.next()), visible.first().cloned());
+
+  // A seek past the last key leaves the cursor past the end.
+  assert!(cursor.seek(b"zzz").is_none());
+  assert_eq!(at(cursor.prev()), visible.last().cloned());
+
+  // A newer version is only visible to a cursor reading at that version.
+  let k = key(50);
+  assert!(l.cursor(3).seek(&k).unwrap().key() > k.as_slice());
+  assert_eq!(l.cursor(5).seek(&k).unwrap().key(), k.as_slice());
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_get_or_insert_with_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_get_or_insert_with_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    get_or_insert_with(SkipMap::map_mut(p, open_options, map_options).unwrap());
-  })
+fn test_cursor() {
+  run(|| cursor(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_get_or_insert_with_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_or_insert_with(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_cursor_unify() {
+  run(|| cursor(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_get_or_insert_with_map_anon_unify() {
+fn test_cursor_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_or_insert_with(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    cursor(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn insert_in(l: SkipMap) {
-  let k = 0u64.to_le_bytes();
+fn reserved(mut l: SkipMap) {
+  assert_eq!(l.reserved_slice(), &[0; 16]);
+  l.reserved_slice_mut().unwrap().copy_from_slice(&[7; 16]);
+
+  let clone = l.clone();
+  assert_eq!(l.reserved_slice_mut().unwrap_err(), Error::Shared);
+  drop(clone);
+
   for i in 0..100 {
-    let v = new_value(i);
-    let old = l.insert(0, &k, &v).unwrap();
-    if let Some(old) = old {
-      assert_eq!(old.key(), k);
-      assert_eq!(old.value(), new_value(i - 1));
-    }
+    l.insert(0, &key(i), &new_value(i)).unwrap();
   }
+  assert_eq!(l.reserved_slice(), &[7; 16]);
+  assert!(l.data_offset() > 16);
 
-  let ent = l.get(0, &k).unwrap();
-  assert_eq!(ent.key(), k);
-  assert_eq!(ent.value(), new_value(99));
+  l.reset().unwrap();
+  assert_eq!(l.reserved_slice(), &[0; 16]);
 }
 
 #[test]
-fn test_insert_in() {
+fn test_reserved() {
   run(|| {
-    insert_in(SkipMap::with_options(TEST_OPTIONS).unwrap());
+    reserved(
+      SkipMap::with_options(
+        Options::new()
+          .with_capacity(ARENA_SIZE as u32)
+          .with_reserved(16),
+      )
+      .unwrap(),
+    )
   })
 }
 
 #[test]
-fn test_insert_in_unify() {
+fn test_reserved_unify() {
+  run(|| reserved(SkipMap::with_options(UNIFY_TEST_OPTIONS.with_reserved(16)).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_reserved_map_anon() {
   run(|| {
-    insert_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    reserved(
+      SkipMap::map_anon_with_options(Options::new().with_reserved(16), map_options).unwrap(),
+    );
   })
 }
 
 #[test]
 #[cfg(feature = "memmap")]
 #[cfg_attr(miri, ignore)]
-fn test_insert_in_map_mut() {
+fn test_reserved_reopen_mmap() {
   run(|| {
     let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_insert_in_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
+    let p = dir.path().join("reserved_skipmap");
+    let meta: [u8; 16] = core::array::from_fn(|i| i as u8 + 1);
+    {
+      let open_options = OpenOptions::default()
+        .create_new(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let mut l = SkipMap::map_mut_with_options(
+        &p,
+        Options::new().with_reserved(16),
+        open_options,
+        map_options,
+      )
+      .unwrap();
+      l.reserved_slice_mut().unwrap().copy_from_slice(&meta);
+      for i in 0..100 {
+        l.insert(0, &key(i), &new_value(i)).unwrap();
+      }
+      l.flush().unwrap();
+    }
+
+    let open_options = OpenOptions::default().read(true);
     let map_options = MmapOptions::default();
-    insert_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    assert_eq!(l.reserved_slice(), &meta);
+    assert_eq!(l.len(), 100);
+    for i in 0..100 {
+      assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+    }
   })
 }
 
+fn iter_tombstones(l: SkipMap) {
+  for i in 0..100 {
+    l.insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  for i in (0..100).filter(|i| i % 3 == 0) {
+    l.remove(1, &key(i)).unwrap();
+  }
+  // Inserted again after the removal, it is only removed at version 1.
+  l.insert(2, &key(3), &new_value(3)).unwrap();
+  // Removed at a version which is not visible yet.
+  l.remove(5, &key(1)).unwrap();
+
+  assert_eq!(l.iter_tombstones(0).count(), 0);
+
+  let removed = l
+    .iter_tombstones(1)
+    .map(|ent| {
+      assert!(ent.value().is_none());
+      assert_eq!(ent.version(), 1);
+      ent.key().to_vec()
+    })
+    .collect::<Vec<_>>();
+  let expected = (0..100).filter(|i| i % 3 == 0).map(key).collect::<Vec<_>>();
+  assert_eq!(removed, expected);
+
+  let removed = l
+    .iter_tombstones(2)
+    .map(|ent| ent.key().to_vec())
+    .collect::<Vec<_>>();
+  let expected = (0..100)
+    .filter(|i| i % 3 == 0 && *i != 3)
+    .map(key)
+    .collect::<Vec<_>>();
+  assert_eq!(removed, expected);
+
+  // The visible entries and the tombstones partition the keys.
+  let live = l.iter(u64::MAX).count();
+  let removed = l.iter_tombstones(u64::MAX).count();
+  assert_eq!(live + removed, 100);
+  assert!(l.iter_tombstones(u64::MAX).any(|ent| ent.key() == key(1)));
+}
+
+#[test]
+fn test_iter_tombstones() {
+  run(|| iter_tombstones(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_iter_tombstones_unify() {
+  run(|| iter_tombstones(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_insert_in_map_anon() {
+fn test_iter_tombstones_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    insert_in(SkipMap::map_anon(map_options).unwrap());
+    iter_tombstones(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+fn compare_remove_if(l: SkipMap) {
+  let k = key(1);
+  l.insert(1, &k, &new_value(1)).unwrap();
+
+  // A mismatch reports the current entry and does not remove it.
+  let current = l
+    .compare_remove_if(1, &k, &new_value(2))
+    .unwrap()
+    .unwrap_right();
+  assert_eq!(current.value(), new_value(1));
+  assert_eq!(current.version(), 1);
+  assert_eq!(l.get(1, &k).unwrap().value(), new_value(1));
+  assert_eq!(l.stats().tombstones(), 0);
+
+  // A match at the same version removes the value in place.
+  assert!(l
+    .compare_remove_if(1, &k, &new_value(1))
+    .unwrap()
+    .unwrap_left());
+  assert!(l.get(1, &k).is_none());
+  assert_eq!(l.len(), 1);
+  assert_eq!(l.stats().tombstones(), 1);
+
+  // Already removed.
+  assert!(!l
+    .compare_remove_if(1, &k, &new_value(1))
+    .unwrap()
+    .unwrap_left());
+
+  // A match on an older version inserts a tombstone, the older version stays readable.
+  let k = key(2);
+  l.insert(1, &k, &new_value(2)).unwrap();
+  assert!(l
+    .compare_remove_if(3, &k, &new_value(2))
+    .unwrap()
+    .unwrap_left());
+  assert!(l.get(3, &k).is_none());
+  assert_eq!(l.get(2, &k).unwrap().value(), new_value(2));
+  assert_eq!(l.len(), 3);
+  assert_eq!(l.stats().tombstones(), 2);
+
+  // The tombstone is only linked right before the compared entry, so a version inserted
+  // between them meanwhile is compared again instead of being removed.
+  let k = key(4);
+  l.insert(1, &k, &new_value(4)).unwrap();
+  let compared = l.get(3, &k).unwrap().0.ptr.offset;
+  l.insert(2, &k, &new_value(5)).unwrap();
+  let mut ins = Inserter {
+    expected_next: Some(compared),
+    ..Default::default()
+  };
+  l.update(
+    3,
+    Key::Remove(&k),
+    0,
+    noop::<Infallible>,
+    Ordering::AcqRel,
+    Ordering::Acquire,
+    &mut ins,
+    false,
+  )
+  .unwrap();
+  assert!(!ins.inserted);
+  assert_eq!(l.get(3, &k).unwrap().value(), new_value(5));
+  assert_eq!(l.len(), 5);
+  let current = l
+    .compare_remove_if(3, &k, &new_value(4))
+    .unwrap()
+    .unwrap_right();
+  assert_eq!(current.value(), new_value(5));
+  assert!(l
+    .compare_remove_if(3, &k, &new_value(5))
+    .unwrap()
+    .unwrap_left());
+  assert_eq!(l.get(1, &k).unwrap().value(), new_value(4));
+  assert_eq!(l.stats().tombstones(), 3);
+
+  // A missing key writes nothing.
+  let len = l.len();
+  assert!(!l
+    .compare_remove_if(1, &key(3), &new_value(3))
+    .unwrap()
+    .unwrap_left());
+  assert_eq!(l.len(), len);
+  assert!(l.get(u64::MAX, &key(3)).is_none());
+}
+
+#[test]
+fn test_compare_remove_if() {
+  run(|| compare_remove_if(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_compare_remove_if_unify() {
+  run(|| compare_remove_if(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_insert_in_map_anon_unify() {
+fn test_compare_remove_if_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    insert_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    compare_remove_if(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
-fn insert_with_value(l: SkipMap) {
-  let alice = Person {
-    id: 1,
-    name: std::string::String::from("Alice"),
-  };
-
-  let encoded_size = alice.encoded_size() as u32;
+fn lazy_init(opts: Options) {
+  let lazy_opts = opts.with_capacity(1024).with_lazy_init(true);
+  let eager = SkipMap::<u64>::with_options(lazy_opts.with_lazy_init(false)).unwrap();
+  let fresh = SkipMap::<u64>::with_options(lazy_opts).unwrap().allocated();
+  assert!(fresh < eager.allocated());
 
-  l.insert_with_value::<()>(1, b"alice", encoded_size, |val| {
-    assert_eq!(val.capacity(), encoded_size as usize);
-    assert!(val.is_empty());
-    val.write(&alice.id.to_le_bytes()).unwrap();
-    assert_eq!(val.len(), 4);
-    assert_eq!(val.remaining(), encoded_size as usize - 4);
-    assert_eq!(val, alice.id.to_le_bytes());
-    val[..4].copy_from_slice(&alice.id.to_be_bytes());
-    assert_eq!(val, alice.id.to_be_bytes());
-    val.write(alice.name.as_bytes()).unwrap();
-    assert_eq!(val.len(), encoded_size as usize);
-    let err = val.write(&[1]).unwrap_err();
-    assert_eq!(
-      std::string::ToString::to_string(&err),
-      "buffer does not have enough space (remaining 0, want 1)"
-    );
-    Ok(())
-  })
-  .unwrap();
+  #[cfg(not(miri))]
+  const N: usize = 10_000;
+  #[cfg(miri)]
+  const N: usize = 10;
 
-  let alice2 = Person {
-    id: 2,
-    name: std::string::String::from("Alice"),
-  };
+  let maps = (0..N)
+    .map(|_| SkipMap::<u64>::with_options(lazy_opts).unwrap())
+    .collect::<Vec<_>>();
+  for (i, l) in maps.iter().enumerate().filter(|(i, _)| i % 2 == 0) {
+    l.insert(0, &key(i), &new_value(i)).unwrap();
+  }
 
-  let old = l
-    .insert_with_value::<()>(1, b"alice", encoded_size, |val| {
-      assert_eq!(val.capacity(), encoded_size as usize);
-      assert!(val.is_empty());
-      val.write(&alice2.id.to_le_bytes()).unwrap();
-      assert_eq!(val.len(), 4);
-      assert_eq!(val.remaining(), encoded_size as usize - 4);
-      assert_eq!(&*val, alice2.id.to_le_bytes());
-      val[..4].copy_from_slice(&alice2.id.to_be_bytes());
-      assert_eq!(&*val, alice2.id.to_be_bytes());
-      val.write(alice2.name.as_bytes()).unwrap();
-      assert_eq!(val.len(), encoded_size as usize);
-      let err = val.write(&[1]).unwrap_err();
-      assert_eq!(
-        std::string::ToString::to_string(&err),
-        "buffer does not have enough space (remaining 0, want 1)"
-      );
-      Ok(())
-    })
-    .unwrap()
-    .unwrap();
+  for (i, l) in maps.iter().enumerate() {
+    if i % 2 == 0 {
+      assert!(l.allocated() > eager.allocated());
+      assert_eq!(l.len(), 1);
+      assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+      assert_eq!(l.first(0).unwrap().key(), key(i));
+      assert_eq!(l.last(0).unwrap().key(), key(i));
+      continue;
+    }
 
-  assert_eq!(old.key(), b"alice");
-  assert!(old.value().starts_with(&alice.id.to_be_bytes()));
+    assert_eq!(l.allocated(), fresh);
+    assert_eq!(l.len(), 0);
+    assert!(l.is_empty());
+    assert!(l.get(0, &key(i)).is_none());
+    assert!(!l.contains_key(0, &key(i)));
+    assert!(l.first(0).is_none());
+    assert!(l.last(0).is_none());
+    assert!(l.lower_bound(0, Bound::Unbounded).is_none());
+    assert!(l.upper_bound(0, Bound::Unbounded).is_none());
+    assert_eq!(l.iter(0).count(), 0);
+    assert!(l.iter(0).next_back().is_none());
+    assert!(l.iter_all_versions(0).next().is_none());
+    assert!(l.range(0, key(0).as_slice()..).next().is_none());
+  }
 
-  let ent = l.get(1, b"alice").unwrap();
-  assert_eq!(ent.key(), b"alice");
-  assert!(ent.value().starts_with(&alice2.id.to_be_bytes()));
+  // Resetting a lazy map gives the sentinel nodes back.
+  let mut l = maps.into_iter().next().unwrap();
+  l.reset().unwrap();
+  assert!(l.allocated() <= fresh);
+  assert!(l.get(0, &key(0)).is_none());
+  l.insert(0, &key(1), &new_value(1)).unwrap();
+  assert_eq!(l.iter(0).count(), 1);
+
+  // The sentinel nodes cannot be allocated where the map expects them after the ARENA was
+  // allocated from directly.
+  let l = SkipMap::<u64>::with_options(lazy_opts).unwrap();
+  l.allocator().alloc_bytes(16).unwrap().detach();
+  for _ in 0..2 {
+    assert!(matches!(
+      l.insert(0, &key(0), &new_value(0)),
+      Err(Error::Corrupted(_))
+    ));
+  }
+  assert!(l.is_empty());
+  assert!(l.get(0, &key(0)).is_none());
 }
 
 #[test]
-fn test_insert_with_value() {
-  run(|| insert_with_value(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+fn test_lazy_init() {
+  run(|| lazy_init(Options::new()))
 }
 
 #[test]
-fn test_insert_with_value_unify() {
-  run(|| insert_with_value(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+fn test_lazy_init_unify() {
+  run(|| lazy_init(UNIFY_TEST_OPTIONS))
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_insert_with_value_map_mut() {
+fn test_lazy_init_concurrent() {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 16;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 4;
+
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir
-      .path()
-      .join("test_skipmap_get_or_insert_with_value_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    insert_with_value(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let l = Arc::new(SkipMap::with_options(TEST_OPTIONS.with_lazy_init(true)).unwrap());
+    let wg = WaitGroup::new();
+    for i in 0..N {
+      let wg = wg.add(1);
+      let l = l.clone();
+      std::thread::spawn(move || {
+        l.insert(0, &key(i), &new_value(i)).unwrap();
+        wg.done();
+      });
+    }
+    wg.wait();
+
+    assert_eq!(l.len(), N);
+    for i in 0..N {
+      assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+    }
   })
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_insert_with_value_map_anon() {
+#[cfg_attr(miri, ignore)]
+fn test_lazy_init_reopen_mmap() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    insert_with_value(SkipMap::map_anon(map_options).unwrap());
+    let dir = tempfile::tempdir().unwrap();
+    for written in [false, true] {
+      let p = dir.path().join(format!("lazy_skipmap_{written}"));
+      {
+        let open_options = OpenOptions::default()
+          .create_new(Some(ARENA_SIZE as u32))
+          .read(true)
+          .write(true);
+        let map_options = MmapOptions::default();
+        let l = SkipMap::map_mut_with_options(
+          &p,
+          Options::new().with_lazy_init(true),
+          open_options,
+          map_options,
+        )
+        .unwrap();
+        if written {
+          for i in 0..100 {
+            l.insert(0, &key(i), &new_value(i)).unwrap();
+          }
+        }
+        l.flush().unwrap();
+      }
+
+      let open_options = OpenOptions::default().read(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+      if written {
+        assert_eq!(l.len(), 100);
+        assert_eq!(l.iter(0).count(), 100);
+        for i in 0..100 {
+          assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+        }
+      } else {
+        assert!(l.is_empty());
+        assert!(l.get(0, &key(0)).is_none());
+        assert!(l.first(0).is_none());
+        assert!(l.last(0).is_none());
+        assert_eq!(l.iter(0).count(), 0);
+      }
+    }
   })
 }
 
-#[test]
-#[cfg(feature = "memmap")]
+#[cfg(feature = "rayon")]
+fn par_iter(l: SkipMap) {
+  use rayon::prelude::*;
+
+  assert_eq!(l.par_iter(0).count(), 0);
+
+  for i in 0..1000 {
+    l.insert(0, &key(i), &new_value(i)).unwrap();
+    if i % 3 == 0 {
+      l.insert(1, &key(i), &new_value(i * 10)).unwrap();
+    }
+    if i % 7 == 0 {
+      l.get_or_remove(2, &key(i)).unwrap();
+    }
+  }
+
+  for version in 0..3 {
+    let sequential: usize = l.iter(version).map(|ent| ent.value().len()).sum();
+    let parallel: usize = l.par_iter(version).map(|ent| ent.value().len()).sum();
+    assert_eq!(parallel, sequential);
+
+    let keys = l
+      .par_iter(version)
+      .map(|ent| (ent.key().to_vec(), ent.version()))
+      .collect::<Vec<_>>();
+    let expected = l
+      .iter(version)
+      .map(|ent| (ent.key().to_vec(), ent.version()))
+      .collect::<Vec<_>>();
+    assert_eq!(keys, expected);
+  }
+}
+
+#[test]
+#[cfg(feature = "rayon")]
+fn test_par_iter() {
+  run(|| par_iter(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "rayon")]
+fn test_par_iter_unify() {
+  run(|| par_iter(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(all(feature = "rayon", feature = "memmap"))]
+fn test_par_iter_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    par_iter(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn build_sorted(opts: Options) {
+  #[cfg(not(miri))]
+  const N: usize = 1000;
+  #[cfg(miri)]
+  const N: usize = 10;
+
+  let entries = (0..N)
+    .flat_map(|i| {
+      (0..3u64)
+        .rev()
+        .map(move |v| (v, key(i), make_value(i * 3 + v as usize)))
+    })
+    .collect::<Vec<_>>();
+
+  let sorted = SkipMap::build_sorted(opts, entries.iter().cloned()).unwrap();
+  let l = SkipMap::with_options(opts).unwrap();
+  for (version, k, v) in entries.iter().rev() {
+    l.insert(*version, k, v).unwrap();
+  }
+
+  sorted.validate().unwrap();
+  assert_eq!(sorted.len(), l.len());
+  assert_eq!(sorted.max_version(), 2);
+  assert_eq!(sorted.min_version(), 0);
+  for version in 0..3 {
+    assert!(sorted
+      .iter_all_versions(version)
+      .map(|ent| (
+        ent.key().to_vec(),
+        ent.version(),
+        ent.value().map(|v| v.to_vec())
+      ))
+      .eq(l.iter_all_versions(version).map(|ent| (
+        ent.key().to_vec(),
+        ent.version(),
+        ent.value().map(|v| v.to_vec())
+      ))));
+    assert!(sorted
+      .iter(version)
+      .rev()
+      .map(|ent| (ent.key().to_vec(), ent.value().to_vec()))
+      .eq(
+        l.iter(version)
+          .rev()
+          .map(|ent| (ent.key().to_vec(), ent.value().to_vec()))
+      ));
+  }
+  for i in 0..N {
+    assert_eq!(
+      sorted.get(1, &key(i)).unwrap().value(),
+      make_value(i * 3 + 1)
+    );
+  }
+
+  // A duplicate entry is skipped, an out of order one is rejected.
+  let dup = [(1u64, key(0), new_value(0)), (1, key(0), new_value(1))];
+  let map = SkipMap::build_sorted(opts, dup).unwrap();
+  assert_eq!(map.len(), 1);
+  assert_eq!(map.get(1, &key(0)).unwrap().value(), new_value(0));
+
+  let older_first = [(0u64, key(0), new_value(0)), (1, key(0), new_value(1))];
+  assert_eq!(
+    SkipMap::build_sorted(opts, older_first).unwrap_err(),
+    Error::Unsorted
+  );
+  let unsorted = [(0u64, key(1), new_value(1)), (0, key(0), new_value(0))];
+  assert_eq!(
+    SkipMap::build_sorted(opts, unsorted).unwrap_err(),
+    Error::Unsorted
+  );
+}
+
+#[test]
+fn test_build_sorted() {
+  run(|| build_sorted(TEST_OPTIONS))
+}
+
+#[test]
+fn test_build_sorted_unify() {
+  run(|| build_sorted(UNIFY_TEST_OPTIONS))
+}
+
+fn entry_at_offset(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in 0..N {
+    for version in 0..2 {
+      l.get_or_insert(version, &key(i), &make_value(i * 2 + version as usize))
+        .unwrap();
+    }
+  }
+
+  let offsets = l
+    .iter_all_versions(1)
+    .map(|ent| (ent.node_offset(), ent.key().to_vec(), ent.version()))
+    .collect::<Vec<_>>();
+  assert_eq!(offsets.len(), N * 2);
+
+  for (offset, k, version) in &offsets {
+    let ent = unsafe { l.entry_at_offset(*offset) }.unwrap();
+    assert_eq!(ent.key(), k.as_slice());
+    assert_eq!(ent.version(), *version);
+    assert_eq!(ent.node_offset(), *offset);
+    assert_eq!(l.resolve(*offset).unwrap().value(), ent.value());
+  }
+
+  // The entries are re-read, so the later writes to the same nodes are visible.
+  let (offset, k, version) = offsets[0].clone();
+  l.insert(version, &k, &new_value(0)).unwrap();
+  assert_eq!(
+    unsafe { l.entry_at_offset(offset) }.unwrap().value(),
+    new_value(0)
+  );
+  l.remove(version, &k).unwrap();
+  assert!(unsafe { l.entry_at_offset(offset) }.is_none());
+}
+
+#[test]
+fn test_entry_at_offset() {
+  run(|| entry_at_offset(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_entry_at_offset_unify() {
+  run(|| entry_at_offset(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_entry_at_offset_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    entry_at_offset(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn capacity_for(opts: Options) {
+  #[cfg(not(miri))]
+  const N: usize = 10_000;
+  #[cfg(miri)]
+  const N: usize = 100;
+
+  let opts = opts.with_capacity_for(N, 8, 16);
+  let l = SkipMap::<u64>::with_options(opts).unwrap();
+  for i in 0..N {
+    l.insert(0, &(i as u64).to_be_bytes(), &[i as u8; 16])
+      .unwrap();
+  }
+  assert_eq!(l.len(), N);
+  // The estimate does not waste more than its margin and the fixed part.
+  assert!(l.allocated() * 2 > l.capacity());
+
+  let small = Options::new().with_capacity_for(1, 8, 16);
+  let l = SkipMap::<u64>::with_options(small).unwrap();
+  l.insert(0, &0u64.to_be_bytes(), &[0; 16]).unwrap();
+
+  assert_eq!(
+    Options::new()
+      .with_capacity_for(usize::MAX, 8, 16)
+      .capacity(),
+    u32::MAX
+  );
+}
+
+#[test]
+fn test_capacity_for() {
+  run(|| capacity_for(Options::new()))
+}
+
+#[test]
+fn test_capacity_for_unify() {
+  run(|| capacity_for(Options::new().with_unify(true)))
+}
+
+fn level_lengths(l: SkipMap) {
+  const N: usize = 1000;
+
+  assert!(l.level_lengths().iter().all(|&len| len == 0));
+
+  for i in 0..N {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  l.get_or_remove(1, &key(N)).unwrap();
+
+  // With p = 1/e, 1001 nodes are expected to be about ln(1001) ~ 7 levels high.
+  let height = l.height();
+  assert!((3..=20).contains(&height), "unexpected height {height}");
+
+  let lengths = l.level_lengths();
+  assert_eq!(lengths.len(), height as usize);
+  assert_eq!(lengths[0], l.len());
+  assert!(lengths.windows(2).all(|w| w[0] >= w[1]));
+  assert!(*lengths.last().unwrap() > 0);
+
+  // A node of height `h` is linked at the levels below `h`.
+  let heights = l.memory_footprint().heights().to_vec();
+  for (level, len) in lengths.iter().enumerate() {
+    assert_eq!(*len, heights[level..].iter().sum::<usize>());
+  }
+}
+
+#[test]
+fn test_level_lengths() {
+  run(|| level_lengths(SkipMap::with_options(TEST_OPTIONS.with_height_seed(42)).unwrap()));
+}
+
+#[test]
+fn test_level_lengths_unify() {
+  run(|| level_lengths(SkipMap::with_options(UNIFY_TEST_OPTIONS.with_height_seed(42)).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_level_lengths_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    level_lengths(
+      SkipMap::map_anon_with_options(Options::new().with_height_seed(42), map_options).unwrap(),
+    );
+  })
+}
+
+fn insert_batch(l: SkipMap) {
+  const N: usize = 100;
+
+  let keys = (0..N).map(key).collect::<Vec<_>>();
+  let values = (0..N).map(new_value).collect::<Vec<_>>();
+  let kvs = keys
+    .iter()
+    .zip(values.iter())
+    .map(|(k, v)| (k.as_slice(), v.as_slice()))
+    .collect::<Vec<_>>();
+
+  l.insert_batch(1, &kvs).unwrap();
+  assert_eq!(l.len(), N);
+  for i in 0..N {
+    let k = key(i);
+    let ent = l.get(1, &k).unwrap();
+    assert_eq!(ent.value(), new_value(i));
+    assert_eq!(ent.version(), 1);
+  }
+
+  // The pairs are upserted, in any order.
+  let updated = (0..N).map(|i| new_value(i + 1)).collect::<Vec<_>>();
+  let kvs = keys
+    .iter()
+    .zip(updated.iter())
+    .rev()
+    .map(|(k, v)| (k.as_slice(), v.as_slice()))
+    .collect::<Vec<_>>();
+  l.insert_batch(1, &kvs).unwrap();
+  assert_eq!(l.len(), N);
+  for i in 0..N {
+    assert_eq!(l.get(1, &key(i)).unwrap().value(), new_value(i + 1));
+  }
+
+  // A scrambled batch with repeated keys at an older version, the last write of a key wins.
+  let scrambled = (0..N * 2)
+    .map(|i| (i * 37 % N, new_value(i)))
+    .collect::<Vec<_>>();
+  let scrambled_keys = scrambled.iter().map(|(i, _)| key(*i)).collect::<Vec<_>>();
+  let kvs = scrambled_keys
+    .iter()
+    .zip(scrambled.iter())
+    .map(|(k, (_, v))| (k.as_slice(), v.as_slice()))
+    .collect::<Vec<_>>();
+  l.insert_batch(0, &kvs).unwrap();
+  l.validate().unwrap();
+  assert_eq!(l.len(), N * 2);
+  for (i, (idx, _)) in scrambled.iter().enumerate().skip(N) {
+    let k = key(*idx);
+    assert_eq!(l.get(0, &k).unwrap().value(), new_value(i));
+    assert_eq!(l.get(1, &k).unwrap().value(), new_value(idx + 1));
+  }
+}
+
+#[test]
+fn test_insert_batch() {
+  run(|| insert_batch(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_insert_batch_unify() {
+  run(|| insert_batch(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_insert_batch_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    insert_batch(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn insert_batch_overflow(opts: Options) {
+  const N: usize = 1000;
+
+  let keys = (0..N).map(key).collect::<Vec<_>>();
+  let values = (0..N).map(new_value).collect::<Vec<_>>();
+  let kvs = keys
+    .iter()
+    .zip(values.iter())
+    .map(|(k, v)| (k.as_slice(), v.as_slice()))
+    .collect::<Vec<_>>();
+
+  // The values fit, but the nodes and the keys overflow the ARENA partway.
+  let l = SkipMap::with_options(opts.with_capacity(16 << 10)).unwrap();
+  let (idx, err) = l.insert_batch(0, &kvs).unwrap_err();
+  assert!(matches!(
+    err,
+    Error::Arena(ArenaError::InsufficientSpace { .. })
+  ));
+  assert!(idx > 0 && idx < N);
+  assert_eq!(l.len(), idx);
+  for i in 0..idx {
+    assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+  }
+  assert!(l.get(0, &key(idx)).is_none());
+
+  // The remainder is retried in a larger map.
+  let larger = SkipMap::with_options(opts.with_capacity(ARENA_SIZE as u32)).unwrap();
+  larger.insert_batch(0, &kvs[idx..]).unwrap();
+  assert_eq!(larger.len(), N - idx);
+  assert_eq!(l.len() + larger.len(), N);
+
+  // A batch whose values cannot fit fails before writing anything.
+  let allocated = l.allocated();
+  let (idx, err) = l
+    .insert_batch(1, &[(b"a", b"a"), (b"b", &[0; 16 << 10])])
+    .unwrap_err();
+  assert_eq!(idx, 0);
+  assert!(matches!(
+    err,
+    Error::Arena(ArenaError::InsufficientSpace { .. })
+  ));
+  assert_eq!(l.allocated(), allocated);
+  assert!(l.get(1, b"a").is_none());
+}
+
+#[test]
+fn test_insert_batch_overflow() {
+  run(|| insert_batch_overflow(Options::new()));
+}
+
+#[test]
+fn test_insert_batch_overflow_unify() {
+  run(|| insert_batch_overflow(Options::new().with_unify(true)));
+}
+
+#[test]
+fn test_comparator_matches_slice_cmp() {
+  use rand::Rng;
+
+  #[cfg(not(miri))]
+  const N: usize = 10_000;
+  #[cfg(miri)]
+  const N: usize = 100;
+
+  let mut rng = rand::thread_rng();
+  for _ in 0..N {
+    // A long shared prefix, then a few random bytes from a small alphabet, so the slices are often
+    // equal or a prefix of each other.
+    let prefix_len = rng.gen_range(0..40);
+    let prefix = (0..prefix_len).map(|_| rng.gen()).collect::<Vec<u8>>();
+    let tail = |rng: &mut rand::rngs::ThreadRng| {
+      let mut v = prefix.clone();
+      v.extend((0..rng.gen_range(0..20)).map(|_| rng.gen_range(0..3u8)));
+      v
+    };
+    let a = tail(&mut rng);
+    let b = tail(&mut rng);
+
+    assert_eq!(Ascend.compare(&a, &b), a.cmp(&b), "{a:?} {b:?}");
+    assert_eq!(Descend.compare(&a, &b), b.cmp(&a), "{a:?} {b:?}");
+    // A proper prefix is ordered first.
+    let half = &a[..a.len() / 2];
+    assert_eq!(Ascend.compare(&a, half), a.len().cmp(&half.len()));
+  }
+}
+
+fn version_range(l: SkipMap) {
+  assert_eq!(l.version_range(), (0, 0));
+
+  for version in 1..=10 {
+    l.insert(
+      version,
+      &key(version as usize),
+      &new_value(version as usize),
+    )
+    .unwrap();
+  }
+  assert_eq!(l.version_range(), (1, 10));
+  assert_eq!(l.min_version(), 1);
+  assert_eq!(l.max_version(), 10);
+
+  // Writes to an existing version do not change the range.
+  l.insert(5, &key(5), &new_value(50)).unwrap();
+  l.get_or_remove(10, &key(10)).unwrap();
+  assert_eq!(l.version_range(), (1, 10));
+
+  l.insert(100, &key(100), &new_value(100)).unwrap();
+  assert_eq!(l.version_range(), (1, 100));
+
+  // A tombstone carries a version too.
+  l.remove(0, &key(1)).unwrap();
+  assert_eq!(l.version_range(), (0, 100));
+  assert_eq!(l.stats().tombstones(), 1);
+}
+
+#[test]
+fn test_version_range() {
+  run(|| version_range(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_version_range_unify() {
+  run(|| version_range(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_version_range_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    version_range(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+fn test_version_range_reset() {
+  run(|| {
+    let mut l = SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap();
+    l.insert(7, &key(7), &new_value(7)).unwrap();
+    l.insert(9, &key(9), &new_value(9)).unwrap();
+    assert_eq!(l.version_range(), (7, 9));
+
+    l.reset().unwrap();
+    assert_eq!(l.version_range(), (0, 0));
+    l.insert(8, &key(8), &new_value(8)).unwrap();
+    assert_eq!(l.version_range(), (8, 8));
+
+    l.clear().unwrap();
+    assert_eq!(l.version_range(), (0, 0));
+    l.insert(3, &key(3), &new_value(3)).unwrap();
+    assert_eq!(l.version_range(), (3, 3));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_expirable_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    expirable(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn ordering() {
+  let l = SkipMap::with_options_and_comparator(TEST_OPTIONS, Descend).unwrap();
+
+  l.get_or_insert(1, b"a1", b"a1").unwrap();
+  l.get_or_insert(2, b"a2", b"a2").unwrap();
+  l.get_or_insert(3, b"a3", b"a3").unwrap();
+
+  let mut it = l.iter_all_versions(3);
+  for i in (1..=3).rev() {
+    let ent = it.next().unwrap();
+    assert_eq!(ent.key(), format!("a{i}").as_bytes());
+    assert_eq!(ent.value().unwrap(), format!("a{i}").as_bytes());
+  }
+}
+
+#[test]
+fn test_ordering() {
+  run(ordering);
+}
+
+fn get_mvcc(l: SkipMap) {
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  l.get_or_insert(3, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"c", b"c1").unwrap();
+  l.get_or_insert(3, b"c", b"c2").unwrap();
+
+  let ent = l.get(1, b"a").unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.get(2, b"a").unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.get(3, b"a").unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.get(4, b"a").unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  assert!(l.get(0, b"b").is_none());
+  assert!(l.get(1, b"b").is_none());
+  assert!(l.get(2, b"b").is_none());
+  assert!(l.get(3, b"b").is_none());
+  assert!(l.get(4, b"b").is_none());
+
+  let ent = l.get(1, b"c").unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.get(2, b"c").unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.get(3, b"c").unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.get(4, b"c").unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  assert!(l.get(5, b"d").is_none());
+}
+
+#[test]
+fn test_get_mvcc() {
+  run(|| get_mvcc(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_get_mvcc_unify() {
+  run(|| get_mvcc(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_get_mvcc_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_get_mvcc_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    get_mvcc(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_mvcc_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_mvcc(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_mvcc_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_mvcc(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn gt_in(l: SkipMap) {
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  l.get_or_insert(3, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"c", b"c1").unwrap();
+  l.get_or_insert(3, b"c", b"c2").unwrap();
+  l.get_or_insert(5, b"c", b"c3").unwrap();
+
+  assert!(l.lower_bound(0, Bound::Excluded(b"a")).is_none());
+  assert!(l.lower_bound(0, Bound::Excluded(b"b")).is_none());
+  assert!(l.lower_bound(0, Bound::Excluded(b"c")).is_none());
+
+  let ent = l.lower_bound(1, Bound::Excluded(b"")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(2, Bound::Excluded(b"")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(3, Bound::Excluded(b"")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(1, Bound::Excluded(b"a")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(2, Bound::Excluded(b"a")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(3, Bound::Excluded(b"a")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(1, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(2, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(3, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(4, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(5, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c3");
+  assert_eq!(ent.trailer().version(), 5);
+
+  let ent = l.lower_bound(6, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c3");
+  assert_eq!(ent.trailer().version(), 5);
+
+  assert!(l.lower_bound(1, Bound::Excluded(b"c")).is_none());
+  assert!(l.lower_bound(2, Bound::Excluded(b"c")).is_none());
+  assert!(l.lower_bound(3, Bound::Excluded(b"c")).is_none());
+  assert!(l.lower_bound(4, Bound::Excluded(b"c")).is_none());
+  assert!(l.lower_bound(5, Bound::Excluded(b"c")).is_none());
+  assert!(l.lower_bound(6, Bound::Excluded(b"c")).is_none());
+}
+
+#[test]
+fn test_gt() {
+  run(|| gt_in(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_gt_unify() {
+  run(|| gt_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_gt_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_gt_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    gt_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_gt_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    gt_in(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_gt_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    gt_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn ge_in(l: SkipMap) {
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  l.get_or_insert(3, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"c", b"c1").unwrap();
+  l.get_or_insert(3, b"c", b"c2").unwrap();
+
+  assert!(l.lower_bound(0, Bound::Included(b"a")).is_none());
+  assert!(l.lower_bound(0, Bound::Included(b"b")).is_none());
+  assert!(l.lower_bound(0, Bound::Included(b"c")).is_none());
+
+  let ent = l.lower_bound(1, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(2, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(3, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(4, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(1, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(2, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(3, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(4, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(1, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(2, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.lower_bound(3, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.lower_bound(4, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  assert!(l.lower_bound(0, Bound::Included(b"d")).is_none());
+  assert!(l.lower_bound(1, Bound::Included(b"d")).is_none());
+  assert!(l.lower_bound(2, Bound::Included(b"d")).is_none());
+  assert!(l.lower_bound(3, Bound::Included(b"d")).is_none());
+  assert!(l.lower_bound(4, Bound::Included(b"d")).is_none());
+}
+
+#[test]
+fn test_ge() {
+  run(|| ge_in(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_ge_unify() {
+  run(|| ge_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_ge_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_ge_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    ge_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_ge_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    ge_in(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_ge_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    ge_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn le_in(l: SkipMap) {
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  l.get_or_insert(3, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"c", b"c1").unwrap();
+  l.get_or_insert(3, b"c", b"c2").unwrap();
+
+  assert!(l.upper_bound(0, Bound::Included(b"a")).is_none());
+  assert!(l.upper_bound(0, Bound::Included(b"b")).is_none());
+  assert!(l.upper_bound(0, Bound::Included(b"c")).is_none());
+
+  let ent = l.upper_bound(1, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(2, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(3, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(4, Bound::Included(b"a")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(1, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(2, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(3, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(4, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(1, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(2, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(3, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(4, Bound::Included(b"c")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(1, Bound::Included(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(2, Bound::Included(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(3, Bound::Included(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(4, Bound::Included(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+}
+
+#[test]
+fn test_le() {
+  run(|| le_in(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_le_unify() {
+  run(|| le_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_le_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_le_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    le_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_le_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    le_in(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_le_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    le_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn le_mvcc_in(l: SkipMap) {
+  l.get_or_insert(2, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"b", b"b1").unwrap();
+  l.get_or_insert(3, b"b", b"b3").unwrap();
+  l.get_or_insert(5, b"b", b"b5").unwrap();
+  l.get_or_insert(4, b"c", b"c4").unwrap();
+  l.get_or_remove(7, b"b").unwrap();
+
+  // The latest visible version of each key at a version, as (key, value).
+  let model = |version: u64| -> Vec<(&[u8], &[u8])> {
+    let mut visible: Vec<(&[u8], &[u8])> = vec![];
+    if version >= 2 {
+      visible.push((b"a", b"a2"));
+    }
+    match version {
+      1 | 2 => visible.push((b"b", b"b1")),
+      3 | 4 => visible.push((b"b", b"b3")),
+      5 | 6 => visible.push((b"b", b"b5")),
+      _ => {}
+    }
+    if version >= 4 {
+      visible.push((b"c", b"c4"));
+    }
+    visible
+  };
+
+  let keys: [&[u8]; 5] = [b"", b"a", b"b", b"bb", b"c"];
+  for version in 0..=8 {
+    let visible = model(version);
+    for k in keys {
+      let expected = visible.iter().rev().find(|(vk, _)| *vk <= k);
+      let ent = l.upper_bound(version, Bound::Included(k));
+      assert_eq!(
+        ent.as_ref().map(|ent| (ent.key(), ent.value())),
+        expected.copied(),
+        "upper_bound(Included({k:?})) at version {version}",
+      );
+
+      let expected = visible.iter().rev().find(|(vk, _)| *vk < k);
+      let ent = l.upper_bound(version, Bound::Excluded(k));
+      assert_eq!(
+        ent.as_ref().map(|ent| (ent.key(), ent.value())),
+        expected.copied(),
+        "upper_bound(Excluded({k:?})) at version {version}",
+      );
+
+      let expected = visible.iter().find(|(vk, _)| *vk >= k);
+      let ent = l.lower_bound(version, Bound::Included(k));
+      assert_eq!(
+        ent.as_ref().map(|ent| (ent.key(), ent.value())),
+        expected.copied(),
+        "lower_bound(Included({k:?})) at version {version}",
+      );
+
+      let expected = visible.iter().find(|(vk, _)| *vk > k);
+      let ent = l.lower_bound(version, Bound::Excluded(k));
+      assert_eq!(
+        ent.as_ref().map(|ent| (ent.key(), ent.value())),
+        expected.copied(),
+        "lower_bound(Excluded({k:?})) at version {version}",
+      );
+    }
+  }
+
+  let ent = l.upper_bound(4, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.value(), b"b3");
+  assert_eq!(ent.trailer().version(), 3);
+  let ent = l.upper_bound(7, Bound::Included(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+}
+
+#[test]
+fn test_le_mvcc() {
+  run(|| le_mvcc_in(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_le_mvcc_unify() {
+  run(|| le_mvcc_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_le_mvcc_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_le_mvcc_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    le_mvcc_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_le_mvcc_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    le_mvcc_in(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn lt_in(l: SkipMap) {
+  l.get_or_insert(1, b"a", b"a1").unwrap();
+  l.get_or_insert(3, b"a", b"a2").unwrap();
+  l.get_or_insert(1, b"c", b"c1").unwrap();
+  l.get_or_insert(3, b"c", b"c2").unwrap();
+
+  assert!(l.upper_bound(0, Bound::Excluded(b"a")).is_none());
+  assert!(l.upper_bound(0, Bound::Excluded(b"b")).is_none());
+  assert!(l.upper_bound(0, Bound::Excluded(b"c")).is_none());
+  assert!(l.upper_bound(1, Bound::Excluded(b"a")).is_none());
+  assert!(l.upper_bound(2, Bound::Excluded(b"a")).is_none());
+
+  let ent = l.upper_bound(1, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(2, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(3, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(4, Bound::Excluded(b"b")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(1, Bound::Excluded(b"c")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(2, Bound::Excluded(b"c")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(3, Bound::Excluded(b"c")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(4, Bound::Excluded(b"c")).unwrap();
+  assert_eq!(ent.key(), b"a");
+  assert_eq!(ent.value(), b"a2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(1, Bound::Excluded(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(2, Bound::Excluded(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c1");
+  assert_eq!(ent.trailer().version(), 1);
+
+  let ent = l.upper_bound(3, Bound::Excluded(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+
+  let ent = l.upper_bound(4, Bound::Excluded(b"d")).unwrap();
+  assert_eq!(ent.key(), b"c");
+  assert_eq!(ent.value(), b"c2");
+  assert_eq!(ent.trailer().version(), 3);
+}
+
+#[test]
+fn test_lt() {
+  run(|| lt_in(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_lt_unify() {
+  run(|| lt_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_lt_map_mut() {
+  let dir = tempfile::tempdir().unwrap();
+  let p = dir.path().join("test_skipmap_lt_map_mut");
+  let open_options = OpenOptions::default()
+    .create_new(Some(ARENA_SIZE as u32))
+    .read(true)
+    .write(true);
+  let map_options = MmapOptions::default();
+  lt_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+
+fn test_lt_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    lt_in(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_lt_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    lt_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn test_basic_large_testcases_in(l: Arc<SkipMap>) {
+  let n = 1000;
+
+  for i in 0..n {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+
+  for i in 0..n {
+    let k = key(i);
+    let ent = l.get(0, &k).unwrap();
+    assert_eq!(new_value(i), ent.value());
+    assert_eq!(ent.trailer().version(), 0);
+    assert_eq!(ent.key(), k);
+  }
+
+  assert_eq!(n, l.len());
+}
+
+#[test]
+fn test_basic_large_testcases() {
+  run(|| {
+    let l = Arc::new(SkipMap::with_options(TEST_OPTIONS).unwrap());
+    test_basic_large_testcases_in(l);
+  })
+}
+
+#[test]
+fn test_basic_large_testcases_unify() {
+  run(|| {
+    let l = Arc::new(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
+    test_basic_large_testcases_in(l);
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_basic_large_testcases_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_basic_large_testcases_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    let l = Arc::new(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    test_basic_large_testcases_in(l);
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_basic_large_testcases_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    let l = Arc::new(SkipMap::map_anon(map_options).unwrap());
+    test_basic_large_testcases_in(l);
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_basic_large_testcases_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    let l = Arc::new(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    test_basic_large_testcases_in(l);
+  })
+}
+
+#[cfg(feature = "std")]
+fn test_concurrent_basic_runner(l: Arc<SkipMap>) {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 1000;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 5;
+
+  let wg = Arc::new(());
+  for i in 0..N {
+    let w = wg.clone();
+    let l = l.clone();
+    std::thread::spawn(move || {
+      l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      drop(w);
+    });
+  }
+  while Arc::strong_count(&wg) > 1 {}
+  for i in 0..N {
+    let w = wg.clone();
+    let l = l.clone();
+    std::thread::spawn(move || {
+      let k = key(i);
+      assert_eq!(l.get(0, &k).unwrap().value(), new_value(i), "broken: {i}");
+      drop(w);
+    });
+  }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_basic() {
+  run(|| {
+    let l = Arc::new(
+      SkipMap::with_options(TEST_OPTIONS)
+        .unwrap()
+        .with_yield_now(),
+    );
+    test_concurrent_basic_runner(l);
+  })
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_basic_unify() {
+  run(|| {
+    let l = Arc::new(
+      SkipMap::with_options(UNIFY_TEST_OPTIONS)
+        .unwrap()
+        .with_yield_now(),
+    );
+    test_concurrent_basic_runner(l);
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_concurrent_basic_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_concurrent_basic_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    let l = Arc::new(
+      SkipMap::map_mut(p, open_options, map_options)
+        .unwrap()
+        .with_yield_now(),
+    );
+    test_concurrent_basic_runner(l);
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_concurrent_basic_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    test_concurrent_basic_runner(Arc::new(
+      SkipMap::map_anon(map_options).unwrap().with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_concurrent_basic_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    test_concurrent_basic_runner(Arc::new(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[cfg(feature = "std")]
+fn test_concurrent_basic_big_values_runner(l: Arc<SkipMap>) {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 100;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 5;
+
+  for i in 0..N {
+    let l = l.clone();
+    std::thread::spawn(move || {
+      l.get_or_insert(0, &key(i), &big_value(i)).unwrap();
+    });
+  }
+  while Arc::strong_count(&l) > 1 {}
+  // assert_eq!(N, l.len());
+  for i in 0..N {
+    let l = l.clone();
+    std::thread::spawn(move || {
+      let k = key(i);
+      assert_eq!(l.get(0, &k).unwrap().value(), big_value(i), "broken: {i}");
+    });
+  }
+  while Arc::strong_count(&l) > 1 {}
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_basic_big_values() {
+  run(|| {
+    test_concurrent_basic_big_values_runner(Arc::new(
+      SkipMap::with_options(BIG_TEST_OPTIONS)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_basic_big_values_unify() {
+  run(|| {
+    test_concurrent_basic_big_values_runner(Arc::new(
+      SkipMap::with_options(UNIFY_BIG_TEST_OPTIONS)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_concurrent_basic_big_values_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_concurrent_basic_big_values_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(120 << 20))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    test_concurrent_basic_big_values_runner(Arc::new(
+      SkipMap::map_mut(p, open_options, map_options)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_concurrent_basic_big_values_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(120 << 20);
+    test_concurrent_basic_big_values_runner(Arc::new(
+      SkipMap::map_anon(map_options).unwrap().with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_concurrent_basic_big_values_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(120 << 20);
+    test_concurrent_basic_big_values_runner(Arc::new(
+      SkipMap::map_anon_with_options(UNIFY_BIG_TEST_OPTIONS, map_options)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[cfg(feature = "std")]
+fn concurrent_backoff(backoff: crate::options::Backoff) {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 8;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 2;
+  #[cfg(not(any(miri, feature = "loom")))]
+  const KEYS: usize = 500;
+  #[cfg(any(miri, feature = "loom"))]
+  const KEYS: usize = 10;
+
+  let l = Arc::new(SkipMap::with_options(TEST_OPTIONS.with_backoff(backoff)).unwrap());
+  let wg = WaitGroup::new();
+  for t in 0..N {
+    let wg = wg.add(1);
+    let l = l.clone();
+    std::thread::spawn(move || {
+      // All the threads race on the same keys, and each of them adds its own version.
+      for i in 0..KEYS {
+        l.get_or_insert(t as u64, &key(i), &new_value(i)).unwrap();
+      }
+      wg.done();
+    });
+  }
+  wg.wait();
+
+  assert_eq!(l.len(), N * KEYS);
+  for i in 0..KEYS {
+    let k = key(i);
+    for t in 0..N {
+      let ent = l.get(t as u64, &k).unwrap();
+      assert_eq!(ent.trailer().version(), t as u64);
+      assert_eq!(ent.value(), new_value(i));
+    }
+  }
+  assert_eq!(l.iter(N as u64).count(), KEYS);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_backoff_none() {
+  run(|| concurrent_backoff(crate::options::Backoff::None))
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_backoff_spin() {
+  run(|| concurrent_backoff(crate::options::Backoff::Spin))
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_backoff_yield() {
+  run(|| concurrent_backoff(crate::options::Backoff::Yield))
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_backoff_exponential() {
+  run(|| concurrent_backoff(crate::options::Backoff::Exponential { max_spins: 64 }))
+}
+
+#[cfg(feature = "std")]
+static CUSTOM_BACKOFF_ATTEMPTS: core::sync::atomic::AtomicU32 =
+  core::sync::atomic::AtomicU32::new(0);
+
+#[cfg(feature = "std")]
+fn custom_backoff(attempt: u32) {
+  CUSTOM_BACKOFF_ATTEMPTS.fetch_max(attempt + 1, Ordering::Relaxed);
+  std::thread::yield_now();
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_backoff_custom() {
+  let backoff = crate::options::Backoff::Custom(custom_backoff);
+  assert_eq!(TEST_OPTIONS.with_backoff(backoff).backoff(), backoff);
+
+  backoff.snooze(3);
+  assert!(CUSTOM_BACKOFF_ATTEMPTS.load(Ordering::Relaxed) >= 4);
+
+  run(move || concurrent_backoff(backoff))
+}
+
+fn session(l: SkipMap) {
+  let k1 = key(1);
+  let k2 = key(2);
+  l.insert(5, &k1, &new_value(0)).unwrap();
+
+  let session = l.session(5);
+  assert_eq!(session.get(&k1).unwrap().value(), new_value(0));
+  assert!(session.get(&k2).is_none());
+
+  for i in 1..10 {
+    let value = new_value(i);
+    let version = session.version();
+    session.insert(&k1, &value).unwrap();
+    assert_eq!(session.version(), version + 1);
+    assert_eq!(session.get(&k1).unwrap().value(), new_value(i));
+    assert_eq!(session.get(&k1).unwrap().version(), session.version());
+
+    if i % 2 == 0 {
+      session.insert(&k2, &value).unwrap();
+      assert!(session.contains_key(&k2));
+    } else {
+      session.remove(&k2).unwrap();
+      assert!(!session.contains_key(&k2));
+    }
+  }
+
+  // The writes before the session started are still visible at their versions.
+  assert_eq!(l.get(5, &k1).unwrap().value(), new_value(0));
+  assert_eq!(l.get(6, &k1).unwrap().value(), new_value(1));
+  assert_eq!(session.version(), 5 + 18);
+}
+
+#[test]
+fn test_session() {
+  run(|| session(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_session_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    session(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[cfg(feature = "std")]
+fn concurrent_session(l: SkipMap) {
+  use std::collections::HashSet;
+
+  let session = l.session(0);
+  let versions = std::thread::scope(|s| {
+    let handles = (0..4)
+      .map(|t| {
+        let session = &session;
+        s.spawn(move || {
+          (0..100)
+            .map(|i| {
+              let k = key(t * 100 + i);
+              session.insert(&k, &k).unwrap();
+              assert_eq!(session.get(&k).unwrap().value(), k);
+              session.get(&k).unwrap().version()
+            })
+            .collect::<std::vec::Vec<_>>()
+        })
+      })
+      .collect::<std::vec::Vec<_>>();
+    handles
+      .into_iter()
+      .flat_map(|h| h.join().unwrap())
+      .collect::<HashSet<_>>()
+  });
+
+  assert_eq!(versions.len(), 400);
+  assert_eq!(session.version(), 400);
+  assert_eq!(l.len(), 400);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_session() {
+  concurrent_session(SkipMap::with_options(TEST_OPTIONS).unwrap());
+}
+
+#[cfg(feature = "std")]
+fn concurrent_try_get_or_insert(l: Arc<SkipMap>) {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 1000;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 5;
+  const THREADS: u64 = 8;
+
+  let wg = WaitGroup::new();
+  let contended = Arc::new(std::sync::Mutex::new(std::vec::Vec::new()));
+  for version in 0..THREADS {
+    let wg = wg.add(1);
+    let l = l.clone();
+    let contended = contended.clone();
+    std::thread::spawn(move || {
+      for i in 0..N {
+        let k = key(i);
+        match l.try_get_or_insert(version, &k, &new_value(i), 0).unwrap() {
+          Ok(old) => assert!(old.is_none()),
+          Err(Contended) => contended.lock().unwrap().push((version, i)),
+        }
+      }
+      wg.done();
+    });
+  }
+
+  wg.wait();
+
+  let contended = contended.lock().unwrap().clone();
+  #[cfg(not(any(miri, feature = "loom")))]
+  assert!(!contended.is_empty());
+  assert_eq!(l.len(), THREADS as usize * N - contended.len());
+  l.validate().unwrap();
+
+  // A contended insert leaves nothing behind, and can be retried.
+  for (version, i) in contended {
+    let k = key(i);
+    assert!(l.get_exact(version, &k).is_none());
+    assert!(l
+      .try_get_or_insert(version, &k, &new_value(i), usize::MAX)
+      .unwrap()
+      .unwrap()
+      .is_none());
+  }
+  assert_eq!(l.len(), THREADS as usize * N);
+  for version in 0..THREADS {
+    for i in 0..N {
+      let k = key(i);
+      assert_eq!(
+        l.get_exact(version, &k).unwrap().value().unwrap(),
+        new_value(i)
+      );
+    }
+  }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_try_get_or_insert() {
+  run(|| {
+    concurrent_try_get_or_insert(Arc::new(
+      SkipMap::with_options(BIG_TEST_OPTIONS)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_concurrent_try_get_or_insert_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(BIG_ARENA_SIZE as u32);
+    concurrent_try_get_or_insert(Arc::new(
+      SkipMap::map_anon(map_options).unwrap().with_yield_now(),
+    ));
+  })
+}
+
+#[cfg(feature = "std")]
+fn concurrent_acquire_value(l: Arc<SkipMap>) {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 1000;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 5;
+
+  let writer = {
+    let l = l.clone();
+    std::thread::spawn(move || {
+      for i in 0..N {
+        l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      }
+      for i in 0..N {
+        l.insert(0, &key(i), &make_value(i)).unwrap();
+      }
+    })
+  };
+
+  // A reader observing a published node, or a published value, sees all of its bytes.
+  for i in 0..N {
+    let k = key(i);
+    loop {
+      if let Some(ent) = l.get(0, &k) {
+        let val = ent.value();
+        assert!(val == new_value(i) || val == make_value(i), "key {i}");
+        break;
+      }
+      std::thread::yield_now();
+    }
+  }
+
+  writer.join().unwrap();
+  for i in 0..N {
+    assert_eq!(l.get(0, &key(i)).unwrap().value(), make_value(i));
+  }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_acquire_value() {
+  run(|| concurrent_acquire_value(Arc::new(SkipMap::with_options(TEST_OPTIONS).unwrap())))
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_acquire_value_unify() {
+  run(|| concurrent_acquire_value(Arc::new(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap())))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_concurrent_acquire_value_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    concurrent_acquire_value(Arc::new(SkipMap::map_anon(map_options).unwrap()));
+  })
+}
+
+fn concurrent_one_key(l: Arc<SkipMap>) {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 100;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 5;
+
+  let wg = WaitGroup::new();
+  for i in 0..N {
+    let wg = wg.add(1);
+    let l = l.clone();
+    std::thread::spawn(move || {
+      let _ = l.get_or_insert(0, b"thekey", &make_value(i));
+      wg.done();
+    });
+  }
+
+  wg.wait();
+
+  let saw_value = Arc::new(crate::sync::AtomicU32::new(0));
+  for _ in 0..N {
+    let wg = wg.add(1);
+    let l = l.clone();
+    let saw_value = saw_value.clone();
+    std::thread::spawn(move || {
+      let ent = l.get(0, b"thekey").unwrap();
+      let val = ent.value();
+      let num: usize = core::str::from_utf8(&val[1..]).unwrap().parse().unwrap();
+      assert!((0..N).contains(&num));
+
+      let mut it = l.iter_all_versions(0);
+      let ent = it.seek_lower_bound(Bound::Included(b"thekey")).unwrap();
+      let val = ent.value().unwrap();
+      let num: usize = core::str::from_utf8(&val[1..]).unwrap().parse().unwrap();
+      assert!((0..N).contains(&num));
+      assert_eq!(ent.key(), b"thekey");
+      saw_value.fetch_add(1, Ordering::SeqCst);
+      wg.done();
+    });
+  }
+
+  wg.wait();
+
+  assert_eq!(N, saw_value.load(Ordering::SeqCst) as usize);
+  assert_eq!(l.len(), 1);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_one_key() {
+  run(|| {
+    concurrent_one_key(Arc::new(
+      SkipMap::with_options(TEST_OPTIONS)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_one_key_unify() {
+  run(|| {
+    concurrent_one_key(Arc::new(
+      SkipMap::with_options(UNIFY_TEST_OPTIONS)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_concurrent_one_key_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_concurrent_one_key_map_mut");
+    let open_options = OpenOptions::default()
+      .create(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    concurrent_one_key(Arc::new(
+      SkipMap::map_mut(p, open_options, map_options)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_concurrent_one_key_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    concurrent_one_key(Arc::new(
+      SkipMap::map_anon(map_options).unwrap().with_yield_now(),
+    ));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_concurrent_one_key_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    concurrent_one_key(Arc::new(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options)
+        .unwrap()
+        .with_yield_now(),
+    ));
+  })
+}
+
+fn iter_all_versions_next(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in (0..N).rev() {
+    l.get_or_insert(0, &make_int_key(i), &make_value(i))
+      .unwrap();
+  }
+
+  let mut it = l.iter_all_versions(0);
+  let mut ent = it.seek_lower_bound(Bound::Unbounded).unwrap();
+  for i in 0..N {
+    assert_eq!(ent.key(), make_int_key(i));
+    assert_eq!(ent.value().unwrap(), make_value(i));
+    if i != N - 1 {
+      ent = it.next().unwrap();
+    }
+  }
+
+  assert!(it.next().is_none());
+}
+
+#[test]
+fn test_iter_all_versions_next() {
+  run(|| iter_all_versions_next(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_iter_all_versions_next_unify() {
+  run(|| iter_all_versions_next(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_iter_all_versions_next_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_iter_all_versions_next_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    iter_all_versions_next(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_next_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_next(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_next_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_next(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
+    );
+  })
+}
+
+fn range_next(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in (0..N).rev() {
+    l.get_or_insert(0, &make_int_key(i), &make_value(i))
+      .unwrap();
+  }
+
+  let upper = make_int_key(50);
+  let mut it = l.range(0, ..=upper.as_slice());
+  let mut ent = it.seek_lower_bound(Bound::Unbounded);
+  for i in 0..N {
+    if i <= 50 {
+      {
+        let ent = ent.unwrap();
+        assert_eq!(ent.key(), make_int_key(i));
+        assert_eq!(ent.value(), make_value(i));
+      }
+      ent = it.next();
+    } else {
+      assert!(ent.is_none());
+      ent = it.next();
+    }
+  }
+
+  assert!(it.next().is_none());
+}
+
+#[test]
+fn test_range_next() {
+  run(|| range_next(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_range_next_unify() {
+  run(|| range_next(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_range_next_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_range_next_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    iter_all_versions_next(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_next_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_next(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_next_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_next(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
+    );
+  })
+}
+
+fn iter_all_versions_prev(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in 0..N {
+    l.get_or_insert(0, &make_int_key(i), &make_value(i))
+      .unwrap();
+  }
+
+  let mut it = l.iter_all_versions(0);
+  let mut ent = it.seek_upper_bound(Bound::Unbounded).unwrap();
+  for i in (0..N).rev() {
+    assert_eq!(ent.key(), make_int_key(i));
+    assert_eq!(ent.value().unwrap(), make_value(i));
+    if i != 0 {
+      ent = it.next_back().unwrap();
+    }
+  }
+
+  assert!(it.next_back().is_none());
+}
+
+#[test]
+fn test_iter_all_versions_next_back() {
+  run(|| iter_all_versions_prev(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_iter_all_versions_prev_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_iter_all_versions_prev_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    iter_all_versions_prev(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_prev_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_prev(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_prev_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_prev(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
+    );
+  })
+}
+
+fn range_prev(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in 0..N {
+    l.get_or_insert(0, &make_int_key(i), &make_value(i))
+      .unwrap();
+  }
+
+  let lower = make_int_key(50);
+  let mut it = l.range(0, lower.as_slice()..);
+  let mut ent = it.seek_upper_bound(Bound::Unbounded);
+  for i in (0..N).rev() {
+    if i >= 50 {
+      {
+        let ent = ent.unwrap();
+        assert_eq!(ent.key(), make_int_key(i));
+        assert_eq!(ent.value(), make_value(i));
+      }
+      ent = it.next_back();
+    } else {
+      assert!(ent.is_none());
+      ent = it.next_back();
+    }
+  }
+
+  assert!(it.next_back().is_none());
+}
+
+#[test]
+fn test_range_prev() {
+  run(|| range_prev(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_range_prev_unify() {
+  run(|| range_prev(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_range_prev_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_range_prev_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    range_prev(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_prev_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    range_prev(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_prev_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    range_prev(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn iter_all_versions_seek_ge(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in (0..N).rev() {
+    let v = i * 10 + 1000;
+    l.get_or_insert(0, &make_int_key(v), &make_value(v))
+      .unwrap();
+  }
+
+  let mut it = l.iter_all_versions(0);
+  let ent = it.seek_lower_bound(Bound::Included(b"")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1000));
+  assert_eq!(ent.value().unwrap(), make_value(1000));
+
+  let ent = it.seek_lower_bound(Bound::Included(b"01000")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1000));
+  assert_eq!(ent.value().unwrap(), make_value(1000));
+
+  let ent = it.seek_lower_bound(Bound::Included(b"01005")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1010));
+  assert_eq!(ent.value().unwrap(), make_value(1010));
+
+  let ent = it.seek_lower_bound(Bound::Included(b"01010")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1010));
+  assert_eq!(ent.value().unwrap(), make_value(1010));
+
+  let ent = it.seek_lower_bound(Bound::Included(b"01020")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1020));
+  assert_eq!(ent.value().unwrap(), make_value(1020));
+
+  let ent = it.seek_lower_bound(Bound::Included(b"01200")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1200));
+  assert_eq!(ent.value().unwrap(), make_value(1200));
+
+  let ent = it.seek_lower_bound(Bound::Included(b"01100")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1100));
+  assert_eq!(ent.value().unwrap(), make_value(1100));
+
+  let ent = it.seek_lower_bound(Bound::Included(b"99999"));
+  assert!(ent.is_none());
+
+  l.get_or_insert(0, &[], &[]).unwrap();
+  let ent = it.seek_lower_bound(Bound::Included(b"")).unwrap();
+  assert_eq!(ent.key(), &[]);
+  assert_eq!(ent.value().unwrap(), &[]);
+
+  let ent = it.seek_lower_bound(Bound::Included(b"")).unwrap();
+  assert_eq!(ent.key(), &[]);
+  assert_eq!(ent.value().unwrap(), &[]);
+}
+
+#[test]
+fn test_iter_all_versions_seek_ge() {
+  run(|| iter_all_versions_seek_ge(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_iter_all_versions_seek_ge_unify() {
+  run(|| iter_all_versions_seek_ge(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_iter_all_versions_seek_ge_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_iter_all_versions_seek_ge_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    iter_all_versions_seek_ge(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_seek_ge_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_seek_ge(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_seek_ge_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_seek_ge(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
+    );
+  })
+}
+
+fn iter_all_versions_seek_lt(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in (0..N).rev() {
+    let v = i * 10 + 1000;
+    l.get_or_insert(0, &make_int_key(v), &make_value(v))
+      .unwrap();
+  }
+
+  let mut it = l.iter_all_versions(0);
+  assert!(it.seek_upper_bound(Bound::Excluded(b"")).is_none());
+
+  let ent = it.seek_upper_bound(Bound::Excluded(b"01000"));
+  assert!(ent.is_none());
+
+  let ent = it.seek_upper_bound(Bound::Excluded(b"01001")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1000));
+  assert_eq!(ent.value().unwrap(), make_value(1000));
+
+  let ent = it.seek_upper_bound(Bound::Excluded(b"01991")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1990));
+  assert_eq!(ent.value().unwrap(), make_value(1990));
+
+  let ent = it.seek_upper_bound(Bound::Excluded(b"99999")).unwrap();
+  assert_eq!(ent.key(), make_int_key(1990));
+  assert_eq!(ent.value().unwrap(), make_value(1990));
+
+  l.get_or_insert(0, &[], &[]).unwrap();
+  assert!(l.lt(0, &[]).is_none());
+
+  let ent = it.seek_upper_bound(Bound::Excluded(b""));
+  assert!(ent.is_none());
+
+  let ent = it.seek_upper_bound(Bound::Excluded(b"\x01")).unwrap();
+  assert_eq!(ent.key(), &[]);
+  assert_eq!(ent.value().unwrap(), &[]);
+}
+
+#[test]
+fn test_iter_all_versions_seek_lt() {
+  run(|| iter_all_versions_seek_lt(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_iter_all_versions_seek_lt_unify() {
+  run(|| iter_all_versions_seek_lt(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_iter_all_versions_seek_lt_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_iter_all_versions_seek_lt_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    iter_all_versions_seek_lt(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_seek_lt_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_seek_lt(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_all_versions_seek_lt_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_all_versions_seek_lt(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
+    );
+  })
+}
+
+fn range(l: SkipMap) {
+  for i in 1..10 {
+    l.get_or_insert(0, &make_int_key(i), &make_value(i))
+      .unwrap();
+  }
+
+  let k3 = make_int_key(3);
+  let k7 = make_int_key(7);
+  let mut it = l.range(0, k3.as_slice()..k7.as_slice()).clone();
+  assert_eq!(it.bounds(), &(k3.as_slice()..k7.as_slice()));
+
+  for i in 3..=6 {
+    let k = make_int_key(i);
+    let ent = it.seek_lower_bound(Bound::Included(&k)).unwrap();
+    assert_eq!(ent.key(), make_int_key(i));
+    assert_eq!(ent.value(), make_value(i));
+  }
+
+  for i in 1..3 {
+    let k = make_int_key(i);
+    let ent = it.seek_lower_bound(Bound::Included(&k)).unwrap();
+    assert_eq!(ent.key(), make_int_key(3));
+    assert_eq!(ent.value(), make_value(3));
+  }
+
+  for i in 7..10 {
+    let k = make_int_key(i);
+    assert!(it.seek_lower_bound(Bound::Included(&k)).is_none());
+  }
+
+  for i in 7..10 {
+    let k = make_int_key(i);
+    let ent = it.seek_upper_bound(Bound::Included(&k)).unwrap();
+    assert_eq!(ent.key(), make_int_key(6));
+    assert_eq!(ent.value(), make_value(6));
+  }
+
+  let ent = it
+    .seek_lower_bound(Bound::Included(&make_int_key(6)))
+    .unwrap();
+  assert_eq!(ent.key(), make_int_key(6));
+  assert_eq!(ent.value(), make_value(6));
+
+  assert!(it.next().is_none());
+
+  let ent = it
+    .seek_upper_bound(Bound::Included(&make_int_key(6)))
+    .unwrap();
+  assert_eq!(ent.key(), make_int_key(6));
+  assert_eq!(ent.value(), make_value(6));
+
+  assert!(it.next().is_none());
+
+  for i in 4..=7 {
+    let k = make_int_key(i);
+    let ent = it.seek_upper_bound(Bound::Excluded(&k)).unwrap();
+    assert_eq!(ent.key(), make_int_key(i - 1));
+    assert_eq!(ent.value(), make_value(i - 1));
+  }
+
+  for i in 7..10 {
+    let k = make_int_key(i);
+    let ent = it.seek_upper_bound(Bound::Excluded(&k)).unwrap();
+    assert_eq!(ent.key(), make_int_key(6));
+    assert_eq!(ent.value(), make_value(6));
+  }
+
+  for i in 1..3 {
+    let k = make_int_key(i);
+    let ent = it.seek_lower_bound(Bound::Excluded(&k)).unwrap();
+    assert_eq!(ent.key(), make_int_key(3));
+    assert_eq!(ent.value(), make_value(3));
+  }
+
+  for i in 1..4 {
+    let k = make_int_key(i);
+    assert!(it.seek_upper_bound(Bound::Excluded(&k)).is_none());
+  }
+
+  let ent = it
+    .seek_upper_bound(Bound::Excluded(&make_int_key(4)))
+    .unwrap();
+  assert_eq!(ent.key(), make_int_key(3));
+  assert_eq!(ent.value(), make_value(3));
+
+  assert!(it.next_back().is_none());
+}
+
+#[test]
+fn test_range() {
+  run(|| range(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_range_unify() {
+  run(|| range(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_range_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_range_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    range(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    range(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    range(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn iter_latest(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in 0..N {
+    l.get_or_insert(0, &make_int_key(i), &make_value(i))
+      .unwrap();
+  }
+
+  for i in 50..N {
+    l.get_or_insert(1, &make_int_key(i), &make_value(i + 1000))
+      .unwrap();
+  }
+
+  for i in 0..50 {
+    l.get_or_insert(2, &make_int_key(i), &make_value(i + 1000))
+      .unwrap();
+  }
+
+  let mut it = l.iter(4);
+  let mut num = 0;
+  for i in 0..N {
+    let ent = it.next().unwrap();
+    assert_eq!(ent.key(), make_int_key(i));
+    assert_eq!(ent.value(), make_value(i + 1000));
+
+    num += 1;
+  }
+  assert_eq!(num, N);
+}
+
+#[test]
+fn test_iter_latest() {
+  run(|| iter_latest(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_iter_latest_unify() {
+  run(|| iter_latest(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_iter_latest_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_iter_latest_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    iter_latest(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_latest_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_latest(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_iter_latest_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    iter_latest(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn range_latest(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in 0..N {
+    l.get_or_insert(0, &make_int_key(i), &make_value(i))
+      .unwrap();
+  }
+
+  for i in 50..N {
+    l.get_or_insert(1, &make_int_key(i), &make_value(i + 1000))
+      .unwrap();
+  }
+
+  for i in 0..50 {
+    l.get_or_insert(2, &make_int_key(i), &make_value(i + 1000))
+      .unwrap();
+  }
+
+  let mut it = l.range(4, ..);
+  let mut num = 0;
+  for i in 0..N {
+    let ent = it.next().unwrap();
+    assert_eq!(ent.key(), make_int_key(i));
+    assert_eq!(ent.value(), make_value(i + 1000));
+
+    num += 1;
+  }
+  assert_eq!(num, N);
+}
+
+#[test]
+fn test_range_latest() {
+  run(|| range_latest(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_range_latest_unify() {
+  run(|| range_latest(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_range_latest_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_range_latest_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    range_latest(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_latest_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    range_latest(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_range_latest_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    range_latest(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_reopen_mmap() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("reopen_skipmap");
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::map_mut(&p, open_options, map_options).unwrap();
+      for i in 0..1000 {
+        l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      }
+      l.flush().unwrap();
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    assert_eq!(1000, l.len());
+    for i in 0..1000 {
+      let k = key(i);
+      let ent = l.get(0, &k).unwrap();
+      assert_eq!(new_value(i), ent.value());
+      assert_eq!(ent.trailer().version(), 0);
+      assert_eq!(ent.key(), k);
+    }
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_reopen_mmap_rejects_unrelated_file() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("unrelated_file");
+    std::fs::write(&p, std::vec![0xAB; ARENA_SIZE]).unwrap();
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let err = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_reopen_mmap_comparator() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("reopen_skipmap_comparator");
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l =
+        SkipMap::<u64, Descend>::map_mut_with_comparator(&p, open_options, map_options, Descend)
+          .unwrap();
+      for i in 0..100 {
+        l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      }
+      l.flush().unwrap();
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let err = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(
+      err
+        .into_inner()
+        .unwrap()
+        .downcast::<Error>()
+        .unwrap()
+        .as_ref(),
+      &Error::IncompatibleFormat
+    );
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64, Descend>::map_with_comparator(&p, open_options, map_options, Descend, 0)
+      .unwrap();
+    assert_eq!(100, l.len());
+    let k = key(99);
+    assert_eq!(l.first(0).unwrap().key(), k);
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_reopen_mmap_checksum() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("reopen_skipmap_checksum");
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::map_mut_with_options(
+        &p,
+        Options::new().with_checksum(true),
+        open_options,
+        map_options,
+      )
+      .unwrap();
+      for i in 0..100 {
+        l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      }
+      l.get_or_insert(0, b"checksum", b"checksummed value")
+        .unwrap();
+      l.flush().unwrap();
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    assert_eq!(101, l.len());
+    drop(l);
+
+    // Flip a byte of the value.
+    let mut data = std::fs::read(&p).unwrap();
+    let pos = data
+      .windows(17)
+      .position(|w| w == b"checksummed value")
+      .unwrap();
+    data[pos] ^= 0xFF;
+    std::fs::write(&p, data).unwrap();
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let err = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(
+      err
+        .into_inner()
+        .unwrap()
+        .downcast::<Error>()
+        .unwrap()
+        .as_ref(),
+      &Error::ChecksumMismatch
+    );
+
+    // A write after the flush clears the checksum, which no longer covers the file.
+    let p = dir.path().join("reopen_skipmap_checksum_written");
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::map_mut_with_options(
+        &p,
+        Options::new().with_checksum(true),
+        open_options,
+        map_options,
+      )
+      .unwrap();
+      l.get_or_insert(0, &key(0), &new_value(0)).unwrap();
+      l.flush().unwrap();
+      l.get_or_insert(0, &key(1), &new_value(1)).unwrap();
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    assert_eq!(2, l.len());
+    assert_eq!(l.get(0, &key(1)).unwrap().value(), new_value(1));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_flush_on_drop() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("flush_on_drop_skipmap");
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::map_mut_with_options(
+        &p,
+        Options::new().with_flush_on_drop(true).with_checksum(true),
+        open_options,
+        map_options,
+      )
+      .unwrap();
+      for i in 0..1000 {
+        l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      }
+      l.flush().unwrap();
+      // A clone keeps the map alive, only the last reference flushes.
+      let l2 = l.clone();
+      drop(l);
+      l2.get_or_insert(1, &key(0), &make_value(0)).unwrap();
+      // So does a handle to the bytes of the map, which outlives the clones.
+      let value = l2.get(1, &key(0)).unwrap().value_bytes();
+      drop(l2);
+      assert_eq!(&*value, make_value(0).as_slice());
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    assert_eq!(1001, l.len());
+    for i in 0..1000 {
+      let k = key(i);
+      assert_eq!(l.get(0, &k).unwrap().value(), new_value(i));
+    }
+    assert_eq!(l.get(1, &key(0)).unwrap().value(), make_value(0));
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_reopen_mmap2() {
+  run(|| {
+    use rand::seq::SliceRandom;
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("reopen_skipmap2");
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::map_mut_with_comparator(&p, open_options, map_options, Ascend).unwrap();
+      let mut data = (0..1000).collect::<Vec<usize>>();
+      data.shuffle(&mut rand::thread_rng());
+      for i in data {
+        l.get_or_insert(i as u64, &key(i), &new_value(i)).unwrap();
+      }
+      l.flush_async().unwrap();
+      assert_eq!(l.max_version(), 999);
+      assert_eq!(l.min_version(), 0);
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64, Ascend>::map_with_comparator(&p, open_options, map_options, Ascend, 0)
+      .unwrap();
+    assert_eq!(1000, l.len());
+    let mut data = (0..1000).collect::<Vec<usize>>();
+    data.shuffle(&mut rand::thread_rng());
+    for i in data {
+      let k = key(i);
+      let ent = l.get(i as u64, &k).unwrap();
+      assert_eq!(new_value(i), ent.value());
+      assert_eq!(ent.trailer().version(), i as u64);
+      assert_eq!(ent.key(), k);
+    }
+    assert_eq!(l.max_version(), 999);
+    assert_eq!(l.min_version(), 0);
+  })
+}
+
+struct Person {
+  id: u32,
+  name: std::string::String,
+}
+
+impl Person {
+  fn encoded_size(&self) -> usize {
+    4 + self.name.len()
+  }
+}
+
+fn get_or_insert_with_value(l: SkipMap) {
+  let alice = Person {
+    id: 1,
+    name: std::string::String::from("Alice"),
+  };
+
+  let encoded_size = alice.encoded_size() as u32;
+
+  l.get_or_insert_with_value::<()>(1, b"alice", encoded_size, |val| {
+    assert_eq!(val.capacity(), encoded_size as usize);
+    assert!(val.is_empty());
+    val.write(&alice.id.to_le_bytes()).unwrap();
+    assert_eq!(val.len(), 4);
+    assert_eq!(val.remaining(), encoded_size as usize - 4);
+    assert_eq!(&*val, alice.id.to_le_bytes());
+    val[..4].copy_from_slice(&alice.id.to_be_bytes());
+    assert_eq!(&*val, alice.id.to_be_bytes());
+    val.write(alice.name.as_bytes()).unwrap();
+    assert_eq!(val.len(), encoded_size as usize);
+    let err = val.write(&[1]).unwrap_err();
+    assert_eq!(
+      std::string::ToString::to_string(&err),
+      "buffer does not have enough space (remaining 0, want 1)"
+    );
+    Ok(())
+  })
+  .unwrap();
+}
+
+#[test]
+fn test_get_or_insert_with_value() {
+  run(|| {
+    get_or_insert_with_value(SkipMap::with_options(TEST_OPTIONS).unwrap());
+  })
+}
+
+#[test]
+fn test_get_or_insert_with_value_unify() {
+  run(|| {
+    get_or_insert_with_value(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_get_or_insert_with_value_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_get_or_insert_with_value_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    get_or_insert_with_value(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_or_insert_with_value_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_or_insert_with_value(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_or_insert_with_value_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_or_insert_with_value(
+      SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap(),
+    );
+  })
+}
+
+fn get_or_insert_with(l: SkipMap) {
+  let alice = Person {
+    id: 1,
+    name: std::string::String::from("Alice"),
+  };
+
+  let encoded_size = alice.encoded_size() as u32;
+
+  l.get_or_insert_with::<()>(
+    1,
+    u27::new(5),
+    |key| {
+      key.write(b"alice").unwrap();
+      Ok(())
+    },
+    encoded_size,
+    |val| {
+      assert_eq!(val.capacity(), encoded_size as usize);
+      assert!(val.is_empty());
+      val.write(&alice.id.to_le_bytes()).unwrap();
+      assert_eq!(val.len(), 4);
+      assert_eq!(val.remaining(), encoded_size as usize - 4);
+      assert_eq!(&*val, alice.id.to_le_bytes());
+      val[..4].copy_from_slice(&alice.id.to_be_bytes());
+      assert_eq!(&*val, alice.id.to_be_bytes());
+      val.write(alice.name.as_bytes()).unwrap();
+      assert_eq!(val.len(), encoded_size as usize);
+      let err = val.write(&[1]).unwrap_err();
+      assert_eq!(
+        std::string::ToString::to_string(&err),
+        "buffer does not have enough space (remaining 0, want 1)"
+      );
+      Ok(())
+    },
+  )
+  .unwrap();
+}
+
+#[test]
+fn test_get_or_insert_with() {
+  run(|| get_or_insert_with(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_get_or_insert_with_unify() {
+  run(|| get_or_insert_with(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_get_or_insert_with_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_get_or_insert_with_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    get_or_insert_with(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_or_insert_with_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_or_insert_with(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_or_insert_with_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_or_insert_with(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn insert_in(l: SkipMap) {
+  let k = 0u64.to_le_bytes();
+  for i in 0..100 {
+    let v = new_value(i);
+    let old = l.insert(0, &k, &v).unwrap();
+    if let Some(old) = old {
+      assert_eq!(old.key(), k);
+      assert_eq!(old.value(), new_value(i - 1));
+    }
+  }
+
+  let ent = l.get(0, &k).unwrap();
+  assert_eq!(ent.key(), k);
+  assert_eq!(ent.value(), new_value(99));
+}
+
+#[test]
+fn test_insert_in() {
+  run(|| {
+    insert_in(SkipMap::with_options(TEST_OPTIONS).unwrap());
+  })
+}
+
+#[test]
+fn test_insert_in_unify() {
+  run(|| {
+    insert_in(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_insert_in_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_insert_in_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    insert_in(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_insert_in_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    insert_in(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_insert_in_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    insert_in(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn insert_with_value(l: SkipMap) {
+  let alice = Person {
+    id: 1,
+    name: std::string::String::from("Alice"),
+  };
+
+  let encoded_size = alice.encoded_size() as u32;
+
+  l.insert_with_value::<()>(1, b"alice", encoded_size, |val| {
+    assert_eq!(val.capacity(), encoded_size as usize);
+    assert!(val.is_empty());
+    val.write(&alice.id.to_le_bytes()).unwrap();
+    assert_eq!(val.len(), 4);
+    assert_eq!(val.remaining(), encoded_size as usize - 4);
+    assert_eq!(val, alice.id.to_le_bytes());
+    val[..4].copy_from_slice(&alice.id.to_be_bytes());
+    assert_eq!(val, alice.id.to_be_bytes());
+    val.write(alice.name.as_bytes()).unwrap();
+    assert_eq!(val.len(), encoded_size as usize);
+    let err = val.write(&[1]).unwrap_err();
+    assert_eq!(
+      std::string::ToString::to_string(&err),
+      "buffer does not have enough space (remaining 0, want 1)"
+    );
+    Ok(())
+  })
+  .unwrap();
+
+  let alice2 = Person {
+    id: 2,
+    name: std::string::String::from("Alice"),
+  };
+
+  let old = l
+    .insert_with_value::<()>(1, b"alice", encoded_size, |val| {
+      assert_eq!(val.capacity(), encoded_size as usize);
+      assert!(val.is_empty());
+      val.write(&alice2.id.to_le_bytes()).unwrap();
+      assert_eq!(val.len(), 4);
+      assert_eq!(val.remaining(), encoded_size as usize - 4);
+      assert_eq!(&*val, alice2.id.to_le_bytes());
+      val[..4].copy_from_slice(&alice2.id.to_be_bytes());
+      assert_eq!(&*val, alice2.id.to_be_bytes());
+      val.write(alice2.name.as_bytes()).unwrap();
+      assert_eq!(val.len(), encoded_size as usize);
+      let err = val.write(&[1]).unwrap_err();
+      assert_eq!(
+        std::string::ToString::to_string(&err),
+        "buffer does not have enough space (remaining 0, want 1)"
+      );
+      Ok(())
+    })
+    .unwrap()
+    .unwrap();
+
+  assert_eq!(old.key(), b"alice");
+  assert!(old.value().starts_with(&alice.id.to_be_bytes()));
+
+  let ent = l.get(1, b"alice").unwrap();
+  assert_eq!(ent.key(), b"alice");
+  assert!(ent.value().starts_with(&alice2.id.to_be_bytes()));
+}
+
+#[test]
+fn test_insert_with_value() {
+  run(|| insert_with_value(SkipMap::with_options(TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+fn test_insert_with_value_unify() {
+  run(|| insert_with_value(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()));
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_insert_with_value_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir
+      .path()
+      .join("test_skipmap_get_or_insert_with_value_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    insert_with_value(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_insert_with_value_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    insert_with_value(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
 fn test_insert_with_value_map_anon_unify() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    insert_with_value(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    insert_with_value(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn insert_with(l: SkipMap) {
+  let alice = Person {
+    id: 1,
+    name: std::string::String::from("Alice"),
+  };
+
+  let encoded_size = alice.encoded_size() as u32;
+
+  l.insert_with::<()>(
+    1,
+    u27::new(5),
+    |key| {
+      key.write(b"alice").unwrap();
+      Ok(())
+    },
+    encoded_size,
+    |val| {
+      assert_eq!(val.capacity(), encoded_size as usize);
+      assert!(val.is_empty());
+      val.write(&alice.id.to_le_bytes()).unwrap();
+      assert_eq!(val.len(), 4);
+      assert_eq!(val.remaining(), encoded_size as usize - 4);
+      assert_eq!(val, alice.id.to_le_bytes());
+      val[..4].copy_from_slice(&alice.id.to_be_bytes());
+      assert_eq!(val, alice.id.to_be_bytes());
+      val.write(alice.name.as_bytes()).unwrap();
+      assert_eq!(val.len(), encoded_size as usize);
+      let err = val.write(&[1]).unwrap_err();
+      assert_eq!(
+        std::string::ToString::to_string(&err),
+        "buffer does not have enough space (remaining 0, want 1)"
+      );
+      Ok(())
+    },
+  )
+  .unwrap();
+
+  let alice2 = Person {
+    id: 2,
+    name: std::string::String::from("Alice"),
+  };
+
+  let old = l
+    .insert_with::<()>(
+      1,
+      u27::new(5),
+      |key| {
+        key.write(b"alice").unwrap();
+        Ok(())
+      },
+      encoded_size,
+      |val| {
+        assert_eq!(val.capacity(), encoded_size as usize);
+        assert!(val.is_empty());
+        val.write(&alice2.id.to_le_bytes()).unwrap();
+        assert_eq!(val.len(), 4);
+        assert_eq!(val.remaining(), encoded_size as usize - 4);
+        assert_eq!(&*val, alice2.id.to_le_bytes());
+        val[..4].copy_from_slice(&alice2.id.to_be_bytes());
+        assert_eq!(&*val, alice2.id.to_be_bytes());
+        val.write(alice2.name.as_bytes()).unwrap();
+        assert_eq!(val.len(), encoded_size as usize);
+        let err = val.write(&[1]).unwrap_err();
+        assert_eq!(
+          std::string::ToString::to_string(&err),
+          "buffer does not have enough space (remaining 0, want 1)"
+        );
+        Ok(())
+      },
+    )
+    .unwrap()
+    .unwrap();
+
+  assert_eq!(old.key(), b"alice");
+  assert!(old.value().starts_with(&alice.id.to_be_bytes()));
+
+  let ent = l.get(1, b"alice").unwrap();
+  assert_eq!(ent.key(), b"alice");
+  assert!(ent.value().starts_with(&alice2.id.to_be_bytes()));
+}
+
+#[test]
+fn test_insert_with() {
+  run(|| insert_with(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_insert_with_unify() {
+  run(|| insert_with(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_insert_with_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_insert_with_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    insert_with(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_insert_with_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    insert_with(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_insert_with_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    insert_with(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn get_or_remove(l: SkipMap) {
+  for i in 0..100 {
+    let v = new_value(i);
+    l.insert(0, &key(i), &v).unwrap();
+  }
+
+  for i in 0..100 {
+    let k = key(i);
+    let old = l.get_or_remove(0, &k).unwrap().unwrap();
+    assert_eq!(old.key(), k);
+    assert_eq!(old.value(), new_value(i));
+
+    let old = l.get_or_remove(0, &k).unwrap().unwrap();
+    assert_eq!(old.key(), k);
+    assert_eq!(old.value(), new_value(i));
+  }
+
+  for i in 0..100 {
+    let k = key(i);
+    let ent = l.get(0, &k).unwrap();
+    assert_eq!(ent.key(), k);
+    assert_eq!(ent.value(), new_value(i));
+  }
+}
+
+#[test]
+fn test_get_or_remove() {
+  run(|| get_or_remove(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_get_or_remove_unify() {
+  run(|| get_or_remove(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_get_or_remove_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_get_or_remove_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    get_or_remove(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_or_remove_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_or_remove(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_get_or_remove_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    get_or_remove(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn remove(l: SkipMap) {
+  for i in 0..100 {
+    let v = new_value(i);
+    l.insert(0, &key(i), &v).unwrap();
+  }
+
+  for i in 0..100 {
+    let k = key(i);
+    // no race, remove should succeed
+    let old = l
+      .compare_remove(0, &k, Ordering::SeqCst, Ordering::Acquire)
+      .unwrap();
+    assert!(old.is_none());
+
+    // key already removed
+    let old = l
+      .compare_remove(0, &k, Ordering::SeqCst, Ordering::Acquire)
+      .unwrap();
+    assert!(old.is_none());
+  }
+
+  for i in 0..100 {
+    let k = key(i);
+    let ent = l.get(0, &k);
+    assert!(ent.is_none());
+  }
+}
+
+#[test]
+fn test_remove() {
+  run(|| remove(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_remove_unify() {
+  run(|| remove(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_remove_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_remove_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    remove(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_remove_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    remove(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_remove_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    remove(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn remove2(l: SkipMap) {
+  for i in 0..100 {
+    let v = new_value(i);
+    l.insert(0, &key(i), &v).unwrap();
+  }
+
+  for i in 0..100 {
+    let k = key(i);
+    // not found, remove should succeed
+    let old = l
+      .compare_remove(1, &k, Ordering::SeqCst, Ordering::Acquire)
+      .unwrap();
+    assert!(old.is_none());
+
+    // no-race, remove should succeed
+    let old = l
+      .compare_remove(0, &k, Ordering::SeqCst, Ordering::Acquire)
+      .unwrap();
+    assert!(old.is_none());
+  }
+
+  for i in 0..100 {
+    let k = key(i);
+    let ent = l.get(0, &k);
+    assert!(ent.is_none());
+  }
+}
+
+#[test]
+fn test_remove2() {
+  run(|| remove2(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_remove2_unify() {
+  run(|| remove2(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_remove2_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_remove2_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    remove2(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_remove2_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    remove2(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_remove2_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    remove2(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn closest(l: SkipMap) {
+  const N: usize = 100;
+
+  for i in 0..N {
+    l.get_or_insert(0, &make_int_key(i * 10), &make_value(i * 10))
+      .unwrap();
+  }
+
+  let collect = |key: usize, n: usize| {
+    l.closest(0, &make_int_key(key), n)
+      .into_iter()
+      .map(|ent| {
+        assert_eq!(
+          ent.value(),
+          make_value(std::str::from_utf8(ent.key()).unwrap().parse().unwrap())
+        );
+        std::str::from_utf8(ent.key())
+          .unwrap()
+          .parse::<usize>()
+          .unwrap()
+      })
+      .collect::<std::vec::Vec<_>>()
+  };
+
+  // exact match is included and the window is centered on it
+  assert_eq!(collect(500, 1), [500]);
+  assert_eq!(collect(500, 3), [490, 500, 510]);
+  assert_eq!(collect(500, 5), [480, 490, 500, 510, 520]);
+
+  // between two keys, the floor and the ceil are the closest
+  assert_eq!(collect(505, 2), [500, 510]);
+  assert_eq!(collect(505, 4), [490, 500, 510, 520]);
+
+  // near the ends, the other side fills the result
+  assert_eq!(collect(5, 4), [0, 10, 20, 30]);
+  assert_eq!(collect(0, 3), [0, 10, 20]);
+  assert_eq!(collect(995, 3), [970, 980, 990]);
+
+  // the returned set is always the n closest by key distance
+  for (target, n) in [(123, 6), (777, 8), (42, 2), (960, 10)] {
+    let mut expected = (0..N).map(|i| i * 10).collect::<std::vec::Vec<_>>();
+    expected.sort_by_key(|&k| (k.max(target) - k.min(target), k));
+    expected.truncate(n);
+    expected.sort_unstable();
+    assert_eq!(collect(target, n), expected);
+  }
+
+  assert!(collect(500, 0).is_empty());
+  assert_eq!(collect(500, N + 10).len(), N);
+  assert_eq!(l.closest(1, &make_int_key(500), 3).len(), 3);
+}
+
+#[test]
+fn test_closest() {
+  run(|| closest(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_closest_unify() {
+  run(|| closest(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_closest_map_mut() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("test_skipmap_closest_map_mut");
+    let open_options = OpenOptions::default()
+      .create_new(Some(ARENA_SIZE as u32))
+      .read(true)
+      .write(true);
+    let map_options = MmapOptions::default();
+    closest(SkipMap::map_mut(p, open_options, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_closest_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    closest(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_closest_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    closest(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+fn seek_from(l: SkipMap) {
+  const N: usize = 10_000;
+
+  for i in 0..N {
+    l.get_or_insert(0, &make_int_key(i * 2), &make_value(i * 2))
+      .unwrap();
+  }
+
+  // monotonically increasing lookups, both hitting and missing keys
+  let mut it = l.iter(0);
+  let mut from = it.seek_lower_bound(Bound::Unbounded).unwrap();
+  for target in (1..N * 2).step_by(3) {
+    let k = make_int_key(target);
+    let expected = l.lower_bound(0, Bound::Included(&k));
+    let got = it.seek_from(&from, &k);
+    assert_eq!(
+      got.as_ref().map(|ent| ent.key()),
+      expected.as_ref().map(|ent| ent.key())
+    );
+    match got {
+      Some(ent) => {
+        assert_eq!(ent.value(), make_value(target + target % 2));
+        from = ent;
+      }
+      None => assert!(target > (N - 1) * 2),
+    }
+  }
+
+  // keys not greater than `from` fall back to a full search
+  let mut it = l.iter(0);
+  let from = it
+    .seek_lower_bound(Bound::Included(&make_int_key(1000)))
+    .unwrap();
+  for target in [0, 999, 1000] {
+    let k = make_int_key(target);
+    assert_eq!(
+      it.seek_from(&from, &k).unwrap().key(),
+      l.lower_bound(0, Bound::Included(&k)).unwrap().key()
+    );
+  }
+
+  // the all versions iterator agrees with the latest version iterator
+  let mut all = l.iter_all_versions(0);
+  let from = all.seek_lower_bound(Bound::Unbounded).unwrap();
+  let ent = all.seek_from(&from, &make_int_key(7777)).unwrap();
+  assert_eq!(ent.key(), make_int_key(7778));
+  assert_eq!(ent.value().unwrap(), make_value(7778));
+}
+
+#[test]
+fn test_seek_from() {
+  run(|| seek_from(SkipMap::with_options(Options::new().with_capacity(8 << 20)).unwrap()))
+}
+
+#[test]
+fn test_seek_from_unify() {
+  run(|| {
+    seek_from(
+      SkipMap::with_options(Options::new().with_capacity(8 << 20).with_unify(true)).unwrap(),
+    )
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_seek_from_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(8 << 20);
+    seek_from(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+fn inline_keys(l: SkipMap) {
+  const N: usize = 1000;
+  let inline = l.opts.inline_keys() as usize;
+
+  // keys from 5 bytes up to twice the inline threshold
+  let make_key = |i: usize| {
+    let mut k = format!("{:05}", i).into_bytes();
+    k.resize(5 + i % (inline * 2), b'-');
+    k
+  };
+
+  for i in 0..N {
+    l.insert(0, &make_key(i), &make_value(i)).unwrap();
+  }
+  for i in (0..N).step_by(3) {
+    l.get_or_remove(1, &make_key(i)).unwrap();
+  }
+
+  for i in 0..N {
+    let k = make_key(i);
+    let ent = l.get(0, &k).unwrap();
+    assert_eq!(ent.key(), k);
+    assert_eq!(ent.value(), make_value(i));
+
+    // small keys are stored right after the tower of their node
+    if k.len() <= inline {
+      unsafe {
+        let node = ent.0.ptr.as_ref();
+        assert_eq!(
+          node.key_offset as usize,
+          ent.0.ptr.offset as usize + Node::<u64>::size(node.height())
+        );
+      }
+    }
+
+    if i % 3 == 0 {
+      assert!(l.get(1, &k).is_none());
+    } else {
+      assert_eq!(l.get(1, &k).unwrap().value(), make_value(i));
+    }
+  }
+
+  let mut expected = (0..N).map(make_key).collect::<std::vec::Vec<_>>();
+  expected.sort();
+  let keys = l
+    .iter(0)
+    .map(|ent| ent.key().to_vec())
+    .collect::<std::vec::Vec<_>>();
+  assert_eq!(keys, expected);
+}
+
+#[test]
+fn test_inline_keys() {
+  run(|| inline_keys(SkipMap::with_options(TEST_OPTIONS.with_inline_keys(8)).unwrap()))
+}
+
+#[test]
+fn test_inline_keys_unify() {
+  run(|| inline_keys(SkipMap::with_options(UNIFY_TEST_OPTIONS.with_inline_keys(8)).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_inline_keys_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    inline_keys(
+      SkipMap::map_anon_with_options(TEST_OPTIONS.with_inline_keys(8), map_options).unwrap(),
+    );
+  })
+}
+
+#[cfg(feature = "std")]
+fn concurrent_publish(l: Arc<SkipMap>) {
+  #[cfg(not(any(miri, feature = "loom")))]
+  const N: usize = 1000;
+  #[cfg(any(miri, feature = "loom"))]
+  const N: usize = 5;
+  const WRITERS: usize = 4;
+  const READERS: usize = 4;
+
+  let done = Arc::new(crate::sync::AtomicU32::new(0));
+  let wg = WaitGroup::new();
+
+  // Writers interleave their keys, so they keep racing on the same splices.
+  for w in 0..WRITERS {
+    let wg = wg.add(1);
+    let l = l.clone();
+    let done = done.clone();
+    std::thread::spawn(move || {
+      for i in (w..N).step_by(WRITERS) {
+        l.get_or_insert(0, &make_int_key(i), &make_value(i))
+          .unwrap();
+      }
+      done.fetch_add(1, Ordering::AcqRel);
+      wg.done();
+    });
+  }
+
+  // Readers must only ever observe fully initialized nodes in order.
+  for _ in 0..READERS {
+    let wg = wg.add(1);
+    let l = l.clone();
+    let done = done.clone();
+    std::thread::spawn(move || {
+      loop {
+        let finished = done.load(Ordering::Acquire) as usize == WRITERS;
+        let mut prev: Option<std::vec::Vec<u8>> = None;
+        for ent in l.iter(0) {
+          let i: usize = core::str::from_utf8(ent.key()).unwrap().parse().unwrap();
+          assert_eq!(ent.value(), make_value(i));
+          if let Some(prev) = &prev {
+            assert!(prev.as_slice() < ent.key());
+          }
+          prev = Some(ent.key().to_vec());
+        }
+
+        let mut it = l.iter(0);
+        if let Some(ent) = it.seek_upper_bound(Bound::Unbounded) {
+          let i: usize = core::str::from_utf8(ent.key()).unwrap().parse().unwrap();
+          assert_eq!(ent.value(), make_value(i));
+        }
+
+        if finished {
+          break;
+        }
+      }
+      wg.done();
+    });
+  }
+
+  wg.wait();
+
+  assert_eq!(l.len(), N);
+  for i in 0..N {
+    assert_eq!(l.get(0, &make_int_key(i)).unwrap().value(), make_value(i));
+  }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_publish() {
+  run(|| concurrent_publish(Arc::new(SkipMap::with_options(TEST_OPTIONS).unwrap())))
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_concurrent_publish_unify() {
+  run(|| concurrent_publish(Arc::new(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap())))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_concurrent_publish_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    concurrent_publish(Arc::new(SkipMap::map_anon(map_options).unwrap()));
   })
 }
 
-fn insert_with(l: SkipMap) {
-  let alice = Person {
-    id: 1,
-    name: std::string::String::from("Alice"),
-  };
+fn resolve(l: SkipMap) {
+  const N: usize = 100;
 
-  let encoded_size = alice.encoded_size() as u32;
+  let offsets = (0..N)
+    .map(|i| {
+      l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      l.get(0, &key(i)).unwrap().node_offset()
+    })
+    .collect::<std::vec::Vec<_>>();
 
-  l.insert_with::<()>(
-    1,
-    u27::new(5),
-    |key| {
-      key.write(b"alice").unwrap();
-      Ok(())
-    },
-    encoded_size,
-    |val| {
-      assert_eq!(val.capacity(), encoded_size as usize);
-      assert!(val.is_empty());
-      val.write(&alice.id.to_le_bytes()).unwrap();
-      assert_eq!(val.len(), 4);
-      assert_eq!(val.remaining(), encoded_size as usize - 4);
-      assert_eq!(val, alice.id.to_le_bytes());
-      val[..4].copy_from_slice(&alice.id.to_be_bytes());
-      assert_eq!(val, alice.id.to_be_bytes());
-      val.write(alice.name.as_bytes()).unwrap();
-      assert_eq!(val.len(), encoded_size as usize);
-      let err = val.write(&[1]).unwrap_err();
-      assert_eq!(
-        std::string::ToString::to_string(&err),
-        "buffer does not have enough space (remaining 0, want 1)"
-      );
-      Ok(())
-    },
-  )
-  .unwrap();
+  for (i, offset) in offsets.iter().enumerate() {
+    let ent = l.resolve(*offset).unwrap();
+    assert_eq!(ent.key(), key(i));
+    assert_eq!(ent.value(), new_value(i));
+    assert_eq!(ent.node_offset(), *offset);
 
-  let alice2 = Person {
-    id: 2,
-    name: std::string::String::from("Alice"),
+    // offsets which do not point at the start of a node
+    assert!(l.resolve(offset + 1).is_none());
+    assert!(l.resolve(offset + 8).is_none());
+  }
+
+  assert!(l.resolve(0).is_none());
+  assert!(l.resolve(l.head.offset).is_none());
+  assert!(l.resolve(l.tail.offset).is_none());
+  assert!(l.resolve(l.allocated() as u32).is_none());
+  assert!(l.resolve(u32::MAX).is_none());
+
+  // removed entries cannot be resolved
+  l.compare_remove(0, &key(0), Ordering::SeqCst, Ordering::Acquire)
+    .unwrap();
+  assert!(l.resolve(offsets[0]).is_none());
+}
+
+fn resolve_forged(l: SkipMap) {
+  l.get_or_insert(0, b"a", b"a1").unwrap();
+
+  // Two forged nodes of height 1, `prev` links to `node` at the base level and the other way round.
+  let size = Node::<u64>::size(1);
+  let mut buf = l.alloc_bytes(size * 3).unwrap();
+  let align = Node::<u64>::ALIGN;
+  let prev = (buf.offset() + align - 1) / align * align;
+  let node = prev + size as u32;
+  let start = (prev - buf.offset()) as usize;
+  let forge = |buf: &mut [u8], value: u64, key_offset: u32, next: u32, prev: u32| {
+    buf[..8].copy_from_slice(&value.to_ne_bytes());
+    buf[8..12].copy_from_slice(&key_offset.to_ne_bytes());
+    buf[12..16].copy_from_slice(&encode_key_size_and_height(1, 1).to_ne_bytes());
+    buf[16..20].copy_from_slice(&next.to_ne_bytes());
+    buf[20..24].copy_from_slice(&prev.to_ne_bytes());
   };
+  let in_bounds = encode_value_pointer(prev, 1);
+  forge(&mut buf[start..], in_bounds, prev, node, l.head.offset);
+  // The value of the node is far out of the ARENA.
+  let out_of_bounds = encode_value_pointer(prev, u32::MAX - 1);
+  forge(&mut buf[start + size..], out_of_bounds, prev, 0, prev);
+  drop(buf);
+  assert!(l.resolve(node).is_none());
 
-  let old = l
-    .insert_with::<()>(
-      1,
-      u27::new(5),
-      |key| {
-        key.write(b"alice").unwrap();
-        Ok(())
-      },
-      encoded_size,
-      |val| {
-        assert_eq!(val.capacity(), encoded_size as usize);
-        assert!(val.is_empty());
-        val.write(&alice2.id.to_le_bytes()).unwrap();
-        assert_eq!(val.len(), 4);
-        assert_eq!(val.remaining(), encoded_size as usize - 4);
-        assert_eq!(&*val, alice2.id.to_le_bytes());
-        val[..4].copy_from_slice(&alice2.id.to_be_bytes());
-        assert_eq!(&*val, alice2.id.to_be_bytes());
-        val.write(alice2.name.as_bytes()).unwrap();
-        assert_eq!(val.len(), encoded_size as usize);
-        let err = val.write(&[1]).unwrap_err();
-        assert_eq!(
-          std::string::ToString::to_string(&err),
-          "buffer does not have enough space (remaining 0, want 1)"
-        );
-        Ok(())
-      },
+  // The value is in bounds, but the node is not linked into the map.
+  let buf = unsafe { l.arena.get_bytes_mut(node as usize, size) };
+  forge(buf, in_bounds, prev, 0, prev);
+  assert!(l.resolve(node).is_none());
+  assert!(l.resolve(prev).is_none());
+}
+
+#[test]
+fn test_resolve_forged() {
+  run(|| resolve_forged(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_resolve() {
+  run(|| resolve(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_resolve_unify() {
+  run(|| resolve(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_resolve_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    resolve(SkipMap::map_anon(map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_resolve_map_anon_unify() {
+  run(|| {
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    resolve(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+#[cfg_attr(miri, ignore)]
+fn test_resolve_reopen_mmap() {
+  run(|| {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("resolve_reopen_skipmap");
+    let offsets = {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::map_mut(&p, open_options, map_options).unwrap();
+      let offsets = (0..1000)
+        .map(|i| {
+          l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+          l.get(0, &key(i)).unwrap().node_offset()
+        })
+        .collect::<std::vec::Vec<_>>();
+      l.flush().unwrap();
+      offsets
+    };
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    for (i, offset) in offsets.into_iter().enumerate() {
+      let ent = l.resolve(offset).unwrap();
+      assert_eq!(ent.key(), key(i));
+      assert_eq!(ent.value(), new_value(i));
+    }
+  })
+}
+
+fn max_height(l: SkipMap) {
+  const N: usize = 5000;
+  let max_height = u8::from(l.opts.max_height());
+
+  for i in (0..N).rev() {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  assert_eq!(l.len(), N);
+  assert!(l.height() <= max_height);
+
+  for ent in l.iter_all_versions(0) {
+    assert!(unsafe { ent.ptr.as_ref().height() } <= max_height);
+  }
+
+  for i in 0..N {
+    let k = key(i);
+    let ent = l.get(0, &k).unwrap();
+    assert_eq!(ent.key(), k);
+    assert_eq!(ent.value(), new_value(i));
+  }
+
+  let lower = key(1000);
+  let upper = key(2000);
+  let mut count = 0;
+  for (i, ent) in l.range(0, lower.as_slice()..upper.as_slice()).enumerate() {
+    assert_eq!(ent.key(), key(1000 + i));
+    assert_eq!(ent.value(), new_value(1000 + i));
+    count += 1;
+  }
+  assert_eq!(count, 1000);
+
+  let mut it = l.iter(0);
+  let mut ent = it.seek_upper_bound(Bound::Unbounded);
+  for i in (0..N).rev() {
+    assert_eq!(ent.unwrap().key(), key(i));
+    ent = it.next_back();
+  }
+  assert!(ent.is_none());
+}
+
+#[test]
+fn test_max_height_one() {
+  run(|| {
+    max_height(
+      SkipMap::with_options(
+        Options::new()
+          .with_capacity(4 << 20)
+          .with_max_height(u5::new(1)),
+      )
+      .unwrap(),
+    )
+  })
+}
+
+#[test]
+fn test_max_height_zero() {
+  run(|| {
+    let l = SkipMap::with_options(
+      Options::new()
+        .with_capacity(4 << 20)
+        .with_max_height(u5::new(0)),
     )
-    .unwrap()
     .unwrap();
+    assert_eq!(l.opts.max_height(), u5::new(1));
+    max_height(l)
+  })
+}
 
-  assert_eq!(old.key(), b"alice");
-  assert!(old.value().starts_with(&alice.id.to_be_bytes()));
+#[test]
+fn test_max_height_max() {
+  run(|| {
+    max_height(
+      SkipMap::with_options(
+        Options::new()
+          .with_capacity(4 << 20)
+          .with_max_height(u5::MAX),
+      )
+      .unwrap(),
+    )
+  })
+}
 
-  let ent = l.get(1, b"alice").unwrap();
-  assert_eq!(ent.key(), b"alice");
-  assert!(ent.value().starts_with(&alice2.id.to_be_bytes()));
+#[test]
+fn test_max_height_max_unify() {
+  run(|| {
+    max_height(
+      SkipMap::with_options(
+        Options::new()
+          .with_capacity(4 << 20)
+          .with_max_height(u5::MAX)
+          .with_unify(true),
+      )
+      .unwrap(),
+    )
+  })
 }
 
 #[test]
-fn test_insert_with() {
-  run(|| insert_with(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+#[cfg(feature = "memmap")]
+fn test_max_height_one_map_anon() {
+  run(|| {
+    let map_options = MmapOptions::default().len(4 << 20);
+    max_height(
+      SkipMap::map_anon_with_options(Options::new().with_max_height(u5::new(1)), map_options)
+        .unwrap(),
+    );
+  })
+}
+
+fn branching_probability(l: SkipMap) {
+  const N: usize = 100_000;
+  let p = l.opts.branching_probability();
+
+  for i in 0..N {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  assert_eq!(l.len(), N);
+
+  let total: u64 = l
+    .iter_all_versions(0)
+    .map(|ent| unsafe { ent.ptr.as_ref().height() } as u64)
+    .sum();
+  let avg = total as f64 / N as f64;
+
+  // The height of a node follows a geometric distribution, the expected height is `1 / (1 - p)`,
+  // the truncation caused by the max height is negligible.
+  let expected = 1.0 / (1.0 - p);
+  assert!(
+    (avg - expected).abs() < 0.05,
+    "p = {p}, average height {avg}, expected {expected}"
+  );
+}
+
+#[test]
+fn test_branching_probability() {
+  run(|| {
+    for p in [0.5, 0.25] {
+      branching_probability(
+        SkipMap::with_options(
+          Options::new()
+            .with_capacity(32 << 20)
+            .with_branching_probability(p),
+        )
+        .unwrap(),
+      )
+    }
+  })
+}
+
+#[test]
+fn test_branching_probability_unify() {
+  run(|| {
+    for p in [0.5, 0.25] {
+      branching_probability(
+        SkipMap::with_options(
+          Options::new()
+            .with_capacity(32 << 20)
+            .with_unify(true)
+            .with_branching_probability(p),
+        )
+        .unwrap(),
+      )
+    }
+  })
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_branching_probability_map_anon() {
+  run(|| {
+    for p in [0.5, 0.25] {
+      let map_options = MmapOptions::default().len(32 << 20);
+      branching_probability(
+        SkipMap::map_anon_with_options(Options::new().with_branching_probability(p), map_options)
+          .unwrap(),
+      )
+    }
+  })
+}
+
+#[test]
+fn test_invalid_branching_probability() {
+  for p in [0.0, 1.0, -0.5, 1.5, f64::NAN] {
+    let err =
+      SkipMap::<u64>::with_options(Options::new().with_branching_probability(p)).unwrap_err();
+    assert_eq!(err, Error::InvalidBranchingProbability);
+  }
+}
+
+fn iter_cancellable(l: SkipMap) {
+  const N: usize = 1000;
+
+  for i in 0..N {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+
+  let token = CancelToken::new();
+  let mut it = l.iter_cancellable(0, token.clone());
+  for i in 0..N / 2 {
+    let ent = it.next().unwrap();
+    assert_eq!(ent.key(), key(i));
+    assert_eq!(ent.value(), new_value(i));
+  }
+
+  std::thread::spawn(move || token.cancel()).join().unwrap();
+  assert!(it.token().is_cancelled());
+  assert!(it.next().is_none());
+  assert!(it.next_back().is_none());
+  assert_eq!(it.entry().unwrap().key(), key(N / 2 - 1));
+
+  assert_eq!(l.iter_cancellable(0, CancelToken::new()).count(), N);
 }
 
 #[test]
-fn test_insert_with_unify() {
-  run(|| insert_with(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_iter_cancellable() {
+  run(|| iter_cancellable(SkipMap::with_options(TEST_OPTIONS).unwrap()))
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_insert_with_map_mut() {
-  run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_insert_with_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    insert_with(SkipMap::map_mut(p, open_options, map_options).unwrap());
-  })
+fn test_iter_cancellable_unify() {
+  run(|| iter_cancellable(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_insert_with_map_anon() {
+fn test_iter_cancellable_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    insert_with(SkipMap::map_anon(map_options).unwrap());
+    iter_cancellable(SkipMap::map_anon(map_options).unwrap());
   })
 }
 
+#[cfg(feature = "std")]
+fn get_or_compute_single_flight(l: Arc<SkipMap>) {
+  const THREADS: usize = 16;
+  const ROUNDS: usize = 20;
+
+  let computed = Arc::new(AtomicU32::new(0));
+  for round in 0..ROUNDS {
+    let k = key(round);
+    let barrier = Arc::new(std::sync::Barrier::new(THREADS));
+    let wg = WaitGroup::new();
+    for _ in 0..THREADS {
+      let wg = wg.add(1);
+      let l = l.clone();
+      let k = k.clone();
+      let barrier = barrier.clone();
+      let computed = computed.clone();
+      std::thread::spawn(move || {
+        barrier.wait();
+        let val = l
+          .get_or_compute_single_flight(
+            0,
+            &k,
+            || {
+              computed.fetch_add(1, Ordering::AcqRel);
+              // Keep the computation slow, so the other threads surely miss on the key.
+              std::thread::sleep(core::time::Duration::from_millis(10));
+              new_value(round)
+            },
+            |ent| ent.value().to_vec(),
+          )
+          .unwrap();
+        assert_eq!(val, new_value(round));
+        wg.done();
+      });
+    }
+    wg.wait();
+
+    assert_eq!(computed.load(Ordering::Acquire) as usize, round + 1);
+  }
+  assert_eq!(l.len(), ROUNDS);
+
+  // A removed key is computed again.
+  l.compare_remove(0, &key(0), Ordering::SeqCst, Ordering::Acquire)
+    .unwrap();
+  assert!(l.get(0, &key(0)).is_none());
+  let val = l
+    .get_or_compute_single_flight(0, &key(0), || new_value(100), |ent| ent.value().to_vec())
+    .unwrap();
+  assert_eq!(val, new_value(100));
+  assert_eq!(l.get(0, &key(0)).unwrap().value(), new_value(100));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_get_or_compute_single_flight() {
+  run(|| get_or_compute_single_flight(Arc::new(SkipMap::with_options(TEST_OPTIONS).unwrap())))
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_get_or_compute_single_flight_unify() {
+  run(|| get_or_compute_single_flight(Arc::new(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap())))
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_insert_with_map_anon_unify() {
+fn test_get_or_compute_single_flight_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    insert_with(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    get_or_compute_single_flight(Arc::new(SkipMap::map_anon(map_options).unwrap()));
   })
 }
 
-fn get_or_remove(l: SkipMap) {
-  for i in 0..100 {
-    let v = new_value(i);
-    l.insert(0, &key(i), &v).unwrap();
-  }
+#[test]
+#[cfg(feature = "std")]
+fn test_single_flight_lazy() {
+  let l = SkipMap::with_options(TEST_OPTIONS).unwrap();
+  l.get_or_insert(0, &key(0), &new_value(0)).unwrap();
 
-  for i in 0..100 {
-    let k = key(i);
-    let old = l.get_or_remove(0, &k).unwrap().unwrap();
-    assert_eq!(old.key(), k);
-    assert_eq!(old.value(), new_value(i));
+  // A hit does not allocate the locks.
+  l.get_or_compute_single_flight(0, &key(0), || -> Vec<u8> { unreachable!() }, |_| ())
+    .unwrap();
+  assert!(l.single_flight.0.load(Ordering::Acquire).is_null());
 
-    let old = l.get_or_remove(0, &k).unwrap().unwrap();
-    assert_eq!(old.key(), k);
-    assert_eq!(old.value(), new_value(i));
-  }
+  // A clone allocates them, so that it shares them with the map.
+  let clone = l.clone();
+  let locks = l.single_flight.0.load(Ordering::Acquire);
+  assert!(!locks.is_null());
+  assert_eq!(clone.single_flight.0.load(Ordering::Acquire), locks);
 
-  for i in 0..100 {
-    let k = key(i);
-    let ent = l.get(0, &k).unwrap();
-    assert_eq!(ent.key(), k);
-    assert_eq!(ent.value(), new_value(i));
-  }
+  clone
+    .get_or_compute_single_flight(0, &key(1), || new_value(1), |_| ())
+    .unwrap();
+  assert_eq!(l.single_flight.0.load(Ordering::Acquire), locks);
+  assert_eq!(l.get(0, &key(1)).unwrap().value(), new_value(1));
+}
+
+#[cfg(feature = "std")]
+fn get_or_compute_single_flight_nested(l1: SkipMap, l2: SkipMap) {
+  // The maps do not share the locks, so computing the same key of another map does not deadlock.
+  let val = l1
+    .get_or_compute_single_flight(
+      0,
+      &key(0),
+      || {
+        l2.get_or_compute_single_flight(0, &key(0), || new_value(0), |ent| ent.value().to_vec())
+          .unwrap()
+      },
+      |ent| ent.value().to_vec(),
+    )
+    .unwrap();
+  assert_eq!(val, new_value(0));
+  assert_eq!(l2.get(0, &key(0)).unwrap().value(), new_value(0));
 }
 
 #[test]
-fn test_get_or_remove() {
-  run(|| get_or_remove(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+#[cfg(feature = "std")]
+fn test_get_or_compute_single_flight_nested() {
+  run(|| {
+    get_or_compute_single_flight_nested(
+      SkipMap::with_options(TEST_OPTIONS).unwrap(),
+      SkipMap::with_options(TEST_OPTIONS).unwrap(),
+    )
+  })
 }
 
 #[test]
-fn test_get_or_remove_unify() {
-  run(|| get_or_remove(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+#[cfg(feature = "std")]
+fn test_get_or_compute_single_flight_nested_unify() {
+  run(|| {
+    get_or_compute_single_flight_nested(
+      SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap(),
+      SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap(),
+    )
+  })
+}
+
+fn height_seed(l1: SkipMap, l2: SkipMap) {
+  const N: usize = 1000;
+
+  for i in 0..N {
+    l1.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+    l2.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+  }
+  assert_eq!(l1.height(), l2.height());
+
+  let heights = |l: &SkipMap| {
+    l.iter_all_versions(0)
+      .map(|ent| (ent.key().to_vec(), unsafe { ent.ptr.as_ref().height() }))
+      .collect::<std::vec::Vec<_>>()
+  };
+  let h1 = heights(&l1);
+  assert_eq!(h1.len(), N);
+  assert_eq!(h1, heights(&l2));
+  // the towers are not all flat, so the seed really drives the heights
+  assert!(h1.iter().any(|(_, h)| *h > 1));
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_get_or_remove_map_mut() {
+fn test_height_seed() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_get_or_remove_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    get_or_remove(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let opts = TEST_OPTIONS.with_height_seed(42);
+    height_seed(
+      SkipMap::with_options(opts).unwrap(),
+      SkipMap::with_options(opts).unwrap(),
+    )
   })
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_get_or_remove_map_anon() {
+fn test_height_seed_unify() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_or_remove(SkipMap::map_anon(map_options).unwrap());
+    let opts = UNIFY_TEST_OPTIONS.with_height_seed(42);
+    height_seed(
+      SkipMap::with_options(opts).unwrap(),
+      SkipMap::with_options(opts).unwrap(),
+    )
   })
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_get_or_remove_map_anon_unify() {
+fn test_height_seed_map_anon() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    get_or_remove(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    let opts = Options::new().with_height_seed(42);
+    let map_options = || MmapOptions::default().len(ARENA_SIZE as u32);
+    height_seed(
+      SkipMap::map_anon_with_options(opts, map_options()).unwrap(),
+      SkipMap::map_anon_with_options(opts, map_options()).unwrap(),
+    )
   })
 }
 
-fn remove(l: SkipMap) {
-  for i in 0..100 {
-    let v = new_value(i);
-    l.insert(0, &key(i), &v).unwrap();
+fn bloom_filter(l: SkipMap) {
+  const N: usize = 1000;
+  const ABSENT: usize = 10000;
+
+  for i in 0..N {
+    l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
   }
+  // a newer version of a key must not be counted as another key
+  l.get_or_insert(1, &key(0), &new_value(0)).unwrap();
 
-  for i in 0..100 {
-    let k = key(i);
-    // no race, remove should succeed
-    let old = l
-      .compare_remove(0, &k, Ordering::SeqCst, Ordering::Acquire)
-      .unwrap();
-    assert!(old.is_none());
+  assert!(!l.is_sealed());
+  l.seal().unwrap();
+  assert!(l.is_sealed());
+  // sealing twice does nothing
+  l.seal().unwrap();
 
-    // key already removed
-    let old = l
-      .compare_remove(0, &k, Ordering::SeqCst, Ordering::Acquire)
-      .unwrap();
-    assert!(old.is_none());
+  for i in 0..N {
+    assert!(l.bloom_may_contain(&key(i)));
+    assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+    assert!(l.contains_key(0, &key(i)));
   }
 
-  for i in 0..100 {
-    let k = key(i);
-    let ent = l.get(0, &k);
-    assert!(ent.is_none());
-  }
+  let rejected = (N..N + ABSENT)
+    .filter(|i| {
+      assert!(l.get(1, &key(*i)).is_none());
+      !l.bloom_may_contain(&key(*i))
+    })
+    .count();
+  // 10 bits per key gives a false positive rate of about 1%
+  assert!(rejected > ABSENT * 95 / 100, "rejected {rejected}");
+
+  assert_eq!(
+    l.get_or_insert(0, &key(N), &new_value(N)).unwrap_err(),
+    Error::Sealed
+  );
+  assert_eq!(
+    l.insert(0, &key(0), &new_value(0)).unwrap_err(),
+    Error::Sealed
+  );
+  assert_eq!(l.get_or_remove(2, &key(0)).unwrap_err(), Error::Sealed);
+  assert_eq!(l.len(), N + 1);
 }
 
 #[test]
-fn test_remove() {
-  run(|| remove(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+fn test_bloom_filter() {
+  run(|| bloom_filter(SkipMap::with_options(TEST_OPTIONS.with_bloom_filter(10)).unwrap()))
 }
 
 #[test]
-fn test_remove_unify() {
-  run(|| remove(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_bloom_filter_unify() {
+  run(|| bloom_filter(SkipMap::with_options(UNIFY_TEST_OPTIONS.with_bloom_filter(10)).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_remove_map_mut() {
+fn test_bloom_filter_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_remove_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    remove(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    bloom_filter(
+      SkipMap::map_anon_with_options(Options::new().with_bloom_filter(10), map_options).unwrap(),
+    );
   })
 }
 
 #[test]
-#[cfg(feature = "memmap")]
-fn test_remove_map_anon() {
+fn test_seal_without_bloom_filter() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    remove(SkipMap::map_anon(map_options).unwrap());
+    let l = SkipMap::with_options(TEST_OPTIONS).unwrap();
+    l.get_or_insert(0, b"a", b"a1").unwrap();
+    l.seal().unwrap();
+    assert!(l.is_sealed());
+    assert!(l.bloom_may_contain(b"b"));
+    assert!(l.get(0, b"b").is_none());
+    assert_eq!(l.get(0, b"a").unwrap().value(), b"a1");
+    assert_eq!(l.insert(0, b"b", b"b1").unwrap_err(), Error::Sealed);
   })
 }
 
+#[test]
+fn test_bloom_filter_comparator() {
+  // The keys equal for a prefix comparator have different bytes, so the filter would reject them.
+  assert_eq!(
+    SkipMap::<u64, _>::with_options_and_comparator(
+      TEST_OPTIONS.with_bloom_filter(10),
+      PrefixComparator::new(Ascend, 4),
+    )
+    .unwrap_err(),
+    Error::UnsupportedBloomFilter
+  );
+
+  let l =
+    SkipMap::<u64, _>::with_options_and_comparator(TEST_OPTIONS.with_bloom_filter(10), Descend)
+      .unwrap();
+  l.get_or_insert(0, b"a", b"a1").unwrap();
+  l.seal().unwrap();
+  assert_eq!(l.get(0, b"a").unwrap().value(), b"a1");
+}
+
 #[test]
 #[cfg(feature = "memmap")]
-fn test_remove_map_anon_unify() {
+#[cfg_attr(miri, ignore)]
+fn test_bloom_filter_reopen_mmap() {
   run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    remove(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    const N: usize = 1000;
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("bloom_filter_reopen_skipmap");
+    {
+      let open_options = OpenOptions::default()
+        .create(Some(ARENA_SIZE as u32))
+        .read(true)
+        .write(true);
+      let map_options = MmapOptions::default();
+      let l = SkipMap::map_mut_with_options(
+        &p,
+        Options::new().with_bloom_filter(10),
+        open_options,
+        map_options,
+      )
+      .unwrap();
+      for i in 0..N {
+        l.get_or_insert(0, &key(i), &new_value(i)).unwrap();
+      }
+      l.seal().unwrap();
+      l.flush().unwrap();
+    }
+
+    let open_options = OpenOptions::default().read(true);
+    let map_options = MmapOptions::default();
+    let l = SkipMap::<u64>::map(&p, open_options, map_options, 0).unwrap();
+    assert!(l.is_sealed());
+    for i in 0..N {
+      assert!(l.bloom_may_contain(&key(i)));
+      assert_eq!(l.get(0, &key(i)).unwrap().value(), new_value(i));
+    }
+    let rejected = (N..2 * N)
+      .filter(|i| !l.bloom_may_contain(&key(*i)))
+      .count();
+    assert!(rejected > N * 95 / 100, "rejected {rejected}");
   })
 }
 
-fn remove2(l: SkipMap) {
-  for i in 0..100 {
-    let v = new_value(i);
-    l.insert(0, &key(i), &v).unwrap();
-  }
+fn discard(mut l: SkipMap) {
+  const N: usize = 100;
+  // the slot of a value is its trailer followed by the value bytes
+  let slot = (mem::size_of::<u64>() + new_value(0).len()) as u64;
 
-  for i in 0..100 {
-    let k = key(i);
-    // not found, remove should succeed
-    let old = l
-      .compare_remove(1, &k, Ordering::SeqCst, Ordering::Acquire)
-      .unwrap();
-    assert!(old.is_none());
+  l.insert(0, b"key", &new_value(0)).unwrap();
+  let base = l.discarded();
 
-    // no-race, remove should succeed
-    let old = l
-      .compare_remove(0, &k, Ordering::SeqCst, Ordering::Acquire)
-      .unwrap();
-    assert!(old.is_none());
+  for i in 1..N {
+    let v = new_value(i);
+    let old = l.insert(0, b"key", &v).unwrap().unwrap();
+    assert_eq!(old.value(), new_value(i - 1));
+    assert_eq!(l.discarded(), base + slot * i as u64);
   }
+  assert_eq!(l.len(), 1);
+  assert_eq!(l.get(0, b"key").unwrap().value(), new_value(N - 1));
 
-  for i in 0..100 {
-    let k = key(i);
-    let ent = l.get(0, &k);
-    assert!(ent.is_none());
-  }
+  // inserting a new version or a new key does not supersede any value
+  let discarded = l.discarded();
+  l.insert(1, b"key", &new_value(N)).unwrap();
+  l.insert(0, b"other", &new_value(N)).unwrap();
+  assert_eq!(l.discarded(), discarded);
+
+  l.clear().unwrap();
+  assert_eq!(l.discarded(), 0);
+  assert!(l.get(0, b"key").is_none());
 }
 
 #[test]
-fn test_remove2() {
-  run(|| remove2(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+fn test_discard() {
+  run(|| discard(SkipMap::with_options(TEST_OPTIONS.with_freelist(Freelist::None)).unwrap()))
 }
 
 #[test]
-fn test_remove2_unify() {
-  run(|| remove2(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
+fn test_discard_unify() {
+  run(|| discard(SkipMap::with_options(UNIFY_TEST_OPTIONS.with_freelist(Freelist::None)).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-#[cfg_attr(miri, ignore)]
-fn test_remove2_map_mut() {
+fn test_discard_map_anon() {
   run(|| {
-    let dir = tempfile::tempdir().unwrap();
-    let p = dir.path().join("test_skipmap_remove2_map_mut");
-    let open_options = OpenOptions::default()
-      .create_new(Some(ARENA_SIZE as u32))
-      .read(true)
-      .write(true);
-    let map_options = MmapOptions::default();
-    remove2(SkipMap::map_mut(p, open_options, map_options).unwrap());
+    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
+    discard(
+      SkipMap::map_anon_with_options(Options::new().with_freelist(Freelist::None), map_options)
+        .unwrap(),
+    );
   })
 }
 
+fn get_or_insert_profiled(l: SkipMap) {
+  const N: usize = 1000;
+
+  for i in 0..N {
+    let k = key(i);
+    let v = new_value(i);
+    let (old, profile) = l.get_or_insert_profiled(0, &k, &v).unwrap();
+    assert!(old.is_none());
+
+    let ent = l.get(0, &k).unwrap();
+    let height = unsafe { ent.0.ptr.as_ref().height() };
+    let expected = Node::<u64>::size(height) + k.len() + mem::size_of::<u64>() + v.len();
+    assert_eq!(profile.bytes_allocated(), expected as u64);
+    assert_eq!(profile.cas_retries(), 0);
+    assert!(profile.levels_traversed() >= 1);
+    if i > 0 {
+      assert!(profile.comparisons() > 0);
+    }
+  }
+
+  for i in 0..N {
+    let k = key(i);
+    let v = new_value(i + 1);
+    let (old, profile) = l.get_or_insert_profiled(0, &k, &v).unwrap();
+    assert_eq!(old.unwrap().value(), new_value(i));
+    assert_eq!(profile.bytes_allocated(), 0);
+    assert_eq!(profile.cas_retries(), 0);
+    assert!(profile.comparisons() > 0);
+    assert!(profile.levels_traversed() <= l.height() as u64);
+  }
+  assert_eq!(l.len(), N);
+}
+
 #[test]
-#[cfg(feature = "memmap")]
-fn test_remove2_map_anon() {
-  run(|| {
-    let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    remove2(SkipMap::map_anon(map_options).unwrap());
-  })
+fn test_get_or_insert_profiled() {
+  run(|| get_or_insert_profiled(SkipMap::with_options(TEST_OPTIONS).unwrap()))
+}
+
+#[test]
+fn test_get_or_insert_profiled_unify() {
+  run(|| get_or_insert_profiled(SkipMap::with_options(UNIFY_TEST_OPTIONS).unwrap()))
 }
 
 #[test]
 #[cfg(feature = "memmap")]
-fn test_remove2_map_anon_unify() {
+fn test_get_or_insert_profiled_map_anon() {
   run(|| {
     let map_options = MmapOptions::default().len(ARENA_SIZE as u32);
-    remove2(SkipMap::map_anon_with_options(UNIFY_TEST_OPTIONS, map_options).unwrap());
+    get_or_insert_profiled(SkipMap::map_anon(map_options).unwrap());
   })
 }
@@ -0,0 +1,61 @@
+use std::{collections::BTreeMap, vec::Vec};
+
+use super::*;
+
+/// An in-memory secondary index over the values of a [`SkipMap`], built by [`SkipMap::build_value_index`].
+///
+/// The index maps a key extracted from the values to the offsets of the nodes holding them,
+/// it is a snapshot of the map at the version it was built at, and it is not updated by
+/// later writes. Rebuild it after mutating the map, or only build it over a sealed map,
+/// see [`SkipMap::seal`].
+pub struct ValueIndex<'a, T, C> {
+  map: &'a SkipMap<T, C>,
+  index: BTreeMap<u64, Vec<u32>>,
+}
+
+impl<'a, T, C> ValueIndex<'a, T, C> {
+  /// Returns the number of distinct value keys in the index.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.index.len()
+  }
+
+  /// Returns `true` if the index is empty.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.index.is_empty()
+  }
+}
+
+impl<'a, T: Trailer, C: Comparator> ValueIndex<'a, T, C> {
+  #[inline]
+  pub(super) fn build(
+    map: &'a SkipMap<T, C>,
+    version: u64,
+    extract: impl Fn(&[u8]) -> u64,
+  ) -> Self {
+    let mut index = BTreeMap::<u64, Vec<u32>>::new();
+    for ent in map.iter(version) {
+      index
+        .entry(extract(ent.value()))
+        .or_default()
+        .push(ent.node_offset());
+    }
+
+    Self { map, index }
+  }
+
+  /// Returns the entries whose extracted value key equals `value_key`, in the key order of the map.
+  ///
+  /// An entry whose value was removed in place after the index was built, i.e. by a removal
+  /// at the version of the entry, is skipped. An entry shadowed by a newer version of its key,
+  /// a tombstone included, is still returned, compare it with [`SkipMap::get`] to skip it.
+  pub fn lookup(&self, value_key: u64) -> impl Iterator<Item = EntryRef<'a, T, C>> + '_ {
+    self
+      .index
+      .get(&value_key)
+      .into_iter()
+      .flatten()
+      .filter_map(|offset| self.map.resolve(*offset))
+  }
+}
@@ -6,16 +6,125 @@ pub use rarena_allocator::Freelist;
 
 use ux2::{u27, u5};
 
+/// The backoff strategy of the CAS retry loops of the [`SkipMap`](super::SkipMap), see [`Options::with_backoff`].
+///
+/// The strategy never affects the results of the writes, only how the threads wait for each other
+/// under contention.
+#[derive(Debug, Clone, Copy)]
+pub enum Backoff {
+  /// Retry immediately.
+  None,
+  /// Emit a spin loop hint before retrying.
+  Spin,
+  /// Yield the current thread before retrying. Without the `std` feature, this is the same as [`Backoff::Spin`].
+  Yield,
+  /// Spin `2^attempt` times before retrying, and yield the current thread once the number of spins
+  /// would exceed `max_spins`.
+  Exponential {
+    /// The maximum number of spins before yielding.
+    max_spins: u32,
+  },
+  /// Call the function with the number of the retry, starting from `0`, before retrying.
+  ///
+  /// This is the extension point for the strategies not covered by the other variants, e.g.
+  /// parking the thread, or a backoff tuned for a specific machine.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::{Options, SkipMap, options::Backoff};
+  ///
+  /// fn sleep(attempt: u32) {
+  ///   std::thread::sleep(std::time::Duration::from_micros(1 << attempt.min(10)));
+  /// }
+  ///
+  /// let map = SkipMap::with_options(Options::new().with_backoff(Backoff::Custom(sleep))).unwrap();
+  /// map.insert(0, b"hello", b"world").unwrap();
+  /// ```
+  Custom(fn(u32)),
+}
+
+// The custom functions are compared by address, as the options are compared for equality.
+impl PartialEq for Backoff {
+  fn eq(&self, other: &Self) -> bool {
+    match (self, other) {
+      (Self::None, Self::None) | (Self::Spin, Self::Spin) | (Self::Yield, Self::Yield) => true,
+      (Self::Exponential { max_spins: a }, Self::Exponential { max_spins: b }) => a == b,
+      (Self::Custom(a), Self::Custom(b)) => *a as usize == *b as usize,
+      _ => false,
+    }
+  }
+}
+
+impl Eq for Backoff {}
+
+impl core::hash::Hash for Backoff {
+  fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+    core::mem::discriminant(self).hash(state);
+    match self {
+      Self::Exponential { max_spins } => max_spins.hash(state),
+      Self::Custom(f) => (*f as usize).hash(state),
+      _ => {}
+    }
+  }
+}
+
+impl Default for Backoff {
+  #[inline]
+  fn default() -> Self {
+    Self::None
+  }
+}
+
+impl Backoff {
+  /// Waits before the `attempt`-th retry, starting from `0`.
+  #[inline]
+  pub(crate) fn snooze(&self, attempt: u32) {
+    match *self {
+      Self::None => {}
+      Self::Spin => core::hint::spin_loop(),
+      Self::Yield => yield_now(),
+      Self::Exponential { max_spins } => match 1u32.checked_shl(attempt) {
+        Some(spins) if spins <= max_spins => {
+          for _ in 0..spins {
+            core::hint::spin_loop();
+          }
+        }
+        _ => yield_now(),
+      },
+      Self::Custom(f) => f(attempt),
+    }
+  }
+}
+
+#[inline]
+fn yield_now() {
+  #[cfg(feature = "std")]
+  std::thread::yield_now();
+
+  #[cfg(not(feature = "std"))]
+  core::hint::spin_loop();
+}
+
 /// Options for `SkipMap`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Options {
   max_value_size: u32,
   max_key_size: u27,
   max_height: u5,
+  inline_keys: u32,
+  branching_probability: Option<u64>,
+  height_seed: Option<u64>,
+  bloom_filter: u32,
   magic_version: u16,
   capacity: u32,
+  reserved: u32,
+  lazy_init: bool,
   unify: bool,
+  flush_on_drop: bool,
+  checksum: bool,
   freelist: Freelist,
+  backoff: Backoff,
 }
 
 impl Default for Options {
@@ -33,10 +142,19 @@ impl Options {
       max_value_size: u32::MAX,
       max_key_size: u27::MAX,
       max_height: u5::new(20),
+      inline_keys: 0,
+      branching_probability: None,
+      height_seed: None,
+      bloom_filter: 0,
       capacity: 1024,
+      reserved: 0,
+      lazy_init: false,
       unify: false,
+      flush_on_drop: false,
+      checksum: false,
       magic_version: 0,
       freelist: Freelist::Optimistic,
+      backoff: Backoff::None,
     }
   }
 
@@ -78,6 +196,24 @@ impl Options {
     self
   }
 
+  /// Set the [`Backoff`] strategy used when a CAS fails because of a concurrent write,
+  /// before the write is retried.
+  ///
+  /// The default value is [`Backoff::None`].
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::{Options, options::Backoff};
+  ///
+  /// let opts = Options::new().with_backoff(Backoff::Exponential { max_spins: 64 });
+  /// ```
+  #[inline]
+  pub const fn with_backoff(mut self, backoff: Backoff) -> Self {
+    self.backoff = backoff;
+    self
+  }
+
   /// Set if use the unify memory layout of the [`SkipMap`](super::SkipMap).
   ///
   /// File backed [`SkipMap`](super::SkipMap) has different memory layout with other kind backed [`SkipMap`](super::SkipMap),
@@ -101,7 +237,59 @@ impl Options {
     self
   }
 
-  /// Sets the maximum size of the value.
+  /// Set if the [`SkipMap`](super::SkipMap) flushes the file backed memory map when the last
+  /// reference to it is dropped.
+  ///
+  /// This only applies to the writable file backed [`SkipMap`](super::SkipMap)s, e.g. created by
+  /// [`SkipMap::map_mut`](super::SkipMap::map_mut). Since `Drop` cannot return an error, a flush
+  /// failure is ignored, call [`SkipMap::flush`](super::SkipMap::flush) before dropping the map
+  /// if the error needs to be handled.
+  ///
+  /// The default value is `false`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let opts = Options::new().with_flush_on_drop(true);
+  /// ```
+  #[inline]
+  pub const fn with_flush_on_drop(mut self, flush_on_drop: bool) -> Self {
+    self.flush_on_drop = flush_on_drop;
+    self
+  }
+
+  /// Set if the [`SkipMap`](super::SkipMap) stores a CRC32C checksum of the ARENA when it is flushed.
+  ///
+  /// This only applies to the writable file backed [`SkipMap`](super::SkipMap)s. The checksum covers
+  /// the allocated region after the header, it is recomputed by every
+  /// [`SkipMap::flush`](super::SkipMap::flush), and validated when the file is reopened by
+  /// [`SkipMap::map`](super::SkipMap::map), which fails with
+  /// [`Error::ChecksumMismatch`](super::map::Error::ChecksumMismatch) if the file was corrupted.
+  ///
+  /// The first write after a flush clears the checksum, so a file written after its last flush is
+  /// reopened without validation. A write concurrent with a flush may leave a stale checksum, so
+  /// the map should not be written while it is flushed. [`SkipMap::map_mut`](super::SkipMap::map_mut)
+  /// initializes a new map in the file, so it does not validate the checksum.
+  ///
+  /// The default value is `false`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let opts = Options::new().with_checksum(true);
+  /// ```
+  #[inline]
+  pub const fn with_checksum(mut self, checksum: bool) -> Self {
+    self.checksum = checksum;
+    self
+  }
+
+  /// Sets the maximum size of the value, writing a larger value fails with
+  /// [`Error::ValueTooLarge`](crate::map::Error::ValueTooLarge) before allocating.
   ///
   /// Default is `u32::MAX`.
   ///
@@ -118,7 +306,8 @@ impl Options {
     self
   }
 
-  /// Sets the maximum size of the key.
+  /// Sets the maximum size of the key, writing a larger key fails with
+  /// [`Error::KeyTooLarge`](crate::map::Error::KeyTooLarge) before allocating.
   ///
   /// The maximum size of the key is `u27::MAX`.
   ///
@@ -139,7 +328,13 @@ impl Options {
 
   /// Sets the maximum height.
   ///
-  /// Default is `20`. The maximum height is `31`. The minimum height is `1`.
+  /// The maximum height bounds the length of the tower of each node, a height of `1` turns
+  /// the map into a sorted linked list, which is cheap for small short-lived maps, while a
+  /// larger height keeps searches logarithmic for very large maps. The random level generator
+  /// never generates a height larger than this value.
+  ///
+  /// Default is `20`. The maximum height is `31`. The minimum height is `1`, a height of `0`
+  /// will be treated as `1`.
   ///
   /// # Example
   ///
@@ -154,6 +349,97 @@ impl Options {
     self
   }
 
+  /// Sets the maximum size of the keys which will be stored inline with the node.
+  ///
+  /// For a new key whose size is less than or equal to `max_len`, the key bytes are allocated
+  /// in the same allocation as the node, right after its tower, so reading the key after
+  /// reaching the node does not jump to another region of the ARENA. This improves the cache
+  /// locality of read-heavy workloads with small keys.
+  ///
+  /// Default is `0`, which means keys are never inlined.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_inline_keys(16);
+  /// ```
+  #[inline]
+  pub const fn with_inline_keys(mut self, max_len: u32) -> Self {
+    self.inline_keys = max_len;
+    self
+  }
+
+  /// Sets the probability that a node is promoted to the next level of the skiplist.
+  ///
+  /// A larger probability builds taller towers, which costs more memory per node but
+  /// shortens searches, a smaller one builds a flatter list. The probability must be in the
+  /// range `(0.0, 1.0)`, otherwise constructing the [`SkipMap`](super::SkipMap) fails with
+  /// [`Error::InvalidBranchingProbability`](crate::map::Error::InvalidBranchingProbability).
+  ///
+  /// Default is `1 / e` (about `0.368`).
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_branching_probability(0.25);
+  /// ```
+  #[inline]
+  pub fn with_branching_probability(mut self, p: f64) -> Self {
+    self.branching_probability = Some(p.to_bits());
+    self
+  }
+
+  /// Sets the seed of the generator of the node heights.
+  ///
+  /// By default, the height of a new node is drawn from the thread RNG. When a seed is set,
+  /// the [`SkipMap`](super::SkipMap) uses a small deterministic PRNG (splitmix64) instead, so the
+  /// same seed and the same sequence of inserts always build the same towers, which makes
+  /// structural bugs reproducible in tests. Each clone of the map continues from its own copy
+  /// of the generator state.
+  ///
+  /// Default is `None`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_height_seed(42);
+  /// ```
+  #[inline]
+  pub const fn with_height_seed(mut self, seed: u64) -> Self {
+    self.height_seed = Some(seed);
+    self
+  }
+
+  /// Sets the number of bits per key of the Bloom filter built when the map is
+  /// [sealed](super::SkipMap::seal).
+  ///
+  /// The filter lets lookups of absent keys on a sealed map return without searching the
+  /// skiplist, `10` bits per key gives a false positive rate of about 1%. The filter hashes the
+  /// raw key bytes, so it is only supported with [`Ascend`](crate::Ascend) and [`Descend`](crate::Descend),
+  /// constructing a map with another comparator fails with
+  /// [`Error::UnsupportedBloomFilter`](crate::map::Error::UnsupportedBloomFilter).
+  ///
+  /// Default is `0`, which means no filter is built.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_bloom_filter(10);
+  /// ```
+  #[inline]
+  pub const fn with_bloom_filter(mut self, bits_per_key: u32) -> Self {
+    self.bloom_filter = bits_per_key;
+    self
+  }
+
   /// Sets the capacity of the underlying ARENA.
   ///
   /// Default is `1024`. This configuration will be ignored if the map is memory-mapped.
@@ -171,6 +457,99 @@ impl Options {
     self
   }
 
+  /// Sets the capacity of the underlying ARENA to hold `num_entries` entries of the given average
+  /// key and value lengths in a `SkipMap<u64>`, instead of guessing it for [`with_capacity`](Options::with_capacity).
+  ///
+  /// Every entry is assumed to be inserted once, the values replaced by later writes are not
+  /// counted. With `p` the [branching probability](Options::with_branching_probability) and the
+  /// sizes aligned up to the 8-byte node alignment, the capacity is estimated as:
+  ///
+  /// ```text
+  /// entry    = node + link * 1 / (1 - p) + key + (trailer + value)
+  /// fixed    = meta + reserved + 3 * (node + link * max_height + trailer)
+  /// capacity = (fixed + num_entries * entry) * 9 / 8
+  /// ```
+  ///
+  /// where `1 / (1 - p)` is the expected tower height of a node, the fixed part holds the head and
+  /// the tail nodes plus a node of the maximum height for the first nodes being taller than
+  /// expected, and the last `1/8` is a margin for the variance of the heights. The capacity is
+  /// capped at `u32::MAX`, so the options used for the height and the reserved region should be
+  /// set before this one.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::{Options, SkipMap};
+  ///
+  /// let options = Options::new().with_capacity_for(1000, 8, 16);
+  /// let map = SkipMap::with_options(options).unwrap();
+  /// for i in 0..1000u64 {
+  ///   map.insert(0, &i.to_be_bytes(), &[0; 16]).unwrap();
+  /// }
+  /// ```
+  #[inline]
+  pub fn with_capacity_for(
+    mut self,
+    num_entries: usize,
+    avg_key_len: usize,
+    avg_value_len: usize,
+  ) -> Self {
+    let capacity = crate::map::estimate_capacity(&self, num_entries, avg_key_len, avg_value_len);
+    self.capacity = capacity.min(u32::MAX as usize) as u32;
+    self
+  }
+
+  /// Sets the number of bytes reserved for the application right after the meta of the
+  /// [`SkipMap`](super::SkipMap), e.g. to store a sequence number in the same file as the map.
+  ///
+  /// The reserved region is never used by the nodes, it can be accessed by
+  /// [`SkipMap::reserved_slice`](super::SkipMap::reserved_slice) and
+  /// [`SkipMap::reserved_slice_mut`](super::SkipMap::reserved_slice_mut).
+  /// Its size is stored in the ARENA, so a memory-mapped map opened again by
+  /// [`SkipMap::map`](super::SkipMap::map) finds the region without setting this option.
+  /// [`SkipMap::map_mut`](super::SkipMap::map_mut) always initializes a new map in the file,
+  /// so it does not preserve the region either.
+  /// Clearing or resetting the map zeroes the region.
+  ///
+  /// Default is `0`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_reserved(16);
+  /// ```
+  #[inline]
+  pub const fn with_reserved(mut self, reserved: u32) -> Self {
+    self.reserved = reserved;
+    self
+  }
+
+  /// Sets whether the head and the tail nodes of the [`SkipMap`](super::SkipMap) are allocated by
+  /// the first write instead of the constructor, e.g. for a pool of maps of which some are never written.
+  ///
+  /// Until the first write, the map is empty and [`allocated`](super::SkipMap::allocated) only counts
+  /// the meta, the iterators created before the first write do not see the entries written later.
+  /// The ARENA must not be allocated from directly, e.g. through [`allocator`](super::SkipMap::allocator),
+  /// before the first write, which expects the nodes at the start of the ARENA, otherwise the writes
+  /// fail with [`Error::Corrupted`](crate::map::Error::Corrupted).
+  ///
+  /// Default is `false`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_lazy_init(true);
+  /// ```
+  #[inline]
+  pub const fn with_lazy_init(mut self, lazy_init: bool) -> Self {
+    self.lazy_init = lazy_init;
+    self
+  }
+
   /// Returns the maximum size of the value.
   ///
   /// Default is `u32::MAX`.
@@ -225,6 +604,86 @@ impl Options {
     self.max_height
   }
 
+  /// Returns the maximum size of the keys which will be stored inline with the node.
+  ///
+  /// Default is `0`, which means keys are never inlined.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_inline_keys(16);
+  ///
+  /// assert_eq!(options.inline_keys(), 16);
+  /// ```
+  #[inline]
+  pub const fn inline_keys(&self) -> u32 {
+    self.inline_keys
+  }
+
+  /// Returns the probability that a node is promoted to the next level of the skiplist.
+  ///
+  /// Default is `1 / e` (about `0.368`).
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_branching_probability(0.25);
+  ///
+  /// assert_eq!(options.branching_probability(), 0.25);
+  /// ```
+  #[inline]
+  pub fn branching_probability(&self) -> f64 {
+    self
+      .custom_branching_probability()
+      .unwrap_or(1.0 / core::f64::consts::E)
+  }
+
+  /// Returns the seed of the generator of the node heights.
+  ///
+  /// Default is `None`, which means the heights are drawn from the thread RNG.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_height_seed(42);
+  ///
+  /// assert_eq!(options.height_seed(), Some(42));
+  /// ```
+  #[inline]
+  pub const fn height_seed(&self) -> Option<u64> {
+    self.height_seed
+  }
+
+  /// Returns the number of bits per key of the Bloom filter built when the map is sealed.
+  ///
+  /// Default is `0`, which means no filter is built.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_bloom_filter(10);
+  ///
+  /// assert_eq!(options.bloom_filter(), 10);
+  /// ```
+  #[inline]
+  pub const fn bloom_filter(&self) -> u32 {
+    self.bloom_filter
+  }
+
+  /// Returns the branching probability set by users, `None` means the default one.
+  #[inline]
+  pub(crate) fn custom_branching_probability(&self) -> Option<f64> {
+    self.branching_probability.map(f64::from_bits)
+  }
+
   /// Returns the configuration of underlying ARENA size.
   ///
   /// Default is `1024`. This configuration will be ignored if the map is memory-mapped.
@@ -241,6 +700,40 @@ impl Options {
     self.capacity
   }
 
+  /// Returns the number of bytes reserved for the application.
+  ///
+  /// Default is `0`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_reserved(16);
+  /// assert_eq!(options.reserved(), 16);
+  /// ```
+  #[inline]
+  pub const fn reserved(&self) -> u32 {
+    self.reserved
+  }
+
+  /// Returns whether the head and the tail nodes are allocated by the first write.
+  ///
+  /// Default is `false`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::Options;
+  ///
+  /// let options = Options::new().with_lazy_init(true);
+  /// assert_eq!(options.lazy_init(), true);
+  /// ```
+  #[inline]
+  pub const fn lazy_init(&self) -> bool {
+    self.lazy_init
+  }
+
   /// Get if use the unify memory layout of the [`SkipMap`](super::SkipMap).
   ///
   /// File backed [`SkipMap`](super::SkipMap) has different memory layout with other kind backed [`SkipMap`](super::SkipMap),
@@ -265,6 +758,43 @@ impl Options {
     self.unify
   }
 
+  /// Get if the [`SkipMap`](super::SkipMap) flushes the file backed memory map when the last
+  /// reference to it is dropped.
+  ///
+  /// The default value is `false`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::Options;
+  ///
+  /// let opts = Options::new().with_flush_on_drop(true);
+  ///
+  /// assert_eq!(opts.flush_on_drop(), true);
+  /// ```
+  #[inline]
+  pub const fn flush_on_drop(&self) -> bool {
+    self.flush_on_drop
+  }
+
+  /// Get if the [`SkipMap`](super::SkipMap) stores a CRC32C checksum of the ARENA when it is flushed.
+  ///
+  /// The default value is `false`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::Options;
+  ///
+  /// let opts = Options::new().with_checksum(true);
+  ///
+  /// assert_eq!(opts.checksum(), true);
+  /// ```
+  #[inline]
+  pub const fn checksum(&self) -> bool {
+    self.checksum
+  }
+
   /// Get the magic version of the [`SkipMap`](super::SkipMap).
   ///
   /// This is used by the application using [`SkipMap`](super::SkipMap)
@@ -304,4 +834,22 @@ impl Options {
   pub const fn freelist(&self) -> Freelist {
     self.freelist
   }
+
+  /// Get the [`Backoff`] strategy used when a CAS fails because of a concurrent write.
+  ///
+  /// The default value is [`Backoff::None`].
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use skl::{Options, options::Backoff};
+  ///
+  /// let opts = Options::new().with_backoff(Backoff::Yield);
+  ///
+  /// assert_eq!(opts.backoff(), Backoff::Yield);
+  /// ```
+  #[inline]
+  pub const fn backoff(&self) -> Backoff {
+    self.backoff
+  }
 }
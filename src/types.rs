@@ -18,6 +18,110 @@ impl core::fmt::Display for TooLarge {
 #[cfg(feature = "std")]
 impl std::error::Error for TooLarge {}
 
+#[cfg(all(not(feature = "std"), core_error))]
+impl core::error::Error for TooLarge {}
+
+/// Returned by [`SkipMap::try_get_or_insert`](crate::SkipMap::try_get_or_insert) when publishing
+/// the new entry fails more than the allowed number of times because of concurrent writes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Contended;
+
+impl core::fmt::Display for Contended {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "the insert is contended by concurrent writes")
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Contended {}
+
+#[cfg(all(not(feature = "std"), core_error))]
+impl core::error::Error for Contended {}
+
+/// Returned by a [`TryComparator`](crate::TryComparator) when two keys cannot be compared,
+/// e.g. because one of them is malformed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompareError(&'static str);
+
+impl CompareError {
+  /// Creates a new error with the reason why the keys cannot be compared.
+  #[inline]
+  pub const fn new(reason: &'static str) -> Self {
+    Self(reason)
+  }
+
+  /// Returns the reason why the keys cannot be compared.
+  #[inline]
+  pub const fn reason(&self) -> &'static str {
+    self.0
+  }
+}
+
+impl core::fmt::Display for CompareError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "failed to compare keys: {}", self.0)
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CompareError {}
+
+#[cfg(all(not(feature = "std"), core_error))]
+impl core::error::Error for CompareError {}
+
+/// Converts a range over owned keys, e.g. `Vec<u8>`, into the byte slice bounds accepted by
+/// [`SkipMap::range`](crate::SkipMap::range) and the other range methods.
+///
+/// The range methods compare the bounds against `&[u8]`, which an owned key type cannot
+/// implement, so ranges such as `start..end` with `Vec<u8>` bounds go through this adapter
+/// instead of mapping each bound with `as_slice`.
+///
+/// # Example
+///
+/// ```rust
+/// use skl::{SkipMap, SliceBounds};
+///
+/// let map = SkipMap::new().unwrap();
+/// for key in [b"a", b"b", b"c", b"d"] {
+///   map.insert(0, key, key).unwrap();
+/// }
+///
+/// let (start, end) = (b"b".to_vec(), b"c".to_vec());
+/// let range = start..=end;
+/// let keys: Vec<_> = map.range(0, range.slice_bounds()).map(|ent| ent.key().to_vec()).collect();
+/// assert_eq!(keys, [b"b".to_vec(), b"c".to_vec()]);
+/// ```
+pub trait SliceBounds<K: ?Sized> {
+  /// Returns the bounds of the range as byte slices.
+  fn slice_bounds<'a>(&'a self) -> (core::ops::Bound<&'a [u8]>, core::ops::Bound<&'a [u8]>)
+  where
+    K: 'a;
+}
+
+impl<K, R> SliceBounds<K> for R
+where
+  K: ?Sized + core::borrow::Borrow<[u8]>,
+  R: core::ops::RangeBounds<K>,
+{
+  #[inline]
+  fn slice_bounds<'a>(&'a self) -> (core::ops::Bound<&'a [u8]>, core::ops::Bound<&'a [u8]>)
+  where
+    K: 'a,
+  {
+    fn as_slice<K: ?Sized + core::borrow::Borrow<[u8]>>(
+      bound: core::ops::Bound<&K>,
+    ) -> core::ops::Bound<&[u8]> {
+      match bound {
+        core::ops::Bound::Included(k) => core::ops::Bound::Included(k.borrow()),
+        core::ops::Bound::Excluded(k) => core::ops::Bound::Excluded(k.borrow()),
+        core::ops::Bound::Unbounded => core::ops::Bound::Unbounded,
+      }
+    }
+
+    (as_slice(self.start_bound()), as_slice(self.end_bound()))
+  }
+}
+
 /// A vacant buffer in the skiplist.
 #[must_use = "vacant buffer must be filled with bytes."]
 #[derive(Debug)]